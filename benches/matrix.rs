@@ -1,6 +1,6 @@
 use std::hint::black_box;
 use criterion::{criterion_group, criterion_main, Criterion};
-use rosemary_renderer::Matrix;
+use rosemary_renderer::{Matrix, Tuple};
 
 fn matrix_ops(c: &mut Criterion) {
     let three = black_box(Matrix::new_3x3([
@@ -22,11 +22,15 @@ fn matrix_ops(c: &mut Criterion) {
     |b| b.iter(|| four.inverse()));
     c.bench_function("4x4 matrix determinant",
     |b| b.iter(|| four.determinant()));
+    c.bench_function("4x4 matrix multiplication",
+    |b| b.iter(|| four * black_box(four)));
+    c.bench_function("4x4 matrix tuple multiplication",
+    |b| b.iter(|| four * black_box(Tuple::point(1.0, 2.0, 3.0))));
     c.bench_function("4x4 matrix cofactor",
     |b| b.iter(|| four.cofactor(black_box(2), black_box(1))));
 
     c.bench_function("3x3 matrix submatrixation",
-        |b| b.iter(|| (three.submatrix(black_box(0), black_box(2)))));
+        |b| b.iter(|| three.submatrix(black_box(0), black_box(2))));
 }
 
 criterion_group!(benches, matrix_ops);
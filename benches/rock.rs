@@ -0,0 +1,18 @@
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, Criterion};
+use rosemary_renderer::types::{ray::Ray, rock::RockGenerator, shape::Shape};
+use rosemary_renderer::Tuple;
+
+fn rock_ops(c: &mut Criterion) {
+    let generator = black_box(RockGenerator::new(7));
+    let rock = generator.generate();
+    let ray = black_box(Ray::new(Tuple::point(0.1, 0.2, -5.0), Tuple::vector(0.0, 0.0, 1.0)));
+
+    c.bench_function("rock generation",
+    |b| b.iter(|| generator.generate()));
+    c.bench_function("rock intersection",
+    |b| b.iter(|| rock.intersect(ray)));
+}
+
+criterion_group!(benches, rock_ops);
+criterion_main!(benches);
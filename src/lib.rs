@@ -1,10 +1,24 @@
 pub mod types;
 pub use types::tuple::Tuple;
 pub use types::matrix::Matrix;
+pub use types::error::RosemaryError;
 
-const EPSILON: f32 = 0.0001;
+/// Scalar used for all geometry and colour maths. Build with the `f64`
+/// feature when grazing angles or huge scenes show acne from f32 rounding
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
 
-fn eq(a: f32, b: f32) -> bool {
+/// Mathematical constants at the precision of `Float`
+#[cfg(not(feature = "f64"))]
+pub use std::f32::consts;
+#[cfg(feature = "f64")]
+pub use std::f64::consts;
+
+const EPSILON: Float = 0.0001;
+
+fn eq(a: Float, b: Float) -> bool {
     (a - b).abs() < EPSILON
 }
 
@@ -1,13 +1,22 @@
-use std::{fs::File, io::Write};
-use std::f32::consts::PI;
+use std::{fs::{self, File}, io, ops::Range, path::{Path, PathBuf}, process::{self, ExitCode}, thread, time::{Duration, Instant}};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rosemary_renderer::consts::PI;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rosemary_renderer::types::{ansi, bmp, hdr, pfm, png, tga};
+use rosemary_renderer::types::camera::Camera;
+use rosemary_renderer::types::export::Geometry;
 use rosemary_renderer::types::light::PointLight;
 use rosemary_renderer::types::material::Material;
+use rosemary_renderer::types::metadata::Metadata;
 use rosemary_renderer::types::ray::Ray;
+use rosemary_renderer::types::rock::RockGenerator;
+use rosemary_renderer::types::scene::{Built, IntegratorDesc, ProjectionDesc, Scene, SceneError};
+use rosemary_renderer::types::shape::Shape;
 use rosemary_renderer::types::sphere::Sphere;
-use rosemary_renderer::{tick, types::{canvas::Canvas, colour::Colour, intersection::Intersection}, Enviroment, Projectile, Tuple, Matrix};
+use rosemary_renderer::types::world::World;
+use rosemary_renderer::{Float, tick, types::{canvas::Canvas, colour::Colour, intersection::Intersection}, Enviroment, Projectile, Tuple, Matrix};
 
-fn projectile_fun() {
-// projectile fun things
+fn projectile_demo() -> Canvas {
     let mut proj = Projectile::new(
         Tuple::point(0.0, 1.0, 0.0),
         Tuple::vector(1.0, 2.0, 0.0).norm() * 11.0
@@ -19,24 +28,22 @@ fn projectile_fun() {
     );
 
     let mut canvas = Canvas::new(1500, 500);
+    let height = canvas.height as isize;
+    let to_pixel = |pos: Tuple| (pos.x as isize, height - pos.y as isize);
 
-    let mut total_ticks = 0;
     while proj.pos.y > 0.0 {
+        let from = to_pixel(proj.pos);
         tick(&env, &mut proj);
-        let x = proj.pos.x as usize;
-        let y = canvas.height - proj.pos.y as usize;
-        if x < canvas.width && y < canvas.height {
-            canvas[(x, y)] = Colour::new(1.0, 0.0, 0.0)
-        }
+        canvas.draw_line(from, to_pixel(proj.pos), Colour::new(1.0, 0.0, 0.0));
     }
 
-    let mut file = File::create("images/proj.ppm").unwrap();
-    write!(file, "{}", canvas.to_ppm()).unwrap();
+    canvas
 }
 
+#[allow(dead_code)]
 fn matrix_fun() {
     // matrix fun things
-    let identity = Matrix::identity(4);
+    let identity = Matrix::identity();
     dbg!(identity.inverse());
 
     let mat = Matrix::new_4x4([
@@ -49,34 +56,32 @@ fn matrix_fun() {
     dbg!(mat.transpose().inverse());
     dbg!(mat.inverse().unwrap().transpose());
 
-    let mut identity = Matrix::identity(4);
+    let mut identity = Matrix::identity();
     identity[(2, 0)] = 2.0;
     let tup = Tuple::new(1.0, 2.0, 3.0, 4.0);
     dbg!(identity * tup);
 }
 
-fn clock_fun() {
+fn clock_demo() -> Canvas {
     let angle = (2.0 * PI) / 12.0; // 2pi / 12;
     let mut canvas = Canvas::new(1000, 1000);
 
-    for i in 0..12 {
-        let transform = Matrix::rotation_z(angle * i as f32).scale(50.0, 50.0, 0.0).translate(500.0, 500.0, 0.0);
+    let hour = |i: usize| {
+        let transform = Matrix::rotation_z(angle * i as Float).scale(50.0, 50.0, 0.0).translate(500.0, 500.0, 0.0);
         let point = Tuple::point(0.0, 1.0, 0.0) * transform;
+        (point.x, point.y)
+    };
 
-        let x = point.x as usize;
-        let y = point.y as usize;
-        if x < canvas.width && y < canvas.height {
-            canvas[(x, y)] = Colour::new(1.0, 0.0, 0.0);
-        }
+    // join up the hours into the clock face
+    for i in 0..12 {
+        canvas.draw_line_aa(hour(i), hour(i + 1), Colour::new(1.0, 0.0, 0.0));
     }
 
-    let mut file = File::create("images/clock.ppm").unwrap();
-    write!(file, "{}", canvas.to_ppm()).unwrap();
+    canvas
 }
 
-fn sphere_fun() {
+fn sphere_demo() -> Canvas {
     let size = 1000;
-    let half = (size / 2) as f32;
     let mut canvas = Canvas::new(size, size);
 
     let mut sphere = Sphere::default();
@@ -87,39 +92,877 @@ fn sphere_fun() {
     let light = PointLight::new(Colour::white(), Tuple::point(-10.0, -10.0, -10.0));
 
     //sphere.transform = Matrix::scaling(2.0, 2.0, 2.0);
-    let red = Colour::new(1.0, 0.0, 0.0);
-    let ray_direction = Tuple::vector(0.0, 0.0, 1.0);
     let ray_origin = Tuple::point(0.0, 0.0, -5.0);
     let wall_z = 10.0;
     let wall_size = 7.0;
     let half = wall_size / 2.0;
-    let pixel_size = wall_size / size as f32;
+    let pixel_size = wall_size / size as Float;
 
     for y in 0..canvas.height {
-        let world_y = half - pixel_size * y as f32;
+        let world_y = half - pixel_size * y as Float;
         for x in 0..canvas.width {
-            let world_x = -half + pixel_size * x as f32;
+            let world_x = -half + pixel_size * x as Float;
             let position = Tuple::point(world_x, world_y, wall_z);
 
             let ray = Ray::new(ray_origin, (position - ray_origin).norm());
             if let Some(hit) = Intersection::hit(sphere.intersect(ray)) {
                 let hit_point = ray.position(hit.t);
-                let hit_norm = hit.obj.normal(hit_point);
+                let hit_norm = hit.obj.normal(hit_point, &hit);
                 let eye = -ray.direction;
-                let col = hit.obj.material.lighting(hit_point, light, eye, hit_norm);
+                let col = hit.obj.material().lighting(hit_point, &light, eye, hit_norm, 1.0);
 
                 canvas[(x, y)] = col;
             }
         }
     }
 
-    let mut file = File::create("images/sphere.ppm").unwrap();
-    write!(file, "{}", canvas.to_ppm()).unwrap();
+    canvas
+}
+
+/// The walled room with three spheres from the end of the book's camera chapter
+fn three_spheres_demo() -> Canvas {
+    let wall = Material {
+        colour: Colour::new(1.0, 0.9, 0.9),
+        specular: 0.0,
+        ..Material::default()
+    };
+    let floor = Sphere::new(Matrix::scaling(10.0, 0.01, 10.0), wall);
+    let left_wall = Sphere::new(Matrix::scaling(10.0, 0.01, 10.0).rotate_x(PI / 2.0).rotate_y(-PI / 4.0)
+        .translate(0.0, 0.0, 5.0), wall);
+    let right_wall = Sphere::new(Matrix::scaling(10.0, 0.01, 10.0).rotate_x(PI / 2.0).rotate_y(PI / 4.0)
+        .translate(0.0, 0.0, 5.0), wall);
+
+    let sphere = |transform, colour| Sphere::new(transform, Material {
+        colour,
+        diffuse: 0.7,
+        specular: 0.3,
+        ..Material::default()
+    });
+    let middle = sphere(Matrix::translation(-0.5, 1.0, 0.5), Colour::new(0.1, 1.0, 0.5));
+    let right = sphere(Matrix::scaling(0.5, 0.5, 0.5).translate(1.5, 0.5, -0.5), Colour::new(0.5, 1.0, 0.1));
+    let left = sphere(Matrix::scaling(0.33, 0.33, 0.33).translate(-1.5, 0.33, -0.75), Colour::new(1.0, 0.8, 0.1));
+
+    let light = PointLight::new(Colour::white(), Tuple::point(-10.0, 10.0, -10.0));
+    let world = World::new(vec![Box::new(floor), Box::new(left_wall), Box::new(right_wall), Box::new(middle),
+        Box::new(right), Box::new(left)], vec![Box::new(light)]);
+    let camera = Camera::look_at(Tuple::point(0.0, 1.5, -5.0), Tuple::point(0.0, 1.0, 0.0),
+        Tuple::vector(0.0, 1.0, 0.0), PI / 3.0, 1000, 500);
+
+    camera.render(&world)
+}
+
+fn rock_demo() -> Canvas {
+    let size = 500;
+    let mut canvas = Canvas::new(size, size);
+
+    let material = Material {
+        colour: Colour::new(0.6, 0.55, 0.5),
+        specular: 0.1,
+        ..Material::default()
+    };
+    let rock = RockGenerator::new(7).generate_with(Matrix::identity(), material);
+
+    let light = PointLight::new(Colour::white(), Tuple::point(-10.0, 10.0, -10.0));
+    let world = World::new(vec![Box::new(rock)], vec![Box::new(light)]);
+
+    let ray_origin = Tuple::point(0.0, 0.0, -5.0);
+    let wall_z = 10.0;
+    let wall_size = 7.0;
+    let half = wall_size / 2.0;
+    let pixel_size = wall_size / size as Float;
+
+    for y in 0..canvas.height {
+        let world_y = half - pixel_size * y as Float;
+        for x in 0..canvas.width {
+            let world_x = -half + pixel_size * x as Float;
+            let position = Tuple::point(world_x, world_y, wall_z);
+
+            let ray = Ray::new(ray_origin, (position - ray_origin).norm());
+            canvas[(x, y)] = world.colour_at(ray);
+        }
+    }
+
+    canvas
+}
+
+/// Showcase scenes built into the binary
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Demo {
+    /// Path of a projectile thrown through wind and gravity
+    Projectile,
+    /// The hours of a clock face joined up by lines
+    Clock,
+    /// A single shaded sphere
+    Sphere,
+    /// Three spheres in a walled room, seen through a camera
+    ThreeSpheres,
+    /// A procedurally generated rock
+    Rock,
+}
+
+impl Demo {
+    fn render(self) -> Canvas {
+        match self {
+            Demo::Projectile => projectile_demo(),
+            Demo::Clock => clock_demo(),
+            Demo::Sphere => sphere_demo(),
+            Demo::ThreeSpheres => three_spheres_demo(),
+            Demo::Rock => rock_demo(),
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(name = "rosemary", about = "A ray tracer")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check a scene file and summarise what's in it
+    Inspect {
+        scene: PathBuf,
+        /// Also render a small preview to this PPM file
+        #[arg(long)]
+        thumbnail: Option<PathBuf>,
+        /// Longest side of the thumbnail in pixels
+        #[arg(long, default_value_t = 64)]
+        thumbnail_size: usize,
+    },
+    /// Render a scene to an image file
+    Render {
+        /// Scene file, or `-` to read YAML or JSON from standard input
+        scene: PathBuf,
+        /// Image file, or `-` for standard output as PPM, or PNG and the
+        /// rest with --format
+        output: PathBuf,
+        /// Image format, guessed from the output's extension if not given
+        #[arg(long)]
+        format: Option<Format>,
+        #[command(flatten)]
+        overrides: Overrides,
+    },
+    /// Render a scene every time it's saved, at a quarter of the size first
+    Watch {
+        scene: PathBuf,
+        output: PathBuf,
+        /// Image format, guessed from the output's extension if not given
+        #[arg(long)]
+        format: Option<Format>,
+        #[command(flatten)]
+        overrides: Overrides,
+    },
+    /// Render a scene straight to the terminal in coloured blocks, for a
+    /// quick look without an image viewer
+    Show {
+        scene: PathBuf,
+        /// Characters across, the terminal's width (or 80) if not given.
+        /// Rendered at this many pixels wide unless --width is given
+        #[arg(long)]
+        columns: Option<usize>,
+        #[command(flatten)]
+        overrides: Overrides,
+    },
+    /// Render every scene file in a directory, several at once when they're
+    /// small. Files without a camera are left for other scenes to include
+    Batch {
+        dir: PathBuf,
+        /// Directory the images are written to, made if it doesn't exist
+        #[arg(short, long, default_value = "renders")]
+        out: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Ppm)]
+        format: Format,
+        #[command(flatten)]
+        overrides: Overrides,
+    },
+    /// Render a scene's animation as numbered frames
+    Animate {
+        scene: PathBuf,
+        /// Frames to render like `0..240`, the end left out. All of the
+        /// animation if not given
+        #[arg(long, value_parser = frame_range)]
+        frames: Option<Range<usize>>,
+        #[arg(long, default_value_t = 24.0)]
+        fps: Float,
+        /// Directory the frames are written to, made if it doesn't exist
+        #[arg(long, default_value = "frames")]
+        out: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Ppm)]
+        format: Format,
+        /// Also join the frames into this video with ffmpeg, if it's installed
+        #[arg(long)]
+        video: Option<PathBuf>,
+        #[command(flatten)]
+        overrides: Overrides,
+    },
+    /// Write a scene's geometry to an OBJ (with an MTL library) or glTF file
+    Export {
+        scene: PathBuf,
+        output: PathBuf,
+        /// How many times spheres' icosahedrons are subdivided
+        #[arg(long, default_value_t = 3)]
+        subdivisions: usize,
+    },
+    /// Render built-in showcase scenes to PPM files, all of them if none are named
+    Demo {
+        names: Vec<Demo>,
+        /// Directory the images are written to, made if it doesn't exist
+        #[arg(long, default_value = "images")]
+        out: PathBuf,
+    },
+    /// Render a scene in a window, watching it fill in row by row
+    #[cfg(feature = "preview")]
+    Preview {
+        scene: PathBuf,
+        /// Show the whole image at low resolution first, then sharpen it
+        #[arg(long)]
+        progressive: bool,
+        #[command(flatten)]
+        overrides: Overrides,
+    },
+}
+
+/// Scene settings to change from the command line, for trying out quality
+/// and speed trade-offs without editing the scene file
+#[derive(Args)]
+struct Overrides {
+    /// Image width in pixels, the height follows to keep the aspect ratio
+    /// unless it's given too
+    #[arg(long)]
+    width: Option<usize>,
+    /// Image height in pixels, the width follows unless it's given too
+    #[arg(long)]
+    height: Option<usize>,
+    /// Horizontal field of view in degrees
+    #[arg(long)]
+    fov: Option<Float>,
+    /// Camera samples along each side of a pixel
+    #[arg(long)]
+    samples: Option<usize>,
+    /// Most bounces a path traced ray takes
+    #[arg(long)]
+    max_bounces: Option<usize>,
+    /// A value for one of the scene's params, like `--param seed=7`
+    #[arg(long = "param", value_name = "NAME=VALUE", value_parser = param)]
+    params: Vec<(String, String)>,
+}
+
+/// `name=value` for `--param`
+fn param(param: &str) -> Result<(String, String), String> {
+    let (name, value) = param.split_once('=').ok_or("expected NAME=VALUE")?;
+    Ok((name.trim().to_string(), value.to_string()))
+}
+
+impl Overrides {
+    fn apply(&self, scene: &mut Scene) -> Result<(), String> {
+        let camera = &mut scene.camera;
+        let aspect = camera.width as Float / camera.height as Float;
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => (camera.width, camera.height) = (width, height),
+            (Some(width), None) => (camera.width, camera.height) = (width, (width as Float / aspect).round().max(1.0) as usize),
+            (None, Some(height)) => (camera.width, camera.height) = ((height as Float * aspect).round().max(1.0) as usize, height),
+            (None, None) => {},
+        }
+        if let Some(fov) = self.fov {
+            camera.fov = fov.to_radians();
+        }
+        if let Some(samples) = self.samples {
+            camera.samples = samples;
+        }
+
+        if let Some(bounces) = self.max_bounces {
+            match &mut scene.integrator {
+                IntegratorDesc::PathTracing { max_bounces, .. } => *max_bounces = bounces,
+                IntegratorDesc::Whitted => return Err("--max-bounces needs the path tracing integrator".to_string()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Image formats `render` can write
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Ppm,
+    /// PPM with 16 bits per channel
+    Ppm16,
+    Bmp,
+    Tga,
+    Png,
+    /// PNG with 16 bits per channel
+    Png16,
+    /// Floating point, skipping tone mapping
+    Pfm,
+    /// Radiance RGBE, skipping tone mapping
+    Hdr,
+}
+
+impl Format {
+    fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::value_variants().iter().copied()
+            .find(|format| !matches!(format, Format::Ppm16 | Format::Png16) && format.to_possible_value().unwrap().get_name() == extension)
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Ppm | Format::Ppm16 => "ppm",
+            Format::Bmp => "bmp",
+            Format::Tga => "tga",
+            Format::Png | Format::Png16 => "png",
+            Format::Pfm => "pfm",
+            Format::Hdr => "hdr",
+        }
+    }
+
+    fn is_hdr(self) -> bool {
+        matches!(self, Format::Pfm | Format::Hdr)
+    }
+
+    /// Whether images in this format have somewhere to keep the render's
+    /// metadata
+    fn keeps_metadata(self) -> bool {
+        !matches!(self, Format::Bmp | Format::Pfm)
+    }
+
+    /// Say so if the render's metadata is going to be left out
+    fn warn_about_metadata(self) {
+        if !self.keeps_metadata() {
+            eprintln!("warning: {} images have nowhere to keep the render's metadata, it's left out",
+                self.extension().to_uppercase());
+        }
+    }
+
+    /// Write to `path`, or standard output if it's `-`
+    fn save(self, image: &Canvas, path: &Path, metadata: &Metadata) -> io::Result<()> {
+        if is_stdio(path) {
+            self.write(image, io::stdout().lock(), metadata)
+        }
+        else {
+            self.write(image, File::create(path)?, metadata)
+        }
+    }
+
+    /// BMP and PFM leave out `metadata`, see `keeps_metadata`
+    fn write(self, image: &Canvas, writer: impl io::Write, metadata: &Metadata) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        match self {
+            Format::Ppm => image.write_ppm_with_metadata(&mut writer, metadata)?,
+            Format::Ppm16 => image.write_ppm_16_with_metadata(&mut writer, metadata)?,
+            Format::Bmp => bmp::encode(image, &mut writer)?,
+            Format::Tga => tga::encode_with_metadata(image, &mut writer, true, metadata)?,
+            Format::Png => png::encode_with_metadata(image, &mut writer, false, metadata)?,
+            Format::Png16 => png::encode_with_metadata(image, &mut writer, true, metadata)?,
+            Format::Pfm => pfm::encode(image, &mut writer)?,
+            Format::Hdr => hdr::encode_with_metadata(image, &mut writer, metadata)?,
+        }
+        io::Write::flush(&mut writer)
+    }
+}
+
+fn fmt_tuple(t: Tuple) -> String {
+    format!("({:.2}, {:.2}, {:.2})", t.x, t.y, t.z)
+}
+
+fn inspect(path: &Path, thumbnail: Option<&Path>, thumbnail_size: usize) -> ExitCode {
+    let scene = match Scene::load(path) {
+        Ok(scene) => scene,
+        Err(SceneError::Invalid(problems)) => {
+            eprintln!("{}: {} problem(s):", path.display(), problems.len());
+            for problem in problems {
+                eprintln!("  {problem}");
+            }
+            return ExitCode::FAILURE;
+        },
+        Err(err) => {
+            eprintln!("{}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let camera = &scene.camera;
+    println!("{}", path.display());
+    let view = match camera.projection {
+        ProjectionDesc::Perspective => format!("fov {:.1} degrees", camera.fov.to_degrees()),
+        ProjectionDesc::Orthographic { width } => format!("orthographic {width} wide"),
+        ProjectionDesc::Equirectangular => "equirectangular".to_string(),
+    };
+    println!("camera: {}x{}, {view}, from {:?} to {:?}", camera.width, camera.height, camera.from, camera.to);
+    println!("lights: {}", scene.lights.len());
+    for (i, light) in scene.lights.iter().enumerate() {
+        println!("  {}: {}", light.label(i), light.kind());
+    }
+    if let Some(sky) = &scene.sky {
+        println!("sky: turbidity {:.1}, sun {:.1} degrees up", sky.turbidity, sky.elevation.to_degrees());
+    }
+    if scene.duration() > 0.0 {
+        println!("animation: {:.2} seconds", scene.duration());
+    }
+
+    let Built { mut world, camera, sources, warnings } = match scene.build_all() {
+        Ok(built) => built,
+        Err(err) => {
+            eprintln!("{}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    for warning in warnings.into_iter().chain(world.prepare()) {
+        eprintln!("warning: {warning}");
+    }
+
+    println!("objects: {}", scene.objects.len());
+    let mut min = Tuple::point(Float::INFINITY, Float::INFINITY, Float::INFINITY);
+    let mut max = Tuple::point(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY);
+    // a model split into parts lists each of them under its name
+    for (&i, object) in sources.iter().zip(world.objects()) {
+        let (desc, bounds) = (&scene.objects[i], object.bounds());
+        println!("  {}: {}, centre {}, radius {:.2}", desc.label(i), desc.kind(), fmt_tuple(bounds.centre), bounds.radius);
+
+        min = Tuple::point(min.x.min(bounds.centre.x - bounds.radius), min.y.min(bounds.centre.y - bounds.radius),
+            min.z.min(bounds.centre.z - bounds.radius));
+        max = Tuple::point(max.x.max(bounds.centre.x + bounds.radius), max.y.max(bounds.centre.y + bounds.radius),
+            max.z.max(bounds.centre.z + bounds.radius));
+    }
+    if !scene.objects.is_empty() {
+        println!("bounding box: {} to {}", fmt_tuple(min), fmt_tuple(max));
+    }
+
+    if let Some(thumbnail) = thumbnail {
+        let scale = thumbnail_size as Float / camera.hsize().max(camera.vsize()) as Float;
+        let mut small = camera;
+        small.set_size(((camera.hsize() as Float * scale).round() as usize).max(1),
+            ((camera.vsize() as Float * scale).round() as usize).max(1));
+
+        let (mut image, stats) = small.render_with_stats(&world);
+        image.tone_map(&scene.output.build());
+        image.dither(scene.output.dither());
+        let written = File::create(thumbnail).and_then(|file| image.write_ppm(file));
+        if let Err(err) = written {
+            eprintln!("couldn't write {}: {err}", thumbnail.display());
+            return ExitCode::FAILURE;
+        }
+        println!("thumbnail: {} ({stats})", thumbnail.display());
+    }
+
+    println!("ok");
+    ExitCode::SUCCESS
+}
+
+fn export(path: &Path, output: &Path, subdivisions: usize) -> ExitCode {
+    let exported = Scene::load(path)
+        .and_then(|scene| scene.build())
+        .map_err(|err| format!("{}: {err}", path.display()))
+        .and_then(|(world, _)| {
+            Geometry::from_world(&world, subdivisions).save(output)
+                .map_err(|err| format!("couldn't write {}: {err}", output.display()))
+        });
+    match exported {
+        Ok(()) => {
+            println!("{}", output.display());
+            ExitCode::SUCCESS
+        },
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn demo(names: &[Demo], out: &Path) -> ExitCode {
+    if let Err(err) = fs::create_dir_all(out) {
+        eprintln!("couldn't create {}: {err}", out.display());
+        return ExitCode::FAILURE;
+    }
+
+    let names = if names.is_empty() { Demo::value_variants() } else { names };
+    for demo in names {
+        let path = out.join(format!("{}.ppm", demo.to_possible_value().unwrap().get_name()));
+        let written = File::create(&path).and_then(|file| demo.render().write_ppm(file));
+        if let Err(err) = written {
+            eprintln!("couldn't write {}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+        println!("{}", path.display());
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Whether `path` is `-`, meaning standard input or output
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// How to refer to `path` in messages
+fn describe(path: &Path) -> String {
+    if is_stdio(path) { "standard input".to_string() } else { path.display().to_string() }
+}
+
+/// `path` loaded with `overrides` applied, or why it couldn't be. A `path`
+/// of `-` reads YAML or JSON from standard input
+fn load_scene(path: &Path, overrides: &Overrides) -> Result<Scene, String> {
+    let loaded = if is_stdio(path) {
+        io::read_to_string(io::stdin()).map_err(SceneError::from)
+            .and_then(|source| Scene::from_yaml_with_params(&source, &overrides.params))
+    }
+    else {
+        Scene::load_with_params(path, &overrides.params)
+    };
+    let mut scene = loaded.map_err(|err| format!("{}: {err}", describe(path)))?;
+    overrides.apply(&mut scene)?;
+    Ok(scene)
+}
+
+/// The format to write `output` in, or why there isn't one. Standard
+/// output gets PPM unless told otherwise, any format can be piped
+fn output_format(output: &Path, format: Option<Format>) -> Result<Format, String> {
+    format.or_else(|| Format::from_extension(output))
+        .or_else(|| is_stdio(output).then_some(Format::Ppm))
+        .ok_or_else(|| format!("can't tell the image format of {}, use --format", output.display()))
+}
+
+/// Render the scene at `path` to `output`. A `quick` render is a quarter
+/// of the size with one sample per pixel, for a first look
+fn render_to(path: &Path, output: &Path, format: Format, overrides: &Overrides, quick: bool) -> Result<(), String> {
+    let mut scene = load_scene(path, overrides)?;
+    if quick {
+        let camera = &mut scene.camera;
+        (camera.width, camera.height, camera.samples) = ((camera.width / 4).max(1), (camera.height / 4).max(1), 1);
+    }
+    render_scene(&scene, path, output, format, threads())
+}
+
+/// How many threads to render with
+fn threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+/// Render `scene`, read from `path`, to `output` with `threads` threads
+fn render_scene(scene: &Scene, path: &Path, output: &Path, format: Format, threads: usize) -> Result<(), String> {
+    let Built { mut world, camera, warnings, .. } = scene.build_all().map_err(|err| format!("{}: {err}", describe(path)))?;
+    for warning in warnings.into_iter().chain(world.prepare()) {
+        eprintln!("warning: {warning}");
+    }
+
+    let start = Instant::now();
+    let mut image = camera.render_parallel(&world, threads);
+    let metadata = render_metadata(scene, path, start.elapsed());
+    if !format.is_hdr() {
+        image.tone_map(&scene.output.build());
+        image.dither(scene.output.dither());
+    }
+    format.save(&image, output, &metadata).map_err(|err| format!("couldn't write {}: {err}", output.display()))?;
+
+    if is_stdio(output) {
+        // keep the image alone on standard output
+        eprintln!("{}x{}", camera.hsize(), camera.vsize());
+    }
+    else {
+        println!("{} ({}x{})", output.display(), camera.hsize(), camera.vsize());
+    }
+    Ok(())
+}
+
+/// What went into rendering `scene` from `path`, to keep in the image
+fn render_metadata(scene: &Scene, path: &Path, time: Duration) -> Metadata {
+    let camera = &scene.camera;
+    let [from, to, up] = [camera.from, camera.to, camera.up].map(|[x, y, z]| fmt_tuple(Tuple::vector(x, y, z)));
+    let integrator = match scene.integrator {
+        IntegratorDesc::Whitted => "whitted".to_string(),
+        IntegratorDesc::PathTracing { max_bounces, seed, .. } => {
+            format!("path tracing, {max_bounces} bounces, seed {seed}")
+        },
+    };
+
+    let mut metadata = Metadata::new();
+    metadata.set("software", format!("rosemary {}", env!("CARGO_PKG_VERSION")))
+        .set("scene", describe(path))
+        .set("camera", format!("{}x{} from {from} to {to} up {up}, fov {:.1} degrees",
+            camera.width, camera.height, camera.fov.to_degrees()))
+        .set("samples", format!("{0}x{0} per pixel", camera.samples))
+        .set("integrator", integrator)
+        .set("render time", format!("{time:.2?}"));
+    metadata
+}
+
+fn render(path: &Path, output: &Path, format: Option<Format>, overrides: &Overrides) -> ExitCode {
+    let rendered = output_format(output, format).and_then(|format| {
+        format.warn_about_metadata();
+        render_to(path, output, format, overrides, false)
+    });
+    match rendered {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Render `path` and print it to the terminal `columns` characters wide
+fn show(path: &Path, columns: Option<usize>, overrides: &Overrides) -> ExitCode {
+    let columns = columns
+        .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+        .unwrap_or(80)
+        .max(1);
+    let shown = load_scene(path, overrides).and_then(|mut scene| {
+        if overrides.width.is_none() && overrides.height.is_none() {
+            let camera = &mut scene.camera;
+            let scale = columns as Float / camera.width as Float;
+            (camera.width, camera.height) = (columns, ((camera.height as Float * scale).round() as usize).max(1));
+        }
+        let Built { mut world, camera, warnings, .. } = scene.build_all().map_err(|err| format!("{}: {err}", path.display()))?;
+        for warning in warnings.into_iter().chain(world.prepare()) {
+            eprintln!("warning: {warning}");
+        }
+
+        let mut image = camera.render_parallel(&world, threads());
+        image.tone_map(&scene.output.build());
+        Ok(ansi::render(&image, columns))
+    });
+    match shown {
+        Ok(text) => {
+            print!("{text}");
+            ExitCode::SUCCESS
+        },
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Render `path` to `output` every time it's saved, quickly first and then
+/// in full. Runs until interrupted, mistakes in the scene are reported and
+/// then wait for the next save
+fn watch(path: &Path, output: &Path, format: Option<Format>, overrides: &Overrides) -> ExitCode {
+    let format = match output_format(output, format) {
+        Ok(format) => format,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    format.warn_about_metadata();
+
+    let modified = || fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut last = None;
+    loop {
+        let now = modified();
+        if now.is_some() && now != last {
+            last = now;
+            let rendered = render_to(path, output, format, overrides, true)
+                .and_then(|()| render_to(path, output, format, overrides, false));
+            if let Err(err) = rendered {
+                eprintln!("{err}");
+            }
+            println!("watching {} for changes", path.display());
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// Scenes with fewer camera samples than this are rendered side by side on
+/// a thread each, since splitting them up gains little
+const SMALL_RENDER: usize = 256 * 256;
+
+/// Render every scene file in `dir` into `out`, named after the scene.
+/// Scenes that fail are reported and the rest still rendered
+fn batch(dir: &Path, out: &Path, format: Format, overrides: &Overrides) -> ExitCode {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_file() && Scene::is_scene_file(path) && !Scene::is_fragment(path))
+            .collect(),
+        Err(err) => {
+            eprintln!("couldn't read {}: {err}", dir.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    paths.sort();
+    if let Err(err) = fs::create_dir_all(out) {
+        eprintln!("couldn't create {}: {err}", out.display());
+        return ExitCode::FAILURE;
+    }
+    format.warn_about_metadata();
+
+    let failed = AtomicUsize::new(0);
+    let fail = |err: String| {
+        eprintln!("{err}");
+        failed.fetch_add(1, Ordering::Relaxed);
+    };
+    let output = |path: &Path| {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        out.join(format!("{name}.{}", format.extension()))
+    };
+
+    let (mut small, mut large) = (Vec::new(), Vec::new());
+    let mut outputs: Vec<(PathBuf, &PathBuf)> = Vec::new();
+    for path in &paths {
+        // scenes like a.yaml and a.json would overwrite each other's render
+        let image = output(path);
+        if let Some((_, first)) = outputs.iter().find(|(taken, _)| *taken == image) {
+            fail(format!("{}: would overwrite the render of {} at {}, skipping", path.display(), first.display(), image.display()));
+            continue;
+        }
+        outputs.push((image, path));
+
+        match load_scene(path, overrides) {
+            Ok(scene) => {
+                let camera = &scene.camera;
+                let samples = camera.width * camera.height * camera.samples * camera.samples;
+                if samples < SMALL_RENDER { &mut small } else { &mut large }.push((path, scene));
+            },
+            Err(err) => fail(err),
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..threads().min(small.len()) {
+            scope.spawn(|| {
+                while let Some((path, scene)) = small.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if let Err(err) = render_scene(scene, path, &output(path), format, 1) {
+                        fail(err);
+                    }
+                }
+            });
+        }
+    });
+    for (path, scene) in &large {
+        if let Err(err) = render_scene(scene, path, &output(path), format, threads()) {
+            fail(err);
+        }
+    }
+
+    let failed = failed.into_inner();
+    println!("rendered {} of {} scenes into {}", paths.len() - failed, paths.len(), out.display());
+    if failed == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE }
 }
 
-fn main() {
-    // projectile_fun();
-    // matrix_fun();
-    // clock_fun();
-    sphere_fun();
+/// `start..end` for `--frames`
+fn frame_range(range: &str) -> Result<Range<usize>, String> {
+    let (start, end) = range.split_once("..").ok_or("expected a range like 0..240")?;
+    let parse = |frame: &str| frame.trim().parse::<usize>().map_err(|err| format!("{frame}: {err}"));
+    let frames = parse(start)?..parse(end)?;
+    if frames.is_empty() {
+        return Err(format!("{range} has no frames in it"));
+    }
+    Ok(frames)
+}
+
+/// Render `frames` of the animation in `path` into `out`, as `frame0000`
+/// and on, then join them into `video` if there's one and ffmpeg is around
+fn animate(path: &Path, frames: Option<Range<usize>>, fps: Float, out: &Path, format: Format, video: Option<&Path>,
+    overrides: &Overrides) -> ExitCode {
+    let scene = match load_scene(path, overrides) {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if fps <= 0.0 {
+        eprintln!("--fps must be positive");
+        return ExitCode::FAILURE;
+    }
+    if let Err(err) = fs::create_dir_all(out) {
+        eprintln!("couldn't create {}: {err}", out.display());
+        return ExitCode::FAILURE;
+    }
+    format.warn_about_metadata();
+
+    let frames = frames.unwrap_or(0..(scene.duration() * fps).floor() as usize + 1);
+    for frame in frames.clone() {
+        let output = out.join(format!("frame{frame:04}.{}", format.extension()));
+        if let Err(err) = render_scene(&scene.at(frame as Float / fps), path, &output, format, threads()) {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let Some(video) = video else { return ExitCode::SUCCESS };
+    let joined = process::Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-framerate", &fps.to_string(), "-start_number", &frames.start.to_string(), "-i"])
+        .arg(out.join(format!("frame%04d.{}", format.extension())))
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(video)
+        .status();
+    match joined {
+        Ok(status) if status.success() => {
+            println!("{}", video.display());
+            ExitCode::SUCCESS
+        },
+        Ok(status) => {
+            eprintln!("ffmpeg failed ({status})");
+            ExitCode::FAILURE
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            eprintln!("ffmpeg isn't installed, the frames are in {}", out.display());
+            ExitCode::SUCCESS
+        },
+        Err(err) => {
+            eprintln!("couldn't run ffmpeg: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(feature = "preview")]
+fn preview(path: &Path, progressive: bool, overrides: &Overrides) -> ExitCode {
+    use rosemary_renderer::types::preview::Preview;
+
+    let built = load_scene(path, overrides)
+        .and_then(|scene| Ok((scene.build().map_err(|err| format!("{}: {err}", path.display()))?, scene.output.build())));
+    let ((world, camera), tone_mapping) = match built {
+        Ok(built) => built,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut window = match Preview::new(&path.display().to_string(), camera.hsize(), camera.vsize()) {
+        Ok(window) => window,
+        Err(err) => {
+            eprintln!("couldn't open a window: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    window.tone_mapping = tone_mapping;
+    if progressive {
+        window.render_progressive(&camera, &world);
+    }
+    else {
+        window.render(&camera, &world);
+    }
+    window.wait();
+
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
+    match Cli::parse().command {
+        Some(Command::Inspect { scene, thumbnail, thumbnail_size }) => {
+            inspect(&scene, thumbnail.as_deref(), thumbnail_size)
+        },
+        Some(Command::Render { scene, output, format, overrides }) => render(&scene, &output, format, &overrides),
+        Some(Command::Watch { scene, output, format, overrides }) => watch(&scene, &output, format, &overrides),
+        Some(Command::Show { scene, columns, overrides }) => show(&scene, columns, &overrides),
+        Some(Command::Batch { dir, out, format, overrides }) => batch(&dir, &out, format, &overrides),
+        Some(Command::Animate { scene, frames, fps, out, format, video, overrides }) => {
+            animate(&scene, frames, fps, &out, format, video.as_deref(), &overrides)
+        },
+        Some(Command::Export { scene, output, subdivisions }) => export(&scene, &output, subdivisions),
+        Some(Command::Demo { names, out }) => demo(&names, &out),
+        #[cfg(feature = "preview")]
+        Some(Command::Preview { scene, progressive, overrides }) => preview(&scene, progressive, &overrides),
+        None => {
+            // matrix_fun();
+            demo(&[Demo::Sphere], Path::new("images"))
+        },
+    }
 }
@@ -1,12 +1,49 @@
+pub mod error;
 pub mod colour;
 pub mod tuple;
 pub mod canvas;
+pub mod font;
+pub mod cpu;
 pub mod matrix;
 pub mod ray;
+pub mod shape;
+pub mod bounds;
+pub mod bvh;
 pub mod sphere;
 pub mod intersection;
+pub mod arena;
 pub mod light;
 pub mod material;
 pub mod world;
+pub mod camera;
+pub mod stats;
+pub mod lut;
+pub mod tonemap;
+pub mod hdr;
+pub mod environment;
+pub mod medium;
+pub mod sky;
+pub mod pfm;
+pub mod bmp;
+pub mod tga;
+pub mod png;
+pub mod ansi;
+pub mod metadata;
+pub mod aov;
+pub mod stereo;
+pub mod mesh;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod export;
+pub mod rng;
+pub mod sampler;
+pub mod noise;
+pub mod rock;
+pub mod scene;
+#[cfg(feature = "rhai")]
+pub mod script;
+#[cfg(feature = "preview")]
+pub mod preview;
 
 use super::eq;
\ No newline at end of file
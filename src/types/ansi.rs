@@ -0,0 +1,82 @@
+//! Renders drawn in a terminal with coloured blocks, for a quick look over
+//! SSH or anywhere else without an image viewer. Each character is two
+//! pixels, an upper half block with the top pixel as its foreground colour
+//! and the bottom one as its background, in 24-bit ANSI colour
+
+use std::fmt::Write;
+
+use crate::Float;
+use super::{canvas::{channel_to_byte, Canvas}, colour::Colour};
+
+const UPPER_HALF: char = '▀';
+
+/// `canvas` shrunk to `columns` characters wide, keeping its shape, with a
+/// line for every two rows of pixels. Each pixel is the average of the
+/// pixels it covers. Canvases already narrower are shown as they are
+pub fn render(canvas: &Canvas, columns: usize) -> String {
+    if canvas.width == 0 || canvas.height == 0 {
+        return String::new();
+    }
+    let width = columns.clamp(1, canvas.width);
+    let height = ((canvas.height as Float * width as Float / canvas.width as Float).round() as usize).max(1);
+    let pixel = |x, y| average(canvas, x * canvas.width / width..(x + 1) * canvas.width / width,
+        y * canvas.height / height..(y + 1) * canvas.height / height);
+
+    let mut text = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let [r, g, b] = bytes(pixel(x, y));
+            write!(text, "\x1b[38;2;{r};{g};{b}m").unwrap();
+            if y + 1 < height {
+                let [r, g, b] = bytes(pixel(x, y + 1));
+                write!(text, "\x1b[48;2;{r};{g};{b}m").unwrap();
+            }
+            else {
+                text.push_str("\x1b[49m");
+            }
+            text.push(UPPER_HALF);
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    text
+}
+
+/// Mean colour of the pixels in the ranges, which always hold at least one
+fn average(canvas: &Canvas, xs: std::ops::Range<usize>, ys: std::ops::Range<usize>) -> Colour {
+    let xs = xs.start..xs.end.max(xs.start + 1);
+    let ys = ys.start..ys.end.max(ys.start + 1);
+    let count = (xs.len() * ys.len()) as Float;
+    let total = ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+        .fold(Colour::new(0.0, 0.0, 0.0), |total, (x, y)| total + canvas[(x, y)]);
+    Colour::new(total.r / count, total.g / count, total.b / count)
+}
+
+fn bytes(colour: Colour) -> [u8; 3] {
+    [colour.r, colour.g, colour.b].map(channel_to_byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let mut canvas = Canvas::new(4, 3);
+        canvas.fill(Colour::new(1.0, 0.0, 0.0));
+        canvas[(0, 1)] = Colour::new(0.0, 0.0, 1.0);
+        let text = super::render(&canvas, 10);
+
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].matches(UPPER_HALF).count(), 4);
+        assert!(lines[0].starts_with("\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m▀"));
+        // the odd row out is drawn over the terminal's own background
+        assert!(lines[1].starts_with("\x1b[38;2;255;0;0m\x1b[49m▀"));
+        assert!(lines[1].ends_with("\x1b[0m"));
+
+        // halving averages the pixels each one covers
+        let text = super::render(&canvas, 2);
+        assert!(text.starts_with("\x1b[38;2;255;0;0m\x1b[48;2;192;0;64m"));
+        assert_eq!(text.lines().count(), 1);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{Float, Tuple};
+use super::{canvas::Canvas, colour::Colour, world::Trace};
+
+/// Arbitrary output variables: data passes rendered alongside the beauty
+/// pass for compositing, reprojection and relighting tools.
+///
+/// Each pass is a canvas whose alpha is 1.0 where a ray hit something and
+/// 0.0 where it escaped.
+#[derive(Debug, Clone)]
+pub struct Aovs {
+    /// World space hit positions with x, y, z stored in r, g, b
+    pub position: Canvas,
+    /// World space normals with x, y, z stored in r, g, b, the guide most
+    /// denoisers want alongside the beauty pass
+    pub normal: Canvas,
+    /// Index of the object hit in `World::objects`, in every channel, for
+    /// building mattes of individual objects
+    pub object: Canvas,
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize) -> Self {
+        let empty = || {
+            let mut canvas = Canvas::new(width, height);
+            for y in 0..height {
+                for x in 0..width {
+                    canvas.set_alpha(x, y, 0.0);
+                }
+            }
+            canvas
+        };
+
+        Self {
+            position: empty(),
+            normal: empty(),
+            object: empty(),
+        }
+    }
+
+    pub fn record(&mut self, x: usize, y: usize, trace: &Trace) {
+        if let Some(point) = trace.position {
+            self.position[(x, y)] = Colour::new(point.x, point.y, point.z);
+            self.position.set_alpha(x, y, 1.0);
+        }
+        if let Some(normal) = trace.normal {
+            self.normal[(x, y)] = Colour::new(normal.x, normal.y, normal.z);
+            self.normal.set_alpha(x, y, 1.0);
+        }
+        if let Some(object) = trace.object {
+            let id = object as Float;
+            self.object[(x, y)] = Colour::new(id, id, id);
+            self.object.set_alpha(x, y, 1.0);
+        }
+    }
+
+    /// World space position seen through pixel `x`, `y`
+    pub fn position(&self, x: usize, y: usize) -> Option<Tuple> {
+        if self.position.alpha(x, y) == 0.0 {
+            return None;
+        }
+
+        let colour = self.position[(x, y)];
+        Some(Tuple::point(colour.r, colour.g, colour.b))
+    }
+
+    /// World space normal of the surface seen through pixel `x`, `y`
+    pub fn normal(&self, x: usize, y: usize) -> Option<Tuple> {
+        if self.normal.alpha(x, y) == 0.0 {
+            return None;
+        }
+
+        let colour = self.normal[(x, y)];
+        Some(Tuple::vector(colour.r, colour.g, colour.b))
+    }
+
+    /// Index in `World::objects` of the object seen through pixel `x`, `y`
+    pub fn object(&self, x: usize, y: usize) -> Option<usize> {
+        (self.object.alpha(x, y) != 0.0).then(|| self.object[(x, y)].r as usize)
+    }
+
+    /// Mask that's white wherever `object` covers the pixel and black elsewhere
+    pub fn matte(&self, object: usize) -> Canvas {
+        let mut matte = Canvas::new(self.object.width, self.object.height);
+        for y in 0..matte.height {
+            for x in 0..matte.width {
+                if self.object(x, y) == Some(object) {
+                    matte[(x, y)] = Colour::white();
+                }
+            }
+        }
+
+        matte
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let mut aovs = Aovs::new(2, 2);
+        assert_eq!(aovs.position(0, 0), None);
+
+        let trace = Trace {
+            colour: Colour::white(),
+            alpha: 1.0,
+            position: Some(Tuple::point(1.0, -2.0, 3.0)),
+            normal: Some(Tuple::vector(0.0, 1.0, 0.0)),
+            object: Some(3),
+            depth: 0,
+        };
+        aovs.record(1, 0, &trace);
+        assert_eq!(aovs.position(1, 0), Some(Tuple::point(1.0, -2.0, 3.0)));
+        assert_eq!(aovs.position.alpha(1, 0), 1.0);
+        assert_eq!(aovs.normal(1, 0), Some(Tuple::vector(0.0, 1.0, 0.0)));
+        assert_eq!(aovs.object(1, 0), Some(3));
+        assert_eq!(aovs.matte(3)[(1, 0)], Colour::white());
+        assert_eq!(aovs.matte(3)[(0, 0)], Colour::black());
+
+        let miss = Trace {
+            colour: Colour::black(),
+            alpha: 0.0,
+            position: None,
+            normal: None,
+            object: None,
+            depth: 0,
+        };
+        aovs.record(0, 1, &miss);
+        assert_eq!(aovs.position(0, 1), None);
+        assert_eq!(aovs.normal(0, 1), None);
+        assert_eq!(aovs.object(0, 1), None);
+    }
+}
@@ -0,0 +1,99 @@
+//! Per-thread pool of intersection buffers for the temporaries of shading.
+//! Reflection, refraction and path tracing need a fresh buffer for every ray
+//! they follow, so taking them from here lets each thread settle into
+//! reusing a handful instead of going to the allocator for each one
+
+use std::{cell::RefCell, mem, ops::{Deref, DerefMut}};
+
+use super::intersection::{Intersection, Intersections};
+
+/// Buffers each thread holds on to, enough for deep recursion
+const MAX_POOLED: usize = 32;
+
+thread_local! {
+    static POOL: RefCell<Vec<Intersections<'static>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Empty intersection buffer borrowed from this thread's pool, which goes
+/// back to the pool when dropped
+#[derive(Debug)]
+pub struct Scratch<'a> {
+    inters: Intersections<'a>,
+}
+
+impl Scratch<'_> {
+    pub fn new() -> Self {
+        let inters = POOL.with_borrow_mut(|pool| pool.pop()).map(relabel).unwrap_or_default();
+        Self {
+            inters,
+        }
+    }
+}
+
+impl Default for Scratch<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Deref for Scratch<'a> {
+    type Target = Intersections<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inters
+    }
+}
+
+impl DerefMut for Scratch<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inters
+    }
+}
+
+impl Drop for Scratch<'_> {
+    fn drop(&mut self) {
+        let inters = relabel(mem::take(&mut self.inters));
+        // the pool is already gone if this thread is shutting down
+        let _ = POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() < MAX_POOLED {
+                pool.push(inters);
+            }
+        });
+    }
+}
+
+/// Move a buffer's allocation over to a different lifetime, emptying it
+fn relabel<'b>(inters: Intersections<'_>) -> Intersections<'b> {
+    let mut inters = mem::ManuallyDrop::new(inters);
+    inters.clear();
+    let (pointer, capacity) = (inters.as_mut_ptr(), inters.capacity());
+    // SAFETY: the allocation is a `Vec`'s of the same element type, whose
+    // layout doesn't depend on the lifetime, and nothing is left in it to
+    // outlive `'b`. The old `Vec` is never dropped, so it's freed only once
+    unsafe { Vec::from_raw_parts(pointer.cast::<Intersection<'b>>(), 0, capacity) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::sphere::Sphere;
+
+    use super::*;
+
+    #[test]
+    fn reuses_buffers() {
+        let sphere = Sphere::default();
+        let (capacity, pointer) = {
+            let mut scratch = Scratch::new();
+            assert!(scratch.is_empty());
+            scratch.extend((0..10).map(|t| Intersection::new(t as crate::Float, &sphere)));
+            (scratch.capacity(), scratch.as_ptr())
+        };
+
+        // back from the pool empty, with the same allocation
+        let scratch = Scratch::new();
+        assert!(scratch.is_empty());
+        assert_eq!(scratch.capacity(), capacity);
+        assert_eq!(scratch.as_ptr() as *const u8, pointer as *const u8);
+    }
+}
@@ -0,0 +1,87 @@
+//! Uncompressed 24 bit Windows bitmaps (`.bmp`), which open in just about
+//! any image viewer without extra dependencies
+
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+
+use super::canvas::{channel_to_byte, Canvas};
+
+const FILE_HEADER_SIZE: u32 = 14;
+const INFO_HEADER_SIZE: u32 = 40;
+
+pub fn save(canvas: &Canvas, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode(canvas, &mut writer)?;
+    writer.flush()
+}
+
+/// Writes the pixels bottom row first in blue, green, red order, each row
+/// padded to a multiple of four bytes as the format expects
+pub fn encode(canvas: &Canvas, mut writer: impl Write) -> io::Result<()> {
+    let row_size = (canvas.width * 3).next_multiple_of(4);
+    let image_size = u32::try_from(row_size * canvas.height)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "canvas too big for a BMP"))?;
+    let offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+
+    // BITMAPFILEHEADER
+    writer.write_all(b"BM")?;
+    writer.write_all(&(offset + image_size).to_le_bytes())?;
+    writer.write_all(&[0; 4])?;
+    writer.write_all(&offset.to_le_bytes())?;
+
+    // BITMAPINFOHEADER
+    writer.write_all(&INFO_HEADER_SIZE.to_le_bytes())?;
+    writer.write_all(&(canvas.width as i32).to_le_bytes())?;
+    writer.write_all(&(canvas.height as i32).to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?; // planes
+    writer.write_all(&24u16.to_le_bytes())?; // bits per pixel
+    writer.write_all(&0u32.to_le_bytes())?; // no compression
+    writer.write_all(&image_size.to_le_bytes())?;
+    writer.write_all(&2835i32.to_le_bytes())?; // 72 dpi
+    writer.write_all(&2835i32.to_le_bytes())?;
+    writer.write_all(&[0; 8])?; // palette sizes
+
+    let mut row = vec![0; row_size];
+    for y in (0..canvas.height).rev() {
+        for x in 0..canvas.width {
+            let colour = canvas[(x, y)];
+            row[x * 3..x * 3 + 3].copy_from_slice(&[
+                channel_to_byte(colour.b),
+                channel_to_byte(colour.g),
+                channel_to_byte(colour.r),
+            ]);
+        }
+        writer.write_all(&row)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::colour::Colour;
+
+    #[test]
+    fn encode() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas[(0, 0)] = Colour::new(1.0, 0.5, 0.0);
+        canvas[(2, 1)] = Colour::new(0.0, 0.0, 2.0);
+
+        let mut data = Vec::new();
+        super::encode(&canvas, &mut data).unwrap();
+
+        // 9 bytes of pixels padded to 12 per row
+        assert_eq!(data.len(), 54 + 12 * 2);
+        assert_eq!(&data[0..2], b"BM");
+        assert_eq!(&data[2..6], &78u32.to_le_bytes());
+        assert_eq!(&data[10..14], &54u32.to_le_bytes());
+        assert_eq!(&data[18..22], &3i32.to_le_bytes());
+        assert_eq!(&data[22..26], &2i32.to_le_bytes());
+        assert_eq!(&data[28..30], &24u16.to_le_bytes());
+
+        // bottom row first, blue green red
+        assert_eq!(&data[54 + 6..54 + 9], &[255, 0, 0]);
+        assert_eq!(&data[54 + 9..54 + 12], &[0, 0, 0]);
+        assert_eq!(&data[66..69], &[0, 128, 255]);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{Float, Matrix, Tuple};
+use super::ray::Ray;
+
+/// Sphere enclosing an object, cheap to test rays against before doing the
+/// real intersection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub centre: Tuple,
+    pub radius: Float,
+}
+
+impl BoundingSphere {
+    pub fn new(centre: Tuple, radius: Float) -> Self {
+        assert!(centre.is_point());
+        assert!(radius >= 0.0);
+
+        Self {
+            centre,
+            radius,
+        }
+    }
+
+    /// Smallest sphere around the middle of `points`' bounding box that contains them all
+    pub fn from_points(points: &[Tuple]) -> Self {
+        if points.is_empty() {
+            return Self::new(Tuple::point(0.0, 0.0, 0.0), 0.0);
+        }
+
+        let (mut min, mut max) = (points[0], points[0]);
+        for p in points {
+            min = Tuple::point(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Tuple::point(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+
+        let centre = Tuple::point((min.x + max.x) / 2.0, (min.y + max.y) / 2.0, (min.z + max.z) / 2.0);
+        let radius = points.iter()
+            .map(|p| (*p - centre).magnitude())
+            .fold(0.0, Float::max);
+
+        Self::new(centre, radius)
+    }
+
+    /// Sphere containing this one after `transform` is applied
+    pub fn transform(&self, transform: &Matrix) -> Self {
+        Self::new(transform * self.centre, self.radius * max_stretch(transform))
+    }
+
+    /// Whether the line through `ray` passes through the sphere, in either direction
+    pub fn intersects(&self, ray: Ray) -> bool {
+        let to_ray = ray.origin - self.centre;
+        let a = ray.direction.dot(ray.direction);
+        let b = ray.direction.dot(to_ray);
+        let c = to_ray.dot(to_ray) - self.radius * self.radius;
+
+        b * b - a * c >= 0.0
+    }
+
+    /// Whether `point` is within `distance` of the sphere's surface or inside it
+    pub fn within(&self, point: Tuple, distance: Float) -> bool {
+        (point - self.centre).magnitude() <= self.radius + distance
+    }
+}
+
+/// Axis aligned box, what the BVH groups objects into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: Tuple,
+    pub max: Tuple,
+}
+
+impl BoundingBox {
+    pub fn new(min: Tuple, max: Tuple) -> Self {
+        Self {
+            min,
+            max,
+        }
+    }
+
+    /// Box containing nothing, the starting point for `union`
+    pub fn empty() -> Self {
+        Self::new(Tuple::point(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+            Tuple::point(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY))
+    }
+
+    pub fn from_sphere(sphere: &BoundingSphere) -> Self {
+        let r = sphere.radius;
+        Self::new(sphere.centre - Tuple::vector(r, r, r), sphere.centre + Tuple::vector(r, r, r))
+    }
+
+    /// Smallest box containing all of `points`, grown by `margin` on every side
+    pub fn from_points(points: &[Tuple], margin: Float) -> Self {
+        let bounds = points.iter().fold(Self::empty(), |bounds, p| bounds.union(&Self::new(*p, *p)));
+        let margin = Tuple::vector(margin, margin, margin);
+        Self::new(bounds.min - margin, bounds.max + margin)
+    }
+
+    /// Smallest box containing both
+    pub fn union(&self, other: &BoundingBox) -> Self {
+        Self::new(Tuple::point(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Tuple::point(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)))
+    }
+
+    pub fn centre(&self) -> Tuple {
+        Tuple::point((self.min.x + self.max.x) / 2.0, (self.min.y + self.max.y) / 2.0, (self.min.z + self.max.z) / 2.0)
+    }
+
+    /// Whether the line through `ray` passes through the box, in either
+    /// direction like `BoundingSphere::intersects`
+    pub fn intersects(&self, ray: Ray) -> bool {
+        let mut near = Float::NEG_INFINITY;
+        let mut far = Float::INFINITY;
+
+        for (min, max, origin, direction) in [
+            (self.min.x, self.max.x, ray.origin.x, ray.direction.x),
+            (self.min.y, self.max.y, ray.origin.y, ray.direction.y),
+            (self.min.z, self.max.z, ray.origin.z, ray.direction.z),
+        ] {
+            // parallel to this pair of faces, so it's either between them all along or never
+            if direction == 0.0 {
+                if origin < min || origin > max {
+                    return false;
+                }
+                continue;
+            }
+
+            let t1 = (min - origin) / direction;
+            let t2 = (max - origin) / direction;
+            near = near.max(t1.min(t2));
+            far = far.min(t1.max(t2));
+        }
+
+        near <= far
+    }
+}
+
+/// Largest factor `transform` scales any vector by, from the largest
+/// eigenvalue of MᵀM for the upper 3x3
+fn max_stretch(transform: &Matrix) -> Float {
+    let mut m = [[0.0; 3]; 3];
+    for (row, values) in m.iter_mut().enumerate() {
+        for (col, value) in values.iter_mut().enumerate() {
+            *value = (0..3).map(|k| transform[(k, row)] * transform[(k, col)]).sum();
+        }
+    }
+
+    // closed form eigenvalues of a symmetric 3x3 matrix
+    let off = m[0][1] * m[0][1] + m[0][2] * m[0][2] + m[1][2] * m[1][2];
+    let q = (m[0][0] + m[1][1] + m[2][2]) / 3.0;
+    let p = (((m[0][0] - q).powi(2) + (m[1][1] - q).powi(2) + (m[2][2] - q).powi(2) + 2.0 * off) / 6.0).sqrt();
+
+    let largest = if p < 1e-6 * q.abs().max(1.0) {
+        q.max(m[0][0]).max(m[1][1]).max(m[2][2])
+    }
+    else {
+        let b = |r: usize, c: usize| (m[r][c] - if r == c { q } else { 0.0 }) / p;
+        let det = b(0, 0) * (b(1, 1) * b(2, 2) - b(1, 2) * b(2, 1))
+            - b(0, 1) * (b(1, 0) * b(2, 2) - b(1, 2) * b(2, 0))
+            + b(0, 2) * (b(1, 0) * b(2, 1) - b(1, 1) * b(2, 0));
+        let phi = (det / 2.0).clamp(-1.0, 1.0).acos() / 3.0;
+        q + 2.0 * p * phi.cos()
+    };
+
+    // pad a little so rounding never makes the bounds too small
+    largest.max(0.0).sqrt() * 1.001
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::FRAC_PI_4;
+
+    use crate::eq;
+
+    use super::*;
+
+    #[test]
+    fn from_points() {
+        let b = BoundingSphere::from_points(&[Tuple::point(-1.0, 0.0, 0.0), Tuple::point(3.0, 0.0, 0.0),
+            Tuple::point(1.0, 1.0, 0.0)]);
+        assert_eq!(b.centre, Tuple::point(1.0, 0.5, 0.0));
+        assert!(eq(b.radius, (4.25 as Float).sqrt()));
+    }
+
+    #[test]
+    fn transform() {
+        let b = BoundingSphere::new(Tuple::point(0.0, 0.0, 0.0), 1.0);
+
+        let t = b.transform(&Matrix::scaling(1.0, 3.0, 2.0).rotate_z(FRAC_PI_4).translate(5.0, 0.0, 0.0));
+        assert_eq!(t.centre, Tuple::point(5.0, 0.0, 0.0));
+        assert!(t.radius >= 3.0 && t.radius < 3.01);
+
+        // shearing stretches more than any single axis
+        let t = b.transform(&Matrix::shearing(1.0, 0.0, 0.0, 0.0, 0.0, 0.0));
+        assert!(t.radius >= 1.618 && t.radius < 1.63);
+    }
+
+    #[test]
+    fn intersects() {
+        let b = BoundingSphere::new(Tuple::point(0.0, 0.0, 5.0), 1.0);
+        assert!(b.intersects(Ray::new(Tuple::point(0.0, 0.5, 0.0), Tuple::vector(0.0, 0.0, 1.0))));
+        assert!(b.intersects(Ray::new(Tuple::point(0.0, 0.0, 10.0), Tuple::vector(0.0, 0.0, 1.0))));
+        assert!(!b.intersects(Ray::new(Tuple::point(0.0, 1.5, 0.0), Tuple::vector(0.0, 0.0, 1.0))));
+
+        assert!(b.within(Tuple::point(0.0, 0.0, 2.0), 2.0));
+        assert!(!b.within(Tuple::point(0.0, 0.0, 1.0), 2.0));
+    }
+
+    #[test]
+    fn bounding_box() {
+        let b = BoundingBox::from_sphere(&BoundingSphere::new(Tuple::point(0.0, 0.0, 5.0), 1.0));
+        assert_eq!(b, BoundingBox::new(Tuple::point(-1.0, -1.0, 4.0), Tuple::point(1.0, 1.0, 6.0)));
+        assert_eq!(BoundingBox::empty().union(&b), b);
+        assert_eq!(b.centre(), Tuple::point(0.0, 0.0, 5.0));
+
+        assert!(b.intersects(Ray::new(Tuple::point(0.0, 0.5, 0.0), Tuple::vector(0.0, 0.0, 1.0))));
+        assert!(b.intersects(Ray::new(Tuple::point(0.0, 0.0, 10.0), Tuple::vector(0.0, 0.0, 1.0))));
+        // clips the corner the sphere doesn't reach
+        assert!(b.intersects(Ray::new(Tuple::point(0.9, 0.9, 0.0), Tuple::vector(0.0, 0.0, 1.0))));
+        assert!(!b.intersects(Ray::new(Tuple::point(0.0, 1.5, 0.0), Tuple::vector(0.0, 0.0, 1.0))));
+        // parallel to the x faces, grazing one of them
+        assert!(b.intersects(Ray::new(Tuple::point(1.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0))));
+        assert!(!b.intersects(Ray::new(Tuple::point(-5.0, 0.0, 0.0), Tuple::vector(1.0, 1.0, 0.0).norm())));
+    }
+}
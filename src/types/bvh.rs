@@ -0,0 +1,166 @@
+//! Bounding volume hierarchy over a world's objects or a mesh's triangles,
+//! so a ray only has to be tested against the few near it instead of every one
+
+use super::{bounds::{BoundingBox, BoundingSphere}, ray::Ray};
+
+/// Objects per leaf, splitting further costs more in box tests than it saves
+const MAX_LEAF_SIZE: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// Objects `order[start..end]`
+    Leaf { bounds: BoundingBox, start: usize, end: usize },
+    /// The left child is the next node, the right child is at `right`
+    Branch { bounds: BoundingBox, right: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &BoundingBox {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Object indices arranged so each leaf's are next to each other
+    order: Vec<usize>,
+}
+
+impl Bvh {
+    /// Build over objects with these bounds, splitting each node in half
+    /// along the axis its objects are most spread out on
+    pub fn new(bounds: &[BoundingSphere]) -> Self {
+        let boxes: Vec<_> = bounds.iter().map(BoundingBox::from_sphere).collect();
+        Self::from_boxes(&boxes)
+    }
+
+    /// Same as `new` for objects with boxes, which fit things like
+    /// triangles much more tightly than spheres
+    pub fn from_boxes(boxes: &[BoundingBox]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * boxes.len()),
+            order: (0..boxes.len()).collect(),
+        };
+
+        if !boxes.is_empty() {
+            bvh.build(boxes, 0, boxes.len());
+        }
+        bvh
+    }
+
+    fn build(&mut self, boxes: &[BoundingBox], start: usize, end: usize) {
+        let objects = &mut self.order[start..end];
+        let bounds = objects.iter().fold(BoundingBox::empty(), |bounds, &i| bounds.union(&boxes[i]));
+        if objects.len() <= MAX_LEAF_SIZE {
+            self.nodes.push(Node::Leaf { bounds, start, end });
+            return;
+        }
+
+        let centres = objects.iter()
+            .fold(BoundingBox::empty(), |centres, &i| centres.union(&BoundingBox::new(boxes[i].centre(), boxes[i].centre())));
+        let extent = centres.max - centres.min;
+        let axis = |i: usize| {
+            let centre = boxes[i].centre();
+            if extent.x >= extent.y && extent.x >= extent.z { centre.x }
+            else if extent.y >= extent.z { centre.y }
+            else { centre.z }
+        };
+
+        let middle = objects.len() / 2;
+        objects.select_nth_unstable_by(middle, |&a, &b| axis(a).total_cmp(&axis(b)));
+
+        let node = self.nodes.len();
+        self.nodes.push(Node::Branch { bounds, right: 0 });
+        self.build(boxes, start, start + middle);
+        let right_child = self.nodes.len();
+        self.build(boxes, start + middle, end);
+        self.nodes[node] = Node::Branch { bounds, right: right_child };
+    }
+
+    /// Call `visit` with the index of every object whose leaf box the line
+    /// through `ray` passes through, skipping whole branches it misses
+    pub fn candidates(&self, ray: Ray, mut visit: impl FnMut(usize)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds().intersects(ray) {
+                continue;
+            }
+
+            match *node {
+                Node::Leaf { start, end, .. } => self.order[start..end].iter().for_each(|&i| visit(i)),
+                Node::Branch { right, .. } => {
+                    stack.push(right);
+                    stack.push(index + 1);
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Float, Tuple};
+
+    use super::*;
+
+    fn grid() -> Vec<BoundingSphere> {
+        // 10x10 unit spheres in the z = 0 plane, two units apart
+        (0..100).map(|i| BoundingSphere::new(Tuple::point((i % 10) as Float * 2.0, (i / 10) as Float * 2.0, 0.0), 0.5))
+            .collect()
+    }
+
+    #[test]
+    fn candidates() {
+        let spheres = grid();
+        let bvh = Bvh::new(&spheres);
+
+        // straight at the sphere in the fourth column of the third row
+        let ray = Ray::new(Tuple::point(6.0, 4.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let mut visited = Vec::new();
+        bvh.candidates(ray, |i| visited.push(i));
+        assert!(visited.contains(&23));
+        assert!(visited.len() <= MAX_LEAF_SIZE);
+
+        // along the second row, leaves can pair those ten with neighbours
+        // in the rows either side but most of the grid is skipped
+        let ray = Ray::new(Tuple::point(-5.0, 2.0, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        let mut visited = Vec::new();
+        bvh.candidates(ray, |i| visited.push(i));
+        assert!((10..20).all(|i| visited.contains(&i)));
+        assert!(visited.len() < 30);
+
+        let ray = Ray::new(Tuple::point(1.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let mut visited = Vec::new();
+        bvh.candidates(ray, |i| visited.push(i));
+        assert!(visited.is_empty());
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let spheres = grid();
+        let bvh = Bvh::new(&spheres);
+
+        for i in 0..50 {
+            let angle = i as Float * 0.37;
+            let ray = Ray::new(Tuple::point(9.0, 9.0, -10.0), Tuple::vector(angle.cos(), angle.sin(), 1.5).norm());
+            let mut visited = Vec::new();
+            bvh.candidates(ray, |i| visited.push(i));
+
+            for (j, sphere) in spheres.iter().enumerate() {
+                if sphere.intersects(ray) {
+                    assert!(visited.contains(&j), "ray {i} missed sphere {j}");
+                }
+            }
+        }
+
+        Bvh::new(&[]).candidates(Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0)), |_| panic!());
+    }
+}
@@ -0,0 +1,1382 @@
+use std::{io::{self, BufWriter, Write}, ops::Range, sync::{atomic::{AtomicUsize, Ordering}, mpsc}, thread, time::Instant};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::consts::{FRAC_PI_3, PI};
+
+use crate::{Float, types::{aov::Aovs, canvas::Canvas, colour::Colour, intersection::{Intersection, Intersections}, light::Jitter, matrix::Transform, ray::{Ray, PACKET_SIZE}, rng::{hash_floats, RandomSource}, sampler::SampleId, stats::RenderStats,
+    world::{Trace, World}},
+    Matrix, RosemaryError, Tuple};
+
+/// How `Camera::render_with_config` splits up a render and seeds its samples.
+/// Each pixel's random numbers only depend on the seed and where the pixel
+/// is, never on which thread got it or when, so any thread count or tile
+/// size gives exactly the same image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderConfig {
+    pub threads: usize,
+    /// Side of the square tiles the threads take from a shared queue
+    pub tile_size: usize,
+    pub seeding: Seeding,
+}
+
+impl Default for RenderConfig {
+    /// One thread per core, 32 pixel tiles and the camera's own seed
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: TILE_SIZE,
+            seeding: Seeding::default(),
+        }
+    }
+}
+
+/// Where the seed for each pixel's samples comes from
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Seeding {
+    /// The camera's jitter seed and the pixel's position, so every render of
+    /// a pixel samples the same points
+    #[default]
+    Pixel,
+    /// Also mix in a frame number, so frames of an animation or passes of
+    /// `render_into` get different noise while still being reproducible.
+    /// Only reseeds the camera's jitter, lights keep their own seeds
+    Frame(u64),
+}
+
+/// Where each camera ray of an earlier render first hit the world, so
+/// rendering again from the same camera with only the shading changed can
+/// skip intersecting them. See `Camera::render_cached`
+#[derive(Debug, Clone, Default)]
+pub struct HitCache {
+    /// The camera the hits were found with, any other starts afresh
+    camera: Option<Camera>,
+    hits: Vec<CachedHit>,
+    reused: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CachedHit {
+    /// Index and id of the object hit, the id catching a rebuilt world
+    object: Option<(usize, Uuid)>,
+    t: Float,
+    face: Option<usize>,
+}
+
+impl HitCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many camera rays of the last render reused their cached hit
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    /// Forget every hit, the next render intersects all its rays again
+    pub fn clear(&mut self) {
+        self.camera = None;
+        self.hits.clear();
+        self.reused = 0;
+    }
+
+    /// First hit along `ray`, the `index`th camera ray of the render, taken
+    /// from the cache if the object is unchanged
+    fn hit<'a>(&mut self, world: &'a World, index: usize, ray: Ray) -> Option<Intersection<'a>> {
+        if let Some(cached) = self.hits.get(index) {
+            let object = match cached.object {
+                None => {
+                    self.reused += 1;
+                    return None;
+                },
+                Some((i, id)) => world.objects().get(i).filter(|obj| obj.id() == id),
+            };
+            if let Some(obj) = object {
+                self.reused += 1;
+                return Some(Intersection { t: cached.t, obj: obj.as_ref(), face: cached.face });
+            }
+        }
+
+        let hit = world.nearest_hit(ray);
+        let cached = CachedHit {
+            object: hit.and_then(|hit| Some((world.object_index(hit.obj.id())?, hit.obj.id()))),
+            t: hit.map_or(0.0, |hit| hit.t),
+            face: hit.and_then(|hit| hit.face),
+        };
+        if index < self.hits.len() {
+            self.hits[index] = cached;
+        }
+        else {
+            self.hits.push(cached);
+        }
+
+        hit
+    }
+}
+
+/// How the camera maps the scene onto the image
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Projection {
+    /// Rays spread out from a single point, things further away look smaller
+    #[default]
+    Perspective,
+    /// Parallel rays, so sizes don't change with distance. `width` is how
+    /// much of the world the image covers horizontally
+    Orthographic { width: Float },
+    /// Full 360° view around the camera mapped to latitude and longitude,
+    /// laid out the same way `Environment` reads its image. The image should
+    /// be twice as wide as it is tall
+    Equirectangular,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "SavedCamera", into = "SavedCamera")]
+pub struct Camera {
+    hsize: usize,
+    vsize: usize,
+    /// Horizontal field of view in radians, unused by orthographic cameras
+    fov: Float,
+    /// Each pixel is split into a `samples` by `samples` grid with a ray
+    /// through each cell, which anti-aliases edges
+    pub samples: usize,
+    /// Randomly place each pixel sample within its cell instead of at the centre
+    pub jitter: Jitter,
+    projection: Projection,
+    transform: Transform,
+    half_width: Float,
+    half_height: Float,
+    pixel_size: Float,
+}
+
+impl Camera {
+    /// Panics if either size is zero, see `try_new`
+    pub fn new(hsize: usize, vsize: usize, fov: Float) -> Self {
+        Self::try_new(hsize, vsize, fov).unwrap()
+    }
+
+    pub fn try_new(hsize: usize, vsize: usize, fov: Float) -> Result<Self, RosemaryError> {
+        if hsize == 0 || vsize == 0 {
+            return Err(RosemaryError::EmptyImage);
+        }
+
+        let mut camera = Self {
+            hsize,
+            vsize,
+            fov,
+            samples: 1,
+            jitter: Jitter::default(),
+            projection: Projection::Perspective,
+            transform: Transform::default(),
+            half_width: 0.0,
+            half_height: 0.0,
+            pixel_size: 0.0,
+        };
+        camera.update_view();
+        Ok(camera)
+    }
+
+    /// Perspective camera at `from` looking towards `to`, with `up` roughly upwards
+    pub fn look_at(from: Tuple, to: Tuple, up: Tuple, fov: Float, hsize: usize, vsize: usize) -> Self {
+        let mut camera = Self::new(hsize, vsize, fov);
+        camera.set_transform(Matrix::view_transform(from, to, up));
+        camera
+    }
+
+    pub fn builder(hsize: usize, vsize: usize) -> CameraBuilder {
+        CameraBuilder::new(hsize, vsize)
+    }
+
+    /// Camera with parallel rays, covering `width` world units across the image
+    pub fn orthographic(hsize: usize, vsize: usize, width: Float) -> Self {
+        Self::new(hsize, vsize, 0.0).with_projection(Projection::Orthographic { width })
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self.update_view();
+        self
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn hsize(&self) -> usize {
+        self.hsize
+    }
+
+    pub fn vsize(&self) -> usize {
+        self.vsize
+    }
+
+    /// Change the image size, keeping the field of view
+    pub fn set_size(&mut self, hsize: usize, vsize: usize) {
+        assert!(hsize > 0 && vsize > 0);
+
+        self.hsize = hsize;
+        self.vsize = vsize;
+        self.update_view();
+    }
+
+    pub fn fov(&self) -> Float {
+        self.fov
+    }
+
+    pub fn set_fov(&mut self, fov: Float) {
+        self.fov = fov;
+        self.update_view();
+    }
+
+    /// Work out the size of the view on the plane one unit in front of the camera
+    fn update_view(&mut self) {
+        let aspect = self.hsize as Float / self.vsize as Float;
+
+        (self.half_width, self.half_height) = match self.projection {
+            Projection::Perspective => {
+                let half_view = (self.fov / 2.0).tan();
+                if aspect >= 1.0 {
+                    (half_view, half_view / aspect)
+                }
+                else {
+                    (half_view * aspect, half_view)
+                }
+            },
+            // width is always horizontal so wide and tall images scale the same way
+            Projection::Orthographic { width } => (width / 2.0, width / 2.0 / aspect),
+            // angles instead of distances
+            Projection::Equirectangular => (PI, PI / 2.0),
+        };
+        self.pixel_size = self.half_width * 2.0 / self.hsize as Float;
+    }
+
+    pub fn transform(&self) -> Matrix {
+        *self.transform.matrix()
+    }
+
+    /// Panics if `transform` can't be inverted, see `try_set_transform`
+    pub fn set_transform(&mut self, transform: Matrix) {
+        self.try_set_transform(transform).unwrap();
+    }
+
+    /// Leaves the camera as it was if `transform` can't be inverted
+    pub fn try_set_transform(&mut self, transform: Matrix) -> Result<(), RosemaryError> {
+        self.transform = Transform::try_from(transform)?;
+        Ok(())
+    }
+
+    pub fn pixel_size(&self) -> Float {
+        self.pixel_size
+    }
+
+    /// Ray from the camera through the centre of pixel `x`, `y`
+    pub fn ray_for_pixel(&self, x: Float, y: Float) -> Ray {
+        self.ray_through(self.column(x), self.row(y), self.transform.inverse() * Tuple::point(0.0, 0.0, 0.0))
+    }
+
+    /// The part of a ray's camera space direction that only depends on `x`.
+    /// Every projection keeps the axes apart, so a whole render's rays can
+    /// be built from one of these per column and one per row, see `RayTable`
+    fn column(&self, x: Float) -> [Float; 2] {
+        match self.projection {
+            // camera looks towards -z so +x is to the left
+            Projection::Perspective | Projection::Orthographic { .. } => [self.half_width - (x + 0.5) * self.pixel_size, 0.0],
+            Projection::Equirectangular => {
+                // longitude from straight ahead, increasing to the right
+                let phi = ((x + 0.5) / self.hsize as Float - 0.5) * 2.0 * PI;
+                [phi.sin(), phi.cos()]
+            },
+        }
+    }
+
+    /// `column` for `y`
+    fn row(&self, y: Float) -> [Float; 2] {
+        match self.projection {
+            Projection::Perspective | Projection::Orthographic { .. } => [self.half_height - (y + 0.5) * self.pixel_size, 0.0],
+            Projection::Equirectangular => {
+                // angle down from straight up
+                let theta = (y + 0.5) / self.vsize as Float * PI;
+                [theta.sin(), theta.cos()]
+            },
+        }
+    }
+
+    /// Ray for a `column` and `row`, where `eye` is the camera's position
+    fn ray_through(&self, column: [Float; 2], row: [Float; 2], eye: Tuple) -> Ray {
+        match self.projection {
+            Projection::Perspective => {
+                let pixel = self.transform.inverse() * Tuple::point(column[0], row[0], -1.0);
+                Ray::new(eye, (pixel - eye).norm())
+            },
+            Projection::Orthographic { .. } => {
+                let origin = self.transform.inverse() * Tuple::point(column[0], row[0], 0.0);
+                let direction = self.transform.inverse() * Tuple::vector(0.0, 0.0, -1.0);
+                Ray::new(origin, direction.norm())
+            },
+            Projection::Equirectangular => {
+                let ([sin_phi, cos_phi], [sin_theta, cos_theta]) = (column, row);
+                let direction = Tuple::vector(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi);
+                Ray::new(eye, (self.transform.inverse() * direction).norm())
+            },
+        }
+    }
+
+    /// Stratified rays through pixel `x`, `y`, one per cell of the sample
+    /// grid. Each is at the centre of its cell unless `rng` is given to jitter it
+    pub fn rays_for_pixel(&self, x: usize, y: usize, mut rng: Option<&mut dyn RandomSource>) -> Vec<Ray> {
+        let n = self.samples.max(1);
+        let mut rays = Vec::with_capacity(n * n);
+
+        for v in 0..n {
+            for u in 0..n {
+                let (du, dv) = match &mut rng {
+                    Some(rng) => (rng.next_float(), rng.next_float()),
+                    None => (0.5, 0.5),
+                };
+                // ray_for_pixel adds half a pixel itself
+                let sx = x as Float + (u as Float + du) / n as Float - 0.5;
+                let sy = y as Float + (v as Float + dv) / n as Float - 0.5;
+                rays.push(self.ray_for_pixel(sx, sy));
+            }
+        }
+
+        rays
+    }
+
+    pub fn render(&self, world: &World) -> Canvas {
+        self.render_with_progress(world, |_, _| {})
+    }
+
+    /// Render, calling `progress` with the rows done so far and the total
+    /// after each row so long renders can report how far along they are
+    pub fn render_with_progress(&self, world: &World, progress: impl FnMut(usize, usize)) -> Canvas {
+        self.render_tile(world, (0, 0), self.hsize, self.vsize, None, progress).0
+    }
+
+    /// Render a row at a time, handing each finished row to `row_done` along
+    /// with its y so it can be displayed straight away. Returning `false`
+    /// stops the render, leaving the remaining rows black and transparent
+    pub fn render_rows(&self, world: &World, mut row_done: impl FnMut(usize, &Canvas) -> bool) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        image.fill_alpha(0.0);
+
+        for y in 0..self.vsize {
+            let (row, _) = self.render_tile(world, (0, y), self.hsize, 1, None, |_, _| {});
+            image.paste(0, y, &row);
+            if !row_done(y, &row) {
+                break;
+            }
+        }
+
+        image
+    }
+
+    /// Render on `threads` threads at once with the default tile size. Gives
+    /// exactly the same image as `render`
+    pub fn render_parallel(&self, world: &World, threads: usize) -> Canvas {
+        self.render_with_config(world, &RenderConfig { threads, ..Default::default() })
+    }
+
+    /// Render on `config.threads` threads at once. The image is cut into tiles
+    /// which the threads take one at a time from a shared queue, so a thread
+    /// that gets cheap tiles of empty sky just goes on to take more of them
+    pub fn render_with_config(&self, world: &World, config: &RenderConfig) -> Canvas {
+        assert!(config.threads > 0 && config.tile_size > 0);
+
+        self.seeded(config.seeding).render_tiles(world, config.threads, config.tile_size, 0..self.vsize)
+    }
+
+    /// Render `stripe_height` rows at a time, each stripe spread over threads
+    /// as in `render_with_config`, handing each to `stripe_done` with the y
+    /// of its top row. Only one stripe is ever held in memory, so images too
+    /// big for RAM can go straight to disk. An error from `stripe_done`
+    /// stops the render and is returned
+    pub fn render_stripes(&self, world: &World, config: &RenderConfig, stripe_height: usize,
+        mut stripe_done: impl FnMut(usize, &Canvas) -> io::Result<()>) -> io::Result<()> {
+        assert!(config.threads > 0 && config.tile_size > 0 && stripe_height > 0);
+
+        let camera = self.seeded(config.seeding);
+        for top in (0..self.vsize).step_by(stripe_height) {
+            let rows = top..(top + stripe_height).min(self.vsize);
+            stripe_done(top, &camera.render_tiles(world, config.threads, config.tile_size, rows))?;
+        }
+
+        Ok(())
+    }
+
+    /// Render straight to a 16 bit binary PPM a stripe at a time, see
+    /// `render_stripes`. Gives the same file as `Canvas::write_ppm_16` would
+    /// for the whole image
+    pub fn render_ppm_16(&self, world: &World, config: &RenderConfig, writer: impl Write) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(Canvas::ppm_16_header(self.hsize, self.vsize).as_bytes())?;
+
+        let mut data = Vec::new();
+        self.render_stripes(world, config, STRIPE_HEIGHT, |_, stripe| {
+            data.clear();
+            stripe.ppm_16_pixels(&mut data);
+            writer.write_all(&data)
+        })?;
+
+        writer.flush()
+    }
+
+    /// This camera with its jitter reseeded for `seeding`
+    fn seeded(&self, seeding: Seeding) -> Self {
+        let mut camera = *self;
+        if let Seeding::Frame(frame) = seeding {
+            camera.jitter.seed ^= frame.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        }
+        camera
+    }
+
+    /// Render the rows in `rows` as tiles shared between `threads` threads
+    fn render_tiles(&self, world: &World, threads: usize, tile_size: usize, rows: Range<usize>) -> Canvas {
+        let top = rows.start;
+        let tiles: Vec<(usize, usize)> = rows.clone().step_by(tile_size)
+            .flat_map(|y| (0..self.hsize).step_by(tile_size).map(move |x| (x, y)))
+            .collect();
+        let next = AtomicUsize::new(0);
+        let mut image = Canvas::new(self.hsize, rows.len());
+
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..threads.min(tiles.len()) {
+                let (sender, tiles, next, rows) = (sender.clone(), &tiles, &next, &rows);
+                scope.spawn(move || {
+                    while let Some(&(x, y)) = tiles.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let (width, height) = (tile_size.min(self.hsize - x), tile_size.min(rows.end - y));
+                        let (tile, _) = self.render_tile(world, (x, y), width, height, None, |_, _| {});
+                        // only fails if the receiver has gone, which means we're panicking anyway
+                        let _ = sender.send((x, y, tile));
+                    }
+                });
+            }
+
+            // the loop ends once every worker has finished and dropped its sender
+            drop(sender);
+            for (x, y, tile) in receiver {
+                image.paste(x, y - top, &tile);
+            }
+        });
+
+        image
+    }
+
+    /// Render coarse to fine so interactive tools have something to show
+    /// almost straight away: every 8th pixel across and down first, then
+    /// every 4th, 2nd and finally the rest. After each pass `pass_done` gets
+    /// the spacing and the image so far, where untraced pixels copy the
+    /// nearest traced one above and to the left. Pixels are never traced
+    /// twice, so the last pass finishes the same image as `render`.
+    /// Returning `false` stops before the next pass
+    pub fn render_progressive(&self, world: &World, mut pass_done: impl FnMut(usize, &Canvas) -> bool) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        let mut scratch = Intersections::new();
+
+        for (pass, step) in PROGRESSIVE_STEPS.into_iter().enumerate() {
+            for y in (0..self.vsize).step_by(step) {
+                for x in (0..self.hsize).step_by(step) {
+                    // already traced by a coarser pass
+                    if pass > 0 && x % (step * 2) == 0 && y % (step * 2) == 0 {
+                        continue;
+                    }
+
+                    // stand in for the rest of its block until finer passes fill it in
+                    let (colour, alpha) = self.render_pixel(world, x, y, &mut scratch);
+                    for by in y..(y + step).min(self.vsize) {
+                        for bx in x..(x + step).min(self.hsize) {
+                            image[(bx, by)] = colour;
+                            image.set_alpha(bx, by, alpha);
+                        }
+                    }
+                }
+            }
+
+            if !pass_done(step, &image) {
+                break;
+            }
+        }
+
+        image
+    }
+
+    /// Render reusing the first hits in `cache` from an earlier render with
+    /// this same camera, then keep this render's hits there for the next.
+    /// Changing lights, ambient or the integrator between renders just
+    /// reshades, while any change to the camera, jitter included, means
+    /// intersecting every ray again. The world's objects must not move
+    pub fn render_cached(&self, world: &World, cache: &mut HitCache) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        if cache.camera != Some(*self) {
+            cache.clear();
+            cache.camera = Some(*self);
+        }
+        cache.reused = 0;
+        let mut index = 0;
+
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let mut rng = self.jitter.rng_for(Tuple::point(x as Float, y as Float, 0.0));
+                let traces: Vec<_> = self.rays_for_pixel(x, y, rng.as_mut().map(|rng| rng as &mut dyn RandomSource))
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, ray)| {
+                        let hit = cache.hit(world, index, ray);
+                        index += 1;
+                        world.trace_hits(ray, hit.as_slice(), Some(self.sample_id(x, y, i)))
+                    })
+                    .collect();
+
+                let (colour, alpha) = resolve(world, &traces);
+                image[(x, y)] = colour;
+                image.set_alpha(x, y, alpha);
+            }
+        }
+        cache.hits.truncate(index);
+
+        image
+    }
+
+    /// Render, also counting the rays traced and timing it
+    pub fn render_with_stats(&self, world: &World) -> (Canvas, RenderStats) {
+        let start = Instant::now();
+        let shadow_rays = world.shadow_rays();
+
+        let (image, depths) = self.render_tile(world, (0, 0), self.hsize, self.vsize, None, |_, _| {});
+
+        let stats = RenderStats {
+            primary_rays: depths.iter().sum(),
+            // other renders sharing the world at the same time get counted too
+            shadow_rays: world.shadow_rays() - shadow_rays,
+            depths,
+            time: start.elapsed(),
+        };
+
+        (image, stats)
+    }
+
+    /// Blend a new render into `image`, which keeps `1 - weight` of what it
+    /// had. Passing `1 / n` for the nth call keeps a running average, so
+    /// noise shrinks with every pass and rendering can stop at any point.
+    /// Change the jitter seed between passes or they all sample the same points
+    pub fn render_into(&self, world: &World, image: &mut Canvas, weight: Float) {
+        assert!(image.width == self.hsize && image.height == self.vsize);
+        assert!((0.0..=1.0).contains(&weight));
+
+        for y in 0..self.vsize {
+            let (row, _) = self.render_tile(world, (0, y), self.hsize, 1, None, |_, _| {});
+            for x in 0..self.hsize {
+                image[(x, y)] = image[(x, y)] * (1.0 - weight) + row[(x, 0)] * weight;
+                image.set_alpha(x, y, image.alpha(x, y) * (1.0 - weight) + row.alpha(x, 0) * weight);
+            }
+        }
+    }
+
+    /// Render an animation. For each frame `setup` is given the frame number
+    /// and a copy of this camera to move, and returns the world as it is at
+    /// that moment. Each finished image is passed to `output` with its frame
+    /// number, see `frame_path` for naming files
+    pub fn render_sequence(&self, frames: Range<usize>, mut setup: impl FnMut(usize, &mut Camera) -> World,
+        mut output: impl FnMut(usize, Canvas) -> io::Result<()>) -> io::Result<()> {
+        for frame in frames {
+            let mut camera = *self;
+            let world = setup(frame, &mut camera);
+            output(frame, camera.render(&world))?;
+        }
+
+        Ok(())
+    }
+
+    /// Render straight to a PPM file, writing each row as soon as it's done
+    /// so the whole image never has to be held in memory
+    pub fn render_ppm(&self, world: &World, writer: impl Write) -> io::Result<()> {
+        let (width, height) = (self.hsize, self.vsize);
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(Canvas::ppm_header(width, height).as_bytes())?;
+
+        let mut data = Vec::new();
+        for y in 0..height {
+            data.clear();
+            self.render_tile(world, (0, y), width, 1, None, |_, _| {}).0.ppm_pixels(&mut data);
+            writer.write_all(&data)?;
+        }
+
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+
+    /// Render just the `width` by `height` pixels with their top left corner
+    /// at `x`, `y`, exactly as they would be in the full image
+    pub fn render_region(&self, world: &World, x: usize, y: usize, width: usize, height: usize) -> Canvas {
+        assert!(x + width <= self.hsize && y + height <= self.vsize);
+        self.render_tile(world, (x, y), width, height, None, |_, _| {}).0
+    }
+
+    /// Render the beauty pass along with auxiliary data passes. Data can't
+    /// be blended like colours, so they come from an extra ray through the
+    /// centre of each pixel
+    pub fn render_with_aovs(&self, world: &World) -> (Canvas, Aovs) {
+        let mut aovs = Aovs::new(self.hsize, self.vsize);
+        let (image, _) = self.render_tile(world, (0, 0), self.hsize, self.vsize, Some(&mut aovs), |_, _| {});
+        (image, aovs)
+    }
+
+    fn render_pixel<'a>(&self, world: &'a World, x: usize, y: usize, scratch: &mut Intersections<'a>) -> (Colour, Float) {
+        let mut rng = self.jitter.rng_for(Tuple::point(x as Float, y as Float, 0.0));
+        let traces: Vec<_> = self.rays_for_pixel(x, y, rng.as_mut().map(|rng| rng as &mut dyn RandomSource))
+            .into_iter()
+            .enumerate()
+            .map(|(i, ray)| world.trace_sample(ray, self.sample_id(x, y, i), scratch))
+            .collect();
+        resolve(world, &traces)
+    }
+
+    /// Sample `index` of pixel `x`, `y`. Reseeding the jitter gives every
+    /// pixel new samples
+    fn sample_id(&self, x: usize, y: usize, index: usize) -> SampleId {
+        SampleId {
+            pixel: self.jitter.seed ^ hash_floats(&[x as Float, y as Float]),
+            index: index as u64,
+        }
+    }
+
+    /// The tile's image, and how many of its camera rays bounced each number
+    /// of times. Data passes are recorded into `aovs` if given
+    fn render_tile(&self, world: &World, (left, top): (usize, usize), width: usize, height: usize,
+        mut aovs: Option<&mut Aovs>, mut progress: impl FnMut(usize, usize)) -> (Canvas, Vec<usize>) {
+        World::forget_occluders();
+        let mut image = Canvas::new(width, height);
+        let mut depths = Vec::new();
+        let mut scratch: [Intersections; PACKET_SIZE] = Default::default();
+        let (mut rays, mut ends, mut row_traces) = (Vec::new(), Vec::with_capacity(width), Vec::new());
+        let mut samples = Vec::new();
+        // without jitter every sample sits at the same place in its pixel
+        let table = (!self.jitter.enabled).then(|| RayTable::new(self, (left, top), width, height));
+
+        for y in 0..image.height {
+            // neighbouring rays along a row are coherent, so trace the whole
+            // row's worth in packets
+            rays.clear();
+            ends.clear();
+            samples.clear();
+            for x in 0..image.width {
+                let (px, py) = (left + x, top + y);
+                let start = rays.len();
+                match &table {
+                    Some(table) => table.rays_for_pixel(x, y, &mut rays),
+                    None => {
+                        let mut rng = self.jitter.rng_for(Tuple::point(px as Float, py as Float, 0.0));
+                        rays.extend(self.rays_for_pixel(px, py, rng.as_mut().map(|rng| rng as &mut dyn RandomSource)));
+                    },
+                }
+                samples.extend((0..rays.len() - start).map(|i| self.sample_id(px, py, i)));
+                ends.push(rays.len());
+            }
+
+            row_traces.clear();
+            let mut packets = rays.chunks_exact(PACKET_SIZE).zip(samples.chunks_exact(PACKET_SIZE));
+            for (packet, ids) in packets.by_ref() {
+                row_traces.extend(world.trace_packet(packet.try_into().unwrap(), ids.try_into().unwrap(), &mut scratch));
+            }
+            let done = row_traces.len();
+            for (ray, sample) in rays[done..].iter().zip(&samples[done..]) {
+                row_traces.push(world.trace_sample(*ray, *sample, &mut scratch[0]));
+            }
+            for trace in &row_traces {
+                if depths.len() <= trace.depth {
+                    depths.resize(trace.depth + 1, 0);
+                }
+                depths[trace.depth] += 1;
+            }
+
+            let mut start = 0;
+            for x in 0..image.width {
+                let traces = &row_traces[start..ends[x]];
+                start = ends[x];
+                let (colour, alpha) = resolve(world, traces);
+                image[(x, y)] = colour;
+                image.set_alpha(x, y, alpha);
+                if let Some(aovs) = aovs.as_deref_mut() {
+                    let (px, py) = ((left + x) as Float, (top + y) as Float);
+                    aovs.record(x, y, &world.probe(self.ray_for_pixel(px, py)));
+                }
+            }
+            progress(y + 1, image.height);
+        }
+
+        (image, depths)
+    }
+}
+
+/// The parts of a camera that get saved, the view is worked out again on load
+#[derive(Serialize, Deserialize)]
+struct SavedCamera {
+    hsize: usize,
+    vsize: usize,
+    fov: Float,
+    samples: usize,
+    jitter: Jitter,
+    projection: Projection,
+    transform: Transform,
+}
+
+impl TryFrom<SavedCamera> for Camera {
+    type Error = RosemaryError;
+
+    fn try_from(saved: SavedCamera) -> Result<Self, Self::Error> {
+        let mut camera = Self::try_new(saved.hsize, saved.vsize, saved.fov)?.with_projection(saved.projection);
+        camera.transform = saved.transform;
+        camera.samples = saved.samples;
+        camera.jitter = saved.jitter;
+        Ok(camera)
+    }
+}
+
+impl From<Camera> for SavedCamera {
+    fn from(camera: Camera) -> Self {
+        Self {
+            hsize: camera.hsize,
+            vsize: camera.vsize,
+            fov: camera.fov,
+            samples: camera.samples,
+            jitter: camera.jitter,
+            projection: camera.projection,
+            transform: camera.transform,
+        }
+    }
+}
+
+/// The `Camera::column` and `Camera::row` of every unjittered sample in a
+/// tile, worked out once so each ray is just a transform and a normalise
+struct RayTable<'a> {
+    camera: &'a Camera,
+    samples: usize,
+    columns: Vec<[Float; 2]>,
+    rows: Vec<[Float; 2]>,
+    eye: Tuple,
+}
+
+impl<'a> RayTable<'a> {
+    fn new(camera: &'a Camera, (left, top): (usize, usize), width: usize, height: usize) -> Self {
+        let n = camera.samples.max(1);
+        // the same sample positions as `rays_for_pixel` without jitter
+        let offset = |pixel: usize, cell: usize| pixel as Float + (cell as Float + 0.5) / n as Float - 0.5;
+
+        Self {
+            camera,
+            samples: n,
+            columns: (left..left + width).flat_map(|x| (0..n).map(move |u| camera.column(offset(x, u)))).collect(),
+            rows: (top..top + height).flat_map(|y| (0..n).map(move |v| camera.row(offset(y, v)))).collect(),
+            eye: camera.transform.inverse() * Tuple::point(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Append the rays for pixel `x`, `y` of the tile, in `rays_for_pixel` order
+    fn rays_for_pixel(&self, x: usize, y: usize, rays: &mut Vec<Ray>) {
+        let n = self.samples;
+        for row in &self.rows[y * n..(y + 1) * n] {
+            for column in &self.columns[x * n..(x + 1) * n] {
+                rays.push(self.camera.ray_through(*column, *row, self.eye));
+            }
+        }
+    }
+}
+
+/// Average colour and alpha of a pixel's samples
+fn resolve(world: &World, traces: &[Trace]) -> (Colour, Float) {
+    let weight = 1.0 / traces.len() as Float;
+    let colour = traces.iter().fold(Colour::black(), |sum, trace| sum + world.clamp_sample(trace.colour));
+    (colour * weight, traces.iter().map(|trace| trace.alpha).sum::<Float>() * weight)
+}
+
+/// Rows `render_ppm_16` renders at once, enough to keep every thread busy
+/// while still only a sliver of a huge image
+const STRIPE_HEIGHT: usize = 64;
+
+/// Pixel spacing of each `render_progressive` pass, coarsest first
+const PROGRESSIVE_STEPS: [usize; 4] = [8, 4, 2, 1];
+
+/// Side of the square tiles `render_parallel` splits images into by default, small
+/// enough to balance well but big enough that handing them out is cheap
+const TILE_SIZE: usize = 32;
+
+/// File name for `frame` of a sequence, with the last run of `#`s in
+/// `pattern` replaced by the zero padded frame number: `spin_###.ppm` gives
+/// `spin_007.ppm`. Without any `#`s the number goes before the extension
+pub fn frame_path(pattern: &str, frame: usize) -> String {
+    match pattern.rfind('#') {
+        Some(end) => {
+            let start = pattern[..end].trim_end_matches('#').len();
+            let width = end + 1 - start;
+            format!("{}{frame:0width$}{}", &pattern[..start], &pattern[end + 1..])
+        },
+        None => match pattern.rfind('.') {
+            Some(dot) => format!("{}{frame}{}", &pattern[..dot], &pattern[dot..]),
+            None => format!("{pattern}{frame}"),
+        },
+    }
+}
+
+/// Camera set up step by step, with anything not given left at its default:
+/// a 60° perspective view from the origin looking down -z
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraBuilder {
+    hsize: usize,
+    vsize: usize,
+    fov: Float,
+    projection: Projection,
+    transform: Matrix,
+    samples: usize,
+    jitter: Jitter,
+}
+
+impl CameraBuilder {
+    pub fn new(hsize: usize, vsize: usize) -> Self {
+        Self {
+            hsize,
+            vsize,
+            fov: FRAC_PI_3,
+            projection: Projection::Perspective,
+            transform: Matrix::identity(),
+            samples: 1,
+            jitter: Jitter::default(),
+        }
+    }
+
+    /// Horizontal field of view in radians
+    pub fn fov(mut self, fov: Float) -> Self {
+        self.fov = fov;
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn transform(mut self, transform: Matrix) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Split each pixel into a `samples` by `samples` grid
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn build(self) -> Camera {
+        let mut camera = Camera::new(self.hsize, self.vsize, self.fov).with_projection(self.projection);
+        camera.set_transform(self.transform);
+        camera.samples = self.samples;
+        camera.jitter = self.jitter;
+        camera
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
+
+    use crate::{eq, types::{environment::Environment, material::Material, mesh::Mesh, rng::Rng, sampler::SamplerKind, sphere::Sphere,
+        world::{Ambient, Integrator}}};
+
+    use super::*;
+
+    #[test]
+    fn new() {
+        let c = Camera::new(160, 120, FRAC_PI_2);
+        assert_eq!(c.hsize(), 160);
+        assert_eq!(c.vsize(), 120);
+        assert_eq!(c.fov(), FRAC_PI_2);
+        assert_eq!(c.transform(), Matrix::identity());
+
+        assert!(eq(Camera::new(200, 125, FRAC_PI_2).pixel_size(), 0.01));
+        assert!(eq(Camera::new(125, 200, FRAC_PI_2).pixel_size(), 0.01));
+
+        // derived sizes follow changes
+        let mut c = Camera::new(100, 125, FRAC_PI_2);
+        c.set_size(200, 125);
+        assert!(eq(c.pixel_size(), 0.01));
+        c.set_fov(FRAC_PI_2 / 2.0);
+        assert!(eq(c.pixel_size(), (FRAC_PI_2 / 4.0).tan() / 100.0));
+
+        assert_eq!(Camera::try_new(0, 10, FRAC_PI_2).unwrap_err(), RosemaryError::EmptyImage);
+        assert_eq!(c.try_set_transform(Matrix::scaling(0.0, 0.0, 0.0)), Err(RosemaryError::NotInvertible));
+        assert_eq!(c.transform(), Matrix::identity());
+    }
+
+    #[test]
+    fn builder() {
+        let transform = Matrix::translation(0.0, -2.0, 5.0);
+        let c = Camera::builder(160, 120)
+            .fov(FRAC_PI_4)
+            .transform(transform)
+            .samples(2)
+            .jitter(Jitter::seeded(1))
+            .build();
+        assert_eq!((c.hsize(), c.vsize(), c.fov()), (160, 120, FRAC_PI_4));
+        assert_eq!(c.transform(), transform);
+        assert_eq!(c.samples, 2);
+        assert_eq!(c.jitter, Jitter::seeded(1));
+        assert_eq!(c.pixel_size(), Camera::new(160, 120, FRAC_PI_4).pixel_size());
+
+        let c = CameraBuilder::new(8, 4).projection(Projection::Equirectangular).build();
+        assert_eq!(c.projection(), Projection::Equirectangular);
+        assert_eq!(c.fov(), FRAC_PI_3);
+    }
+
+    #[test]
+    fn ray_for_pixel() {
+        let mut c = Camera::new(201, 101, FRAC_PI_2);
+        let r = c.ray_for_pixel(100.0, 50.0);
+        assert_eq!(r.origin, Tuple::point(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, Tuple::vector(0.0, 0.0, -1.0));
+
+        let r = c.ray_for_pixel(0.0, 0.0);
+        assert_eq!(r.origin, Tuple::point(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, Tuple::vector(0.66519, 0.33259, -0.66851));
+
+        c.set_transform(Matrix::translation(0.0, -2.0, 5.0).rotate_y(FRAC_PI_4));
+        let r = c.ray_for_pixel(100.0, 50.0);
+        assert_eq!(r.origin, Tuple::point(0.0, 2.0, -5.0));
+        assert_eq!(r.direction, Tuple::vector(SQRT_2 / 2.0, 0.0, -SQRT_2 / 2.0));
+    }
+
+    #[test]
+    fn rays_for_pixel() {
+        let mut c = Camera::new(4, 4, FRAC_PI_2);
+        let rays = c.rays_for_pixel(1, 2, None);
+        assert_eq!(rays.len(), 1);
+        assert_eq!(rays[0].direction, c.ray_for_pixel(1.0, 2.0).direction);
+
+        c.samples = 2;
+        let rays = c.rays_for_pixel(1, 2, None);
+        assert_eq!(rays.len(), 4);
+        assert_eq!(rays[0].direction, c.ray_for_pixel(0.75, 1.75).direction);
+        assert_eq!(rays[3].direction, c.ray_for_pixel(1.25, 2.25).direction);
+
+        // jittered samples stay inside their own cell
+        let mut rng = Rng::new(7);
+        let jittered = c.rays_for_pixel(1, 2, Some(&mut rng));
+        for (ray, centre) in jittered.iter().zip(&rays) {
+            assert_ne!(ray.direction, centre.direction);
+            assert!((ray.direction.x - centre.direction.x).abs() < 0.25 * c.pixel_size());
+        }
+    }
+
+    #[test]
+    fn antialiasing() {
+        let w = World::default();
+        let mut c = Camera::new(11, 11, FRAC_PI_2);
+        c.set_transform(Matrix::view_transform(Tuple::point(0.0, 0.0, -5.0),
+            Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 1.0, 0.0)));
+        c.samples = 3;
+
+        // pixels on the sphere's edge are partly covered
+        let image = c.render(&w);
+        let edge = (0..11).map(|x| image.alpha(x, 5)).find(|a| *a > 0.0 && *a < 1.0);
+        assert!(edge.is_some());
+
+        c.jitter = Jitter::seeded(3);
+        let a = c.render(&w);
+        let b = c.render(&w);
+        assert_eq!(a[(2, 5)], b[(2, 5)]);
+        assert_eq!(a.alpha(2, 5), b.alpha(2, 5));
+    }
+
+    #[test]
+    fn render_into() {
+        let w = World::default();
+        let mut c = Camera::look_at(Tuple::point(0.0, 0.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0), FRAC_PI_2, 11, 11);
+        let full = c.render(&w);
+
+        let mut image = Canvas::new(11, 11);
+        c.render_into(&w, &mut image, 1.0);
+        assert_eq!(image[(5, 5)], full[(5, 5)]);
+        assert_eq!(image.alpha(0, 0), 0.0);
+
+        // running average of differently jittered passes
+        let mut passes = Vec::new();
+        let mut image = Canvas::new(11, 11);
+        for pass in 1..=3 {
+            c.jitter = Jitter::seeded(pass);
+            passes.push(c.render(&w)[(2, 5)]);
+            c.render_into(&w, &mut image, 1.0 / pass as Float);
+        }
+        let average = (passes[0] + passes[1] + passes[2]) * (1.0 / 3.0);
+        assert_eq!(image[(2, 5)], average);
+    }
+
+    #[test]
+    fn render_sequence() {
+        let c = Camera::look_at(Tuple::point(0.0, 0.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0), FRAC_PI_2, 11, 11);
+
+        // move the camera sideways each frame so the sphere drifts out of view
+        let mut rendered = Vec::new();
+        c.render_sequence(2..5, |frame, camera| {
+            camera.set_transform(c.transform().translate(frame as Float - 2.0, 0.0, 0.0));
+            World::default()
+        }, |frame, image| {
+            rendered.push((frame, image.alpha(5, 5)));
+            Ok(())
+        }).unwrap();
+        assert_eq!(rendered, vec![(2, 1.0), (3, 1.0), (4, 0.0)]);
+
+        let failed = c.render_sequence(0..3, |_, _| World::default(), |_, _| Err(io::ErrorKind::Other.into()));
+        assert!(failed.is_err());
+
+        assert_eq!(frame_path("out/spin_###.ppm", 7), "out/spin_007.ppm");
+        assert_eq!(frame_path("f#.ppm", 1234), "f1234.ppm");
+        assert_eq!(frame_path("v1/frame.ppm", 12), "v1/frame12.ppm");
+    }
+
+    #[test]
+    fn orthographic() {
+        let mut c = Camera::orthographic(200, 100, 4.0);
+        assert_eq!(c.projection(), Projection::Orthographic { width: 4.0 });
+        assert!(eq(c.pixel_size(), 0.02));
+
+        let r = c.ray_for_pixel(0.0, 0.0);
+        assert_eq!(r.origin, Tuple::point(1.99, 0.99, 0.0));
+        assert_eq!(r.direction, Tuple::vector(0.0, 0.0, -1.0));
+        assert_eq!(c.ray_for_pixel(199.0, 99.0).direction, r.direction);
+
+        c.set_transform(Matrix::translation(0.0, -2.0, 5.0).rotate_y(FRAC_PI_4));
+        let r = c.ray_for_pixel(99.5, 49.5);
+        assert_eq!(r.origin, Tuple::point(0.0, 2.0, -5.0));
+        assert_eq!(r.direction, Tuple::vector(SQRT_2 / 2.0, 0.0, -SQRT_2 / 2.0));
+
+        // sphere fills the middle half of the image whatever the distance
+        let w = World::default();
+        for distance in [5.0, 50.0] {
+            let mut c = Camera::orthographic(8, 8, 4.0);
+            c.set_transform(Matrix::view_transform(Tuple::point(0.0, 0.0, -distance),
+                Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 1.0, 0.0)));
+            let image = c.render(&w);
+            assert_eq!(image.alpha(2, 4), 1.0);
+            assert_eq!(image.alpha(0, 4), 0.0);
+        }
+    }
+
+    #[test]
+    fn equirectangular() {
+        let c = Camera::new(8, 4, FRAC_PI_2).with_projection(Projection::Equirectangular);
+        let ray = |x: Float, y: Float| c.ray_for_pixel(x - 0.5, y - 0.5);
+        assert_eq!(ray(4.0, 2.0).direction, Tuple::vector(0.0, 0.0, -1.0));
+        assert_eq!(ray(6.0, 2.0).direction, Tuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(ray(0.0, 2.0).direction, Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(ray(4.0, 0.0).direction, Tuple::vector(0.0, 1.0, 0.0));
+
+        // rendering an environment gives back its image
+        let mut image = Canvas::new(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                image[(x, y)] = Colour::new(x as Float, y as Float, 1.0);
+            }
+        }
+        let mut w = World::new(Vec::new(), Vec::new());
+        w.set_environment(Some(Environment::new(image.clone())));
+        let rendered = c.render(&w);
+        for y in 0..4 {
+            for x in 0..8 {
+                assert_eq!(rendered[(x, y)], image[(x, y)]);
+            }
+        }
+    }
+
+    #[test]
+    fn render_progressive() {
+        let mut c = Camera::look_at(Tuple::point(0.0, 0.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0), FRAC_PI_2, 21, 13);
+        c.samples = 2;
+        let w = World::default();
+        let full = c.render(&w);
+
+        let mut steps = Vec::new();
+        let image = c.render_progressive(&w, |step, image| {
+            steps.push(step);
+            // each pass has traced every pixel on its grid
+            for y in (0..image.height).step_by(step) {
+                for x in (0..image.width).step_by(step) {
+                    assert_eq!(image[(x, y)], full[(x, y)]);
+                }
+            }
+            // and the rest copy their block's sample
+            assert_eq!(image[(image.width - 1, image.height - 1)], full[(20 / step * step, 12 / step * step)]);
+            true
+        });
+        assert_eq!(steps, vec![8, 4, 2, 1]);
+        assert!(image.enumerate_pixels().eq(full.enumerate_pixels()));
+
+        // stopping after the first pass
+        let mut passes = 0;
+        c.render_progressive(&w, |_, _| {
+            passes += 1;
+            false
+        });
+        assert_eq!(passes, 1);
+    }
+
+    #[test]
+    fn render_parallel() {
+        // several tiles, with partial ones along the right and bottom
+        let mut c = Camera::look_at(Tuple::point(0.0, 0.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0), FRAC_PI_2, 70, 40);
+        c.samples = 2;
+        let w = World::default();
+
+        let image = c.render(&w);
+        for threads in [1, 3, 16] {
+            let parallel = c.render_parallel(&w, threads);
+            assert!(parallel.enumerate_pixels().eq(image.enumerate_pixels()));
+            assert!(image.enumerate_pixels().all(|(x, y, _)| parallel.alpha(x, y) == image.alpha(x, y)));
+        }
+    }
+
+    #[test]
+    fn render_with_config() {
+        let mut c = Camera::look_at(Tuple::point(0.0, 0.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0), FRAC_PI_2, 30, 20);
+        c.samples = 2;
+        c.jitter = Jitter::seeded(7);
+        let w = World::default();
+        let same = |a: &Canvas, b: &Canvas| a.enumerate_pixels().eq(b.enumerate_pixels());
+
+        // jittered samples don't depend on how the work is shared out
+        let image = c.render(&w);
+        for (threads, tile_size) in [(1, 1), (2, 7), (5, 64)] {
+            let config = RenderConfig { threads, tile_size, seeding: Seeding::Pixel };
+            assert!(same(&c.render_with_config(&w, &config), &image));
+        }
+
+        let mut stripes = Vec::new();
+        c.render_stripes(&w, &RenderConfig { threads: 3, tile_size: 4, seeding: Seeding::Pixel }, 6, |top, stripe| {
+            assert_eq!(stripe.width, 30);
+            assert!(stripe.enumerate_pixels().all(|(x, y, colour)| *colour == image[(x, top + y)]));
+            stripes.push((top, stripe.height));
+            Ok(())
+        }).unwrap();
+        assert_eq!(stripes, [(0, 6), (6, 6), (12, 6), (18, 2)]);
+
+        let mut calls = 0;
+        let stopped = c.render_stripes(&w, &RenderConfig::default(), 6, |_, _| {
+            calls += 1;
+            Err(io::Error::other("disk full"))
+        });
+        assert_eq!(calls, 1);
+        assert_eq!(stopped.unwrap_err().to_string(), "disk full");
+
+        let (mut streamed, mut whole) = (Vec::new(), Vec::new());
+        c.render_ppm_16(&w, &RenderConfig::default(), &mut streamed).unwrap();
+        image.write_ppm_16(&mut whole).unwrap();
+        assert_eq!(streamed, whole);
+
+        // each frame has its own noise, the same every time it's rendered
+        let frame = |n, threads| c.render_with_config(&w, &RenderConfig { threads, tile_size: 8, seeding: Seeding::Frame(n) });
+        assert!(same(&frame(1, 1), &frame(1, 4)));
+        assert!(!same(&frame(1, 1), &frame(2, 1)));
+        assert!(!same(&frame(0, 1), &image));
+    }
+
+    #[test]
+    fn render() {
+        let w = World::default();
+        let c = Camera::look_at(Tuple::point(0.0, 0.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0), FRAC_PI_2, 11, 11);
+        assert_eq!(c.transform(), Matrix::translation(0.0, 0.0, 5.0).rotate_y(PI));
+
+        let image = c.render(&w);
+        assert_eq!(image[(5, 5)], Colour::new(0.38066, 0.47583, 0.2855));
+        assert_eq!(image.alpha(5, 5), 1.0);
+
+        // nothing hit so transparent background
+        assert_eq!(image[(0, 0)], Colour::black());
+        assert_eq!(image.alpha(0, 0), 0.0);
+
+        let region = c.render_region(&w, 4, 3, 3, 5);
+        assert_eq!((region.width, region.height), (3, 5));
+        assert_eq!(region[(1, 2)], image[(5, 5)]);
+        assert_eq!(region.alpha(0, 0), image.alpha(4, 3));
+
+        let mut ppm = Vec::new();
+        c.render_ppm(&w, &mut ppm).unwrap();
+        assert_eq!(String::from_utf8(ppm).unwrap(), image.to_ppm());
+
+        let (_, stats) = c.render_with_stats(&w);
+        assert_eq!(stats.primary_rays, 121);
+        // one per pixel that hits the sphere
+        let hits = (0..11).flat_map(|y| (0..11).map(move |x| (x, y))).filter(|&(x, y)| image.alpha(x, y) == 1.0).count();
+        assert_eq!(stats.shadow_rays, hits);
+        // Whitted shading never bounces
+        assert_eq!(stats.depths, vec![121]);
+
+        let mut rows = Vec::new();
+        let partial = c.render_rows(&w, |y, row| {
+            assert_eq!(row.width, 11);
+            rows.push(y);
+            y < 5
+        });
+        assert_eq!(rows, (0..=5).collect::<Vec<_>>());
+        assert_eq!(partial[(5, 5)], image[(5, 5)]);
+        assert_eq!(partial.alpha(5, 5), 1.0);
+        assert_eq!(partial.alpha(5, 6), 0.0);
+
+        let mut calls = Vec::new();
+        assert_eq!(c.render_with_progress(&w, |done, total| calls.push((done, total)))[(5, 5)], image[(5, 5)]);
+        assert_eq!(calls, (1..=11).map(|done| (done, 11)).collect::<Vec<_>>());
+
+        let (_, aovs) = c.render_with_aovs(&w);
+        assert_eq!(aovs.position(5, 5), Some(Tuple::point(0.0, 0.0, -1.0)));
+        assert_eq!(aovs.position(0, 0), None);
+        assert_eq!(aovs.normal(5, 5), Some(Tuple::vector(0.0, 0.0, -1.0)));
+        assert_eq!(aovs.object(5, 5), Some(0));
+        assert_eq!(aovs.object(0, 0), None);
+
+        // no sample sits in the middle of an even grid, so data comes from a
+        // ray of its own
+        let mut even = c;
+        even.samples = 2;
+        let (_, aovs) = even.render_with_aovs(&w);
+        assert_eq!(aovs.position(5, 5), Some(Tuple::point(0.0, 0.0, -1.0)));
+        assert_eq!(aovs.object(5, 5), Some(0));
+        even.jitter = Jitter::seeded(3);
+        let (_, aovs) = even.render_with_aovs(&w);
+        assert_eq!(aovs.position(5, 5), Some(Tuple::point(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn render_with_stats() {
+        let mut w = World::default();
+        let mut floor = Mesh::plane(20.0);
+        floor.set_transform(Matrix::translation(0.0, -1.0, 0.0));
+        w.add_object(Box::new(floor));
+        w.set_integrator(Integrator::PathTracing { max_bounces: 2, seed: 0, min_throughput: 0.0, sampler: SamplerKind::Random });
+        let c = Camera::look_at(Tuple::point(0.0, 0.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0), FRAC_PI_2, 11, 11);
+
+        let (image, stats) = c.render_with_stats(&w);
+        assert_eq!(stats.primary_rays, 121);
+        assert_eq!(stats.depths.iter().sum::<usize>(), 121);
+        // misses stop straight away, every hit bounces and some bounces
+        // land on the floor or the sphere and go again
+        let misses = (0..11).flat_map(|y| (0..11).map(move |x| (x, y))).filter(|&(x, y)| image.alpha(x, y) == 0.0).count();
+        assert_eq!(stats.depths.len(), 3);
+        assert_eq!(stats.depths[0], misses);
+        assert!(stats.depths[1] > 0 && stats.depths[2] > 0);
+    }
+
+    #[test]
+    fn path_samples() {
+        // a floor beside a ball, lit only by a white sky, so the pixel is
+        // the part of the sky the ball doesn't hide: a soft shadow
+        let mut floor = Mesh::plane(20.0);
+        floor.material.colour = Colour::white();
+        let ball = Sphere::new(Matrix::translation(0.0, 1.0, 0.0), Material::default());
+        let mut w = World::new(vec![Box::new(floor), Box::new(ball)], Vec::new());
+        let mut sky = Canvas::new(4, 2);
+        sky.fill(Colour::white());
+        w.set_environment(Some(Environment::new(sky)));
+
+        // every sample of the pixel goes through the same point, so only the bounces differ
+        let mut c = Camera::look_at(Tuple::point(1.3, 3.0, 0.0), Tuple::point(1.3, 0.0, 0.0),
+            Tuple::vector(0.0, 0.0, 1.0), 0.01, 1, 1);
+        c.samples = 4;
+        let mut variance = |sampler: SamplerKind| {
+            let values: Vec<Float> = (0..64).map(|seed| {
+                w.set_integrator(Integrator::PathTracing { max_bounces: 1, seed, min_throughput: 0.0, sampler });
+                c.render(&w)[(0, 0)].r
+            }).collect();
+            let mean = values.iter().sum::<Float>() / values.len() as Float;
+            values.iter().map(|v| (v - mean).powi(2)).sum::<Float>() / values.len() as Float
+        };
+
+        let random = variance(SamplerKind::Random);
+        assert!(random > 0.0);
+        for sampler in [SamplerKind::Halton, SamplerKind::Sobol] {
+            assert!(variance(sampler) < random / 2.0, "{sampler:?}");
+        }
+    }
+
+    #[test]
+    fn ray_table() {
+        let transform = Matrix::view_transform(Tuple::point(1.0, 2.0, -5.0), Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 1.0, 0.0));
+        for projection in [Projection::Perspective, Projection::Orthographic { width: 4.0 }, Projection::Equirectangular] {
+            let mut c = Camera::new(12, 6, FRAC_PI_2).with_projection(projection);
+            c.set_transform(transform);
+            c.samples = 3;
+
+            let table = RayTable::new(&c, (4, 1), 5, 3);
+            let mut rays = Vec::new();
+            table.rays_for_pixel(2, 1, &mut rays);
+            // exactly the same, so renders don't change
+            let bits = |t: Tuple| [t.x, t.y, t.z, t.w].map(Float::to_bits);
+            for (ray, expected) in rays.iter().zip(c.rays_for_pixel(6, 2, None)) {
+                assert_eq!(bits(ray.origin), bits(expected.origin));
+                assert_eq!(bits(ray.direction), bits(expected.direction));
+            }
+            assert_eq!(rays.len(), 9);
+        }
+    }
+
+    #[test]
+    fn render_cached() {
+        let mut w = World::default();
+        let mut c = Camera::look_at(Tuple::point(0.0, 0.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0), FRAC_PI_2, 11, 11);
+        c.samples = 2;
+        let same = |a: &Canvas, b: &Canvas| a.enumerate_pixels().eq(b.enumerate_pixels())
+            && (0..11).all(|y| (0..11).all(|x| a.alpha(x, y) == b.alpha(x, y)));
+
+        let mut cache = HitCache::new();
+        assert!(same(&c.render_cached(&w, &mut cache), &c.render(&w)));
+        assert_eq!(cache.reused(), 0);
+
+        // only the shading changed, so every hit is reused
+        w.set_ambient(Ambient::Replace(Colour::new(0.5, 0.5, 0.5)));
+        assert!(same(&c.render_cached(&w, &mut cache), &c.render(&w)));
+        assert_eq!(cache.reused(), 11 * 11 * 4);
+
+        // the camera moved so every ray is new
+        c.set_transform(Matrix::view_transform(Tuple::point(1.0, 0.5, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0)));
+        assert!(same(&c.render_cached(&w, &mut cache), &c.render(&w)));
+        assert_eq!(cache.reused(), 0);
+
+        // as are rays jittered differently, even where they'd hit the same object
+        c.jitter = Jitter::seeded(1);
+        assert!(same(&c.render_cached(&w, &mut cache), &c.render(&w)));
+        assert_eq!(cache.reused(), 0);
+        assert!(same(&c.render_cached(&w, &mut cache), &c.render(&w)));
+        assert_eq!(cache.reused(), 11 * 11 * 4);
+
+        // a rebuilt world has new objects, even if they're in the same places
+        let w = World::default();
+        assert!(same(&c.render_cached(&w, &mut cache), &c.render(&w)));
+        assert!(cache.reused() < 11 * 11 * 4);
+    }
+
+    #[test]
+    fn serde() {
+        let mut c = Camera::new(160, 120, PI / 3.0).with_projection(Projection::Orthographic { width: 4.0 });
+        c.set_transform(Matrix::view_transform(Tuple::point(0.0, 2.0, -5.0), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0)));
+        let loaded: Camera = serde_json::from_str(&serde_json::to_string(&c).unwrap()).unwrap();
+        assert_eq!(loaded, c);
+
+        // the view is worked out from the size and field of view, not trusted
+        let json = serde_json::to_string(&Camera::new(20, 10, FRAC_PI_2)).unwrap();
+        let narrow: Camera = serde_json::from_str(&json.replace("\"hsize\":20", "\"hsize\":10")).unwrap();
+        assert_eq!(narrow.pixel_size(), Camera::new(10, 10, FRAC_PI_2).pixel_size());
+        let error = serde_json::from_str::<Camera>(&json.replace("\"hsize\":20", "\"hsize\":0")).unwrap_err();
+        assert!(error.to_string().contains("at least 1x1"), "{error}");
+    }
+}
@@ -1,58 +1,761 @@
-use std::ops::{Index, IndexMut};
-use super::colour::Colour;
+use std::{io::{self, BufWriter, Read, Write}, ops::{Index, IndexMut}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Float, RosemaryError};
+use super::{colour::Colour, font, lut::Lut, metadata::Metadata, tonemap::ToneMapping};
 
 const PPM_MAGIC: &str = "P3";
-const PPM_COLOUR_MULTIPLIER: f32 = 256.0;
-const PPM_MAX_COLOUR: f32 = PPM_COLOUR_MULTIPLIER - 1.0;
+const PPM_COLOUR_MULTIPLIER: Float = 256.0;
+const PPM_MAX_COLOUR: Float = PPM_COLOUR_MULTIPLIER - 1.0;
+/// Widest or tallest image the decoders read, well past any environment map
+const MAX_DECODED_SIDE: usize = 1 << 16;
+/// Most pixels the decoders read, a 16k by 16k image
+const MAX_DECODED_PIXELS: usize = 1 << 28;
+
+/// How `Canvas::resized` picks colours between source pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Filter {
+    /// Copy the closest pixel, blocky but keeps hard edges
+    Nearest,
+    /// Blend the four closest pixels. Shrinking by more than half skips
+    /// some pixels entirely so small details can flicker
+    #[default]
+    Bilinear,
+}
+
+/// How `Canvas::composite` combines a layer with what's underneath.
+/// Colours are treated as premultiplied by alpha, which is how renders come out
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Blend {
+    /// Layer in front, showing through where it's transparent
+    #[default]
+    Over,
+    /// Colours added, for light passes and glows
+    Add,
+    /// Colours multiplied, for shadow and occlusion passes
+    Multiply,
+}
 
-#[derive(Debug, Clone)]
+/// How `Canvas::dither` spreads out the rounding error of 8 bit output
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Dither {
+    /// Plain truncation, smooth gradients show bands
+    #[default]
+    None,
+    /// 4x4 Bayer pattern, fast and stable between animation frames
+    Ordered,
+    /// Floyd-Steinberg error diffusion, less visible pattern
+    FloydSteinberg,
+}
+
+const BAYER: [[Float; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SavedCanvas")]
 pub struct Canvas {
     canvas: Vec<Colour>,
+    /// Coverage of each pixel, 0.0 is fully transparent
+    alpha: Vec<Float>,
     pub width: usize,
     pub height: usize,
 }
 
+/// A canvas as it's loaded, before checking there's a colour and alpha for
+/// every pixel
+#[derive(Deserialize)]
+struct SavedCanvas {
+    canvas: Vec<Colour>,
+    alpha: Vec<Float>,
+    width: usize,
+    height: usize,
+}
+
+impl TryFrom<SavedCanvas> for Canvas {
+    type Error = RosemaryError;
+
+    fn try_from(saved: SavedCanvas) -> Result<Self, Self::Error> {
+        let (width, height) = (saved.width, saved.height);
+        for values in [saved.canvas.len(), saved.alpha.len()] {
+            if width.checked_mul(height) != Some(values) {
+                return Err(RosemaryError::PixelCount { width, height, values });
+            }
+        }
+
+        Ok(Self {
+            canvas: saved.canvas,
+            alpha: saved.alpha,
+            width,
+            height,
+        })
+    }
+}
+
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Self {
         Self {
             canvas: vec![Colour::new(0.0, 0.0, 0.0); width * height],
+            alpha: vec![1.0; width * height],
             width,
             height,
         }
     }
 
+    pub fn alpha(&self, x: usize, y: usize) -> Float {
+        self.alpha[x + y * self.width]
+    }
+
+    pub fn set_alpha(&mut self, x: usize, y: usize, alpha: Float) {
+        self.alpha[x + y * self.width] = alpha;
+    }
+
+    /// Colour at `x`, `y`, or `None` if that's off the canvas
+    pub fn get(&self, x: usize, y: usize) -> Option<Colour> {
+        (x < self.width && y < self.height).then(|| self[(x, y)])
+    }
+
+    /// Set the colour at `x`, `y` if it's on the canvas, returning whether it was
+    pub fn set(&mut self, x: usize, y: usize, colour: Colour) -> bool {
+        let inside = x < self.width && y < self.height;
+        if inside {
+            self[(x, y)] = colour;
+        }
+
+        inside
+    }
+
+    /// Set the colour at `x`, `y`, quietly doing nothing if that's off the
+    /// canvas in any direction
+    pub fn write_pixel(&mut self, x: isize, y: isize, colour: Colour) {
+        self.plot(x, y, colour, 1.0);
+    }
+
+    /// Every pixel with its coordinates, a row at a time from the top left
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (usize, usize, &Colour)> {
+        let width = self.width;
+        self.canvas.iter().enumerate().map(move |(i, colour)| (i % width, i / width, colour))
+    }
+
+    /// Like `enumerate_pixels` but the colours can be changed in place
+    pub fn enumerate_pixels_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut Colour)> {
+        let width = self.width;
+        self.canvas.iter_mut().enumerate().map(move |(i, colour)| (i % width, i / width, colour))
+    }
+
+    /// Set every pixel to `colour` and fully opaque, for reusing a canvas
+    /// between frames or starting from a background other than black
+    pub fn fill(&mut self, colour: Colour) {
+        self.canvas.fill(colour);
+        self.alpha.fill(1.0);
+    }
+
+    /// Back to how `Canvas::new` left it
+    pub fn clear(&mut self) {
+        self.fill(Colour::black());
+    }
+
+    /// Draw a one pixel wide line between two pixel positions with
+    /// Bresenham's algorithm, any part off the canvas is skipped
+    pub fn draw_line(&mut self, p0: (isize, isize), p1: (isize, isize), colour: Colour) {
+        let (mut x, mut y) = p0;
+        let dx = (p1.0 - x).abs();
+        let dy = -(p1.1 - y).abs();
+        let step_x = if x < p1.0 { 1 } else { -1 };
+        let step_y = if y < p1.1 { 1 } else { -1 };
+        let mut error = dx + dy;
+
+        loop {
+            self.plot(x, y, colour, 1.0);
+            if (x, y) == p1 {
+                break;
+            }
+
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Anti-aliased line with Wu's algorithm, the two pixels either side of
+    /// the line are blended towards `colour` by how much it covers them
+    pub fn draw_line_aa(&mut self, p0: (Float, Float), p1: (Float, Float), colour: Colour) {
+        let steep = (p1.1 - p0.1).abs() > (p1.0 - p0.0).abs();
+        // walk along whichever axis changes most, swapping back when plotting
+        let (mut p0, mut p1) = if steep { ((p0.1, p0.0), (p1.1, p1.0)) } else { (p0, p1) };
+        if p0.0 > p1.0 {
+            std::mem::swap(&mut p0, &mut p1);
+        }
+
+        let dx = p1.0 - p0.0;
+        let gradient = if dx == 0.0 { 1.0 } else { (p1.1 - p0.1) / dx };
+        let mut plot = |major: Float, minor: Float, coverage: Float| {
+            let (x, y) = if steep { (minor, major) } else { (major, minor) };
+            self.plot(x as isize, y as isize, colour, coverage);
+        };
+
+        let start = p0.0.round();
+        let end = p1.0.round();
+        let mut minor = p0.1 + gradient * (start - p0.0);
+        let mut major = start;
+        while major <= end {
+            let below = minor.floor();
+            let fraction = minor - below;
+            plot(major, below, 1.0 - fraction);
+            plot(major, below + 1.0, fraction);
+
+            minor += gradient;
+            major += 1.0;
+        }
+    }
+
+    /// Outline of a circle using the midpoint algorithm
+    pub fn draw_circle(&mut self, centre: (isize, isize), radius: isize, colour: Colour) {
+        let (cx, cy) = centre;
+        let (mut x, mut y) = (radius, 0);
+        let mut error = 1 - radius;
+
+        // each step gives one point per octant
+        while x >= y {
+            for (px, py) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+                self.plot(cx + px, cy + py, colour, 1.0);
+            }
+
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            }
+            else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Solid circle covering every pixel whose centre is within `radius`
+    pub fn fill_circle(&mut self, centre: (isize, isize), radius: isize, colour: Colour) {
+        for dy in -radius..=radius {
+            let half = ((radius * radius - dy * dy) as Float).sqrt() as isize;
+            self.fill_span(centre.0 - half, centre.0 + half, centre.1 + dy, colour);
+        }
+    }
+
+    /// Outline of a `width` by `height` rectangle with its top left corner at `corner`
+    pub fn draw_rect(&mut self, corner: (isize, isize), width: usize, height: usize, colour: Colour) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let (left, top) = corner;
+        let (right, bottom) = (left + width as isize - 1, top + height as isize - 1);
+        self.fill_span(left, right, top, colour);
+        self.fill_span(left, right, bottom, colour);
+        for y in top..=bottom {
+            self.plot(left, y, colour, 1.0);
+            self.plot(right, y, colour, 1.0);
+        }
+    }
+
+    pub fn fill_rect(&mut self, corner: (isize, isize), width: usize, height: usize, colour: Colour) {
+        for y in corner.1..corner.1 + height as isize {
+            self.fill_span(corner.0, corner.0 + width as isize - 1, y, colour);
+        }
+    }
+
+    /// Write `text` with the built in 5x7 font, `x`, `y` being the top left of
+    /// the first character. Newlines start a new line under the first
+    pub fn draw_text(&mut self, x: isize, y: isize, text: &str, colour: Colour) {
+        for (line_number, line) in text.lines().enumerate() {
+            let top = y + (line_number * font::LINE_HEIGHT) as isize;
+            for (i, c) in line.chars().enumerate() {
+                let left = x + (i * font::ADVANCE) as isize;
+                for (row, bits) in font::glyph(c).into_iter().enumerate() {
+                    for column in 0..font::GLYPH_WIDTH {
+                        if bits & (1 << (font::GLYPH_WIDTH - 1 - column)) != 0 {
+                            self.plot(left + column as isize, top + row as isize, colour, 1.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Size in pixels `draw_text` would cover, for lining up labels
+    pub fn text_size(text: &str) -> (usize, usize) {
+        let columns = text.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+        let lines = text.lines().count();
+        // no gap after the last character or line
+        (
+            (columns * font::ADVANCE).saturating_sub(font::ADVANCE - font::GLYPH_WIDTH),
+            (lines * font::LINE_HEIGHT).saturating_sub(font::LINE_HEIGHT - font::GLYPH_HEIGHT),
+        )
+    }
+
+    /// Horizontal run of pixels from `left` to `right` inclusive
+    fn fill_span(&mut self, left: isize, right: isize, y: isize, colour: Colour) {
+        for x in left.max(0)..=right.min(self.width as isize - 1) {
+            self.plot(x, y, colour, 1.0);
+        }
+    }
+
+    /// Blend `colour` into a pixel by `coverage`, ignoring positions off the canvas
+    fn plot(&mut self, x: isize, y: isize, colour: Colour, coverage: Float) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+
+        let pixel = &mut self[(x as usize, y as usize)];
+        *pixel = *pixel * (1.0 - coverage) + colour * coverage;
+    }
+
+    /// Copy of the `width` by `height` region with its top left corner at `x`, `y`
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        self.view(x, y, width, height).to_canvas()
+    }
+
+    /// Borrow a region of the canvas without copying it
+    pub fn view(&self, x: usize, y: usize, width: usize, height: usize) -> CanvasView<'_> {
+        assert!(x + width <= self.width && y + height <= self.height);
+
+        CanvasView {
+            canvas: self,
+            left: x,
+            top: y,
+            width,
+            height,
+        }
+    }
+
+    /// Set the alpha of every pixel, leaving colours alone
+    pub fn fill_alpha(&mut self, alpha: Float) {
+        self.alpha.fill(alpha);
+    }
+
+    /// Copy `tile` into this canvas with its top left corner at `x`, `y`
+    pub fn paste(&mut self, x: usize, y: usize, tile: &Canvas) {
+        assert!(x + tile.width <= self.width && y + tile.height <= self.height);
+
+        for ty in 0..tile.height {
+            let row = (y + ty) * self.width + x;
+            let tile_row = ty * tile.width;
+            self.canvas[row..row + tile.width].copy_from_slice(&tile.canvas[tile_row..tile_row + tile.width]);
+            self.alpha[row..row + tile.width].copy_from_slice(&tile.alpha[tile_row..tile_row + tile.width]);
+        }
+    }
+
+    /// Copy of the canvas stretched or shrunk to `width` by `height`
+    pub fn resized(&self, width: usize, height: usize, filter: Filter) -> Self {
+        assert!(self.width > 0 && self.height > 0);
+
+        let mut resized = Self::new(width, height);
+        let (sx, sy) = (self.width as Float / width as Float, self.height as Float / height as Float);
+
+        for y in 0..height {
+            for x in 0..width {
+                // pixel centres line up between the two sizes
+                let (u, v) = ((x as Float + 0.5) * sx - 0.5, (y as Float + 0.5) * sy - 0.5);
+                let (colour, alpha) = match filter {
+                    Filter::Nearest => {
+                        let (nx, ny) = (self.clamp_x(u.round()), self.clamp_y(v.round()));
+                        (self[(nx, ny)], self.alpha(nx, ny))
+                    },
+                    Filter::Bilinear => self.bilinear(u, v),
+                };
+                resized[(x, y)] = colour;
+                resized.set_alpha(x, y, alpha);
+            }
+        }
+
+        resized
+    }
+
+    fn clamp_x(&self, x: Float) -> usize {
+        (x.max(0.0) as usize).min(self.width - 1)
+    }
+
+    fn clamp_y(&self, y: Float) -> usize {
+        (y.max(0.0) as usize).min(self.height - 1)
+    }
+
+    /// Colour and alpha at `x`, `y` in pixel coordinates, blended from the
+    /// four nearest pixel centres and clamped at the edges
+    fn bilinear(&self, x: Float, y: Float) -> (Colour, Float) {
+        let (x, y) = (x.clamp(0.0, (self.width - 1) as Float), y.clamp(0.0, (self.height - 1) as Float));
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (x - x0 as Float, y - y0 as Float);
+
+        let weights = [((x0, y0), (1.0 - tx) * (1.0 - ty)), ((x1, y0), tx * (1.0 - ty)),
+            ((x0, y1), (1.0 - tx) * ty), ((x1, y1), tx * ty)];
+        weights.iter().fold((Colour::black(), 0.0), |(colour, alpha), &(pixel, weight)| {
+            (colour + self[pixel] * weight, alpha + self.alpha(pixel.0, pixel.1) * weight)
+        })
+    }
+
+    /// Combine `layer` on top of this canvas, which must be the same size
+    pub fn composite(&mut self, layer: &Canvas, blend: Blend) {
+        assert!(layer.width == self.width && layer.height == self.height);
+
+        for i in 0..self.canvas.len() {
+            let (top, top_alpha) = (layer.canvas[i], layer.alpha[i]);
+            let (bottom, bottom_alpha) = (self.canvas[i], self.alpha[i]);
+            let over_alpha = top_alpha + bottom_alpha * (1.0 - top_alpha);
+
+            (self.canvas[i], self.alpha[i]) = match blend {
+                Blend::Over => (top + bottom * (1.0 - top_alpha), over_alpha),
+                Blend::Add => (top + bottom, (top_alpha + bottom_alpha).min(1.0)),
+                Blend::Multiply => (bottom * top + bottom * (1.0 - top_alpha) + top * (1.0 - bottom_alpha), over_alpha),
+            };
+        }
+    }
+
+    /// Apply exposure and bring every pixel into displayable range, do this
+    /// before `apply_lut` as LUTs expect values between 0.0 and 1.0
+    pub fn tone_map(&mut self, tone_mapping: &ToneMapping) {
+        for pixel in &mut self.canvas {
+            *pixel = tone_mapping.apply(*pixel);
+        }
+    }
+
+    /// Grade every pixel through `lut`, normally the last step before saving
+    pub fn apply_lut(&mut self, lut: &Lut) {
+        for pixel in &mut self.canvas {
+            *pixel = lut.apply(*pixel);
+        }
+    }
+
+    /// Snap every colour to one of the 8 bit levels the image writers use,
+    /// dithering so gradients such as skies and soft shadows don't band.
+    /// Do this last, after tone mapping and any LUT
+    pub fn dither(&mut self, dither: Dither) {
+        // in units of output levels, with level k centred on k
+        let level = |c: Float| c * PPM_COLOUR_MULTIPLIER - 0.5;
+        let snap = |level: Float| (level.round().clamp(0.0, PPM_MAX_COLOUR) + 0.5) / PPM_COLOUR_MULTIPLIER;
+
+        match dither {
+            Dither::None => (),
+            Dither::Ordered => {
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let offset = (BAYER[y % 4][x % 4] + 0.5) / 16.0 - 0.5;
+                        let pixel = &mut self[(x, y)];
+                        *pixel = Colour::new(
+                            snap(level(pixel.r) + offset),
+                            snap(level(pixel.g) + offset),
+                            snap(level(pixel.b) + offset),
+                        );
+                    }
+                }
+            },
+            Dither::FloydSteinberg => {
+                let mut error = vec![[0.0; 3]; self.canvas.len()];
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let i = x + y * self.width;
+                        let pixel = self.canvas[i];
+                        let mut snapped = [0.0; 3];
+                        for (channel, value) in [pixel.r, pixel.g, pixel.b].into_iter().enumerate() {
+                            let wanted = level(value) + error[i][channel];
+                            snapped[channel] = snap(wanted);
+                            let left_over = wanted - level(snapped[channel]);
+
+                            let mut spread = |dx: isize, dy: usize, weight: Float| {
+                                let nx = x as isize + dx;
+                                if nx >= 0 && (nx as usize) < self.width && y + dy < self.height {
+                                    error[nx as usize + (y + dy) * self.width][channel] += left_over * weight;
+                                }
+                            };
+                            spread(1, 0, 7.0 / 16.0);
+                            spread(-1, 1, 3.0 / 16.0);
+                            spread(0, 1, 5.0 / 16.0);
+                            spread(1, 1, 1.0 / 16.0);
+                        }
+                        self.canvas[i] = Colour::new(snapped[0], snapped[1], snapped[2]);
+                    }
+                }
+            },
+        }
+    }
+
     pub fn to_ppm(&self) -> String {
-        let mut data = String::with_capacity(self.canvas.len() * 5);
-        data += &format!("{PPM_MAGIC}\n{} {}\n{}", self.width, self.height, PPM_MAX_COLOUR);
-        
-        let mut line_len = 0;
-        for (i, pixel) in self.canvas.iter().enumerate() {
-            if i % self.width == 0 {
-                data += "\n";
-                line_len = 0;
+        let mut data = Vec::with_capacity(self.canvas.len() * 12);
+        data.extend_from_slice(Self::ppm_header(self.width, self.height).as_bytes());
+        self.ppm_pixels(&mut data);
+        data.push(b'\n'); // terminator
+
+        String::from_utf8(data).expect("PPM data is ASCII")
+    }
+
+    /// Write the image as a PPM a row at a time, without building the whole
+    /// file in memory first like `to_ppm` does
+    pub fn write_ppm(&self, writer: impl Write) -> io::Result<()> {
+        self.write_ppm_with_metadata(writer, &Metadata::new())
+    }
+
+    /// `write_ppm` with `metadata` as comments after the magic number
+    pub fn write_ppm_with_metadata(&self, writer: impl Write, metadata: &Metadata) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(commented(&Self::ppm_header(self.width, self.height), metadata).as_bytes())?;
+
+        let mut row = Vec::with_capacity(self.width * 12 + self.width / 5);
+        for y in 0..self.height {
+            row.clear();
+            self.ppm_row(y, &mut row);
+            writer.write_all(&row)?;
+        }
+
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+
+    /// Write a binary (P6) PPM with 16 bits per channel, keeping far more of
+    /// the canvas' precision than the 8 bit `write_ppm`
+    pub fn write_ppm_16(&self, writer: impl Write) -> io::Result<()> {
+        self.write_ppm_16_with_metadata(writer, &Metadata::new())
+    }
+
+    /// `write_ppm_16` with `metadata` as comments after the magic number
+    pub fn write_ppm_16_with_metadata(&self, writer: impl Write, metadata: &Metadata) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(commented(&Self::ppm_16_header(self.width, self.height), metadata).as_bytes())?;
+
+        let mut data = Vec::new();
+        self.ppm_16_pixels(&mut data);
+        writer.write_all(&data)?;
+        writer.flush()
+    }
+
+    /// Read an ASCII (P3) or binary (P6) PPM, scaling values so the file's
+    /// maximum becomes 1.0
+    pub fn from_ppm(mut reader: impl Read) -> io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let mut pos = 0;
+        let magic = next_token(&data, &mut pos).ok_or_else(|| invalid("missing PPM magic"))?;
+        let binary = match magic {
+            b"P3" => false,
+            b"P6" => true,
+            _ => return Err(invalid("not a P3 or P6 PPM")),
+        };
+        let mut number = |what: &str| -> io::Result<usize> {
+            next_token(&data, &mut pos)
+                .and_then(|token| std::str::from_utf8(token).ok()?.parse().ok())
+                .ok_or_else(|| invalid(&format!("bad {what}")))
+        };
+        let width = number("width")?;
+        let height = number("height")?;
+        let max = number("maximum value")?;
+        if max == 0 || max > 65535 {
+            return Err(invalid("maximum value must be between 1 and 65535"));
+        }
+        // every value takes at least a byte, so this also stops huge sizes
+        // in the header from allocating before the data runs out
+        let count = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(3))
+            .filter(|&count| count <= data.len())
+            .ok_or_else(|| invalid("not enough pixel data"))?;
+
+        let values: Vec<usize> = if binary {
+            // a single whitespace byte separates the header from the data
+            let bytes = &data[(pos + 1).min(data.len())..];
+            if max < 256 {
+                bytes.iter().map(|b| *b as usize).collect()
+            }
+            else {
+                bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).collect()
+            }
+        }
+        else {
+            let mut values = Vec::with_capacity(count);
+            while values.len() < count {
+                values.push(number("pixel value")?);
+            }
+            values
+        };
+
+        if values.len() < count {
+            return Err(invalid("not enough pixel data"));
+        }
+
+        let mut canvas = Self::new(width, height);
+        let scale = 1.0 / max as Float;
+        for (pixel, rgb) in canvas.canvas.iter_mut().zip(values.chunks_exact(3)) {
+            *pixel = Colour::new(rgb[0] as Float * scale, rgb[1] as Float * scale, rgb[2] as Float * scale);
+        }
+
+        Ok(canvas)
+    }
+
+    /// Pixels in a `width` by `height` image read from a file header, refusing
+    /// sizes that are empty, overflow or are too big to be real before
+    /// anything is allocated for them
+    pub(crate) fn checked_size(width: usize, height: usize) -> io::Result<usize> {
+        if width == 0 || height == 0 {
+            return Err(invalid("image has no pixels"));
+        }
+        width.checked_mul(height)
+            .filter(|&pixels| width <= MAX_DECODED_SIDE && height <= MAX_DECODED_SIDE && pixels <= MAX_DECODED_PIXELS)
+            .ok_or_else(|| invalid(&format!("{width}x{height} image is too large")))
+    }
+
+    /// Opaque canvas of `pixels` in rows from the top, for decoders that
+    /// collect them as they're read. Panics unless there are `width * height`
+    pub(crate) fn from_pixels(width: usize, height: usize, pixels: Vec<Colour>) -> Self {
+        assert_eq!(Some(pixels.len()), width.checked_mul(height));
+        Self {
+            alpha: vec![1.0; pixels.len()],
+            canvas: pixels,
+            width,
+            height,
+        }
+    }
+
+    /// Start of a PPM file, the pixels from `ppm_pixels` follow it
+    pub(crate) fn ppm_header(width: usize, height: usize) -> String {
+        format!("{PPM_MAGIC}\n{width} {height}\n{PPM_MAX_COLOUR}")
+    }
+
+    /// Start of a 16 bit binary PPM, the pixels from `ppm_16_pixels` follow it
+    pub(crate) fn ppm_16_header(width: usize, height: usize) -> String {
+        format!("P6\n{width} {height}\n65535\n")
+    }
+
+    /// Append the pixel data of a 16 bit binary PPM
+    pub(crate) fn ppm_16_pixels(&self, data: &mut Vec<u8>) {
+        for pixel in &self.canvas {
+            for channel in [pixel.r, pixel.g, pixel.b] {
+                // as with 8 bits, anything outside 0.0 to 1.0 saturates
+                data.extend_from_slice(&((channel * 65536.0) as u16).to_be_bytes());
             }
+        }
+    }
+
+    /// Append the pixel data of a PPM file, so rows can be written a few at a time
+    pub(crate) fn ppm_pixels(&self, data: &mut Vec<u8>) {
+        for y in 0..self.height {
+            self.ppm_row(y, data);
+        }
+    }
 
+    fn ppm_row(&self, y: usize, data: &mut Vec<u8>) {
+        data.push(b'\n');
+        let mut line_len = 0;
+        for pixel in &self.canvas[y * self.width..(y + 1) * self.width] {
             let cols = [pixel.r, pixel.g, pixel.b];
             for col in cols {
-                let col = Self::convert_colour(col) + " ";
-                line_len += col.len();
-                
-                if line_len  >= 70 {
-                    data += "\n";
-                    line_len = col.len();
+                let (digits, len) = byte_digits(channel_to_byte(col));
+                line_len += len + 1;
+
+                if line_len >= 70 {
+                    data.push(b'\n');
+                    line_len = len + 1;
                 }
-                data += &col;
+                data.extend_from_slice(&digits[3 - len..]);
+                data.push(b' ');
             }
         }
+    }
+}
+
+/// Decimal digits of `value` right aligned in three bytes, and how many of
+/// them are used, so PPM values are written without allocating a `String`
+#[inline]
+fn byte_digits(value: u8) -> ([u8; 3], usize) {
+    let digits = [b'0' + value / 100, b'0' + value / 10 % 10, b'0' + value % 10];
+    let len = match value {
+        0..=9 => 1,
+        10..=99 => 2,
+        _ => 3,
+    };
+    (digits, len)
+}
 
-        data += "\n"; // terminator
+/// 0.0 to 1.0 channel to the 8 bits used by PPM and the other image writers,
+/// anything outside that range is clamped
+pub(crate) fn channel_to_byte(channel: Float) -> u8 {
+    (channel * PPM_COLOUR_MULTIPLIER) as u8
+}
+
+/// A PPM `header` with a `#` comment line for each entry of `metadata`
+/// after its magic number
+fn commented(header: &str, metadata: &Metadata) -> String {
+    let (magic, rest) = header.split_once('\n').unwrap_or((header, ""));
+    let comments: String = metadata.lines().map(|line| format!("# {line}\n")).collect();
+    format!("{magic}\n{comments}{rest}")
+}
 
-        data
+/// Next whitespace separated token in a PPM header, skipping `#` comments
+fn next_token<'a>(data: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    loop {
+        while data.get(*pos).is_some_and(u8::is_ascii_whitespace) {
+            *pos += 1;
+        }
+        if data.get(*pos) != Some(&b'#') {
+            break;
+        }
+        while data.get(*pos).is_some_and(|b| *b != b'\n') {
+            *pos += 1;
+        }
     }
 
-    #[inline]
-    fn convert_colour(colour: f32) -> String {
-        ((colour * PPM_COLOUR_MULTIPLIER) as u8).to_string()
+    let start = *pos;
+    while data.get(*pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+
+    (*pos > start).then(|| &data[start..*pos])
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid PPM: {message}"))
+}
+
+/// Read only window onto part of a canvas, from `Canvas::view`. Coordinates
+/// are relative to the top left of the window
+#[derive(Debug, Clone, Copy)]
+pub struct CanvasView<'a> {
+    canvas: &'a Canvas,
+    left: usize,
+    top: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl CanvasView<'_> {
+    pub fn alpha(&self, x: usize, y: usize) -> Float {
+        assert!(x < self.width && y < self.height);
+        self.canvas.alpha(self.left + x, self.top + y)
+    }
+
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height);
+        for y in 0..self.height {
+            let row = (self.top + y) * self.canvas.width + self.left;
+            let out = y * self.width;
+            canvas.canvas[out..out + self.width].copy_from_slice(&self.canvas.canvas[row..row + self.width]);
+            canvas.alpha[out..out + self.width].copy_from_slice(&self.canvas.alpha[row..row + self.width]);
+        }
+
+        canvas
+    }
+}
+
+impl Index<(usize, usize)> for CanvasView<'_> {
+    type Output = Colour;
+
+    fn index(&self, index: (usize, usize)) -> &Self::Output {
+        assert!(index.0 < self.width && index.1 < self.height);
+        &self.canvas[(self.left + index.0, self.top + index.1)]
     }
 }
 
@@ -74,9 +777,10 @@ impl IndexMut<(usize, usize)> for Canvas {
 
 #[cfg(test)]
 mod tests {
-    use crate::types::colour::Colour;
+    use crate::Float;
+    use crate::types::{colour::Colour, lut::Lut, tonemap::{ToneMap, ToneMapping}};
 
-    use super::Canvas;
+    use super::{channel_to_byte, Blend, Canvas, Dither, Filter};
 
     #[test]
     fn constructor() {
@@ -88,6 +792,18 @@ mod tests {
         for pixel in canvas.canvas {
             assert_eq!(pixel, white);
         }
+
+        for alpha in canvas.alpha {
+            assert_eq!(alpha, 1.0);
+        }
+    }
+
+    #[test]
+    fn alpha() {
+        let mut canvas = Canvas::new(10, 20);
+        canvas.set_alpha(2, 3, 0.25);
+        assert_eq!(canvas.alpha(2, 3), 0.25);
+        assert_eq!(canvas.alpha[32], 0.25);
     }
 
     #[test]
@@ -106,6 +822,320 @@ mod tests {
         assert_eq!(canvas[(2, 3)], red);
     }
 
+    #[test]
+    fn paste() {
+        let mut canvas = Canvas::new(4, 3);
+        let mut tile = Canvas::new(2, 2);
+        tile[(1, 1)] = Colour::new(1.0, 0.0, 0.0);
+        tile.set_alpha(0, 1, 0.5);
+
+        canvas.paste(2, 1, &tile);
+        assert_eq!(canvas[(3, 2)], Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(canvas.alpha(2, 2), 0.5);
+        assert_eq!(canvas.alpha(1, 2), 1.0);
+    }
+
+    #[test]
+    fn resized() {
+        let mut canvas = Canvas::new(2, 2);
+        canvas[(1, 0)] = Colour::white();
+        canvas[(1, 1)] = Colour::white();
+        canvas.set_alpha(0, 1, 0.0);
+
+        let nearest = canvas.resized(4, 4, Filter::Nearest);
+        assert_eq!(nearest[(1, 0)], Colour::black());
+        assert_eq!(nearest[(2, 3)], Colour::white());
+        assert_eq!(nearest.alpha(0, 3), 0.0);
+
+        let bilinear = canvas.resized(4, 1, Filter::Bilinear);
+        assert_eq!(bilinear[(0, 0)], Colour::black());
+        assert_eq!(bilinear[(1, 0)], Colour::new(0.25, 0.25, 0.25));
+        assert_eq!(bilinear[(2, 0)], Colour::new(0.75, 0.75, 0.75));
+        assert_eq!(bilinear.alpha(0, 0), 0.5);
+
+        let small = canvas.resized(1, 1, Filter::Bilinear);
+        assert_eq!(small[(0, 0)], Colour::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn checked_access() {
+        let red = Colour::new(1.0, 0.0, 0.0);
+        let mut canvas = Canvas::new(3, 2);
+
+        assert!(canvas.set(2, 1, red));
+        assert!(!canvas.set(3, 1, red));
+        assert!(!canvas.set(0, 2, red));
+        assert_eq!(canvas.get(2, 1), Some(red));
+        assert_eq!(canvas.get(3, 0), None);
+
+        canvas.write_pixel(-1, 0, red);
+        canvas.write_pixel(0, 5, red);
+        canvas.write_pixel(1, 0, red);
+        assert_eq!(canvas.get(1, 0), Some(red));
+        assert_eq!(canvas.get(0, 0), Some(Colour::black()));
+    }
+
+    #[test]
+    fn enumerate_pixels() {
+        let mut canvas = Canvas::new(3, 2);
+        // horizontal gradient
+        for (x, _, colour) in canvas.enumerate_pixels_mut() {
+            *colour = Colour::white() * (x as Float / 2.0);
+        }
+
+        let coords: Vec<_> = canvas.enumerate_pixels().map(|(x, y, _)| (x, y)).collect();
+        assert_eq!(coords, vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]);
+        assert_eq!(canvas[(1, 1)], Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(canvas[(2, 0)], Colour::white());
+    }
+
+    #[test]
+    fn crop() {
+        let mut canvas = Canvas::new(4, 3);
+        canvas[(2, 1)] = Colour::white();
+        canvas.set_alpha(3, 2, 0.25);
+
+        let view = canvas.view(1, 1, 3, 2);
+        assert_eq!((view.width, view.height), (3, 2));
+        assert_eq!(view[(1, 0)], Colour::white());
+        assert_eq!(view.alpha(2, 1), 0.25);
+
+        let cropped = canvas.crop(2, 1, 2, 2);
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+        assert_eq!(cropped[(0, 0)], Colour::white());
+        assert_eq!(cropped.alpha(1, 1), 0.25);
+        assert_eq!(cropped[(1, 0)], Colour::black());
+    }
+
+    #[test]
+    #[should_panic]
+    fn crop_out_of_bounds() {
+        Canvas::new(4, 3).crop(2, 2, 3, 1);
+    }
+
+    #[test]
+    fn fill() {
+        let blue = Colour::new(0.1, 0.2, 0.8);
+        let mut canvas = Canvas::new(3, 2);
+        canvas.set_alpha(1, 1, 0.0);
+        canvas.fill(blue);
+        assert!((0..2).all(|y| (0..3).all(|x| canvas[(x, y)] == blue && canvas.alpha(x, y) == 1.0)));
+
+        canvas.fill_rect((1, 0), 2, 1, Colour::white());
+        assert_eq!(canvas[(0, 0)], blue);
+        assert_eq!(canvas[(2, 0)], Colour::white());
+
+        canvas.clear();
+        assert_eq!(canvas.to_ppm(), Canvas::new(3, 2).to_ppm());
+    }
+
+    #[test]
+    fn draw_line() {
+        let red = Colour::new(1.0, 0.0, 0.0);
+        let mut canvas = Canvas::new(5, 5);
+        canvas.draw_line((0, 0), (3, 1), red);
+
+        let lit: Vec<_> = (0..5).flat_map(|y| (0..5).map(move |x| (x, y)))
+            .filter(|&p| canvas[p] == red)
+            .collect();
+        assert_eq!(lit, vec![(0, 0), (1, 0), (2, 1), (3, 1)]);
+
+        // clipped rather than panicking
+        canvas.draw_line((-3, 4), (8, 4), red);
+        assert!((0..5).all(|x| canvas[(x, 4)] == red));
+
+        canvas.draw_line((2, 3), (2, 3), Colour::white());
+        assert_eq!(canvas[(2, 3)], Colour::white());
+    }
+
+    #[test]
+    fn draw_line_aa() {
+        let mut canvas = Canvas::new(5, 5);
+        canvas.draw_line_aa((0.0, 1.0), (4.0, 1.0), Colour::white());
+        assert!((0..5).all(|x| canvas[(x, 1)] == Colour::white() && canvas[(x, 2)] == Colour::black()));
+
+        // half way between two rows, both get half the colour
+        canvas.draw_line_aa((3.0, 2.5), (3.0, 4.5), Colour::new(1.0, 0.0, 0.0));
+        canvas.draw_line_aa((0.0, 3.5), (2.0, 3.5), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(canvas[(1, 3)], Colour::new(0.5, 0.0, 0.0));
+        assert_eq!(canvas[(1, 4)], Colour::new(0.5, 0.0, 0.0));
+        assert_eq!(canvas[(3, 3)], Colour::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn circles_and_rects() {
+        let red = Colour::new(1.0, 0.0, 0.0);
+        let lit = |canvas: &Canvas| (0..canvas.height)
+            .flat_map(|y| (0..canvas.width).map(move |x| (x, y)))
+            .filter(|&p| canvas[p] == red)
+            .count();
+
+        let mut canvas = Canvas::new(7, 7);
+        canvas.draw_circle((3, 3), 2, red);
+        assert_eq!(lit(&canvas), 12);
+        assert_eq!(canvas[(3, 3)], Colour::black());
+        assert_eq!(canvas[(5, 3)], red);
+
+        canvas.fill_circle((3, 3), 2, red);
+        assert_eq!(lit(&canvas), 21);
+
+        let mut canvas = Canvas::new(7, 7);
+        canvas.draw_rect((1, 1), 4, 3, red);
+        assert_eq!(lit(&canvas), 10);
+        assert_eq!(canvas[(2, 2)], Colour::black());
+
+        canvas.fill_rect((1, 1), 4, 3, red);
+        assert_eq!(lit(&canvas), 12);
+
+        // partly off the canvas
+        let mut canvas = Canvas::new(4, 4);
+        canvas.fill_rect((-2, 2), 10, 10, red);
+        canvas.draw_circle((0, 0), 3, red);
+        // the circle only adds (3, 0) and (3, 1) above the rectangle
+        assert_eq!(lit(&canvas), 8 + 2);
+    }
+
+    #[test]
+    fn draw_text() {
+        let white = Colour::white();
+        let mut canvas = Canvas::new(12, 10);
+        canvas.draw_text(0, 0, "T1", white);
+
+        // top bar of the T and the foot of the 1
+        assert!((0..5).all(|x| canvas[(x, 0)] == white));
+        assert!((7..10).all(|x| canvas[(x, 6)] == white));
+        assert_eq!(canvas[(2, 6)], white);
+        assert_eq!(canvas[(0, 6)], Colour::black());
+        assert_eq!(canvas[(5, 0)], Colour::black());
+
+        // runs off the edge without panicking
+        canvas.draw_text(8, 8, "frame 12\nnext", white);
+
+        assert_eq!(Canvas::text_size("T1"), (11, 7));
+        assert_eq!(Canvas::text_size("frame 12\nnext"), (47, 16));
+        assert_eq!(Canvas::text_size(""), (0, 0));
+    }
+
+    #[test]
+    fn composite() {
+        let mut plate = Canvas::new(2, 1);
+        plate[(0, 0)] = Colour::new(0.2, 0.4, 0.6);
+        plate[(1, 0)] = Colour::new(0.2, 0.4, 0.6);
+
+        // opaque on the left, half covered on the right
+        let mut layer = Canvas::new(2, 1);
+        layer[(0, 0)] = Colour::new(1.0, 0.0, 0.0);
+        layer[(1, 0)] = Colour::new(0.5, 0.0, 0.0);
+        layer.set_alpha(1, 0, 0.5);
+
+        let mut over = plate.clone();
+        over.composite(&layer, Blend::Over);
+        assert_eq!(over[(0, 0)], Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(over[(1, 0)], Colour::new(0.6, 0.2, 0.3));
+        assert_eq!(over.alpha(1, 0), 1.0);
+
+        let mut add = plate.clone();
+        add.composite(&layer, Blend::Add);
+        assert_eq!(add[(1, 0)], Colour::new(0.7, 0.4, 0.6));
+
+        let mut multiply = plate.clone();
+        multiply.composite(&layer, Blend::Multiply);
+        assert_eq!(multiply[(0, 0)], Colour::new(0.2, 0.0, 0.0));
+        assert_eq!(multiply[(1, 0)], Colour::new(0.2, 0.2, 0.3));
+
+        // multiplying onto nothing leaves the layer as it was
+        let mut clear = Canvas::new(2, 1);
+        clear.set_alpha(0, 0, 0.0);
+        clear.set_alpha(1, 0, 0.0);
+        clear.composite(&layer, Blend::Multiply);
+        assert_eq!(clear[(0, 0)], Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(clear[(1, 0)], Colour::new(0.5, 0.0, 0.0));
+        assert_eq!(clear.alpha(1, 0), 0.5);
+
+        let mut empty = Canvas::new(2, 1);
+        empty.set_alpha(0, 0, 0.0);
+        empty.composite(&layer, Blend::Over);
+        assert_eq!(empty.alpha(0, 0), 1.0);
+    }
+
+    #[test]
+    fn write_ppm_16() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas[(0, 0)] = Colour::new(1.5, 0.5, -1.0);
+        canvas[(1, 0)] = Colour::new(0.001, 0.25, 0.999);
+
+        let mut data = Vec::new();
+        canvas.write_ppm_16(&mut data).unwrap();
+        assert!(data.starts_with(b"P6\n2 1\n65535\n"));
+        assert_eq!(data.len(), 13 + 2 * 6);
+        assert_eq!(&data[13..19], &[0xFF, 0xFF, 0x80, 0x00, 0x00, 0x00]);
+
+        // survives a round trip far better than 8 bits would
+        let read = Canvas::from_ppm(&data[..]).unwrap();
+        assert!((read[(1, 0)].r - 0.001).abs() < 1e-4);
+        assert_eq!(read[(1, 0)], canvas[(1, 0)]);
+    }
+
+    #[test]
+    fn dither() {
+        // a quarter of the way between levels 10 and 11
+        let value = 10.75 / 256.0;
+        let mut flat = Canvas::new(16, 16);
+        flat.fill(Colour::new(value, value, value));
+
+        let average = |canvas: &Canvas| canvas.enumerate_pixels()
+            .map(|(_, _, colour)| channel_to_byte(colour.r) as Float)
+            .sum::<Float>() / 256.0;
+
+        let mut plain = flat.clone();
+        plain.dither(Dither::None);
+        assert_eq!(average(&plain), 10.0);
+
+        let mut ordered = flat.clone();
+        ordered.dither(Dither::Ordered);
+        assert_eq!(average(&ordered), 10.25);
+        assert!(ordered.enumerate_pixels().all(|(_, _, c)| (10..=11).contains(&channel_to_byte(c.r))));
+
+        let mut diffused = flat.clone();
+        diffused.dither(Dither::FloydSteinberg);
+        assert!((average(&diffused) - 10.25).abs() < 0.02);
+        assert!(diffused.enumerate_pixels().all(|(_, _, c)| (10..=11).contains(&channel_to_byte(c.g))));
+
+        // already on a level so nothing changes
+        let mut white = Canvas::new(2, 2);
+        white.fill(Colour::white());
+        white.dither(Dither::FloydSteinberg);
+        assert_eq!(white.to_ppm(), "P3\n2 2\n255\n255 255 255 255 255 255 \n255 255 255 255 255 255 \n");
+    }
+
+    #[test]
+    fn tone_map() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas[(0, 0)] = Colour::new(1.9, 1.0, 0.0);
+        canvas.tone_map(&ToneMapping::new(0.0, ToneMap::Reinhard));
+        assert_eq!(canvas[(0, 0)], Colour::new(0.65517, 0.5, 0.0));
+    }
+
+    #[test]
+    fn apply_lut() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas[(0, 0)] = Colour::new(0.5, 0.5, 0.5);
+        canvas[(1, 0)] = Colour::new(2.0, 1.0, 0.0);
+
+        let lut = Lut::parse("LUT_1D_SIZE 2\n1 0 0\n0 1 1").unwrap();
+        canvas.apply_lut(&lut);
+        assert_eq!(canvas[(0, 0)], Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(canvas[(1, 0)], Colour::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn byte_digits() {
+        for value in 0..=255 {
+            let (digits, len) = super::byte_digits(value);
+            assert_eq!(&digits[3 - len..], value.to_string().as_bytes());
+        }
+    }
+
     #[test]
     fn to_ppm() {
         let mut canvas = Canvas::new(5, 3);
@@ -140,4 +1170,65 @@ mod tests {
 
         assert_eq!(ppm.chars().last().unwrap(), '\n');
     }
+
+    #[test]
+    fn from_ppm() {
+        let canvas = Canvas::from_ppm("P3\n# made by hand\n2 1\n10\n10 5 0  0 0 10\n".as_bytes()).unwrap();
+        assert_eq!((canvas.width, canvas.height), (2, 1));
+        assert_eq!(canvas[(0, 0)], Colour::new(1.0, 0.5, 0.0));
+        assert_eq!(canvas[(1, 0)], Colour::new(0.0, 0.0, 1.0));
+
+        let mut binary = b"P6 1 2 255\n".to_vec();
+        binary.extend([255, 0, 51, 0, 102, 255]);
+        let canvas = Canvas::from_ppm(binary.as_slice()).unwrap();
+        assert_eq!(canvas[(0, 0)], Colour::new(1.0, 0.0, 0.2));
+        assert_eq!(canvas[(0, 1)], Colour::new(0.0, 0.4, 1.0));
+
+        // 16 bit samples are big endian
+        let mut wide = b"P6 1 1 65535\n".to_vec();
+        wide.extend([0xFF, 0xFF, 0x80, 0x00, 0x00, 0x00]);
+        assert_eq!(Canvas::from_ppm(wide.as_slice()).unwrap()[(0, 0)], Colour::new(1.0, 0.50001, 0.0));
+
+        // round trip through to_ppm
+        let mut original = Canvas::new(30, 2);
+        original[(3, 1)] = Colour::new(0.2, 0.4, 1.0);
+        let loaded = Canvas::from_ppm(original.to_ppm().as_bytes()).unwrap();
+        assert_eq!(loaded[(3, 1)], original[(3, 1)]);
+        assert_eq!(loaded[(0, 0)], original[(0, 0)]);
+
+        assert!(Canvas::from_ppm("P5 1 1 255\n".as_bytes()).is_err());
+        assert!(Canvas::from_ppm("P3 2 2 255\n1 2 3".as_bytes()).is_err());
+        assert!(Canvas::from_ppm("P6 2 2 255\n123".as_bytes()).is_err());
+        for huge in ["P3 4000000000 4000000000 255\n1 2 3", "P3 100000 100000 255\n1 2 3", "P6 100000 100000 255\n123"] {
+            assert_eq!(Canvas::from_ppm(huge.as_bytes()).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn write_ppm() {
+        let mut canvas = Canvas::new(30, 3);
+        canvas[(4, 2)] = Colour::new(0.2, 0.4, 1.0);
+
+        let mut ppm = Vec::new();
+        canvas.write_ppm(&mut ppm).unwrap();
+        assert_eq!(String::from_utf8(ppm).unwrap(), canvas.to_ppm());
+    }
+
+    #[test]
+    fn serde() {
+        let mut canvas = Canvas::new(2, 3);
+        canvas[(1, 2)] = Colour::new(0.2, 0.4, 1.0);
+        canvas.set_alpha(0, 1, 0.5);
+        let json = serde_json::to_string(&canvas).unwrap();
+        let loaded: Canvas = serde_json::from_str(&json).unwrap();
+        assert_eq!((loaded.width, loaded.height), (2, 3));
+        assert_eq!(loaded[(1, 2)], canvas[(1, 2)]);
+        assert_eq!(loaded.alpha(0, 1), 0.5);
+
+        let short = r#"{"canvas": [[0, 0, 0]], "alpha": [1, 1], "width": 2, "height": 1}"#;
+        let error = serde_json::from_str::<Canvas>(short).unwrap_err();
+        assert!(error.to_string().contains("2x1 image has 1 pixel values"), "{error}");
+        let huge = r#"{"canvas": [], "alpha": [], "width": 18446744073709551615, "height": 2}"#;
+        assert!(serde_json::from_str::<Canvas>(huge).is_err());
+    }
 }
\ No newline at end of file
@@ -1,16 +1,19 @@
 use std::ops::Mul;
+
 use derive_more::{Add, Sub, AddAssign, SubAssign};
+use serde::{Deserialize, Serialize};
+use crate::Float;
 use super::eq;
 
-#[derive(Debug, Clone, Copy, Add, Sub, AddAssign, SubAssign)]
+#[derive(Debug, Clone, Copy, Add, Sub, AddAssign, SubAssign, Serialize, Deserialize)]
 pub struct Colour {
-    pub r: f32,
-    pub g: f32,
-    pub b: f32
+    pub r: Float,
+    pub g: Float,
+    pub b: Float
 }
 
 impl Colour {
-    pub fn new(r: f32, g: f32, b: f32) -> Self {
+    pub fn new(r: Float, g: Float, b: Float) -> Self {
         Self {
             r,
             g,
@@ -33,6 +36,21 @@ impl Colour {
             b: 1.0,
         }
     }
+
+    pub fn max_channel(&self) -> Float {
+        self.r.max(self.g).max(self.b)
+    }
+
+    /// Scale down so no channel is over `max`, keeping the hue
+    pub fn limit(self, max: Float) -> Self {
+        let largest = self.max_channel();
+        if largest > max {
+            self * (max / largest)
+        }
+        else {
+            self
+        }
+    }
 }
 
 impl PartialEq for Colour {
@@ -43,10 +61,10 @@ impl PartialEq for Colour {
     }
 }
 
-impl Mul<f32> for Colour {
+impl Mul<Float> for Colour {
     type Output = Colour;
 
-    fn mul(self, factor: f32) -> Self::Output {
+    fn mul(self, factor: Float) -> Self::Output {
         Self {
             r: self.r * factor,
             g: self.g * factor,
@@ -90,6 +108,12 @@ mod tests {
         assert_ne!(col1, col2);
     }
 
+    #[test]
+    fn limit() {
+        assert_eq!(Colour::new(4.0, 2.0, 1.0).limit(2.0), Colour::new(2.0, 1.0, 0.5));
+        assert_eq!(Colour::new(0.5, 2.0, 1.0).limit(2.0), Colour::new(0.5, 2.0, 1.0));
+    }
+
     #[test]
     fn add() {
         let col1 = Colour::new(0.9, 0.6, 0.75);
@@ -0,0 +1,60 @@
+//! Which SIMD instructions the CPU running the renderer has. Checked once,
+//! the first time anything asks, so one binary built for the baseline
+//! target can still pick wider kernels on machines that have them
+
+use std::sync::OnceLock;
+
+/// Widest SIMD instructions available, in order of preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SimdLevel {
+    Scalar,
+    /// 128 bit vectors, always there on x86_64
+    Sse,
+    /// 256 bit vectors on x86_64
+    Avx2,
+}
+
+impl SimdLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Scalar => "scalar",
+            Self::Sse => "sse",
+            Self::Avx2 => "avx2",
+        }
+    }
+}
+
+/// Best SIMD level this CPU supports
+pub fn simd_level() -> SimdLevel {
+    static LEVEL: OnceLock<SimdLevel> = OnceLock::new();
+    *LEVEL.get_or_init(detect)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> SimdLevel {
+    if is_x86_feature_detected!("avx2") {
+        SimdLevel::Avx2
+    }
+    else {
+        SimdLevel::Sse
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn detect() -> SimdLevel {
+    SimdLevel::Scalar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simd_level() {
+        let level = super::simd_level();
+        assert_eq!(super::simd_level(), level);
+        if cfg!(target_arch = "x86_64") {
+            assert!(level >= SimdLevel::Sse);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::consts::PI;
+
+use crate::{Float, RosemaryError, Tuple};
+use super::{canvas::Canvas, colour::Colour, hdr, intersection::Computations, rng::{cosine_direction, hash_floats}, sampler::SamplerKind,
+    world::World};
+
+/// Image based light from an equirectangular (latitude/longitude) image.
+///
+/// Rays that escape the scene see the image, and surfaces pick up ambient,
+/// diffuse and mirror reflected light from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SavedEnvironment", into = "SavedEnvironment")]
+pub struct Environment {
+    image: Canvas,
+    average: Colour,
+    /// Brightness multiplier for the whole image
+    pub intensity: Float,
+    /// Rotation around the y axis in radians
+    pub rotation: Float,
+    /// Directions sampled per shading point for diffuse lighting
+    pub samples: usize,
+    pub seed: u64,
+    /// Sequence the sampled directions follow
+    pub sampler: SamplerKind,
+}
+
+impl Environment {
+    /// Panics if `image` is empty, see `try_new`
+    pub fn new(image: Canvas) -> Self {
+        Self::try_new(image).unwrap()
+    }
+
+    pub fn try_new(image: Canvas) -> Result<Self, RosemaryError> {
+        if image.width == 0 || image.height == 0 {
+            return Err(RosemaryError::EmptyImage);
+        }
+
+        let mut sum = Colour::black();
+        for y in 0..image.height {
+            for x in 0..image.width {
+                sum += image[(x, y)];
+            }
+        }
+
+        Ok(Self {
+            average: sum * (1.0 / (image.width * image.height) as Float),
+            image,
+            intensity: 1.0,
+            rotation: 0.0,
+            samples: 32,
+            seed: 0,
+            sampler: SamplerKind::default(),
+        })
+    }
+
+    pub fn from_hdr(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::try_new(hdr::load(path)?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Environment colour seen looking along `direction`
+    pub fn sample(&self, direction: Tuple) -> Colour {
+        let d = direction.norm();
+        let u = 0.5 + (d.x.atan2(-d.z) + self.rotation) / (2.0 * PI);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+
+        // bilinear filtering, wrapping around horizontally
+        let x = u * self.image.width as Float - 0.5;
+        let y = (v * self.image.height as Float - 0.5).clamp(0.0, (self.image.height - 1) as Float);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let width = self.image.width as isize;
+        let wrap = |x: Float| (x as isize).rem_euclid(width) as usize;
+        let (x0, x1) = (wrap(x0), wrap(x0 + 1.0));
+        let (y0, y1) = (y0 as usize, (y0 as usize + 1).min(self.image.height - 1));
+
+        let top = self.image[(x0, y0)] * (1.0 - tx) + self.image[(x1, y0)] * tx;
+        let bottom = self.image[(x0, y1)] * (1.0 - tx) + self.image[(x1, y1)] * tx;
+        (top * (1.0 - ty) + bottom * ty) * self.intensity
+    }
+
+    /// Light from the environment reaching a hit
+    pub fn lighting(&self, comps: &Computations, world: &World) -> Colour {
+        let material = comps.obj.material();
+        let ambient = material.colour * self.average * (material.ambient * self.intensity);
+
+        let mut diffuse = Colour::black();
+        if material.diffuse > 0.0 && self.samples > 0 {
+            let mut rng = self.sampler.samples(self.seed ^ hash_floats(&[comps.over_point.x, comps.over_point.y, comps.over_point.z]));
+
+            // cosine weighted directions so the average is the irradiance
+            for _ in 0..self.samples {
+                let direction = cosine_direction(comps.normal, &mut rng);
+                if !world.is_shadowed(comps.over_point, direction) {
+                    diffuse += world.clamp_sample(self.sample(direction));
+                }
+            }
+
+            diffuse = material.colour * diffuse * (material.diffuse / self.samples as Float);
+        }
+
+        let mut specular = Colour::black();
+        if material.specular > 0.0 {
+            let reflected = (-comps.eye).reflect(comps.normal);
+            if !world.is_shadowed(comps.over_point, reflected) {
+                specular = self.sample(reflected) * material.specular;
+            }
+        }
+
+        ambient + diffuse + specular
+    }
+}
+
+/// The parts of an environment that get saved, the average colour is worked
+/// out again on load
+#[derive(Serialize, Deserialize)]
+struct SavedEnvironment {
+    image: Canvas,
+    intensity: Float,
+    rotation: Float,
+    samples: usize,
+    seed: u64,
+    sampler: SamplerKind,
+}
+
+impl TryFrom<SavedEnvironment> for Environment {
+    type Error = RosemaryError;
+
+    fn try_from(saved: SavedEnvironment) -> Result<Self, Self::Error> {
+        Ok(Self {
+            intensity: saved.intensity,
+            rotation: saved.rotation,
+            samples: saved.samples,
+            seed: saved.seed,
+            sampler: saved.sampler,
+            ..Self::try_new(saved.image)?
+        })
+    }
+}
+
+impl From<Environment> for SavedEnvironment {
+    fn from(environment: Environment) -> Self {
+        Self {
+            image: environment.image,
+            intensity: environment.intensity,
+            rotation: environment.rotation,
+            samples: environment.samples,
+            seed: environment.seed,
+            sampler: environment.sampler,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{intersection::Intersection, material::Material, ray::Ray, sphere::Sphere};
+    use crate::Matrix;
+
+    use super::*;
+
+    fn uniform(colour: Colour) -> Environment {
+        let mut image = Canvas::new(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                image[(x, y)] = colour;
+            }
+        }
+        Environment::new(image)
+    }
+
+    #[test]
+    fn sample() {
+        // top half sky, bottom half ground
+        let mut image = Canvas::new(8, 4);
+        for x in 0..8 {
+            image[(x, 0)] = Colour::new(0.0, 0.0, 1.0);
+            image[(x, 3)] = Colour::new(0.0, 1.0, 0.0);
+        }
+        let env = Environment::new(image);
+        assert_eq!(env.sample(Tuple::vector(0.0, 1.0, 0.0)), Colour::new(0.0, 0.0, 1.0));
+        assert_eq!(env.sample(Tuple::vector(0.0, -1.0, 0.0)), Colour::new(0.0, 1.0, 0.0));
+        assert_eq!(env.sample(Tuple::vector(1.0, 0.0, 0.0)), Colour::black());
+
+        let mut env = uniform(Colour::new(0.5, 0.5, 0.5));
+        env.intensity = 2.0;
+        assert_eq!(env.sample(Tuple::vector(0.3, -0.2, 0.9)), Colour::white());
+    }
+
+    #[test]
+    fn lighting() {
+        let env = uniform(Colour::white());
+        let sphere = Sphere::new(Matrix::identity(), Material {
+            specular: 0.0,
+            ..Material::default()
+        });
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let comps = Intersection::new(4.0, &sphere).prepare_computations(r);
+
+        // nothing blocks the hemisphere so it sees all of the uniform environment
+        let world = World::new(vec![], vec![]);
+        assert_eq!(env.lighting(&comps, &world), Colour::new(1.0, 1.0, 1.0));
+
+        // clamping caps each diffuse sample but not the ambient average
+        let mut world = world;
+        world.set_max_sample_radiance(Some(0.5));
+        assert_eq!(env.lighting(&comps, &world), Colour::new(0.55, 0.55, 0.55));
+
+        // a wall in front blocks some of it
+        let wall = Sphere::new(Matrix::scaling(10.0, 10.0, 0.1).translate(0.0, 0.0, -1.5), Material::default());
+        let world = World::new(vec![Box::new(wall)], vec![]);
+        let blocked = env.lighting(&comps, &world);
+        assert!(blocked.r < 0.5 && blocked.r >= 0.1);
+    }
+
+    #[test]
+    fn world_environment() {
+        let mut w = World::new(vec![], vec![]);
+        w.set_environment(Some(uniform(Colour::new(0.2, 0.4, 0.6))));
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(w.colour_at(r), Colour::new(0.2, 0.4, 0.6));
+        assert_eq!(w.colour_alpha_at(r), (Colour::new(0.2, 0.4, 0.6), 1.0));
+    }
+
+    #[test]
+    fn serde() {
+        let mut env = uniform(Colour::new(0.2, 0.4, 0.6));
+        env.intensity = 2.0;
+        let loaded: Environment = serde_json::from_str(&serde_json::to_string(&env).unwrap()).unwrap();
+        assert_eq!(loaded.average, env.average);
+        assert_eq!(loaded.intensity, 2.0);
+        assert_eq!(loaded.sample(Tuple::vector(0.0, 1.0, 0.0)), env.sample(Tuple::vector(0.0, 1.0, 0.0)));
+
+        let empty = r#"{"image": {"canvas": [], "alpha": [], "width": 0, "height": 0},
+            "intensity": 1, "rotation": 0, "samples": 32, "seed": 0, "sampler": "Random"}"#;
+        let error = serde_json::from_str::<Environment>(empty).unwrap_err();
+        assert!(error.to_string().contains("at least 1x1"), "{error}");
+    }
+}
@@ -0,0 +1,56 @@
+//! Errors from building shapes, rays, cameras, lights, images and transforms
+//! out of bad values, or asking a world about a light it hasn't got. The `try_` constructors return these for callers that would
+//! rather handle bad input than have the renderer panic
+
+use std::fmt;
+
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RosemaryError {
+    /// A transform with no inverse, like a scale by zero
+    NotInvertible,
+    /// A vector given where a point was needed, named by what it was for
+    NotAPoint(&'static str),
+    /// A point given where a vector was needed, named by what it was for
+    NotAVector(&'static str),
+    /// A mesh face using a vertex past the end of the list
+    MissingVertex { face: usize, vertex: usize },
+    /// Per corner values for a mesh that don't have one entry per face
+    CornerCount { faces: usize, corners: usize },
+    /// An image with no pixels
+    EmptyImage,
+    /// Saved image data that doesn't have one value per pixel
+    PixelCount { width: usize, height: usize, values: usize },
+    /// A setting below zero that has to be at least zero, named by what it is
+    Negative(&'static str),
+    /// A count of zero that has to be at least one, named by what it counts
+    Zero(&'static str),
+    /// A spot light whose inner cone is wider than its outer one
+    SpotAngles,
+    /// The same object put into a world more than once
+    DuplicateObject(Uuid),
+    /// A light index past the end of a world's lights
+    NoLight(usize),
+}
+
+impl fmt::Display for RosemaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInvertible => write!(f, "transform can't be inverted"),
+            Self::NotAPoint(what) => write!(f, "{what} has to be a point"),
+            Self::NotAVector(what) => write!(f, "{what} has to be a vector"),
+            Self::MissingVertex { face, vertex } => write!(f, "mesh face {face} refers to missing vertex {vertex}"),
+            Self::CornerCount { faces, corners } => write!(f, "mesh has {faces} faces but corners for {corners}"),
+            Self::EmptyImage => write!(f, "image has to be at least 1x1"),
+            Self::PixelCount { width, height, values } => write!(f, "{width}x{height} image has {values} pixel values"),
+            Self::Negative(what) => write!(f, "{what} can't be negative"),
+            Self::Zero(what) => write!(f, "{what} has to be at least 1"),
+            Self::SpotAngles => write!(f, "spot light inner angle can't be wider than its outer angle"),
+            Self::DuplicateObject(id) => write!(f, "object {id} is in the world more than once"),
+            Self::NoLight(index) => write!(f, "world has no light {index}"),
+        }
+    }
+}
+
+impl std::error::Error for RosemaryError {}
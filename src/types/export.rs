@@ -0,0 +1,288 @@
+//! A world's geometry written out as OBJ or glTF, so it can be looked at in
+//! other tools like Blender. Everything is baked into world space triangles,
+//! spheres becoming icospheres. Faces keep the winding meshes use, the same
+//! one OBJ files are read with, so exporting and importing again round trips.
+//! glTF is right handed where the renderer is left handed, so z is negated
+//! and faces wound the other way there
+
+use std::{fmt::Write as _, fs::{self, File}, io::{self, BufWriter, Write}, path::Path};
+
+use crate::{Float, Tuple};
+use super::{material::Material, mesh::Mesh, shape::SavedShape, world::World};
+
+/// One object's triangles
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedMesh {
+    pub name: String,
+    pub vertices: Vec<Tuple>,
+    pub faces: Vec<[usize; 3]>,
+    pub material: Material,
+}
+
+/// Every object in a world as triangles, ready to write out
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Geometry {
+    pub meshes: Vec<ExportedMesh>,
+}
+
+impl Geometry {
+    /// Spheres are split into icospheres of `subdivisions`, see
+    /// `Mesh::icosphere`. Shapes from outside the crate are left out
+    pub fn from_world(world: &World, subdivisions: usize) -> Self {
+        let sphere = Mesh::icosphere(subdivisions);
+        let meshes = world.objects().iter().enumerate().filter_map(|(i, obj)| {
+            let (vertices, faces) = match obj.saved()? {
+                SavedShape::Sphere(_) => (sphere.vertices().to_vec(), sphere.faces().to_vec()),
+                SavedShape::Mesh(mesh) => (mesh.vertices().to_vec(), mesh.faces().to_vec()),
+            };
+            Some(ExportedMesh {
+                name: format!("object{i}"),
+                vertices: vertices.iter().map(|v| obj.transform().matrix() * *v).collect(),
+                faces,
+                material: *obj.material(),
+            })
+        }).collect();
+
+        Self {
+            meshes,
+        }
+    }
+
+    /// Writes `.obj` files with a `.mtl` library of the same name next to
+    /// them, and `.gltf` files with their buffer embedded
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("obj") => {
+                let library = path.with_extension("mtl");
+                let name = library.file_name().and_then(|name| name.to_str());
+                self.write_obj(BufWriter::new(File::create(path)?), name)?;
+                self.write_mtl(BufWriter::new(File::create(&library)?))
+            },
+            Some("gltf") => fs::write(path, self.gltf()),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "can only export .obj or .gltf")),
+        }
+    }
+
+    /// Each mesh as its own object, using the material of the same name
+    /// from `library` if given
+    pub fn write_obj(&self, mut writer: impl Write, library: Option<&str>) -> io::Result<()> {
+        if let Some(library) = library {
+            writeln!(writer, "mtllib {library}")?;
+        }
+
+        let mut offset = 1;
+        for mesh in &self.meshes {
+            writeln!(writer, "o {}", mesh.name)?;
+            if library.is_some() {
+                writeln!(writer, "usemtl {}", mesh.name)?;
+            }
+            for v in &mesh.vertices {
+                writeln!(writer, "v {} {} {}", v.x, v.y, v.z)?;
+            }
+            for [a, b, c] in &mesh.faces {
+                writeln!(writer, "f {} {} {}", a + offset, b + offset, c + offset)?;
+            }
+            offset += mesh.vertices.len();
+        }
+
+        writer.flush()
+    }
+
+    /// Materials for `write_obj`, the reverse of `MtlMaterial::build`
+    pub fn write_mtl(&self, mut writer: impl Write) -> io::Result<()> {
+        for mesh in &self.meshes {
+            let material = &mesh.material;
+            let colour = material.colour;
+            writeln!(writer, "newmtl {}", mesh.name)?;
+            writeln!(writer, "Kd {} {} {}", colour.r, colour.g, colour.b)?;
+            writeln!(writer, "Ks {0} {0} {0}", material.specular)?;
+            writeln!(writer, "Ns {}", material.shininess)?;
+            writeln!(writer, "d {}", 1.0 - material.transparency)?;
+        }
+
+        writer.flush()
+    }
+
+    /// glTF 2.0 JSON with one node per mesh and the vertex and index data
+    /// in a base64 buffer, mirrored in z to be right handed
+    pub fn gltf(&self) -> String {
+        let mut buffer = Vec::new();
+        let (mut nodes, mut meshes, mut materials, mut accessors, mut views) = (vec![], vec![], vec![], vec![], vec![]);
+
+        for mesh in self.meshes.iter().filter(|mesh| !mesh.faces.is_empty()) {
+            let index = meshes.len();
+            let mut view = |buffer: &mut Vec<u8>, start: usize, target: u32| {
+                views.push(serde_json::json!({
+                    "buffer": 0,
+                    "byteOffset": start,
+                    "byteLength": buffer.len() - start,
+                    "target": target,
+                }));
+            };
+
+            let start = buffer.len();
+            let (mut min, mut max) = ([Float::INFINITY; 3], [Float::NEG_INFINITY; 3]);
+            for v in &mesh.vertices {
+                for (axis, value) in [v.x, v.y, -v.z].into_iter().enumerate() {
+                    #[allow(clippy::unnecessary_cast)]
+                    buffer.extend((value as f32).to_le_bytes());
+                    min[axis] = min[axis].min(value);
+                    max[axis] = max[axis].max(value);
+                }
+            }
+            view(&mut buffer, start, 34962);
+
+            let start = buffer.len();
+            for index in mesh.faces.iter().flat_map(|&[a, b, c]| [a, c, b]) {
+                buffer.extend((index as u32).to_le_bytes());
+            }
+            view(&mut buffer, start, 34963);
+
+            accessors.push(serde_json::json!({
+                "bufferView": 2 * index,
+                "componentType": 5126,
+                "count": mesh.vertices.len(),
+                "type": "VEC3",
+                "min": min,
+                "max": max,
+            }));
+            accessors.push(serde_json::json!({
+                "bufferView": 2 * index + 1,
+                "componentType": 5125,
+                "count": 3 * mesh.faces.len(),
+                "type": "SCALAR",
+            }));
+
+            let (colour, material) = (mesh.material.colour, &mesh.material);
+            let base = [colour.r, colour.g, colour.b, 1.0 - material.transparency].map(|c| c.clamp(0.0, 1.0));
+            materials.push(serde_json::json!({
+                "name": mesh.name,
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": base,
+                    "metallicFactor": 0.0,
+                },
+                "alphaMode": if material.transparency > 0.0 { "BLEND" } else { "OPAQUE" },
+            }));
+            meshes.push(serde_json::json!({
+                "name": mesh.name,
+                "primitives": [{
+                    "attributes": { "POSITION": 2 * index },
+                    "indices": 2 * index + 1,
+                    "material": index,
+                }],
+            }));
+            nodes.push(serde_json::json!({ "name": mesh.name, "mesh": index }));
+        }
+
+        let gltf = serde_json::json!({
+            "asset": { "version": "2.0", "generator": "rosemary" },
+            "scene": 0,
+            "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+            "nodes": nodes,
+            "meshes": meshes,
+            "materials": materials,
+            "accessors": accessors,
+            "bufferViews": views,
+            "buffers": [{
+                "byteLength": buffer.len(),
+                "uri": format!("data:application/octet-stream;base64,{}", base64(&buffer)),
+            }],
+        });
+        serde_json::to_string_pretty(&gltf).expect("JSON values always serialise")
+    }
+}
+
+/// Standard base64 with padding
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0, |bits, (i, byte)| bits | u32::from(*byte) << (16 - 8 * i));
+        for i in 0..4 {
+            let c = if i <= chunk.len() { ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char } else { '=' };
+            encoded.write_char(c).unwrap();
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Matrix;
+    use crate::types::{obj::ObjParser, shape::Shape, sphere::Sphere};
+
+    use super::*;
+
+    #[test]
+    fn base64() {
+        assert_eq!(super::base64(b""), "");
+        assert_eq!(super::base64(b"f"), "Zg==");
+        assert_eq!(super::base64(b"fo"), "Zm8=");
+        assert_eq!(super::base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn obj() {
+        let sphere = Sphere::new(Matrix::translation(0.0, 2.0, 0.0), Material::default());
+        let world = World::new(vec![Box::new(sphere)], vec![]);
+        let geometry = Geometry::from_world(&world, 1);
+        assert_eq!(geometry.meshes[0].faces.len(), 80);
+
+        let mut obj = Vec::new();
+        geometry.write_obj(&mut obj, None).unwrap();
+        let parsed = ObjParser::parse(std::str::from_utf8(&obj).unwrap()).unwrap();
+        let mesh = parsed.mesh(Matrix::identity(), Material::default());
+        assert_eq!(mesh.vertices(), geometry.meshes[0].vertices);
+        assert_eq!(mesh.faces(), geometry.meshes[0].faces);
+        assert_eq!(mesh.bounds().centre, Tuple::point(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn gltf() {
+        let world = World::default();
+        let gltf: serde_json::Value = serde_json::from_str(&Geometry::from_world(&world, 0).gltf()).unwrap();
+        assert_eq!(gltf["nodes"].as_array().unwrap().len(), 2);
+        assert_eq!(gltf["accessors"][0]["count"], 12);
+        assert_eq!(gltf["accessors"][1]["count"], 60);
+        // the second sphere is half the size of the first
+        let max = |i: usize| gltf["accessors"][i]["max"][0].as_f64().unwrap();
+        assert!((max(0) - 2.0 * max(2)).abs() < 1e-6);
+        assert_eq!(gltf["buffers"][0]["byteLength"], 2 * (12 * 12 + 60 * 4));
+    }
+
+    #[test]
+    fn gltf_handedness() {
+        let geometry = Geometry {
+            meshes: vec![ExportedMesh {
+                name: "triangle".to_string(),
+                vertices: vec![Tuple::point(1.0, 2.0, 3.0), Tuple::point(0.0, 0.0, 0.0), Tuple::point(0.0, 1.0, 0.0)],
+                faces: vec![[0, 1, 2]],
+                material: Material::default(),
+            }],
+        };
+        let gltf: serde_json::Value = serde_json::from_str(&geometry.gltf()).unwrap();
+        let uri = gltf["buffers"][0]["uri"].as_str().unwrap();
+
+        let mut buffer = Vec::new();
+        let (mut bits, mut count) = (0u32, 0);
+        for c in uri.split(',').nth(1).unwrap().bytes().take_while(|&c| c != b'=') {
+            let value = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/".iter().position(|&a| a == c).unwrap();
+            bits = bits << 6 | value as u32;
+            count += 6;
+            if count >= 8 {
+                count -= 8;
+                buffer.push((bits >> count) as u8);
+            }
+        }
+        let words: Vec<[u8; 4]> = buffer.chunks_exact(4).map(|word| word.try_into().unwrap()).collect();
+        let positions: Vec<f32> = words[..9].iter().map(|word| f32::from_le_bytes(*word)).collect();
+        let indices: Vec<u32> = words[9..].iter().map(|word| u32::from_le_bytes(*word)).collect();
+
+        assert_eq!(positions, [1.0, 2.0, -3.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(indices, [0, 2, 1]);
+        assert_eq!(gltf["accessors"][0]["min"][2], -3.0);
+        assert_eq!(gltf["accessors"][0]["max"][2], 0.0);
+    }
+}
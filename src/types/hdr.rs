@@ -0,0 +1,236 @@
+//! Radiance `.hdr` (RGBE) images, the usual format for HDR environment maps
+
+use std::{fs::File, io::{self, BufRead, BufReader, BufWriter, Read, Write}, path::Path};
+
+use crate::Float;
+use super::{canvas::Canvas, colour::Colour, metadata::Metadata};
+
+pub fn load(path: impl AsRef<Path>) -> io::Result<Canvas> {
+    decode(BufReader::new(File::open(path)?))
+}
+
+pub fn save(canvas: &Canvas, path: impl AsRef<Path>) -> io::Result<()> {
+    save_with_metadata(canvas, path, &Metadata::new())
+}
+
+pub fn save_with_metadata(canvas: &Canvas, path: impl AsRef<Path>, metadata: &Metadata) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode_with_metadata(canvas, &mut writer, metadata)?;
+    writer.flush()
+}
+
+pub fn decode(mut reader: impl BufRead) -> io::Result<Canvas> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid("missing radiance header"));
+    }
+
+    // header ends with an empty line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of header"));
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid("only RGBE images are supported"));
+            }
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let (height, width) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (
+            height.parse().map_err(|_| invalid("bad height"))?,
+            width.parse().map_err(|_| invalid("bad width"))?,
+        ),
+        _ => return Err(invalid("only -Y +X orientation is supported")),
+    };
+    Canvas::checked_size(width, height)?;
+
+    // grown a scanline at a time, so a header claiming more than the file
+    // has fails before much is allocated
+    let mut pixels = Vec::new();
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(&mut reader, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|rgbe| rgbe_to_colour(*rgbe)));
+    }
+
+    Ok(Canvas::from_pixels(width, height, pixels))
+}
+
+/// Writes uncompressed scanlines, which every reader understands
+pub fn encode(canvas: &Canvas, writer: impl Write) -> io::Result<()> {
+    encode_with_metadata(canvas, writer, &Metadata::new())
+}
+
+/// `encode` with `metadata` as comment lines in the header
+pub fn encode_with_metadata(canvas: &Canvas, mut writer: impl Write, metadata: &Metadata) -> io::Result<()> {
+    writeln!(writer, "#?RADIANCE")?;
+    for line in metadata.lines() {
+        writeln!(writer, "# {line}")?;
+    }
+    write!(writer, "FORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", canvas.height, canvas.width)?;
+
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            writer.write_all(&colour_to_rgbe(canvas[(x, y)]))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_scanline(reader: &mut impl Read, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut start = [0u8; 4];
+    reader.read_exact(&mut start)?;
+
+    // new style run length encoding starts with 2 2 and the width
+    let rle = (8..0x8000).contains(&width) && start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0;
+    if !rle {
+        scanline[0] = start;
+        for pixel in &mut scanline[1..] {
+            reader.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    if ((start[2] as usize) << 8 | start[3] as usize) != width {
+        return Err(invalid("scanline width mismatch"));
+    }
+
+    // each channel is run length encoded separately
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+            let mut count = count[0] as usize;
+
+            if count > 128 {
+                count -= 128;
+                if x + count > width {
+                    return Err(invalid("run overflows scanline"));
+                }
+
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = value[0];
+                }
+            }
+            else {
+                if count == 0 || x + count > width {
+                    return Err(invalid("bad run length"));
+                }
+
+                let mut values = vec![0u8; count];
+                reader.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+            }
+
+            x += count;
+        }
+    }
+
+    Ok(())
+}
+
+fn rgbe_to_colour([r, g, b, e]: [u8; 4]) -> Colour {
+    if e == 0 {
+        return Colour::black();
+    }
+
+    let factor = (2.0 as Float).powi(e as i32 - 136);
+    Colour::new((r as Float + 0.5) * factor, (g as Float + 0.5) * factor, (b as Float + 0.5) * factor)
+}
+
+fn colour_to_rgbe(colour: Colour) -> [u8; 4] {
+    let max = colour.r.max(colour.g).max(colour.b);
+    if max < 1e-32 {
+        return [0; 4];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let factor = 256.0 / (2.0 as Float).powi(exponent);
+    let channel = |c: Float| (c.max(0.0) * factor).min(255.0) as u8;
+
+    [channel(colour.r), channel(colour.g), channel(colour.b), (exponent + 128) as u8]
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas[(0, 0)] = Colour::new(1.0, 0.5, 0.25);
+        canvas[(2, 0)] = Colour::new(12.0, 3.0, 0.0);
+        canvas[(1, 1)] = Colour::new(0.001, 0.002, 0.003);
+
+        let mut data = Vec::new();
+        encode(&canvas, &mut data).unwrap();
+        let decoded = decode(&data[..]).unwrap();
+
+        assert_eq!(decoded.width, 3);
+        assert_eq!(decoded.height, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                let (a, b) = (canvas[(x, y)], decoded[(x, y)]);
+                let tolerance = a.r.max(a.g).max(a.b) / 64.0 + 1e-6;
+                assert!((a.r - b.r).abs() <= tolerance && (a.g - b.g).abs() <= tolerance && (a.b - b.b).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_rle() {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        data.extend([2, 2, 0, 8]);
+        // red: run of 8 x 128
+        data.extend([128 + 8, 128]);
+        // green: 8 literal values
+        data.extend([8, 0, 0, 0, 0, 128, 128, 128, 128]);
+        // blue: run of 8 x 0
+        data.extend([128 + 8, 0]);
+        // exponent: run of 8 x 129, so 128 is 1.0
+        data.extend([128 + 8, 129]);
+
+        let canvas = decode(&data[..]).unwrap();
+        assert_eq!(canvas.width, 8);
+        assert!((canvas[(0, 0)].r - 1.0).abs() < 0.01);
+        assert!(canvas[(0, 0)].g < 0.01);
+        assert!((canvas[(7, 0)].g - 1.0).abs() < 0.01);
+        assert!(canvas[(7, 0)].b < 0.01);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert!(decode(&b"P3\n1 1\n255\n"[..]).is_err());
+        assert!(decode(&b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n"[..]).is_err());
+        assert!(decode(&b"#?RADIANCE\n\n+Y 1 +X 1\n"[..]).is_err());
+        assert!(decode(&b"#?RADIANCE\n\n-Y 1 +X 2\n\x01\x01\x01\x80"[..]).is_err());
+        for header in [&b"#?RADIANCE\n\n-Y 1 +X 0\n"[..], b"#?RADIANCE\n\n-Y 0 +X 1\n",
+            b"#?RADIANCE\n\n-Y 4294967296 +X 4294967297\n", b"#?RADIANCE\n\n-Y 3000000 +X 3000000\n",
+            b"#?RADIANCE\n\n-Y 60000 +X 60000\n\x01\x01\x01\x80"] {
+            assert_eq!(decode(header).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
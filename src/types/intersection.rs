@@ -1,21 +1,105 @@
-use super::sphere::Sphere;
+use std::ops::Deref;
+
+use crate::{Float, types::ray::Ray, Tuple, EPSILON};
+use super::shape::Shape;
+
+/// Buffer of intersections that can be cleared and refilled for each ray
+pub type Intersections<'a> = Vec<Intersection<'a>>;
+
+/// Intersections kept in order of `t` as they're added, so the hit can be
+/// found with a binary search rather than sorting or scanning everything
+#[derive(Debug, Clone, Default)]
+pub struct SortedIntersections<'a> {
+    inters: Intersections<'a>,
+}
+
+impl<'a> SortedIntersections<'a> {
+    pub fn new() -> Self {
+        Self {
+            inters: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.inters.clear();
+    }
+
+    pub fn insert(&mut self, inter: Intersection<'a>) {
+        let i = self.inters.partition_point(|other| other.t <= inter.t);
+        self.inters.insert(i, inter);
+    }
+
+    /// Add every intersection in `batch`, leaving it sorted. Shapes give only
+    /// a few hits each, often in order already, so this sorts the batch and
+    /// merges it in from the back instead of sorting everything again
+    pub fn merge(&mut self, batch: &mut [Intersection<'a>]) {
+        batch.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let (mut old, mut new) = (self.inters.len(), batch.len());
+        self.inters.extend_from_slice(batch);
+        for end in (0..self.inters.len()).rev() {
+            if new == 0 {
+                break;
+            }
+
+            if old > 0 && self.inters[old - 1].t > batch[new - 1].t {
+                self.inters[end] = self.inters[old - 1];
+                old -= 1;
+            }
+            else {
+                self.inters[end] = batch[new - 1];
+                new -= 1;
+            }
+        }
+    }
+
+    /// Nearest intersection in front of the ray's origin
+    pub fn hit(&self) -> Option<Intersection<'a>> {
+        self.inters.get(self.inters.partition_point(|inter| inter.t <= 0.0)).copied()
+    }
+}
+
+impl<'a> Deref for SortedIntersections<'a> {
+    type Target = [Intersection<'a>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.inters
+    }
+}
+
+impl<'a> From<SortedIntersections<'a>> for Intersections<'a> {
+    fn from(sorted: SortedIntersections<'a>) -> Self {
+        sorted.inters
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Intersection {
-    pub t: f32,
-    pub obj: Sphere
+pub struct Intersection<'a> {
+    pub t: Float,
+    pub obj: &'a dyn Shape,
+    /// Which face of a mesh was hit
+    pub face: Option<usize>,
 }
 
-impl Intersection {
-    pub fn new(t: f32, obj: Sphere) -> Self {
+impl<'a> Intersection<'a> {
+    pub fn new(t: Float, obj: &'a dyn Shape) -> Self {
+        Self {
+            t,
+            obj,
+            face: None,
+        }
+    }
+
+    pub fn with_face(t: Float, obj: &'a dyn Shape, face: usize) -> Self {
         Self {
             t,
-            obj
+            obj,
+            face: Some(face),
         }
     }
 
-    pub fn hit(inters: Vec<Intersection>) -> Option<Intersection> {
-        let mut min_t = f32::MAX;
+    pub fn hit(inters: impl IntoIterator<Item = Intersection<'a>>) -> Option<Intersection<'a>> {
+        let mut min_t = Float::MAX;
         let mut min_inter = None;
     
         for i in inters {
@@ -27,9 +111,44 @@ impl Intersection {
     
         min_inter
     }
+
+    pub fn prepare_computations(&self, ray: Ray) -> Computations<'a> {
+        let point = ray.position(self.t);
+        let eye = -ray.direction;
+        let mut normal = self.obj.normal(point, self);
+        let inside = normal * eye < 0.0;
+
+        // hit from inside the object so flip normal to face the eye
+        if inside {
+            normal = -normal;
+        }
+
+        Computations {
+            t: self.t,
+            obj: self.obj,
+            point,
+            eye,
+            normal,
+            inside,
+            over_point: point + normal * EPSILON,
+        }
+    }
+}
+
+/// Precomputed values about a hit needed for shading
+#[derive(Debug, Clone, Copy)]
+pub struct Computations<'a> {
+    pub t: Float,
+    pub obj: &'a dyn Shape,
+    pub point: Tuple,
+    pub eye: Tuple,
+    pub normal: Tuple,
+    pub inside: bool,
+    /// `point` nudged along the normal to avoid shadow acne
+    pub over_point: Tuple,
 }
 
-impl PartialOrd for Intersection {
+impl PartialOrd for Intersection<'_> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         self.t.partial_cmp(&other.t)
     }
@@ -37,36 +156,84 @@ impl PartialOrd for Intersection {
 
 #[cfg(test)]
 mod tests {
-    use crate::types::sphere::Sphere;
-    use super::Intersection;
+    use crate::{types::{ray::Ray, shape::Shape, sphere::Sphere}, Matrix, Tuple, EPSILON};
+    use crate::types::material::Material;
+    use super::{Intersection, SortedIntersections};
 
     #[test]
     fn new() {
         let s = Sphere::default();
-        let i = Intersection::new(3.5, s);
+        let i = Intersection::new(3.5, &s);
         assert_eq!(i.t, 3.5);
-        assert_eq!(i.obj, s);
+        assert_eq!(i.obj.id(), s.id());
 
-        let i1 = Intersection::new(1.0, s);
-        let i2 = Intersection::new(2.0, s);
+        let i1 = Intersection::new(1.0, &s);
+        let i2 = Intersection::new(2.0, &s);
         let inter = Intersection::hit(vec![i1, i2]);
         assert_eq!(inter.unwrap(), i1);
 
-        let i1 = Intersection::new(-1.0, s);
-        let i2: Intersection = Intersection::new(1.0, s);
+        let i1 = Intersection::new(-1.0, &s);
+        let i2: Intersection = Intersection::new(1.0, &s);
         let inter = Intersection::hit(vec![i1, i2]);
         assert_eq!(inter.unwrap(), i2);
 
-        let i1 = Intersection::new(-2.0, s);
-        let i2 = Intersection::new(-1.0, s);
+        let i1 = Intersection::new(-2.0, &s);
+        let i2 = Intersection::new(-1.0, &s);
         let inter = Intersection::hit(vec![i1, i2]);
         assert_eq!(inter, None);
 
-        let i1 = Intersection::new(5.0, s);
-        let i2 = Intersection::new(7.0, s);
-        let i3 = Intersection::new(-3.0, s);
-        let i4 = Intersection::new(2.0, s);
+        let i1 = Intersection::new(5.0, &s);
+        let i2 = Intersection::new(7.0, &s);
+        let i3 = Intersection::new(-3.0, &s);
+        let i4 = Intersection::new(2.0, &s);
         let inter = Intersection::hit(vec![i1, i2, i3, i4]);
         assert_eq!(inter.unwrap(), i4);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sorted() {
+        let s = Sphere::default();
+        let mut sorted = SortedIntersections::new();
+        assert_eq!(sorted.hit(), None);
+
+        sorted.merge(&mut [Intersection::new(5.0, &s), Intersection::new(-3.0, &s)]);
+        sorted.insert(Intersection::new(7.0, &s));
+        sorted.merge(&mut [Intersection::new(2.0, &s), Intersection::new(6.0, &s), Intersection::new(-4.0, &s)]);
+        sorted.merge(&mut []);
+        assert_eq!(sorted.iter().map(|i| i.t).collect::<Vec<_>>(), [-4.0, -3.0, 2.0, 5.0, 6.0, 7.0]);
+        assert_eq!(sorted.hit().unwrap().t, 2.0);
+
+        sorted.clear();
+        sorted.insert(Intersection::new(-1.0, &s));
+        sorted.insert(Intersection::new(0.0, &s));
+        assert_eq!(sorted.hit(), None);
+    }
+
+    #[test]
+    fn prepare_computations() {
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let s = Sphere::default();
+        let comps = Intersection::new(4.0, &s).prepare_computations(r);
+        assert_eq!(comps.t, 4.0);
+        assert_eq!(comps.obj.id(), s.id());
+        assert_eq!(comps.point, Tuple::point(0.0, 0.0, -1.0));
+        assert_eq!(comps.eye, Tuple::vector(0.0, 0.0, -1.0));
+        assert_eq!(comps.normal, Tuple::vector(0.0, 0.0, -1.0));
+        assert!(!comps.inside);
+
+        // hit from the inside
+        let r = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0));
+        let comps = Intersection::new(1.0, &s).prepare_computations(r);
+        assert_eq!(comps.point, Tuple::point(0.0, 0.0, 1.0));
+        assert_eq!(comps.eye, Tuple::vector(0.0, 0.0, -1.0));
+        assert_eq!(comps.normal, Tuple::vector(0.0, 0.0, -1.0));
+        assert!(comps.inside);
+
+        // over point is offset
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let s = Sphere::new(Matrix::translation(0.0, 0.0, 1.0), Material::default());
+        let comps = Intersection::new(5.0, &s).prepare_computations(r);
+        assert!(comps.over_point.z < -EPSILON / 2.0);
+        assert!(comps.point.z > comps.over_point.z);
+    }
+}
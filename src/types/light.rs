@@ -1,24 +1,623 @@
-use crate::types::{colour::Colour, tuple::Tuple};
+use std::fmt::Debug;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+use crate::consts::PI;
+use crate::{Float, RosemaryError};
+
+use crate::types::{colour::Colour, sampler::{SamplerKind, Samples}, tuple::Tuple, world::World};
+
+/// Anything that can illuminate a world.
+///
+/// A light is described by a set of sample positions. Point-like samples are
+/// points, while samples at infinity (directional lights) are vectors pointing
+/// towards the light.
+pub trait Light: Debug + Send + Sync {
+    fn intensity(&self) -> Colour;
+
+    /// Positions to sample when lighting `point`
+    fn samples(&self, point: Tuple) -> Vec<Tuple>;
+
+    /// Fraction (0.0 - 1.0) of the light that reaches `point` in `world`
+    fn intensity_at(&self, point: Tuple, world: &World) -> Float {
+        let samples = self.samples(point);
+        let lit = samples.iter()
+            .filter(|sample| !world.is_shadowed(point, **sample))
+            .count();
+
+        lit as Float / samples.len() as Float
+    }
+
+    /// How much of the light's strength is left after travelling to `point`,
+    /// 0.0 means the light can't reach it so lighting and shadows can be skipped
+    fn attenuation(&self, _point: Tuple) -> Float {
+        1.0
+    }
+
+    /// The light as something that can be written out with the world, `None`
+    /// for lights defined outside this crate
+    fn saved(&self) -> Option<SavedLight> {
+        None
+    }
+}
+
+/// Every light type the crate has, so worlds can save and load their lights
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SavedLight {
+    Point(PointLight),
+    Area(AreaLight),
+    Sphere(SphereLight),
+    Tube(TubeLight),
+    Spot(SpotLight),
+    Directional(DirectionalLight),
+}
+
+impl From<SavedLight> for Box<dyn Light> {
+    fn from(light: SavedLight) -> Self {
+        match light {
+            SavedLight::Point(light) => Box::new(light),
+            SavedLight::Area(light) => Box::new(light),
+            SavedLight::Sphere(light) => Box::new(light),
+            SavedLight::Tube(light) => Box::new(light),
+            SavedLight::Spot(light) => Box::new(light),
+            SavedLight::Directional(light) => Box::new(light),
+        }
+    }
+}
+
+/// Gives `$light` a plain copy of its fields to deserialize into, which then
+/// has to pass the light's `check`, so saved lights get the same checks as
+/// `try_new`
+macro_rules! checked_on_load {
+    ($light:ident, $unchecked:ident { $($field:ident: $ty:ty),* }) => {
+        #[derive(Deserialize)]
+        struct $unchecked {
+            $($field: $ty),*
+        }
+
+        impl TryFrom<$unchecked> for $light {
+            type Error = RosemaryError;
+
+            fn try_from(unchecked: $unchecked) -> Result<Self, Self::Error> {
+                let light = Self {
+                    $($field: unchecked.$field),*
+                };
+                light.check()?;
+                Ok(light)
+            }
+        }
+    };
+}
+
+checked_on_load!(PointLight, UncheckedPointLight { intensity: Colour, pos: Tuple, range: Option<Float> });
+checked_on_load!(AreaLight, UncheckedAreaLight {
+    intensity: Colour, corner: Tuple, uvec: Tuple, usteps: usize, vvec: Tuple, vsteps: usize, jitter: Jitter
+});
+checked_on_load!(SphereLight, UncheckedSphereLight {
+    intensity: Colour, centre: Tuple, radius: Float, samples: usize, jitter: Jitter
+});
+checked_on_load!(TubeLight, UncheckedTubeLight {
+    intensity: Colour, start: Tuple, end: Tuple, samples: usize, jitter: Jitter
+});
+checked_on_load!(SpotLight, UncheckedSpotLight {
+    intensity: Colour, pos: Tuple, direction: Tuple, inner_angle: Float, outer_angle: Float, range: Option<Float>
+});
+checked_on_load!(DirectionalLight, UncheckedDirectionalLight { intensity: Colour, direction: Tuple });
+
+/// Soft shadow jitter settings.
+///
+/// When enabled, samples are randomly offset within their cell instead of
+/// sitting at its centre, trading banding for noise. The offsets are seeded
+/// from `seed` and the shaded point so renders are reproducible, and follow
+/// `sampler` so they can be spread more evenly than independent random ones.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Jitter {
+    pub enabled: bool,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+}
+
+impl Jitter {
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            enabled: true,
+            seed,
+            sampler: SamplerKind::default(),
+        }
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerKind) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Offsets for the samples of `point`, or `None` when disabled
+    #[allow(clippy::unnecessary_cast)] // bits are already a u64 with the f64 feature
+    pub fn rng_for(&self, point: Tuple) -> Option<Samples> {
+        if !self.enabled {
+            return None;
+        }
+
+        let hash = [point.x, point.y, point.z].iter()
+            .fold(self.seed, |hash, f| (hash ^ f.to_bits() as u64).wrapping_mul(0x100_0000_01B3));
+        Some(self.sampler.samples(hash))
+    }
+}
+
+/// Smooth falloff from 1.0 at the light to 0.0 at `range`
+fn range_attenuation(range: Option<Float>, distance: Float) -> Float {
+    match range {
+        Some(range) => (1.0 - (distance / range).powi(4)).max(0.0).powi(2),
+        None => 1.0,
+    }
+}
+
+/// Unit vector from `point` towards a light sample
+pub fn light_vector(sample: Tuple, point: Tuple) -> Tuple {
+    if sample.is_vector() {
+        sample.norm()
+    }
+    else {
+        (sample - point).norm()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedPointLight")]
 pub struct PointLight {
     pub intensity: Colour,
-    pub pos: Tuple
+    pub pos: Tuple,
+    /// Distance the light fades out over, `None` lights everything
+    pub range: Option<Float>,
 }
 
 impl PointLight {
+    /// Panics if `pos` isn't a point, see `try_new`
     pub fn new(intensity: Colour, pos: Tuple) -> Self {
-        assert!(pos.is_point());
+        Self::try_new(intensity, pos).unwrap()
+    }
+
+    pub fn try_new(intensity: Colour, pos: Tuple) -> Result<Self, RosemaryError> {
+        let light = Self {
+            intensity,
+            pos,
+            range: None,
+        };
+        light.check()?;
+        Ok(light)
+    }
+
+    fn check(&self) -> Result<(), RosemaryError> {
+        point(self.pos, "point light position")?;
+        range(self.range)
+    }
+
+    pub fn with_range(self, range: Float) -> Self {
+        assert!(range > 0.0);
 
         Self {
+            range: Some(range),
+            ..self
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn intensity(&self) -> Colour {
+        self.intensity
+    }
+
+    fn samples(&self, _point: Tuple) -> Vec<Tuple> {
+        vec![self.pos]
+    }
+
+    fn attenuation(&self, point: Tuple) -> Float {
+        range_attenuation(self.range, (point - self.pos).magnitude())
+    }
+
+    fn saved(&self) -> Option<SavedLight> {
+        Some(SavedLight::Point(*self))
+    }
+}
+
+/// Rectangular light made of `usteps * vsteps` cells spanning `uvec` and `vvec` from `corner`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedAreaLight")]
+pub struct AreaLight {
+    pub intensity: Colour,
+    pub corner: Tuple,
+    pub uvec: Tuple,
+    pub usteps: usize,
+    pub vvec: Tuple,
+    pub vsteps: usize,
+    pub jitter: Jitter,
+}
+
+impl AreaLight {
+    /// Panics if `corner` isn't a point, either edge isn't a vector or
+    /// either step count is zero, see `try_new`
+    pub fn new(intensity: Colour, corner: Tuple, full_uvec: Tuple, usteps: usize,
+        full_vvec: Tuple, vsteps: usize) -> Self {
+        Self::try_new(intensity, corner, full_uvec, usteps, full_vvec, vsteps).unwrap()
+    }
+
+    pub fn try_new(intensity: Colour, corner: Tuple, full_uvec: Tuple, usteps: usize,
+        full_vvec: Tuple, vsteps: usize) -> Result<Self, RosemaryError> {
+        let light = Self {
+            intensity,
+            corner,
+            uvec: full_uvec / usteps.max(1) as Float,
+            usteps,
+            vvec: full_vvec / vsteps.max(1) as Float,
+            vsteps,
+            jitter: Jitter::default(),
+        };
+        light.check()?;
+        Ok(light)
+    }
+
+    fn check(&self) -> Result<(), RosemaryError> {
+        point(self.corner, "area light corner")?;
+        vector(self.uvec, "area light u edge")?;
+        vector(self.vvec, "area light v edge")?;
+        count(self.usteps, "area light u steps")?;
+        count(self.vsteps, "area light v steps")
+    }
+
+    /// Change how many cells (and so shadow rays) the light is split into
+    pub fn set_samples(&mut self, usteps: usize, vsteps: usize) {
+        assert!(usteps > 0 && vsteps > 0);
+
+        self.uvec *= self.usteps as Float / usteps as Float;
+        self.vvec *= self.vsteps as Float / vsteps as Float;
+        self.usteps = usteps;
+        self.vsteps = vsteps;
+    }
+
+    /// Centre of the cell at `u`, `v`
+    pub fn point_on_light(&self, u: usize, v: usize) -> Tuple {
+        self.point_in_cell(u, v, 0.5, 0.5)
+    }
+
+    fn point_in_cell(&self, u: usize, v: usize, u_offset: Float, v_offset: Float) -> Tuple {
+        self.corner + self.uvec * (u as Float + u_offset) + self.vvec * (v as Float + v_offset)
+    }
+}
+
+impl Light for AreaLight {
+    fn intensity(&self) -> Colour {
+        self.intensity
+    }
+
+    fn samples(&self, point: Tuple) -> Vec<Tuple> {
+        let mut samples = Vec::with_capacity(self.usteps * self.vsteps);
+        let mut rng = self.jitter.rng_for(point);
+
+        for v in 0..self.vsteps {
+            for u in 0..self.usteps {
+                let sample = match &mut rng {
+                    Some(rng) => self.point_in_cell(u, v, rng.next_float(), rng.next_float()),
+                    None => self.point_on_light(u, v),
+                };
+                samples.push(sample);
+            }
+        }
+
+        samples
+    }
+
+    fn saved(&self) -> Option<SavedLight> {
+        Some(SavedLight::Area(*self))
+    }
+}
+
+/// Ball shaped light, softer shadows than a point light without having to
+/// orient a rectangle like `AreaLight`.
+///
+/// Samples are spread over the disc of the sphere facing the shaded point.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedSphereLight")]
+pub struct SphereLight {
+    pub intensity: Colour,
+    pub centre: Tuple,
+    pub radius: Float,
+    /// Shadow rays per shaded point
+    pub samples: usize,
+    pub jitter: Jitter,
+}
+
+impl SphereLight {
+    /// Panics if `centre` isn't a point, `radius` is negative or `samples`
+    /// is zero, see `try_new`
+    pub fn new(intensity: Colour, centre: Tuple, radius: Float, samples: usize) -> Self {
+        Self::try_new(intensity, centre, radius, samples).unwrap()
+    }
+
+    pub fn try_new(intensity: Colour, centre: Tuple, radius: Float, samples: usize) -> Result<Self, RosemaryError> {
+        let light = Self {
+            intensity,
+            centre,
+            radius,
+            samples,
+            jitter: Jitter::default(),
+        };
+        light.check()?;
+        Ok(light)
+    }
+
+    fn check(&self) -> Result<(), RosemaryError> {
+        point(self.centre, "sphere light centre")?;
+        if self.radius.is_nan() || self.radius < 0.0 {
+            return Err(RosemaryError::Negative("sphere light radius"));
+        }
+        count(self.samples, "sphere light samples")
+    }
+}
+
+impl Light for SphereLight {
+    fn intensity(&self) -> Colour {
+        self.intensity
+    }
+
+    fn samples(&self, point: Tuple) -> Vec<Tuple> {
+        let to_light = self.centre - point;
+        if self.radius == 0.0 || to_light.magnitude() <= self.radius {
+            return vec![self.centre];
+        }
+
+        // disc facing the point
+        let normal = to_light.norm();
+        let helper = if normal.x.abs() > 0.9 { Tuple::vector(0.0, 1.0, 0.0) } else { Tuple::vector(1.0, 0.0, 0.0) };
+        let u = helper.cross(normal).norm();
+        let v = normal.cross(u);
+
+        let mut rng = self.jitter.rng_for(point);
+        let golden_angle = PI * (3.0 - (5.0 as Float).sqrt());
+
+        (0..self.samples).map(|i| {
+            // evenly spread spiral, or uniformly random when jittered
+            let (r, theta) = match &mut rng {
+                Some(rng) => (rng.next_float().sqrt(), 2.0 * PI * rng.next_float()),
+                None => (((i as Float + 0.5) / self.samples as Float).sqrt(), i as Float * golden_angle),
+            };
+            self.centre + u * (self.radius * r * theta.cos()) + v * (self.radius * r * theta.sin())
+        }).collect()
+    }
+
+    fn saved(&self) -> Option<SavedLight> {
+        Some(SavedLight::Sphere(*self))
+    }
+}
+
+/// Light along the segment from `start` to `end`, like a neon or strip light.
+///
+/// The segment is split into `samples` equal cells, sampled at their centres
+/// or jittered within them like `AreaLight`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedTubeLight")]
+pub struct TubeLight {
+    pub intensity: Colour,
+    pub start: Tuple,
+    pub end: Tuple,
+    pub samples: usize,
+    pub jitter: Jitter,
+}
+
+impl TubeLight {
+    /// Panics if either end isn't a point or `samples` is zero, see `try_new`
+    pub fn new(intensity: Colour, start: Tuple, end: Tuple, samples: usize) -> Self {
+        Self::try_new(intensity, start, end, samples).unwrap()
+    }
+
+    pub fn try_new(intensity: Colour, start: Tuple, end: Tuple, samples: usize) -> Result<Self, RosemaryError> {
+        let light = Self {
+            intensity,
+            start,
+            end,
+            samples,
+            jitter: Jitter::default(),
+        };
+        light.check()?;
+        Ok(light)
+    }
+
+    fn check(&self) -> Result<(), RosemaryError> {
+        point(self.start, "tube light start")?;
+        point(self.end, "tube light end")?;
+        count(self.samples, "tube light samples")
+    }
+
+    /// Point `t` of the way along the tube
+    pub fn point_on_light(&self, t: Float) -> Tuple {
+        self.start + (self.end - self.start) * t
+    }
+}
+
+impl Light for TubeLight {
+    fn intensity(&self) -> Colour {
+        self.intensity
+    }
+
+    fn samples(&self, point: Tuple) -> Vec<Tuple> {
+        let mut rng = self.jitter.rng_for(point);
+
+        (0..self.samples).map(|i| {
+            let offset = match &mut rng {
+                Some(rng) => rng.next_float(),
+                None => 0.5,
+            };
+            self.point_on_light((i as Float + offset) / self.samples as Float)
+        }).collect()
+    }
+
+    fn saved(&self) -> Option<SavedLight> {
+        Some(SavedLight::Tube(*self))
+    }
+}
+
+/// Point light restricted to a cone, fading out between `inner_angle` and `outer_angle` (radians)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedSpotLight")]
+pub struct SpotLight {
+    pub intensity: Colour,
+    pub pos: Tuple,
+    pub direction: Tuple,
+    pub inner_angle: Float,
+    pub outer_angle: Float,
+    /// Distance the light fades out over, `None` lights everything
+    pub range: Option<Float>,
+}
+
+impl SpotLight {
+    /// Panics if `pos` isn't a point, `direction` isn't a vector or the
+    /// inner angle is wider than the outer one, see `try_new`
+    pub fn new(intensity: Colour, pos: Tuple, direction: Tuple, inner_angle: Float, outer_angle: Float) -> Self {
+        Self::try_new(intensity, pos, direction, inner_angle, outer_angle).unwrap()
+    }
+
+    pub fn try_new(intensity: Colour, pos: Tuple, direction: Tuple, inner_angle: Float, outer_angle: Float)
+        -> Result<Self, RosemaryError> {
+        vector(direction, "spot light direction")?;
+        let light = Self {
             intensity,
             pos,
+            direction: direction.norm(),
+            inner_angle,
+            outer_angle,
+            range: None,
+        };
+        light.check()?;
+        Ok(light)
+    }
+
+    fn check(&self) -> Result<(), RosemaryError> {
+        point(self.pos, "spot light position")?;
+        vector(self.direction, "spot light direction")?;
+        if self.inner_angle.partial_cmp(&self.outer_angle).is_none_or(|order| order.is_gt()) {
+            return Err(RosemaryError::SpotAngles);
+        }
+        range(self.range)
+    }
+
+    pub fn with_range(self, range: Float) -> Self {
+        assert!(range > 0.0);
+
+        Self {
+            range: Some(range),
+            ..self
+        }
+    }
+
+    fn falloff(&self, point: Tuple) -> Float {
+        let cos_angle = (point - self.pos).norm().dot(self.direction);
+        let cos_inner = self.inner_angle.cos();
+        let cos_outer = self.outer_angle.cos();
+
+        if cos_angle >= cos_inner {
+            1.0
         }
+        else if cos_angle <= cos_outer {
+            0.0
+        }
+        else {
+            // smoothstep between the two cones
+            let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn intensity(&self) -> Colour {
+        self.intensity
+    }
+
+    fn samples(&self, _point: Tuple) -> Vec<Tuple> {
+        vec![self.pos]
+    }
+
+    fn intensity_at(&self, point: Tuple, world: &World) -> Float {
+        let falloff = self.falloff(point);
+        if falloff == 0.0 || world.is_shadowed(point, self.pos) {
+            return 0.0;
+        }
+
+        falloff
+    }
+
+    fn attenuation(&self, point: Tuple) -> Float {
+        range_attenuation(self.range, (point - self.pos).magnitude())
+    }
+
+    fn saved(&self) -> Option<SavedLight> {
+        Some(SavedLight::Spot(*self))
+    }
+}
+
+/// Light infinitely far away shining along `direction`, like the sun
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedDirectionalLight")]
+pub struct DirectionalLight {
+    pub intensity: Colour,
+    pub direction: Tuple,
+}
+
+impl DirectionalLight {
+    /// Panics if `direction` isn't a vector, see `try_new`
+    pub fn new(intensity: Colour, direction: Tuple) -> Self {
+        Self::try_new(intensity, direction).unwrap()
+    }
+
+    pub fn try_new(intensity: Colour, direction: Tuple) -> Result<Self, RosemaryError> {
+        Ok(Self {
+            intensity,
+            direction: vector(direction, "directional light direction")?.norm(),
+        })
+    }
+
+    fn check(&self) -> Result<(), RosemaryError> {
+        vector(self.direction, "directional light direction").map(|_| ())
+    }
+}
+
+fn point(tuple: Tuple, what: &'static str) -> Result<Tuple, RosemaryError> {
+    if tuple.is_point() { Ok(tuple) } else { Err(RosemaryError::NotAPoint(what)) }
+}
+
+fn vector(tuple: Tuple, what: &'static str) -> Result<Tuple, RosemaryError> {
+    if tuple.is_vector() { Ok(tuple) } else { Err(RosemaryError::NotAVector(what)) }
+}
+
+fn count(count: usize, what: &'static str) -> Result<(), RosemaryError> {
+    if count > 0 { Ok(()) } else { Err(RosemaryError::Zero(what)) }
+}
+
+/// `with_range` only takes ranges above zero
+fn range(range: Option<Float>) -> Result<(), RosemaryError> {
+    match range {
+        Some(range) if range.is_nan() || range <= 0.0 => Err(RosemaryError::Negative("light range")),
+        _ => Ok(()),
+    }
+}
+
+impl Light for DirectionalLight {
+    fn intensity(&self) -> Colour {
+        self.intensity
+    }
+
+    fn samples(&self, _point: Tuple) -> Vec<Tuple> {
+        vec![-self.direction]
+    }
+
+    fn saved(&self) -> Option<SavedLight> {
+        Some(SavedLight::Directional(*self))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{eq, types::{material::Material, sphere::Sphere}, Matrix, EPSILON};
+
     use super::*;
 
     #[test]
@@ -28,5 +627,188 @@ mod tests {
         let light = PointLight::new(col, pos);
         assert_eq!(light.intensity, col);
         assert_eq!(light.pos, pos);
+
+        let dir = Tuple::vector(0.0, -1.0, 0.0);
+        assert_eq!(PointLight::try_new(col, dir), Err(RosemaryError::NotAPoint("point light position")));
+        assert_eq!(AreaLight::try_new(col, pos, dir, 0, dir, 2), Err(RosemaryError::Zero("area light u steps")));
+        assert_eq!(AreaLight::try_new(col, pos, pos, 2, dir, 2), Err(RosemaryError::NotAVector("area light u edge")));
+        assert_eq!(SphereLight::try_new(col, pos, -1.0, 4), Err(RosemaryError::Negative("sphere light radius")));
+        assert_eq!(TubeLight::try_new(col, pos, pos, 0), Err(RosemaryError::Zero("tube light samples")));
+        assert_eq!(SpotLight::try_new(col, pos, dir, 0.5, 0.25), Err(RosemaryError::SpotAngles));
+        assert_eq!(SpotLight::try_new(col, pos, pos, 0.25, 0.5), Err(RosemaryError::NotAVector("spot light direction")));
+        assert_eq!(DirectionalLight::try_new(col, pos), Err(RosemaryError::NotAVector("directional light direction")));
+        assert!(SpotLight::try_new(col, pos, dir, 0.25, 0.5).is_ok());
+    }
+
+    #[test]
+    fn point_intensity_at() {
+        let w = World::default();
+        let light = PointLight::new(Colour::white(), Tuple::point(-10.0, 10.0, -10.0));
+        assert_eq!(light.intensity_at(Tuple::point(0.0, 1.0001, 0.0), &w), 1.0);
+        assert_eq!(light.intensity_at(Tuple::point(-1.0001, 0.0, 0.0), &w), 1.0);
+        assert_eq!(light.intensity_at(Tuple::point(0.0, 0.0, -1.0001), &w), 1.0);
+        assert_eq!(light.intensity_at(Tuple::point(0.0, 0.0, 1.0001), &w), 0.0);
+        assert_eq!(light.intensity_at(Tuple::point(1.0001, 0.0, 0.0), &w), 0.0);
+        assert_eq!(light.intensity_at(Tuple::point(0.0, -1.0001, 0.0), &w), 0.0);
+        assert_eq!(light.intensity_at(Tuple::point(0.0, 0.0, 0.0), &w), 0.0);
+    }
+
+    #[test]
+    fn range_attenuation() {
+        let light = PointLight::new(Colour::white(), Tuple::point(0.0, 0.0, 0.0));
+        assert_eq!(light.attenuation(Tuple::point(100.0, 0.0, 0.0)), 1.0);
+
+        let light = light.with_range(10.0);
+        assert_eq!(light.attenuation(Tuple::point(0.0, 0.0, 0.0)), 1.0);
+        assert!(eq(light.attenuation(Tuple::point(5.0, 0.0, 0.0)), 0.87891));
+        assert_eq!(light.attenuation(Tuple::point(0.0, 10.0, 0.0)), 0.0);
+        assert_eq!(light.attenuation(Tuple::point(0.0, 0.0, 20.0)), 0.0);
+    }
+
+    #[test]
+    fn area_light() {
+        let light = AreaLight::new(Colour::white(), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(2.0, 0.0, 0.0), 4, Tuple::vector(0.0, 0.0, 1.0), 2);
+        assert_eq!(light.uvec, Tuple::vector(0.5, 0.0, 0.0));
+        assert_eq!(light.vvec, Tuple::vector(0.0, 0.0, 0.5));
+        assert_eq!(light.samples(Tuple::point(0.0, 0.0, 0.0)).len(), 8);
+
+        assert_eq!(light.point_on_light(0, 0), Tuple::point(0.25, 0.0, 0.25));
+        assert_eq!(light.point_on_light(1, 0), Tuple::point(0.75, 0.0, 0.25));
+        assert_eq!(light.point_on_light(0, 1), Tuple::point(0.25, 0.0, 0.75));
+        assert_eq!(light.point_on_light(2, 0), Tuple::point(1.25, 0.0, 0.25));
+        assert_eq!(light.point_on_light(3, 1), Tuple::point(1.75, 0.0, 0.75));
+
+        // partially occluded by the default world's spheres
+        let w = World::default();
+        let light = AreaLight::new(Colour::white(), Tuple::point(-0.5, -0.5, -5.0),
+            Tuple::vector(1.0, 0.0, 0.0), 2, Tuple::vector(0.0, 1.0, 0.0), 2);
+        assert_eq!(light.intensity_at(Tuple::point(0.0, 0.0, 2.0), &w), 0.0);
+        assert_eq!(light.intensity_at(Tuple::point(1.0, -1.0, 2.0), &w), 0.25);
+        assert_eq!(light.intensity_at(Tuple::point(1.5, 0.0, 2.0), &w), 0.5);
+        assert_eq!(light.intensity_at(Tuple::point(1.25, 1.25, 3.0), &w), 0.75);
+        assert_eq!(light.intensity_at(Tuple::point(0.0, 0.0, -2.0), &w), 1.0);
+    }
+
+    #[test]
+    fn area_light_quality() {
+        let mut light = AreaLight::new(Colour::white(), Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(2.0, 0.0, 0.0), 4, Tuple::vector(0.0, 0.0, 1.0), 2);
+        light.set_samples(8, 8);
+        assert_eq!(light.uvec, Tuple::vector(0.25, 0.0, 0.0));
+        assert_eq!(light.vvec, Tuple::vector(0.0, 0.0, 0.125));
+
+        let point = Tuple::point(1.0, -2.0, 3.0);
+        let centres = light.samples(point);
+        assert_eq!(centres.len(), 64);
+        assert_eq!(centres[0], Tuple::point(0.125, 0.0, 0.0625));
+
+        // jittered samples stay in their cell and repeat for the same seed and point
+        light.jitter = Jitter::seeded(5);
+        let jittered = light.samples(point);
+        assert_eq!(jittered, light.samples(point));
+        assert_ne!(jittered, centres);
+        assert_ne!(jittered, light.samples(Tuple::point(1.0, -2.0, 3.5)));
+
+        for (jittered, centre) in jittered.iter().zip(&centres) {
+            assert!((jittered.x - centre.x).abs() <= 0.125);
+            assert!((jittered.z - centre.z).abs() <= 0.0625);
+        }
+
+        light.jitter.seed = 6;
+        assert_ne!(jittered, light.samples(point));
+    }
+
+    #[test]
+    fn sphere_light() {
+        let light = SphereLight::new(Colour::white(), Tuple::point(0.0, 5.0, 0.0), 1.0, 16);
+        let point = Tuple::point(0.0, 0.0, 0.0);
+
+        // samples lie on the disc facing the point
+        let samples = light.samples(point);
+        assert_eq!(samples.len(), 16);
+        for sample in &samples {
+            assert!((sample.y - 5.0).abs() < EPSILON);
+            assert!((*sample - light.centre).magnitude() <= 1.0);
+        }
+        assert_eq!(light.samples(Tuple::point(0.0, 5.5, 0.0)), vec![light.centre]);
+
+        // a sphere between the light and floor casts a soft shadow
+        let blocker = Sphere::new(Matrix::scaling(0.5, 0.5, 0.5).translate(0.0, 2.5, 0.0), Material::default());
+        let w = World::new(vec![Box::new(blocker)], vec![]);
+        assert_eq!(light.intensity_at(point, &w), 0.0);
+        let penumbra = light.intensity_at(Tuple::point(0.9, 0.0, 0.0), &w);
+        assert!(penumbra > 0.0 && penumbra < 1.0);
+        assert_eq!(light.intensity_at(Tuple::point(3.0, 0.0, 0.0), &w), 1.0);
+
+        let mut jittered = light;
+        jittered.jitter = Jitter::seeded(1);
+        assert_ne!(jittered.samples(point), samples);
+        assert_eq!(jittered.samples(point), jittered.samples(point));
+    }
+
+    #[test]
+    fn tube_light() {
+        let mut light = TubeLight::new(Colour::white(), Tuple::point(-2.0, 5.0, 0.0), Tuple::point(2.0, 5.0, 0.0), 4);
+        let point = Tuple::point(0.0, 0.0, 0.0);
+        assert_eq!(light.samples(point), vec![Tuple::point(-1.5, 5.0, 0.0), Tuple::point(-0.5, 5.0, 0.0),
+            Tuple::point(0.5, 5.0, 0.0), Tuple::point(1.5, 5.0, 0.0)]);
+
+        // a small blocker shadows part of the tube
+        let blocker = Sphere::new(Matrix::scaling(0.2, 0.2, 0.2).translate(-0.25, 2.5, 0.0), Material::default());
+        let w = World::new(vec![Box::new(blocker)], vec![]);
+        assert_eq!(light.intensity_at(point, &w), 0.75);
+
+        light.jitter = Jitter::seeded(3);
+        let jittered = light.samples(point);
+        assert_eq!(jittered, light.samples(point));
+        for (i, sample) in jittered.iter().enumerate() {
+            assert!(sample.x >= -2.0 + i as Float && sample.x <= -1.0 + i as Float);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn spot_light() {
+        let w = World::new(vec![], vec![]);
+        let light = SpotLight::new(Colour::white(), Tuple::point(0.0, 10.0, 0.0),
+            Tuple::vector(0.0, -1.0, 0.0), 0.2, 0.4);
+
+        assert_eq!(light.intensity_at(Tuple::point(0.0, 0.0, 0.0), &w), 1.0);
+        assert_eq!(light.intensity_at(Tuple::point(10.0, 0.0, 0.0), &w), 0.0);
+
+        let partial = light.intensity_at(Tuple::point(3.0, 0.0, 0.0), &w);
+        assert!(partial > 0.0 && partial < 1.0);
+    }
+
+    #[test]
+    fn directional_light() {
+        let light = DirectionalLight::new(Colour::white(), Tuple::vector(0.0, -2.0, 0.0));
+        let point = Tuple::point(5.0, 0.0, 0.0);
+        assert_eq!(light.samples(point), vec![Tuple::vector(0.0, 1.0, 0.0)]);
+        assert_eq!(light_vector(light.samples(point)[0], point), Tuple::vector(0.0, 1.0, 0.0));
+
+        let w = World::default();
+        assert_eq!(light.intensity_at(Tuple::point(0.0, 1.0001, 0.0), &w), 1.0);
+        assert_eq!(light.intensity_at(Tuple::point(0.0, -1.0001, 0.0), &w), 0.0);
+    }
+
+    #[test]
+    fn serde() {
+        let col = Colour::new(1.0, 1.0, 1.0);
+        let area = AreaLight::new(col, Tuple::point(-1.0, 2.0, 0.0), Tuple::vector(2.0, 0.0, 0.0), 4,
+            Tuple::vector(0.0, 0.0, 2.0), 2);
+        let json = serde_json::to_string(&SavedLight::Area(area)).unwrap();
+        assert_eq!(serde_json::from_str::<SavedLight>(&json).unwrap(), SavedLight::Area(area));
+        let error = serde_json::from_str::<SavedLight>(&json.replace("\"usteps\":4", "\"usteps\":0")).unwrap_err();
+        assert!(error.to_string().contains("area light u steps has to be at least 1"), "{error}");
+
+        let spot = SpotLight::new(col, Tuple::point(0.0, 5.0, 0.0), Tuple::vector(0.0, -1.0, 0.0), 0.2, 0.4);
+        let json = serde_json::to_string(&spot).unwrap();
+        assert_eq!(serde_json::from_str::<SpotLight>(&json).unwrap(), spot);
+        assert!(serde_json::from_str::<SpotLight>(&json.replace("\"inner_angle\":0.2", "\"inner_angle\":0.6")).is_err());
+
+        let point = serde_json::to_string(&PointLight::new(col, Tuple::point(0.0, 1.0, 0.0))).unwrap();
+        assert!(serde_json::from_str::<PointLight>(&point.replace("\"w\":1.0", "\"w\":0.0")).is_err());
+        assert!(serde_json::from_str::<PointLight>(&point.replace("\"range\":null", "\"range\":-1.0")).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use std::{fmt, fs, io, path::Path};
+
+use crate::Float;
+use super::colour::Colour;
+
+/// Colour lookup table loaded from an Adobe/Resolve `.cube` file, used to
+/// give renders a film or camera look before they are written out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut {
+    pub title: Option<String>,
+    pub kind: LutKind,
+    pub size: usize,
+    pub domain_min: Colour,
+    pub domain_max: Colour,
+    /// For 3D tables red changes fastest, then green, then blue
+    table: Vec<Colour>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LutKind {
+    /// Separate curve for each channel
+    OneD,
+    /// Full colour cube, channels can affect each other
+    ThreeD,
+}
+
+#[derive(Debug)]
+pub enum LutError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for LutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "couldn't read LUT: {err}"),
+            Self::Parse { line, message } => write!(f, "invalid LUT on line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for LutError {}
+
+impl From<io::Error> for LutError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl Lut {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LutError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, LutError> {
+        let mut title = None;
+        let mut kind_size = None;
+        let mut domain_min = Colour::black();
+        let mut domain_max = Colour::white();
+        let mut table = Vec::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line_no = i + 1;
+            let error = |message: &str| LutError::Parse { line: line_no, message: message.to_string() };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match keyword {
+                "TITLE" => title = Some(rest.trim_matches('"').to_string()),
+                "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                    if kind_size.is_some() {
+                        return Err(error("LUT size given more than once"));
+                    }
+
+                    let size: usize = rest.parse().map_err(|_| error("size must be a whole number"))?;
+                    if size < 2 {
+                        return Err(error("size must be at least 2"));
+                    }
+
+                    let kind = if keyword == "LUT_1D_SIZE" { LutKind::OneD } else { LutKind::ThreeD };
+                    if kind == LutKind::ThreeD && size.checked_pow(3).is_none() {
+                        return Err(error("size is too large"));
+                    }
+                    kind_size = Some((kind, size));
+                }
+                "DOMAIN_MIN" => domain_min = parse_colour(rest).ok_or_else(|| error("expected 3 numbers"))?,
+                "DOMAIN_MAX" => domain_max = parse_colour(rest).ok_or_else(|| error("expected 3 numbers"))?,
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let range: Vec<Float> = rest.split_whitespace().filter_map(|n| n.parse().ok()).collect();
+                    if range.len() != 2 {
+                        return Err(error("expected 2 numbers"));
+                    }
+                    domain_min = Colour::new(range[0], range[0], range[0]);
+                    domain_max = Colour::new(range[1], range[1], range[1]);
+                }
+                _ => {
+                    let colour = parse_colour(line).ok_or_else(|| error("expected 3 numbers"))?;
+                    table.push(colour);
+                }
+            }
+        }
+
+        let (kind, size) = kind_size.ok_or(LutError::Parse {
+            line: source.lines().count(),
+            message: "missing LUT_1D_SIZE or LUT_3D_SIZE".to_string(),
+        })?;
+
+        let expected = match kind {
+            LutKind::OneD => size,
+            // can't overflow, as checked when the size was read
+            LutKind::ThreeD => size.pow(3),
+        };
+
+        if table.len() != expected {
+            return Err(LutError::Parse {
+                line: source.lines().count(),
+                message: format!("expected {expected} entries but found {}", table.len()),
+            });
+        }
+
+        Ok(Self {
+            title,
+            kind,
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    pub fn apply(&self, colour: Colour) -> Colour {
+        // position of each channel in table cells
+        let scale = (self.size - 1) as Float;
+        let r = normalise(colour.r, self.domain_min.r, self.domain_max.r) * scale;
+        let g = normalise(colour.g, self.domain_min.g, self.domain_max.g) * scale;
+        let b = normalise(colour.b, self.domain_min.b, self.domain_max.b) * scale;
+
+        match self.kind {
+            LutKind::OneD => Colour::new(
+                self.lookup_1d(r, |c| c.r),
+                self.lookup_1d(g, |c| c.g),
+                self.lookup_1d(b, |c| c.b),
+            ),
+            LutKind::ThreeD => self.lookup_3d(r, g, b),
+        }
+    }
+
+    fn lookup_1d(&self, pos: Float, channel: fn(&Colour) -> Float) -> Float {
+        let (i, t) = split(pos, self.size);
+        lerp(channel(&self.table[i]), channel(&self.table[i + 1]), t)
+    }
+
+    fn lookup_3d(&self, r: Float, g: Float, b: Float) -> Colour {
+        let (ri, rt) = split(r, self.size);
+        let (gi, gt) = split(g, self.size);
+        let (bi, bt) = split(b, self.size);
+        let at = |r: usize, g: usize, b: usize| self.table[r + g * self.size + b * self.size * self.size];
+
+        // trilinear interpolation between the 8 surrounding entries
+        let c00 = lerp_colour(at(ri, gi, bi), at(ri + 1, gi, bi), rt);
+        let c10 = lerp_colour(at(ri, gi + 1, bi), at(ri + 1, gi + 1, bi), rt);
+        let c01 = lerp_colour(at(ri, gi, bi + 1), at(ri + 1, gi, bi + 1), rt);
+        let c11 = lerp_colour(at(ri, gi + 1, bi + 1), at(ri + 1, gi + 1, bi + 1), rt);
+
+        lerp_colour(lerp_colour(c00, c10, gt), lerp_colour(c01, c11, gt), bt)
+    }
+}
+
+fn parse_colour(s: &str) -> Option<Colour> {
+    let values: Vec<Float> = s.split_whitespace().map(|n| n.parse().ok()).collect::<Option<_>>()?;
+    match values[..] {
+        [r, g, b] => Some(Colour::new(r, g, b)),
+        _ => None,
+    }
+}
+
+fn normalise(value: Float, min: Float, max: Float) -> Float {
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+/// Index of the cell below `pos` and how far into the cell it is
+fn split(pos: Float, size: usize) -> (usize, Float) {
+    let i = (pos.floor() as usize).min(size - 2);
+    (i, pos - i as Float)
+}
+
+fn lerp(a: Float, b: Float, t: Float) -> Float {
+    a + (b - a) * t
+}
+
+fn lerp_colour(a: Colour, b: Colour, t: Float) -> Colour {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY_3D: &str = "# identity\n\
+        TITLE \"Identity\"\n\
+        LUT_3D_SIZE 2\n\
+        \n\
+        0 0 0\n1 0 0\n0 1 0\n1 1 0\n\
+        0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+
+    #[test]
+    fn parse() {
+        let lut = Lut::parse(IDENTITY_3D).unwrap();
+        assert_eq!(lut.title.as_deref(), Some("Identity"));
+        assert_eq!(lut.kind, LutKind::ThreeD);
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.domain_min, Colour::black());
+        assert_eq!(lut.domain_max, Colour::white());
+        assert_eq!(lut.table.len(), 8);
+
+        let lut = Lut::parse("LUT_1D_SIZE 3\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n0 0 0\n0.5 0.5 0.5\n1 1 1").unwrap();
+        assert_eq!(lut.kind, LutKind::OneD);
+        assert_eq!(lut.domain_max, Colour::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(Lut::parse("0 0 0\n1 1 1"), Err(LutError::Parse { .. })));
+        assert!(matches!(Lut::parse("LUT_1D_SIZE 2\n0 0 0"), Err(LutError::Parse { line: 2, .. })));
+        assert!(matches!(Lut::parse("LUT_1D_SIZE 2\n0 0\n1 1 1"), Err(LutError::Parse { line: 2, .. })));
+        assert!(matches!(Lut::parse("LUT_3D_SIZE x"), Err(LutError::Parse { line: 1, .. })));
+        let huge = format!("TITLE \"huge\"\nLUT_3D_SIZE {}\n0 0 0", usize::MAX / 2);
+        assert!(matches!(Lut::parse(&huge), Err(LutError::Parse { line: 2, message }) if message == "size is too large"));
+        assert!(matches!(Lut::from_file("does/not/exist.cube"), Err(LutError::Io(_))));
+    }
+
+    #[test]
+    fn apply_1d() {
+        // squares each channel
+        let lut = Lut::parse("LUT_1D_SIZE 3\n0 0 0\n0.25 0.25 0.25\n1 1 1").unwrap();
+        assert_eq!(lut.apply(Colour::new(0.0, 0.5, 1.0)), Colour::new(0.0, 0.25, 1.0));
+        assert_eq!(lut.apply(Colour::new(0.25, 0.75, 2.0)), Colour::new(0.125, 0.625, 1.0));
+        assert_eq!(lut.apply(Colour::new(-1.0, 0.0, 0.0)), Colour::black());
+    }
+
+    #[test]
+    fn apply_3d() {
+        let lut = Lut::parse(IDENTITY_3D).unwrap();
+        let colour = Colour::new(0.2, 0.5, 0.9);
+        assert_eq!(lut.apply(colour), colour);
+
+        // swaps red and blue
+        let swap = "LUT_3D_SIZE 2\n\
+            0 0 0\n0 0 1\n0 1 0\n0 1 1\n\
+            1 0 0\n1 0 1\n1 1 0\n1 1 1\n";
+        let lut = Lut::parse(swap).unwrap();
+        assert_eq!(lut.apply(colour), Colour::new(0.9, 0.5, 0.2));
+    }
+}
@@ -1,49 +1,84 @@
-use crate::{types::colour::Colour, Tuple};
+use serde::{Deserialize, Serialize};
 
-use super::light::PointLight;
+use crate::{Float, types::colour::Colour, Tuple};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use super::light::{light_vector, Light};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Material {
     pub colour: Colour,
-    pub ambient: f32,
-    pub diffuse: f32,
-    pub specular: f32,
-    pub shininess: f32,
+    pub ambient: Float,
+    pub diffuse: Float,
+    pub specular: Float,
+    pub shininess: Float,
+    /// 0.0 is opaque, 1.0 lets all light through
+    pub transparency: Float,
+    /// Whether the object blocks light from reaching other objects
+    pub casts_shadow: bool,
+    /// Render as a black, transparent cutout that still occludes and catches
+    /// shadows, for compositing over live action plates
+    pub holdout: bool,
 }
 
 impl Material {
-    pub fn lighting(&self, pos: Tuple, light: PointLight, eye: Tuple, norm: Tuple) -> Colour {
+    /// Phong lighting averaged over the light's samples, with `intensity` the
+    /// fraction of the light reaching `pos` (see `Light::intensity_at`)
+    pub fn lighting(&self, pos: Tuple, light: &dyn Light, eye: Tuple, norm: Tuple, intensity: Float) -> Colour {
+        self.ambient_lighting(light) + self.direct_lighting(pos, light, eye, norm, intensity)
+    }
+
+    /// Ambient part of `lighting`, which doesn't depend on where the light is
+    pub fn ambient_lighting(&self, light: &dyn Light) -> Colour {
+        self.colour * light.intensity() * self.ambient
+    }
+
+    /// Diffuse and specular parts of `lighting`
+    pub fn direct_lighting(&self, pos: Tuple, light: &dyn Light, eye: Tuple, norm: Tuple, intensity: Float) -> Colour {
         assert!(pos.is_point());
         assert!(eye.is_vector());
         assert!(norm.is_vector());
 
-        // combine material + light colours
-        let col = self.colour * light.intensity;
-        let light_vec = (light.pos - pos).norm(); // direction to light source
-        
-        let ambient = col * self.ambient;
-        let light_dot_norm = light_vec * norm ; // dot of light vec and norm is cos of their angles
-        
-        // neg means light behind surface as it is cos
-        if light_dot_norm < 0.0 {
-            // as light is behind, no specular or diffuse so only ambient does stuff
-            return ambient;
+        // fully shadowed so no direct light
+        if intensity == 0.0 {
+            return Colour::black();
         }
 
-        let diffuse = col * self.diffuse * light_dot_norm;
-        let mut specular = Colour::black();
+        // combine material + light colours
+        let col = self.colour * light.intensity();
+
+        let samples = light.samples(pos);
+        let mut sum = Colour::black();
+
+        for sample in &samples {
+            let light_vec = light_vector(*sample, pos); // direction to light source
+            let light_dot_norm = light_vec * norm; // dot of light vec and norm is cos of their angles
+
+            // neg means light behind surface as it is cos
+            if light_dot_norm < 0.0 {
+                // as light is behind, no specular or diffuse from this sample
+                continue;
+            }
+
+            sum += col * self.diffuse * light_dot_norm;
 
-        let reflect_vec = -light_vec.reflect(norm);
-        let reflect_dot_eye = reflect_vec * eye; // same drill, cos of angles
-        
-        // if neg, then light reflects away from eye so no specular
-        if reflect_dot_eye > 0.0 {
-            let factor = reflect_dot_eye.powf(self.shininess);
-            specular = light.intensity * self.specular * factor;
+            let reflect_vec = -light_vec.reflect(norm);
+            let reflect_dot_eye = reflect_vec * eye; // same drill, cos of angles
+
+            // if neg, then light reflects away from eye so no specular
+            if reflect_dot_eye > 0.0 {
+                let factor = reflect_dot_eye.powf(self.shininess);
+                sum += light.intensity() * self.specular * factor;
+            }
         }
 
-        // final result is a combination of the 3
-        ambient + diffuse + specular
+        sum * (intensity / samples.len() as Float)
+    }
+}
+
+impl Material {
+    /// Whether shadow rays should stop at this material
+    pub fn occludes(&self) -> bool {
+        self.casts_shadow && self.transparency < 1.0
     }
 }
 
@@ -54,16 +89,20 @@ impl Default for Material {
             ambient: 0.1,
             diffuse: 0.9,
             specular: 0.9,
-            shininess: 200.0
+            shininess: 200.0,
+            transparency: 0.0,
+            casts_shadow: true,
+            holdout: false,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::f32::consts::SQRT_2;
+    use crate::consts::SQRT_2;
 
     use super::*;
+    use crate::types::light::PointLight;
 
     #[test]
     fn default() {
@@ -73,6 +112,9 @@ mod tests {
         assert_eq!(material.diffuse, 0.9);
         assert_eq!(material.specular, 0.9);
         assert_eq!(material.shininess, 200.0);
+        assert_eq!(material.transparency, 0.0);
+        assert!(material.casts_shadow);
+        assert!(!material.holdout);
     }
 
     #[test]
@@ -86,7 +128,7 @@ mod tests {
         let eye = Tuple::vector(0.0, 0.0, -1.0);
         let norm = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(col, Tuple::point(0.0, 0.0, -10.0));
-        let result = material.lighting(pos, light, eye, norm);
+        let result = material.lighting(pos, &light, eye, norm, 1.0);
         assert_eq!(result, Colour::new(1.9, 1.9, 1.9));
 
         // Eye between light and surface at 45deg angle off norm
@@ -95,7 +137,7 @@ mod tests {
         let eye = Tuple::vector(0.0, SQRT_2 / 2.0, -SQRT_2 / 2.0);
         let norm = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(col, Tuple::point(0.0, 0.0, -10.0));
-        let result = material.lighting(pos, light, eye, norm);
+        let result = material.lighting(pos, &light, eye, norm, 1.0);
         assert_eq!(result, Colour::new(1.0, 1.0, 1.0));
 
         // Eye directly opposite surface with light at 45deg angle off norm
@@ -104,7 +146,7 @@ mod tests {
         let eye = Tuple::vector(0.0, 0.0, -1.0);
         let norm = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(col, Tuple::point(0.0, 10.0, -10.0));
-        let result = material.lighting(pos, light, eye, norm);
+        let result = material.lighting(pos, &light, eye, norm, 1.0);
         assert_eq!(result, Colour::new(0.7364, 0.7364, 0.7364));
 
         // Light at 45deg angle off norm and eye directly in reflection path
@@ -112,7 +154,7 @@ mod tests {
         let eye = Tuple::vector(0.0, -SQRT_2 / 2.0, -SQRT_2 / 2.0);
         let norm = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(col, Tuple::point(0.0, 10.0, -10.0));
-        let result = material.lighting(pos, light, eye, norm);
+        let result = material.lighting(pos, &light, eye, norm, 1.0);
         assert_eq!(result, Colour::new(1.6364, 1.6364, 1.6364));
 
         // Light behind surface
@@ -121,8 +163,15 @@ mod tests {
         let eye = Tuple::vector(0.0, 0.0, -1.0);
         let norm = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(col, Tuple::point(0.0, 0.0, 10.0));
-        let result = material.lighting(pos, light, eye, norm);
+        let result = material.lighting(pos, &light, eye, norm, 1.0);
         assert_eq!(result, Colour::new(0.1, 0.1, 0.1));
 
+        // Surface in shadow
+        // Only ambient (0.1 + 0 + 0 = 0.1)
+        let eye = Tuple::vector(0.0, 0.0, -1.0);
+        let norm = Tuple::vector(0.0, 0.0, -1.0);
+        let light = PointLight::new(col, Tuple::point(0.0, 0.0, -10.0));
+        let result = material.lighting(pos, &light, eye, norm, 0.0);
+        assert_eq!(result, Colour::new(0.1, 0.1, 0.1));
     }
 }
\ No newline at end of file
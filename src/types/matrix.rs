@@ -1,16 +1,13 @@
-use core::panic;
-use std::ops::{Index, IndexMut, Mul};
-use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
-use crate::{eq, Tuple};
+use std::{array, ops::{Index, IndexMut, Mul}};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::{Float, eq, RosemaryError, Tuple};
 
-const SUBMATRIX_INDICES_3X3: [[usize; 4]; 9] = [[4, 5, 7, 8], [3, 5, 6, 8], [3, 4, 6, 7],
-    [1, 2, 7, 8], [0, 2, 6, 8], [3, 4, 6, 7], [1, 2, 4, 5], [0, 2, 3, 5], [0, 1, 3, 4]];
-
-const SUBMATRIX_INDICES_4X4: [[usize; 9]; 16] = [[5, 6, 7, 9, 10, 11, 13, 14, 15], [4, 6, 7, 8, 10, 11, 12, 14, 15],
-    [4, 5, 7, 8, 9, 11, 12, 13, 15], [4, 5, 6, 8, 9, 10, 12, 13, 14], [1, 2, 3, 9, 10, 11, 13, 14, 15],[0, 2, 3, 8, 10, 11, 12, 14, 15],
-    [0, 1, 3, 8, 9, 11, 12, 13, 15], [0, 1, 2, 8, 9, 10, 12, 13, 14], [1, 2, 3, 5, 6, 7, 13, 14, 15], [0, 2, 3, 4, 6, 7, 12, 14, 15],
-    [0, 1, 3, 4, 5, 7, 12, 13, 15], [0, 1, 2, 4, 5, 6, 12, 13, 14], [1, 2, 3, 5, 6, 7, 9, 10, 11], [0, 2, 3, 4, 6, 7, 8, 10, 11],
-    [0, 1, 3, 4, 5, 7, 8, 9, 11], [0, 1, 2, 4, 5, 6, 8, 9, 10]];
+#[cfg(all(not(feature = "f64"), target_arch = "x86_64"))]
+mod avx2;
+#[cfg(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"))]
+mod simd;
+#[cfg(all(not(feature = "f64"), target_arch = "x86_64"))]
+use super::cpu::{simd_level, SimdLevel};
 
 pub enum Axis {
     X,
@@ -18,128 +15,130 @@ pub enum Axis {
     Z
 }
 
+/// Square matrix of `N` rows, 4x4 unless said otherwise as that's what
+/// transforms need. The smaller sizes are only there for cofactors
 #[derive(Debug, Clone, Copy)]
-pub struct Matrix {
-    values: [f32; 16],
-    size: usize,
+pub struct Matrix<const N: usize = 4> {
+    values: [[Float; N]; N],
 }
 
-impl Matrix {
-    pub fn new_4x4(values: [f32; 16]) -> Self {
+impl<const N: usize> Matrix<N> {
+    pub fn new(values: [[Float; N]; N]) -> Self {
         Self {
-            values,
-            size: 4
+            values
         }
     }
 
-    pub fn new_3x3(values: [f32; 9]) -> Self {
-        let mut padded_values = [0.0; 16];
-        padded_values[..9].copy_from_slice(&values);
+    pub fn zero() -> Self {
+        Self::new([[0.0; N]; N])
+    }
 
-        Self {
-            values: padded_values,
-            size: 3
-        }
+    /// Values given row by row
+    fn from_flat(values: &[Float]) -> Self {
+        assert_eq!(values.len(), N * N);
+        Self::new(array::from_fn(|row| array::from_fn(|col| values[row * N + col])))
     }
 
-    pub fn new_2x2(values: [f32; 4]) -> Self {
-        let mut padded_values = [0.0; 16];
-        padded_values[..4].copy_from_slice(&values);
+    pub fn transpose(&self) -> Self {
+        Self::new(array::from_fn(|row| array::from_fn(|col| self.values[col][row])))
+    }
 
-        Self {
-            values: padded_values,
-            size: 2
-        }
+    /// Copy without `row` and `col`. `M` has to be `N - 1`, which can't be
+    /// written as a bound yet, so each size has its own `submatrix` for this
+    fn without<const M: usize>(&self, row: usize, col: usize) -> Matrix<M> {
+        debug_assert_eq!(M + 1, N);
+        Matrix::new(array::from_fn(|r| array::from_fn(|c| {
+            self.values[r + usize::from(r >= row)][c + usize::from(c >= col)]
+        })))
     }
+}
 
-    pub fn default(size: usize) -> Self {
-        Self {
-            values: [0.0; 16],
-            size
-        }
+impl Matrix<2> {
+    pub fn new_2x2(values: [Float; 4]) -> Self {
+        Self::from_flat(&values)
     }
 
-    pub fn transpose(&self) -> Self {
-        Self::new_4x4([
-            self.values[0], self.values[4], self.values[8], self.values[12],
-            self.values[1], self.values[5], self.values[9], self.values[13],
-            self.values[2], self.values[6], self.values[10], self.values[14],
-            self.values[3], self.values[7], self.values[11], self.values[15]
-        ])
+    pub fn determinant(&self) -> Float {
+        self.values[0][0] * self.values[1][1] - self.values[0][1] * self.values[1][0]
     }
+}
 
-    pub fn determinant(&self) -> f32 {
-        if self.size == 2 {
-            return self.values[0] * self.values[3] - self.values[1] * self.values[2];
-        }
+impl Matrix<3> {
+    pub fn new_3x3(values: [Float; 9]) -> Self {
+        Self::from_flat(&values)
+    }
+}
 
-        let mut result = 0.0;
-        for i in 0..self.size {
-            result += self.values[i] * self.cofactor(0, i);
-        }
+/// Determinants by cofactor expansion along the first row, for sizes whose
+/// submatrices already have a `determinant`
+macro_rules! cofactor_expansion {
+    ($n:literal, $sub:literal) => {
+        impl Matrix<$n> {
+            pub fn submatrix(&self, row: usize, col: usize) -> Matrix<$sub> {
+                self.without(row, col)
+            }
 
-        result
-    }
+            pub fn minor(&self, row: usize, col: usize) -> Float {
+                self.submatrix(row, col).determinant()
+            }
 
-    pub fn submatrix(&self, row: usize, col: usize) -> Self {
-        if self.size == 4 {
-            let keep = SUBMATRIX_INDICES_4X4[row * 4 + col];
-            Self::new_3x3([
-                self.values[keep[0]], self.values[keep[1]], self.values[keep[2]],
-                self.values[keep[3]],self.values[keep[4]], self.values[keep[5]],
-                self.values[keep[6]], self.values[keep[7]],self.values[keep[8]]
-            ])
+            pub fn cofactor(&self, row: usize, col: usize) -> Float {
+                if (row + col).is_multiple_of(2) {
+                    self.minor(row, col)
+                }
+                else {
+                    -self.minor(row, col)
+                }
+            }
 
+            pub fn determinant(&self) -> Float {
+                let mut result = 0.0;
+                for col in 0..$n {
+                    result += self.values[0][col] * self.cofactor(0, col);
+                }
+
+                result
+            }
         }
-        else if self.size == 3 {
-            let keep = SUBMATRIX_INDICES_3X3[row * 3 + col];
-            Self::new_2x2([self.values[keep[0]], self.values[keep[1]], self.values[keep[2]], self.values[keep[3]]])
-        }
-        else {
-            panic!("{} sized matrixes don't support submatrix operations", self.size);
-        }
-    }
+    };
+}
 
-    pub fn minor(&self, row: usize, col: usize) -> f32 {
-        self.submatrix(row, col).determinant()
-    }
+cofactor_expansion!(3, 2);
+cofactor_expansion!(4, 3);
 
-    //#[inline]
-    pub fn cofactor(&self, row: usize, col: usize) -> f32 {
-        if (row + col) % 2 == 0 {
-            self.minor(row, col)
-        }
-        else {
-            -self.minor(row, col)
-        }
+impl Matrix<4> {
+    pub fn new_4x4(values: [Float; 16]) -> Self {
+        Self::from_flat(&values)
     }
 
+    /// Whether `inverse` gives a matrix. Asks it rather than checking
+    /// `determinant`, which the simd build doesn't work out the same way
     pub fn invertible(&self) -> bool {
-        self.determinant() != 0.0
+        self.inverse().is_some()
     }
 
     pub fn inverse(&self) -> Option<Self> {
-        if !self.invertible() {
-            return None;
-        }
-
-        let mut result = Self::default(4);
-        let determinant = self.determinant();
-
-        for row in 0..self.size {
-            for col in 0..self.size {
-                let cofactor = self.cofactor(row, col);
-                result[(col, row)] = cofactor / determinant;
+        #[cfg(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"))]
+        // SAFETY: SSE is part of the x86_64 baseline
+        return unsafe { simd::inverse(self.values.as_flattened().try_into().unwrap()) }.map(Self::new_4x4);
+
+        #[cfg(not(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64")))]
+        {
+            // every cofactor is needed anyway, so expand the determinant along
+            // the first row from them rather than working those out again
+            let cofactors: [[Float; 4]; 4] = array::from_fn(|row| array::from_fn(|col| self.cofactor(row, col)));
+            let determinant = self.values[0].iter()
+                .zip(cofactors[0])
+                .fold(0.0, |sum, (value, cofactor)| sum + value * cofactor);
+            if determinant == 0.0 {
+                return None;
             }
-        }
-        Some(result)
-    }
 
-    pub fn identity(size: usize) -> Self {
-        if size != 4 {
-            panic!("Only 4x4 identity matrices supported");
+            Some(Self::new(array::from_fn(|row| array::from_fn(|col| cofactors[col][row] / determinant))))
         }
+    }
 
+    pub fn identity() -> Self {
         Self::new_4x4([
             1.0, 0.0, 0.0, 0.0,
             0.0, 1.0, 0.0, 0.0,
@@ -148,7 +147,7 @@ impl Matrix {
         ])
     }
 
-    pub fn translation(x: f32, y: f32, z: f32) -> Self {
+    pub fn translation(x: Float, y: Float, z: Float) -> Self {
         Self::new_4x4([
             1.0, 0.0, 0.0, x,
             0.0, 1.0, 0.0, y,
@@ -157,11 +156,11 @@ impl Matrix {
         ])
     }
 
-    pub fn translate(&self, x: f32, y: f32, z: f32) -> Self {
+    pub fn translate(&self, x: Float, y: Float, z: Float) -> Self {
         Self::translation(x, y, z) * *self
     }
 
-    pub fn scaling(x: f32, y: f32, z: f32) -> Self {
+    pub fn scaling(x: Float, y: Float, z: Float) -> Self {
         Self::new_4x4([
             x, 0.0, 0.0, 0.0,
             0.0, y, 0.0, 0.0,
@@ -170,11 +169,11 @@ impl Matrix {
         ])
     }
 
-    pub fn scale(&self, x: f32, y: f32, z: f32) -> Self {
+    pub fn scale(&self, x: Float, y: Float, z: Float) -> Self {
         Self::scaling(x, y, z) * *self
     }
 
-    pub fn rotation_x(ang: f32) -> Self {
+    pub fn rotation_x(ang: Float) -> Self {
         Self::new_4x4([
             1.0, 0.0,       0.0,        0.0,
             0.0, ang.cos(), -ang.sin(), 0.0,
@@ -183,7 +182,7 @@ impl Matrix {
         ])    
     }
 
-    pub fn rotation_y(ang: f32) -> Self {
+    pub fn rotation_y(ang: Float) -> Self {
         Self::new_4x4([
             ang.cos(),  0.0, ang.sin(), 0.0,
             0.0,        1.0, 0.0,       0.0,
@@ -192,7 +191,7 @@ impl Matrix {
         ])
     }
 
-    pub fn rotation_z(ang: f32) -> Self {
+    pub fn rotation_z(ang: Float) -> Self {
         Self::new_4x4([
             ang.cos(), -ang.sin(), 0.0, 0.0,
             ang.sin(), ang.cos(),  0.0, 0.0,
@@ -201,7 +200,7 @@ impl Matrix {
         ])
     }
 
-    pub fn rotation(axis: Axis, ang: f32) -> Self {
+    pub fn rotation(axis: Axis, ang: Float) -> Self {
         match axis {
             Axis::X => Self::rotation_x(ang),
             Axis::Y => Self::rotation_y(ang),
@@ -209,23 +208,23 @@ impl Matrix {
         }
     }
 
-    pub fn rotate_x(&self, ang: f32) -> Self {
+    pub fn rotate_x(&self, ang: Float) -> Self {
         Self::rotation_x(ang) * *self
     }
 
-    pub fn rotate_y(&self, ang: f32) -> Self {
+    pub fn rotate_y(&self, ang: Float) -> Self {
         Self::rotation_y(ang) * *self
     }
 
-    pub fn rotate_z(&self, ang: f32) -> Self {
+    pub fn rotate_z(&self, ang: Float) -> Self {
         Self::rotation_z(ang) * *self
     }
 
-    pub fn rotate(&self, axis: Axis, ang: f32) -> Self {
+    pub fn rotate(&self, axis: Axis, ang: Float) -> Self {
         Self::rotation(axis, ang) * *self
     }
 
-    pub fn shearing(xy: f32, xz: f32, yx: f32, yz: f32, zx: f32, zy: f32) -> Self {
+    pub fn shearing(xy: Float, xz: Float, yx: Float, yz: Float, zx: Float, zy: Float) -> Self {
         Self::new_4x4([
             1.0, xy, xz, 0.0,
             yx, 1.0, yz, 0.0,
@@ -234,44 +233,62 @@ impl Matrix {
         ])
     }
 
-    pub fn shear(&self, xy: f32, xz: f32, yx: f32, yz: f32, zx: f32, zy: f32) -> Self {
+    pub fn shear(&self, xy: Float, xz: Float, yx: Float, yz: Float, zx: Float, zy: Float) -> Self {
         Self::shearing(xy, xz, yx, yz, zx, zy) * *self
     }
+
+    /// Transform that orients the world relative to an eye at `from` looking at `to`
+    pub fn view_transform(from: Tuple, to: Tuple, up: Tuple) -> Self {
+        let forward = (to - from).norm();
+        let left = forward.cross(up.norm());
+        let true_up = left.cross(forward);
+
+        let orientation = Self::new_4x4([
+            left.x,     left.y,     left.z,     0.0,
+            true_up.x,  true_up.y,  true_up.z,  0.0,
+            -forward.x, -forward.y, -forward.z, 0.0,
+            0.0,        0.0,        0.0,        1.0
+        ]);
+
+        orientation * Self::translation(-from.x, -from.y, -from.z)
+    }
 }
 
-impl PartialEq for Matrix {
+impl<const N: usize> PartialEq for Matrix<N> {
     fn eq(&self, other: &Self) -> bool {
-        if self.size != other.size {
-            return false;
-        }
+        self.values.as_flattened().iter()
+            .zip(other.values.as_flattened())
+            .all(|(a, b)| eq(*a, *b))
+    }
+}
 
-        for (a, b) in self.values.iter().zip(other.values.iter()) {
-            if !eq(*a, *b) {
-                return false;
-            }
-        }
+impl<const N: usize> Mul for Matrix<N> {
+    type Output = Self;
 
-        true
+    fn mul(self, other: Self) -> Self::Output {
+        Mul::mul(&self, &other)
     }
 }
 
-impl Mul<Matrix> for Matrix {
-    type Output = Matrix;
-
-    fn mul(self, other: Matrix) -> Self::Output {
-        let mut result = Matrix::default(self.size);
+impl<const N: usize> Mul for &Matrix<N> {
+    type Output = Matrix<N>;
 
-        for row in 0..self.size {
-            for col in 0..self.size {
-                result[(row, col)] =
-                    self[(row, 0)] * other[(0, col)] + 
-                    self[(row, 1)] * other[(1, col)] + 
-                    self[(row, 2)] * other[(2, col)] + 
-                    self[(row, 3)] * other[(3, col)]
+    fn mul(self, other: Self) -> Self::Output {
+        #[cfg(all(not(feature = "f64"), target_arch = "x86_64"))]
+        if N == 4 {
+            let (a, b) = (self.values.as_flattened().try_into().unwrap(), other.values.as_flattened().try_into().unwrap());
+            if simd_level() == SimdLevel::Avx2 {
+                // SAFETY: the CPU has just been checked for AVX2
+                return Matrix::from_flat(&unsafe { avx2::mul(a, b) });
             }
+            #[cfg(feature = "simd")]
+            // SAFETY: SSE is part of the x86_64 baseline
+            return Matrix::from_flat(&unsafe { simd::mul(a, b) });
         }
 
-        result
+        Matrix::new(array::from_fn(|row| array::from_fn(|col| {
+            (0..N).map(|i| self.values[row][i] * other.values[i][col]).sum()
+        })))
     }
 }
 
@@ -279,35 +296,115 @@ impl Mul<Tuple> for Matrix {
     type Output = Tuple;
 
     fn mul(self, other: Tuple) -> Self::Output {
-        let x = self[(0, 0)] * other.x + self[(0, 1)] * other.y + self[(0, 2)] * other.z + self[(0, 3)] * other.w;
-        let y = self[(1, 0)] * other.x + self[(1, 1)] * other.y + self[(1, 2)] * other.z + self[(1, 3)] * other.w;
-        let z = self[(2, 0)] * other.x + self[(2, 1)] * other.y + self[(2, 2)] * other.z + self[(2, 3)] * other.w;
-        let w = self[(3, 0)] * other.x + self[(3, 1)] * other.y + self[(3, 2)] * other.z + self[(3, 3)] * other.w;
+        &self * other
+    }
+}
+
+impl Mul<Tuple> for &Matrix {
+    type Output = Tuple;
+
+    fn mul(self, other: Tuple) -> Self::Output {
+        let tuple = [other.x, other.y, other.z, other.w];
+
+        #[cfg(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64"))]
+        // SAFETY: SSE is part of the x86_64 baseline
+        let [x, y, z, w] = unsafe { simd::mul_tuple(self.values.as_flattened().try_into().unwrap(), tuple) };
+
+        #[cfg(not(all(feature = "simd", not(feature = "f64"), target_arch = "x86_64")))]
+        let [x, y, z, w] = self.values.map(|row| row[0] * tuple[0] + row[1] * tuple[1] + row[2] * tuple[2] + row[3] * tuple[3]);
+
         Tuple::new(x, y, z, w)
     }
 }
 
-impl Index<(usize, usize)> for Matrix {
-    type Output = f32;
+/// Saved as its rows
+impl Serialize for Matrix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.values.serialize(serializer)
+    }
+}
 
-    fn index(&self, index: (usize, usize)) -> &Self::Output {
-        let index = index.1 + index.0 * self.size;
-        &self.values[index]
+impl<'de> Deserialize<'de> for Matrix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::new(Deserialize::deserialize(deserializer)?))
     }
 }
 
-impl IndexMut<(usize, usize)> for Matrix {
-    fn index_mut(&mut self, index: (usize, usize)) -> &mut Self::Output {
-        let index = index.1 + index.0 * self.size;
-        &mut self.values[index]
+impl<const N: usize> Index<(usize, usize)> for Matrix<N> {
+    type Output = Float;
+
+    fn index(&self, (row, col): (usize, usize)) -> &Self::Output {
+        &self.values[row][col]
+    }
+}
+
+impl<const N: usize> IndexMut<(usize, usize)> for Matrix<N> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut Self::Output {
+        &mut self.values[row][col]
+    }
+}
+
+/// A transform with its inverse and normal matrix worked out once up front,
+/// so shapes and cameras can hand out references to all three instead of
+/// copying matrices around the render loop
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Matrix", into = "Matrix")]
+pub struct Transform {
+    matrix: Matrix,
+    inverse: Matrix,
+    normal: Matrix,
+}
+
+impl Transform {
+    /// Panics if `matrix` can't be inverted, see `try_from` for a `Result`
+    pub fn new(matrix: Matrix) -> Self {
+        Self::try_from(matrix).unwrap()
+    }
+
+    pub fn matrix(&self) -> &Matrix {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> &Matrix {
+        &self.inverse
+    }
+
+    /// Inverse transpose, taking normals to world space
+    pub fn normal(&self) -> &Matrix {
+        &self.normal
+    }
+}
+
+impl TryFrom<Matrix> for Transform {
+    type Error = RosemaryError;
+
+    fn try_from(matrix: Matrix) -> Result<Self, Self::Error> {
+        let inverse = matrix.inverse().ok_or(RosemaryError::NotInvertible)?;
+        Ok(Self {
+            matrix,
+            inverse,
+            normal: inverse.transpose(),
+        })
+    }
+}
+
+impl From<Transform> for Matrix {
+    fn from(transform: Transform) -> Self {
+        transform.matrix
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new(Matrix::identity())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
+    use crate::consts::{FRAC_PI_2, FRAC_PI_4, SQRT_2};
     use crate::Tuple;
-    use super::{Matrix, Axis};
+    use super::{Matrix, Axis, Transform};
 
     #[test]
     fn new() {
@@ -339,6 +436,7 @@ mod tests {
         assert_eq!(matrix[(0, 1)], 5.0);
         assert_eq!(matrix[(1, 0)], 1.0);
         assert_eq!(matrix[(1, 1)], -2.0);
+        assert_eq!(Matrix::new([[-3.0, 5.0], [1.0, -2.0]]), matrix);
 
         let matrix = Matrix::new_3x3(
             [
@@ -451,8 +549,8 @@ mod tests {
             ]
         );
         let tuple = Tuple::new(1.0, 2.0, 3.0, 4.0);
-        assert_eq!(Matrix::identity(4) * matrix, matrix);
-        assert_eq!(Matrix::identity(4) * tuple, tuple);
+        assert_eq!(Matrix::identity() * matrix, matrix);
+        assert_eq!(Matrix::identity() * tuple, tuple);
     }
 
     #[test]
@@ -671,7 +769,7 @@ mod tests {
         let transformation = translation * scale * rot;
         assert_eq!(transformation * p, Tuple::point(15.0, 0.0, 7.0));
 
-        let transformation = Matrix::identity(4)
+        let transformation = Matrix::identity()
             .rotate_x(FRAC_PI_2)
             .scale(5.0, 5.0, 5.0)
             .translate(10.0, 5.0, 7.0);
@@ -768,4 +866,47 @@ mod tests {
         let shear = Matrix::shearing(2.0, 0.0, 0.0, 0.0, 0.0, 1.0);
         assert_eq!(shear * point, Tuple::point(8.0, 3.0, 7.0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn view_transform() {
+        let from = Tuple::point(0.0, 0.0, 0.0);
+        let up = Tuple::vector(0.0, 1.0, 0.0);
+        assert_eq!(Matrix::view_transform(from, Tuple::point(0.0, 0.0, -1.0), up), Matrix::identity());
+        assert_eq!(Matrix::view_transform(from, Tuple::point(0.0, 0.0, 1.0), up), Matrix::scaling(-1.0, 1.0, -1.0));
+
+        let from = Tuple::point(0.0, 0.0, 8.0);
+        assert_eq!(Matrix::view_transform(from, Tuple::point(0.0, 0.0, 0.0), up), Matrix::translation(0.0, 0.0, -8.0));
+
+        let from = Tuple::point(1.0, 3.0, 2.0);
+        let to = Tuple::point(4.0, -2.0, 8.0);
+        let up = Tuple::vector(1.0, 1.0, 0.0);
+        assert_eq!(Matrix::view_transform(from, to, up), Matrix::new_4x4([
+            -0.50709, 0.50709, 0.67612, -2.36643,
+            0.76772, 0.60609, 0.12122, -2.82843,
+            -0.35857, 0.59761, -0.71714, 0.0,
+            0.0, 0.0, 0.0, 1.0
+        ]));
+    }
+
+    #[test]
+    fn transform() {
+        let matrix = Matrix::scaling(2.0, 1.0, 0.5).rotate_y(0.3).translate(1.0, 2.0, 3.0);
+        let transform = Transform::new(matrix);
+        assert_eq!(*transform.inverse(), matrix.inverse().unwrap());
+        assert_eq!(*transform.normal(), matrix.inverse().unwrap().transpose());
+
+        let point = Tuple::point(1.0, -2.0, 4.0);
+        assert_eq!(transform.inverse() * (transform.matrix() * point), point);
+        assert_eq!(transform.matrix() * transform.inverse(), Matrix::identity());
+    }
+
+    #[test]
+    fn transform_serde() {
+        let transform = Transform::new(Matrix::translation(1.0, 2.0, 3.0));
+        let json = serde_json::to_string(&transform).unwrap();
+        assert_eq!(serde_json::from_str::<Transform>(&json).unwrap(), transform);
+
+        let singular = serde_json::to_string(&Matrix::scaling(1.0, 0.0, 1.0)).unwrap();
+        assert!(serde_json::from_str::<Transform>(&singular).is_err());
+    }
+}
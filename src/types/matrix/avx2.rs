@@ -0,0 +1,62 @@
+//! AVX2 versions of the 4x4 operations, picked at runtime by
+//! `cpu::simd_level` rather than by a feature, so every x86_64 build has
+//! them. Additions happen in the same order as the scalar code, so results
+//! match it exactly
+
+use std::arch::x86_64::*;
+
+/// Product of two row major 4x4 matrices, working on two result rows at once
+#[target_feature(enable = "avx2")]
+pub(super) fn mul(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+    // both halves hold the same row of b, one for each result row
+    let b: [__m256; 4] = std::array::from_fn(|row| {
+        // SAFETY: each row of `b` is four floats
+        let row = unsafe { _mm_loadu_ps(b[row * 4..].as_ptr()) };
+        _mm256_set_m128(row, row)
+    });
+    let mut values = [0.0; 16];
+
+    for (i, pair) in values.chunks_exact_mut(8).enumerate() {
+        let weight = |col: usize| _mm256_setr_m128(_mm_set1_ps(a[i * 8 + col]), _mm_set1_ps(a[i * 8 + 4 + col]));
+        let mut sum = _mm256_mul_ps(weight(0), b[0]);
+        sum = _mm256_add_ps(sum, _mm256_mul_ps(weight(1), b[1]));
+        sum = _mm256_add_ps(sum, _mm256_mul_ps(weight(2), b[2]));
+        sum = _mm256_add_ps(sum, _mm256_mul_ps(weight(3), b[3]));
+        // SAFETY: `pair` has room for eight floats
+        unsafe { _mm256_storeu_ps(pair.as_mut_ptr(), sum) };
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Matrix;
+
+    use super::*;
+
+    #[test]
+    fn mul_matches_scalar() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let matrices = [
+            Matrix::identity(),
+            Matrix::new_4x4([-5.0, 2.0, 6.0, -8.0, 1.0, -5.0, 1.0, 8.0, 7.0, 7.0, -6.0, -7.0, 1.0, -3.0, 7.0, 4.0]),
+            Matrix::identity().rotate_x(0.3).scale(2.0, 0.5, 1.5).shear(0.1, 0.0, 0.3, 0.0, 0.0, 0.2).translate(4.0, -2.0, 7.0),
+        ];
+        let values = |m: Matrix| std::array::from_fn(|i| m[(i / 4, i % 4)]);
+
+        for a in matrices {
+            for b in matrices {
+                let expected: [f32; 16] = std::array::from_fn(|i| {
+                    let (row, col) = (i / 4, i % 4);
+                    a[(row, 0)] * b[(0, col)] + a[(row, 1)] * b[(1, col)] + a[(row, 2)] * b[(2, col)] + a[(row, 3)] * b[(3, col)]
+                });
+                // SAFETY: the CPU has just been checked for AVX2
+                assert_eq!(unsafe { mul(&values(a), &values(b)) }, expected);
+            }
+        }
+    }
+}
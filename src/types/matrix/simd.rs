@@ -0,0 +1,206 @@
+//! SSE versions of the 4x4 operations every ray goes through, behind the
+//! `simd` feature. SSE is part of the x86_64 baseline so there's nothing to
+//! detect at runtime, callers just need an `unsafe` block to say so. The
+//! AVX2 product is in `avx2` and picked at runtime.
+//! The products add up in the same order as the scalar code, so they match
+//! it exactly. The inverse works on 2x2 blocks instead of cofactors, so it
+//! only agrees to within rounding
+
+use std::arch::x86_64::*;
+
+/// `_mm_shuffle_ps` taking lanes `x` and `y` from `a` and `z` and `w` from `b`
+macro_rules! shuffle {
+    ($a:expr, $b:expr, $x:literal, $y:literal, $z:literal, $w:literal) => {
+        _mm_shuffle_ps::<{ $x | ($y << 2) | ($z << 4) | ($w << 6) }>($a, $b)
+    };
+}
+
+macro_rules! swizzle {
+    ($v:expr, $x:literal, $y:literal, $z:literal, $w:literal) => {
+        shuffle!($v, $v, $x, $y, $z, $w)
+    };
+}
+
+#[target_feature(enable = "sse")]
+fn load(values: &[f32]) -> __m128 {
+    assert!(values.len() >= 4);
+    // SAFETY: the assert means four floats can be read
+    unsafe { _mm_loadu_ps(values.as_ptr()) }
+}
+
+#[target_feature(enable = "sse")]
+fn store(vector: __m128) -> [f32; 4] {
+    let mut values = [0.0; 4];
+    // SAFETY: `values` has room for four floats
+    unsafe { _mm_storeu_ps(values.as_mut_ptr(), vector) };
+    values
+}
+
+#[target_feature(enable = "sse")]
+fn rows(values: &[f32; 16]) -> [__m128; 4] {
+    [load(&values[0..]), load(&values[4..]), load(&values[8..]), load(&values[12..])]
+}
+
+#[target_feature(enable = "sse")]
+fn from_rows(rows: [__m128; 4]) -> [f32; 16] {
+    let mut values = [0.0; 16];
+    for (i, row) in rows.into_iter().enumerate() {
+        values[i * 4..i * 4 + 4].copy_from_slice(&store(row));
+    }
+    values
+}
+
+#[target_feature(enable = "sse")]
+pub(super) fn mul(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+    let b = rows(b);
+    let mut result = [_mm_setzero_ps(); 4];
+
+    // each result row is a's row weighting b's rows
+    for (i, row) in result.iter_mut().enumerate() {
+        let mut sum = _mm_mul_ps(_mm_set1_ps(a[i * 4]), b[0]);
+        sum = _mm_add_ps(sum, _mm_mul_ps(_mm_set1_ps(a[i * 4 + 1]), b[1]));
+        sum = _mm_add_ps(sum, _mm_mul_ps(_mm_set1_ps(a[i * 4 + 2]), b[2]));
+        *row = _mm_add_ps(sum, _mm_mul_ps(_mm_set1_ps(a[i * 4 + 3]), b[3]));
+    }
+
+    from_rows(result)
+}
+
+#[target_feature(enable = "sse")]
+pub(super) fn mul_tuple(m: &[f32; 16], t: [f32; 4]) -> [f32; 4] {
+    let [r0, r1, r2, r3] = rows(m);
+
+    // transpose so the columns can be weighted by the tuple's components
+    let (t0, t1) = (shuffle!(r0, r1, 0, 1, 0, 1), shuffle!(r0, r1, 2, 3, 2, 3));
+    let (t2, t3) = (shuffle!(r2, r3, 0, 1, 0, 1), shuffle!(r2, r3, 2, 3, 2, 3));
+    let columns = [shuffle!(t0, t2, 0, 2, 0, 2), shuffle!(t0, t2, 1, 3, 1, 3),
+        shuffle!(t1, t3, 0, 2, 0, 2), shuffle!(t1, t3, 1, 3, 1, 3)];
+
+    let mut sum = _mm_mul_ps(columns[0], _mm_set1_ps(t[0]));
+    sum = _mm_add_ps(sum, _mm_mul_ps(columns[1], _mm_set1_ps(t[1])));
+    sum = _mm_add_ps(sum, _mm_mul_ps(columns[2], _mm_set1_ps(t[2])));
+    sum = _mm_add_ps(sum, _mm_mul_ps(columns[3], _mm_set1_ps(t[3])));
+    store(sum)
+}
+
+/// 2x2 matrices packed row by row into one vector: `a * b`
+#[target_feature(enable = "sse")]
+fn mul_2x2(a: __m128, b: __m128) -> __m128 {
+    _mm_add_ps(_mm_mul_ps(a, swizzle!(b, 0, 3, 0, 3)), _mm_mul_ps(swizzle!(a, 1, 0, 3, 2), swizzle!(b, 2, 1, 2, 1)))
+}
+
+/// `adjugate(a) * b`
+#[target_feature(enable = "sse")]
+fn adj_mul_2x2(a: __m128, b: __m128) -> __m128 {
+    _mm_sub_ps(_mm_mul_ps(swizzle!(a, 3, 3, 0, 0), b), _mm_mul_ps(swizzle!(a, 1, 1, 2, 2), swizzle!(b, 2, 3, 0, 1)))
+}
+
+/// `a * adjugate(b)`
+#[target_feature(enable = "sse")]
+fn mul_adj_2x2(a: __m128, b: __m128) -> __m128 {
+    _mm_sub_ps(_mm_mul_ps(a, swizzle!(b, 3, 0, 3, 0)), _mm_mul_ps(swizzle!(a, 1, 0, 3, 2), swizzle!(b, 2, 1, 2, 1)))
+}
+
+/// Inverse by splitting into 2x2 blocks `[A B; C D]` and working on a
+/// whole block in each vector
+#[target_feature(enable = "sse")]
+pub(super) fn inverse(m: &[f32; 16]) -> Option<[f32; 16]> {
+    let [r0, r1, r2, r3] = rows(m);
+    let a = shuffle!(r0, r1, 0, 1, 0, 1);
+    let b = shuffle!(r0, r1, 2, 3, 2, 3);
+    let c = shuffle!(r2, r3, 0, 1, 0, 1);
+    let d = shuffle!(r2, r3, 2, 3, 2, 3);
+
+    // determinants of A, B, C and D
+    let dets = _mm_sub_ps(
+        _mm_mul_ps(shuffle!(r0, r2, 0, 2, 0, 2), shuffle!(r1, r3, 1, 3, 1, 3)),
+        _mm_mul_ps(shuffle!(r0, r2, 1, 3, 1, 3), shuffle!(r1, r3, 0, 2, 0, 2)),
+    );
+    let [det_a, det_b, det_c, det_d] = store(dets);
+
+    let d_c = adj_mul_2x2(d, c);
+    let a_b = adj_mul_2x2(a, b);
+
+    // adjugates of the inverse's blocks
+    let x = _mm_sub_ps(_mm_mul_ps(_mm_set1_ps(det_d), a), mul_2x2(b, d_c));
+    let w = _mm_sub_ps(_mm_mul_ps(_mm_set1_ps(det_a), d), mul_2x2(c, a_b));
+    let y = _mm_sub_ps(_mm_mul_ps(_mm_set1_ps(det_b), c), mul_adj_2x2(d, a_b));
+    let z = _mm_sub_ps(_mm_mul_ps(_mm_set1_ps(det_c), b), mul_adj_2x2(a, d_c));
+
+    let trace = store(_mm_mul_ps(a_b, swizzle!(d_c, 0, 2, 1, 3)));
+    let det = det_a * det_d + det_b * det_c - (trace[0] + trace[1] + trace[2] + trace[3]);
+    if det == 0.0 {
+        return None;
+    }
+
+    // dividing rather than multiplying by 1 / det saves a rounding, though
+    // the blocks have been summed differently from the scalar cofactors
+    let sign = _mm_setr_ps(1.0, -1.0, -1.0, 1.0);
+    let det = _mm_set1_ps(det);
+    let [x, y, z, w] = [x, y, z, w].map(|block| _mm_div_ps(_mm_mul_ps(block, sign), det));
+
+    // undo the adjugates while putting the blocks back together
+    Some(from_rows([
+        shuffle!(x, y, 3, 1, 3, 1),
+        shuffle!(x, y, 2, 0, 2, 0),
+        shuffle!(z, w, 3, 1, 3, 1),
+        shuffle!(z, w, 2, 0, 2, 0),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Matrix;
+
+    use super::*;
+
+    // SAFETY for the unsafe blocks below: SSE is always there on x86_64
+
+    fn matrices() -> Vec<Matrix> {
+        vec![
+            Matrix::identity(),
+            Matrix::new_4x4([-5.0, 2.0, 6.0, -8.0, 1.0, -5.0, 1.0, 8.0, 7.0, 7.0, -6.0, -7.0, 1.0, -3.0, 7.0, 4.0]),
+            Matrix::new_4x4([3.0, -9.0, 7.0, 3.0, 3.0, -8.0, 2.0, -9.0, -4.0, 4.0, 4.0, 1.0, -6.0, 5.0, -1.0, 1.0]),
+            Matrix::identity().rotate_x(0.3).scale(2.0, 0.5, 1.5).shear(0.1, 0.0, 0.3, 0.0, 0.0, 0.2).translate(4.0, -2.0, 7.0),
+        ]
+    }
+
+    fn values(m: Matrix) -> [f32; 16] {
+        std::array::from_fn(|i| m[(i / 4, i % 4)])
+    }
+
+    #[test]
+    fn mul_matches_scalar() {
+        for a in matrices() {
+            for b in matrices() {
+                let expected: [f32; 16] = std::array::from_fn(|i| {
+                    let (row, col) = (i / 4, i % 4);
+                    a[(row, 0)] * b[(0, col)] + a[(row, 1)] * b[(1, col)] + a[(row, 2)] * b[(2, col)] + a[(row, 3)] * b[(3, col)]
+                });
+                assert_eq!(unsafe { mul(&values(a), &values(b)) }, expected);
+            }
+
+            let t = [1.5, -2.0, 3.0, 1.0];
+            let expected: [f32; 4] = std::array::from_fn(|row| {
+                a[(row, 0)] * t[0] + a[(row, 1)] * t[1] + a[(row, 2)] * t[2] + a[(row, 3)] * t[3]
+            });
+            assert_eq!(unsafe { mul_tuple(&values(a), t) }, expected);
+        }
+    }
+
+    #[test]
+    fn inverse_matches_cofactors() {
+        for m in matrices() {
+            let inverse = Matrix::new_4x4(unsafe { inverse(&values(m)) }.unwrap());
+            let determinant = m.determinant();
+            for row in 0..4 {
+                for col in 0..4 {
+                    assert!((inverse[(col, row)] - m.cofactor(row, col) / determinant).abs() < 1e-5);
+                }
+            }
+        }
+
+        let singular = Matrix::new_4x4([-4.0, 2.0, -2.0, -3.0, 9.0, 6.0, 2.0, 6.0, 0.0, -5.0, 1.0, -5.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(unsafe { inverse(&values(singular)) }, None);
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Float;
+use super::{colour::Colour, ray::Ray, rng::{hash_ray, Rng}};
+
+/// Uniform participating medium filling the world, like dust or haze.
+///
+/// Light scattered towards the camera by the medium is found by marching
+/// along each ray, which makes spot and area lights show visible beams.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Medium {
+    /// How much light is scattered per unit distance
+    pub density: Float,
+    /// Colour of the scattered light
+    pub colour: Colour,
+    /// Shadow tested points along each ray
+    pub steps: usize,
+    /// How far rays that miss everything are marched
+    pub max_distance: Float,
+    pub seed: u64,
+}
+
+impl Medium {
+    pub fn new(density: Float) -> Self {
+        assert!(density >= 0.0);
+
+        Self {
+            density,
+            ..Default::default()
+        }
+    }
+
+    /// Fraction of light surviving `distance` through the medium
+    pub fn transmittance(&self, distance: Float) -> Float {
+        (-self.density * distance).exp()
+    }
+
+    /// Distances along `ray` to sample between 0 and `t`, each randomly
+    /// placed within its step to avoid banding, paired with the step length
+    pub fn steps(&self, ray: Ray, t: Float) -> impl Iterator<Item = (Float, Float)> {
+        let step = t / self.steps as Float;
+        let length = step * ray.direction.magnitude();
+
+        let mut rng = Rng::new(self.seed ^ hash_ray(ray));
+
+        (0..self.steps).map(move |i| ((i as Float + rng.next_float()) * step, length))
+    }
+}
+
+impl Default for Medium {
+    fn default() -> Self {
+        Self {
+            density: 0.05,
+            colour: Colour::white(),
+            steps: 32,
+            max_distance: 100.0,
+            seed: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{eq, Tuple};
+
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let medium = Medium {
+            steps: 4,
+            ..Medium::new(0.5)
+        };
+        assert!(eq(medium.transmittance(2.0), (-1.0 as Float).exp()));
+
+        let ray = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 2.0));
+        let steps: Vec<_> = medium.steps(ray, 4.0).collect();
+        assert_eq!(steps.len(), 4);
+        for (i, (t, length)) in steps.iter().enumerate() {
+            assert!(*t >= i as Float && *t < i as Float + 1.0);
+            assert_eq!(*length, 2.0);
+        }
+        assert_eq!(steps, medium.steps(ray, 4.0).collect::<Vec<_>>());
+    }
+}
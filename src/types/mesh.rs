@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{Float, types::{intersection::Intersection, material::Material, ray::Ray}, Matrix, RosemaryError, Tuple, EPSILON};
+use super::matrix::Transform;
+use super::{bounds::{BoundingBox, BoundingSphere}, bvh::Bvh, shape::{SavedShape, Shape}};
+
+/// Golden ratio, used for the icosahedron's vertices
+const PHI: Float = 1.618034;
+
+const ICOSAHEDRON_FACES: [[usize; 3]; 20] = [[0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+    [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8], [3, 9, 4], [3, 4, 2], [3, 2, 6],
+    [3, 6, 8], [3, 8, 9], [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1]];
+
+/// Triangle mesh, flat shaded unless it's given normals at the corners
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "SavedMesh", into = "SavedMesh")]
+pub struct Mesh {
+    id: Uuid,
+    transform: Transform,
+    pub material: Material,
+    vertices: Vec<Tuple>,
+    faces: Vec<[usize; 3]>,
+    edges: Vec<(Tuple, Tuple)>,
+    normals: Vec<Tuple>,
+    /// Normals at the corners of each face, blended across it
+    corner_normals: Option<Vec<[Tuple; 3]>>,
+    /// Texture coordinates at the corners of each face
+    corner_uvs: Option<Vec<[[Float; 2]; 3]>>,
+    bounds: BoundingSphere,
+    /// Over the faces, so big meshes only test the triangles near a ray
+    bvh: Bvh,
+}
+
+impl Mesh {
+    /// Panics if a vertex isn't a point, a face uses a missing vertex or
+    /// `transform` can't be inverted, see `try_new`
+    pub fn new(vertices: Vec<Tuple>, faces: Vec<[usize; 3]>, transform: Matrix, material: Material) -> Self {
+        Self::try_new(vertices, faces, transform, material).unwrap()
+    }
+
+    pub fn try_new(vertices: Vec<Tuple>, faces: Vec<[usize; 3]>, transform: Matrix, material: Material)
+        -> Result<Self, RosemaryError> {
+        if !vertices.iter().all(|v| v.is_point()) {
+            return Err(RosemaryError::NotAPoint("mesh vertex"));
+        }
+        for (face, corners) in faces.iter().enumerate() {
+            if let Some(&vertex) = corners.iter().find(|i| **i >= vertices.len()) {
+                return Err(RosemaryError::MissingVertex { face, vertex });
+            }
+        }
+        let transform = Transform::try_from(transform)?;
+
+        let mut edges = Vec::with_capacity(faces.len());
+        let mut normals = Vec::with_capacity(faces.len());
+        for [a, b, c] in &faces {
+            let e1 = vertices[*b] - vertices[*a];
+            let e2 = vertices[*c] - vertices[*a];
+            edges.push((e1, e2));
+            normals.push(e2.cross(e1).norm());
+        }
+
+        // padded so rounding can't make a box miss a ray its triangle catches
+        let boxes: Vec<_> = faces.iter()
+            .map(|face| BoundingBox::from_points(&face.map(|i| vertices[i]), EPSILON))
+            .collect();
+
+        Ok(Self {
+            id: Uuid::new_v4(),
+            transform,
+            material,
+            bounds: BoundingSphere::from_points(&vertices),
+            bvh: Bvh::from_boxes(&boxes),
+            vertices,
+            faces,
+            edges,
+            normals,
+            corner_normals: None,
+            corner_uvs: None,
+        })
+    }
+
+    /// Unit sphere made by splitting each face of an icosahedron `subdivisions` times
+    pub fn icosphere(subdivisions: usize) -> Self {
+        let (vertices, faces) = icosphere(subdivisions);
+        Self::new(vertices, faces, Matrix::identity(), Material::default())
+    }
+
+    /// The cube from -1 to 1 on every axis
+    pub fn cube() -> Self {
+        let vertices: Vec<_> = (0..8).map(|i| {
+            let side = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+            Tuple::point(side(1), side(2), side(4))
+        }).collect();
+        let mut faces = [[0, 2, 6, 4], [1, 3, 7, 5], [0, 1, 5, 4], [2, 3, 7, 6], [0, 1, 3, 2], [4, 5, 7, 6]]
+            .iter().flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+            .collect::<Vec<_>>();
+        face_outwards(&vertices, &mut faces);
+        Self::new(vertices, faces, Matrix::identity(), Material::default())
+    }
+
+    /// A square `size` across in the xz plane, centred on the origin and
+    /// facing up
+    pub fn plane(size: Float) -> Self {
+        let h = size / 2.0;
+        let vertices = vec![Tuple::point(-h, 0.0, -h), Tuple::point(h, 0.0, -h), Tuple::point(h, 0.0, h), Tuple::point(-h, 0.0, h)];
+        Self::new(vertices, vec![[0, 1, 2], [0, 2, 3]], Matrix::identity(), Material::default())
+    }
+
+    /// Panics if `transform` can't be inverted, see `try_set_transform`
+    pub fn set_transform(&mut self, transform: Matrix) {
+        self.try_set_transform(transform).unwrap();
+    }
+
+    /// Leaves the mesh as it was if `transform` can't be inverted
+    pub fn try_set_transform(&mut self, transform: Matrix) -> Result<(), RosemaryError> {
+        self.transform = Transform::try_from(transform)?;
+        Ok(())
+    }
+
+    pub fn vertices(&self) -> &[Tuple] {
+        &self.vertices
+    }
+
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    /// Shade smoothly with a normal at each corner of each face, in the
+    /// mesh's own space
+    pub fn set_corner_normals(&mut self, normals: Vec<[Tuple; 3]>) {
+        assert_eq!(normals.len(), self.faces.len());
+        self.corner_normals = Some(normals.into_iter().map(|corners| corners.map(|normal| normal.norm())).collect());
+    }
+
+    pub fn set_corner_uvs(&mut self, uvs: Vec<[[Float; 2]; 3]>) {
+        assert_eq!(uvs.len(), self.faces.len());
+        self.corner_uvs = Some(uvs);
+    }
+
+    /// Texture coordinates at `point`, in the mesh's own space, on the face
+    /// `hit` found. `None` if the mesh has none
+    pub fn uv(&self, point: Tuple, hit: &Intersection) -> Option<[Float; 2]> {
+        let face = hit.face?;
+        let weights = self.barycentric(face, point);
+        let corners = self.corner_uvs.as_ref()?[face];
+        Some([0, 1].map(|axis| (0..3).map(|i| corners[i][axis] * weights[i]).sum()))
+    }
+
+    /// How much each corner of `face` contributes at `point` on it
+    fn barycentric(&self, face: usize, point: Tuple) -> [Float; 3] {
+        let (e1, e2) = self.edges[face];
+        let to_point = point - self.vertices[self.faces[face][0]];
+        let (d11, d12, d22) = (e1.dot(e1), e1.dot(e2), e2.dot(e2));
+        let (p1, p2) = (to_point.dot(e1), to_point.dot(e2));
+        let denom = d11 * d22 - d12 * d12;
+        let v = (d22 * p1 - d12 * p2) / denom;
+        let w = (d11 * p2 - d12 * p1) / denom;
+        [1.0 - v - w, v, w]
+    }
+
+    fn intersect_face(&self, face: usize, ray: Ray) -> Option<Float> {
+        let (e1, e2) = self.edges[face];
+        let dir_cross_e2 = ray.direction.cross(e2);
+        let det = e1.dot(dir_cross_e2);
+
+        // ray is parallel to the triangle
+        if det.abs() < EPSILON {
+            return None;
+        }
+
+        let f = 1.0 / det;
+        let p1_to_origin = ray.origin - self.vertices[self.faces[face][0]];
+        let u = f * p1_to_origin.dot(dir_cross_e2);
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let origin_cross_e1 = p1_to_origin.cross(e1);
+        let v = f * ray.direction.dot(origin_cross_e1);
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        Some(f * e2.dot(origin_cross_e1))
+    }
+}
+
+/// The parts of a mesh that get saved, edges, normals and the bvh are
+/// worked out again on load
+#[derive(Serialize, Deserialize)]
+struct SavedMesh {
+    id: Uuid,
+    transform: Transform,
+    material: Material,
+    vertices: Vec<Tuple>,
+    faces: Vec<[usize; 3]>,
+    #[serde(default)]
+    corner_normals: Option<Vec<[Tuple; 3]>>,
+    #[serde(default)]
+    corner_uvs: Option<Vec<[[Float; 2]; 3]>>,
+}
+
+impl TryFrom<SavedMesh> for Mesh {
+    type Error = RosemaryError;
+
+    fn try_from(saved: SavedMesh) -> Result<Self, Self::Error> {
+        let lengths = [saved.corner_normals.as_ref().map(Vec::len), saved.corner_uvs.as_ref().map(Vec::len)];
+        if let Some(corners) = lengths.into_iter().flatten().find(|len| *len != saved.faces.len()) {
+            return Err(RosemaryError::CornerCount { faces: saved.faces.len(), corners });
+        }
+
+        let mut mesh = Self::try_new(saved.vertices, saved.faces, *saved.transform.matrix(), saved.material)?;
+        mesh.id = saved.id;
+        mesh.corner_normals = saved.corner_normals;
+        mesh.corner_uvs = saved.corner_uvs;
+        Ok(mesh)
+    }
+}
+
+impl From<Mesh> for SavedMesh {
+    fn from(mesh: Mesh) -> Self {
+        Self {
+            id: mesh.id,
+            transform: mesh.transform,
+            material: mesh.material,
+            vertices: mesh.vertices,
+            faces: mesh.faces,
+            corner_normals: mesh.corner_normals,
+            corner_uvs: mesh.corner_uvs,
+        }
+    }
+}
+
+impl Shape for Mesh {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn local_intersect<'a>(&'a self, ray: Ray, inters: &mut Vec<Intersection<'a>>) {
+        if !self.bounds.intersects(ray) {
+            return;
+        }
+
+        self.bvh.candidates(ray, |face| {
+            if let Some(t) = self.intersect_face(face, ray) {
+                inters.push(Intersection::with_face(t, self, face));
+            }
+        });
+    }
+
+    fn local_normal(&self, point: Tuple, hit: &Intersection) -> Tuple {
+        let face = hit.face.expect("mesh intersections always record their face");
+        match &self.corner_normals {
+            Some(normals) => {
+                let weights = self.barycentric(face, point);
+                let corners = normals[face];
+                (corners[0] * weights[0] + corners[1] * weights[1] + corners[2] * weights[2]).norm()
+            },
+            None => self.normals[face],
+        }
+    }
+
+    fn local_bounds(&self) -> BoundingSphere {
+        self.bounds
+    }
+
+    fn saved(&self) -> Option<SavedShape> {
+        Some(SavedShape::Mesh(self.clone()))
+    }
+}
+
+/// Vertices and outward facing triangles of a subdivided unit icosahedron
+pub fn icosphere(subdivisions: usize) -> (Vec<Tuple>, Vec<[usize; 3]>) {
+    let mut vertices: Vec<Tuple> = [
+        (-1.0, PHI, 0.0), (1.0, PHI, 0.0), (-1.0, -PHI, 0.0), (1.0, -PHI, 0.0),
+        (0.0, -1.0, PHI), (0.0, 1.0, PHI), (0.0, -1.0, -PHI), (0.0, 1.0, -PHI),
+        (PHI, 0.0, -1.0), (PHI, 0.0, 1.0), (-PHI, 0.0, -1.0), (-PHI, 0.0, 1.0),
+    ].iter().map(|(x, y, z)| unit_point(Tuple::point(*x, *y, *z))).collect();
+    let mut faces = ICOSAHEDRON_FACES.to_vec();
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut split = Vec::with_capacity(faces.len() * 4);
+
+        for [a, b, c] in faces {
+            let ab = midpoint(&mut vertices, &mut midpoints, a, b);
+            let bc = midpoint(&mut vertices, &mut midpoints, b, c);
+            let ca = midpoint(&mut vertices, &mut midpoints, c, a);
+
+            split.push([a, ab, ca]);
+            split.push([b, bc, ab]);
+            split.push([c, ca, bc]);
+            split.push([ab, bc, ca]);
+        }
+
+        faces = split;
+    }
+
+    face_outwards(&vertices, &mut faces);
+    (vertices, faces)
+}
+
+/// Wind every face of a shape around the origin so its normal points away
+/// from the centre
+fn face_outwards(vertices: &[Tuple], faces: &mut [[usize; 3]]) {
+    for face in faces {
+        let [a, b, c] = *face;
+        let normal = (vertices[c] - vertices[a]).cross(vertices[b] - vertices[a]);
+        let centre = vertices[a] - Tuple::point(0.0, 0.0, 0.0);
+        if normal.dot(centre) < 0.0 {
+            face.swap(1, 2);
+        }
+    }
+}
+
+fn unit_point(point: Tuple) -> Tuple {
+    let vec = (point - Tuple::point(0.0, 0.0, 0.0)).norm();
+    Tuple::point(vec.x, vec.y, vec.z)
+}
+
+fn midpoint(vertices: &mut Vec<Tuple>, cache: &mut HashMap<(usize, usize), usize>, a: usize, b: usize) -> usize {
+    let key = (a.min(b), a.max(b));
+
+    *cache.entry(key).or_insert_with(|| {
+        let mid = vertices[a] + (vertices[b] - vertices[a]) * 0.5;
+        vertices.push(unit_point(mid));
+        vertices.len() - 1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Mesh {
+        Mesh::new(vec![Tuple::point(0.0, 1.0, 0.0), Tuple::point(-1.0, 0.0, 0.0), Tuple::point(1.0, 0.0, 0.0)],
+            vec![[0, 1, 2]], Matrix::identity(), Material::default())
+    }
+
+    #[test]
+    fn new() {
+        let m = triangle();
+        assert_eq!(m.edges[0], (Tuple::vector(-1.0, -1.0, 0.0), Tuple::vector(1.0, -1.0, 0.0)));
+        assert_eq!(m.normals[0], Tuple::vector(0.0, 0.0, -1.0));
+
+        let i = Intersection::with_face(1.0, &m, 0);
+        assert_eq!(m.normal(Tuple::point(0.0, 0.5, 0.0), &i), Tuple::vector(0.0, 0.0, -1.0));
+        assert_eq!(m.normal(Tuple::point(-0.5, 0.75, 0.0), &i), Tuple::vector(0.0, 0.0, -1.0));
+
+        let vertices = m.vertices.clone();
+        let missing = Mesh::try_new(vertices.clone(), vec![[0, 1, 2], [1, 2, 3]], Matrix::identity(), Material::default());
+        assert_eq!(missing.unwrap_err(), RosemaryError::MissingVertex { face: 1, vertex: 3 });
+        let vectors = vec![Tuple::vector(0.0, 0.0, 0.0); 3];
+        let vectors = Mesh::try_new(vectors, vec![[0, 1, 2]], Matrix::identity(), Material::default());
+        assert_eq!(vectors.unwrap_err(), RosemaryError::NotAPoint("mesh vertex"));
+        let flat = Mesh::try_new(vertices, vec![[0, 1, 2]], Matrix::scaling(0.0, 1.0, 1.0), Material::default());
+        assert_eq!(flat.unwrap_err(), RosemaryError::NotInvertible);
+    }
+
+    #[test]
+    fn intersect() {
+        let m = triangle();
+
+        // parallel
+        let r = Ray::new(Tuple::point(0.0, -1.0, -2.0), Tuple::vector(0.0, 1.0, 0.0));
+        assert!(m.intersect(r).is_empty());
+
+        // misses each edge
+        let r = Ray::new(Tuple::point(1.0, 1.0, -2.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(m.intersect(r).is_empty());
+        let r = Ray::new(Tuple::point(-1.0, 1.0, -2.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(m.intersect(r).is_empty());
+        let r = Ray::new(Tuple::point(0.0, -1.0, -2.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(m.intersect(r).is_empty());
+
+        let r = Ray::new(Tuple::point(0.0, 0.5, -2.0), Tuple::vector(0.0, 0.0, 1.0));
+        let inters = m.intersect(r);
+        assert_eq!(inters.len(), 1);
+        assert_eq!(inters[0].t, 2.0);
+        assert_eq!(inters[0].face, Some(0));
+    }
+
+    #[test]
+    fn icosphere() {
+        for subdivisions in 0..4 {
+            let m = Mesh::icosphere(subdivisions);
+            assert_eq!(m.vertices().len(), 10 * 4_usize.pow(subdivisions as u32) + 2);
+            assert_eq!(m.faces().len(), 20 * 4_usize.pow(subdivisions as u32));
+
+            for v in m.vertices() {
+                assert!(crate::eq((*v - Tuple::point(0.0, 0.0, 0.0)).magnitude(), 1.0));
+            }
+        }
+
+        // closed surface so a ray through the middle enters and leaves
+        let m = Mesh::icosphere(2);
+        let r = Ray::new(Tuple::point(0.1, 0.2, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let inters = m.intersect(r);
+        assert_eq!(inters.len(), 2);
+
+        let hit = Intersection::hit(inters).unwrap();
+        let normal = m.normal(r.position(hit.t), &hit);
+        assert!(normal.z < 0.0);
+    }
+
+    #[test]
+    fn smooth() {
+        let mut m = triangle();
+        let (top, left, right) = (Tuple::vector(0.0, 1.0, -1.0), Tuple::vector(-1.0, 0.0, -1.0), Tuple::vector(1.0, 0.0, -1.0));
+        m.set_corner_normals(vec![[top, left, right]]);
+        m.set_corner_uvs(vec![[[0.5, 1.0], [0.0, 0.0], [1.0, 0.0]]]);
+
+        let i = Intersection::with_face(1.0, &m, 0);
+        assert_eq!(m.normal(Tuple::point(0.0, 1.0, 0.0), &i), top.norm());
+        assert_eq!(m.normal(Tuple::point(0.0, 0.0, 0.0), &i), Tuple::vector(0.0, 0.0, -1.0));
+        assert_eq!(m.uv(Tuple::point(0.0, 0.5, 0.0), &i), Some([0.5, 0.5]));
+        assert_eq!(triangle().uv(Tuple::point(0.0, 0.5, 0.0), &i), None);
+
+        let saved: Mesh = serde_json::from_str(&serde_json::to_string(&m).unwrap()).unwrap();
+        assert_eq!(saved, m);
+    }
+
+    #[test]
+    fn cube_and_plane() {
+        let cube = Mesh::cube();
+        assert_eq!(cube.faces().len(), 12);
+        for (origin, direction) in [((0.2, 0.3, -5.0), (0.0, 0.0, 1.0)), ((5.0, 0.1, 0.4), (-1.0, 0.0, 0.0))] {
+            let r = Ray::new(Tuple::point(origin.0, origin.1, origin.2), Tuple::vector(direction.0, direction.1, direction.2));
+            let inters = cube.intersect(r);
+            let mut ts: Vec<_> = inters.iter().map(|i| i.t).collect();
+            ts.sort_by(Float::total_cmp);
+            assert_eq!(ts, [4.0, 6.0]);
+            let hit = Intersection::hit(inters).unwrap();
+            assert_eq!(cube.normal(r.position(hit.t), &hit), -r.direction);
+        }
+
+        let plane = Mesh::plane(10.0);
+        let r = Ray::new(Tuple::point(4.0, 2.0, -4.0), Tuple::vector(0.0, -1.0, 0.0));
+        let hit = Intersection::hit(plane.intersect(r)).unwrap();
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(plane.normal(r.position(hit.t), &hit), Tuple::vector(0.0, 1.0, 0.0));
+        assert!(plane.intersect(Ray::new(Tuple::point(6.0, 2.0, 0.0), Tuple::vector(0.0, -1.0, 0.0))).is_empty());
+    }
+
+    #[test]
+    fn bvh_matches_every_face() {
+        let m = Mesh::icosphere(3);
+        for i in 0..50 {
+            let offset = i as Float / 50.0 - 0.5;
+            let r = Ray::new(Tuple::point(offset * 2.0, offset, -5.0), Tuple::vector(-offset, 0.1, 1.0).norm());
+
+            let mut found: Vec<_> = m.intersect(r).iter().map(|i| i.face.unwrap()).collect();
+            found.sort_unstable();
+            let every: Vec<_> = (0..m.faces().len()).filter(|&f| m.intersect_face(f, r).is_some()).collect();
+            assert_eq!(found, every);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Notes on how an image was made, like the scene, camera and render time,
+//! written into the formats with room for text so renders stay
+//! self-describing when they're compared later. PPM keeps them as header
+//! comments, Radiance HDR as header lines, TGA in its image ID field and
+//! PNG as tEXt chunks, while BMP and PFM have nowhere to put them
+
+/// Key and value pairs in the order they were added
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry, replacing any earlier one with the same key. Line
+    /// breaks become spaces so each entry stays on one line
+    pub fn set(&mut self, key: &str, value: impl ToString) -> &mut Self {
+        let key = one_line(key).replace(':', " ");
+        let value = one_line(&value.to_string());
+        match self.entries.iter_mut().find(|(existing, _)| *existing == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key, value)),
+        }
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|(existing, _)| existing == key).map(|(_, value)| value.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Each entry as a `key: value` line, without line breaks
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.iter().map(|(key, value)| format!("{key}: {value}"))
+    }
+
+    /// Entries read back from `key: value` lines in the text of a header,
+    /// each of which may start with a `#`. Other lines are skipped
+    pub fn parse(text: &str) -> Self {
+        let mut metadata = Self::new();
+        for line in text.lines() {
+            let line = line.strip_prefix('#').unwrap_or(line);
+            if let Some((key, value)) = line.split_once(": ") {
+                metadata.set(key.trim(), value.trim());
+            }
+        }
+        metadata
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{canvas::Canvas, colour::Colour, hdr, tga};
+
+    fn metadata() -> Metadata {
+        let mut metadata = Metadata::new();
+        metadata.set("scene", "scenes/three_spheres.yaml")
+            .set("camera", "100x50,\nfov 60")
+            .set("render time", "1.50s");
+        metadata
+    }
+
+    #[test]
+    fn set_and_parse() {
+        let mut metadata = metadata();
+        assert_eq!(metadata.get("camera"), Some("100x50, fov 60"));
+        metadata.set("scene", "other.yaml");
+        assert_eq!(metadata.iter().count(), 3);
+        assert_eq!(metadata.get("scene"), Some("other.yaml"));
+
+        let text = metadata.lines().map(|line| format!("# {line}\n")).collect::<String>() + "not an entry\n";
+        assert_eq!(Metadata::parse(&text), metadata);
+    }
+
+    #[test]
+    fn written_into_images() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas[(1, 0)] = Colour::new(1.0, 0.5, 0.0);
+
+        let mut ppm = Vec::new();
+        canvas.write_ppm_with_metadata(&mut ppm, &metadata()).unwrap();
+        let text = String::from_utf8(ppm.clone()).unwrap();
+        assert!(text.starts_with("P3\n# scene: scenes/three_spheres.yaml\n"));
+        assert_eq!(Metadata::parse(&text), metadata());
+        let mut plain = Vec::new();
+        canvas.write_ppm(&mut plain).unwrap();
+        assert_eq!(Canvas::from_ppm(&ppm[..]).unwrap()[(1, 0)], Canvas::from_ppm(&plain[..]).unwrap()[(1, 0)]);
+
+        let mut ppm_16 = Vec::new();
+        canvas.write_ppm_16_with_metadata(&mut ppm_16, &metadata()).unwrap();
+        assert_eq!(Canvas::from_ppm(&ppm_16[..]).unwrap().width, 2);
+
+        let mut rgbe = Vec::new();
+        hdr::encode_with_metadata(&canvas, &mut rgbe, &metadata()).unwrap();
+        assert_eq!(Metadata::parse(&String::from_utf8_lossy(&rgbe)), metadata());
+        assert_eq!(hdr::decode(&rgbe[..]).unwrap().width, 2);
+
+        let mut targa = Vec::new();
+        tga::encode_with_metadata(&canvas, &mut targa, false, &metadata()).unwrap();
+        let id = &targa[18..18 + targa[0] as usize];
+        assert_eq!(Metadata::parse(std::str::from_utf8(id).unwrap()), metadata());
+        assert_eq!(targa.len(), 18 + id.len() + 2 * 4);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{Float, Tuple};
+use super::rng::Rng;
+
+const GRADIENTS: [(Float, Float, Float); 12] = [(1.0, 1.0, 0.0), (-1.0, 1.0, 0.0), (1.0, -1.0, 0.0), (-1.0, -1.0, 0.0),
+    (1.0, 0.0, 1.0), (-1.0, 0.0, 1.0), (1.0, 0.0, -1.0), (-1.0, 0.0, -1.0),
+    (0.0, 1.0, 1.0), (0.0, -1.0, 1.0), (0.0, 1.0, -1.0), (0.0, -1.0, -1.0)];
+
+/// Seedable 3D Perlin gradient noise
+#[derive(Debug, Clone, PartialEq)]
+pub struct Perlin {
+    perm: [u8; 512],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut rng = Rng::new(seed);
+
+        // fisher-yates shuffle
+        for i in (1..table.len()).rev() {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+
+        Self {
+            perm: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    /// Noise value at `point`, roughly in `[-1, 1]` and 0 at integer lattice points
+    pub fn noise(&self, point: Tuple) -> Float {
+        let (xi, yi, zi) = (point.x.floor(), point.y.floor(), point.z.floor());
+        let (x, y, z) = (point.x - xi, point.y - yi, point.z - zi);
+        let (xi, yi, zi) = (xi as i32 as usize & 255, yi as i32 as usize & 255, zi as i32 as usize & 255);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let p = &self.perm;
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(w,
+            lerp(v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(u, grad(p[ab], x, y - 1.0, z), grad(p[bb], x - 1.0, y - 1.0, z))),
+            lerp(v,
+                lerp(u, grad(p[aa + 1], x, y, z - 1.0), grad(p[ba + 1], x - 1.0, y, z - 1.0)),
+                lerp(u, grad(p[ab + 1], x, y - 1.0, z - 1.0), grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0))))
+    }
+
+    /// Fractal brownian motion: `octaves` layers of noise, each at double the
+    /// frequency and `gain` times the amplitude of the last
+    pub fn fbm(&self, point: Tuple, octaves: usize, gain: Float) -> Float {
+        let mut result = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+
+        for _ in 0..octaves {
+            let scaled = Tuple::point(point.x * frequency, point.y * frequency, point.z * frequency);
+            result += self.noise(scaled) * amplitude;
+            amplitude *= gain;
+            frequency *= 2.0;
+        }
+
+        result
+    }
+}
+
+fn fade(t: Float) -> Float {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: Float, a: Float, b: Float) -> Float {
+    a + t * (b - a)
+}
+
+fn grad(hash: u8, x: Float, y: Float, z: Float) -> Float {
+    let (gx, gy, gz) = GRADIENTS[hash as usize % GRADIENTS.len()];
+    gx * x + gy * y + gz * z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise() {
+        let perlin = Perlin::new(7);
+        assert_eq!(perlin.noise(Tuple::point(1.0, 2.0, 3.0)), 0.0);
+
+        let p = Tuple::point(0.3, 1.7, -2.2);
+        assert_eq!(perlin.noise(p), Perlin::new(7).noise(p));
+        assert_ne!(perlin.noise(p), Perlin::new(8).noise(p));
+
+        for i in 0..100 {
+            let f = i as Float * 0.37;
+            let n = perlin.fbm(Tuple::point(f, -f, f * 0.5), 4, 0.5);
+            assert!(n.abs() < 2.0);
+        }
+    }
+}
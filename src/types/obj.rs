@@ -0,0 +1,507 @@
+//! Wavefront `.obj` models. Vertices, texture coordinates, normals, faces,
+//! groups and materials are read, polygons are split into triangle fans, and
+//! every other kind of line is skipped. Materials come from the `.mtl`
+//! libraries the model names
+
+use std::{fs, io, path::Path};
+
+use crate::{Float, Matrix, Tuple};
+use super::{colour::Colour, material::Material, mesh::Mesh};
+
+/// Name of the group faces go in before any `g` line
+pub const DEFAULT_GROUP: &str = "default";
+
+/// Everything read from an OBJ file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjParser {
+    pub vertices: Vec<Tuple>,
+    /// `vt` lines
+    pub uvs: Vec<[Float; 2]>,
+    /// `vn` lines
+    pub normals: Vec<Tuple>,
+    /// In the order they first appear. A group that switches material part
+    /// way through is split into one entry per material
+    pub groups: Vec<ObjGroup>,
+    /// Files named by `mtllib` lines, relative to the model
+    pub libraries: Vec<String>,
+    /// From `libraries`, only filled in by `load`
+    pub materials: Vec<MtlMaterial>,
+    /// Libraries `load` couldn't read. Groups using their materials get the
+    /// one passed to `meshes` instead
+    pub missing: Vec<String>,
+    /// Lines that weren't vertices, faces, groups or materials
+    pub ignored: usize,
+}
+
+/// Triangles sharing a group and a material
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjGroup {
+    pub name: String,
+    /// Set by the last `usemtl` before the faces
+    pub material: Option<String>,
+    /// Indices into `ObjParser::vertices`
+    pub faces: Vec<[usize; 3]>,
+    /// Indices into `ObjParser::uvs` for each face, if every corner gave one
+    pub uvs: Vec<Option<[usize; 3]>>,
+    /// Indices into `ObjParser::normals` for each face, if every corner gave one
+    pub normals: Vec<Option<[usize; 3]>>,
+}
+
+/// One corner of an `f` line
+#[derive(Clone, Copy)]
+struct Corner {
+    vertex: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+impl ObjParser {
+    /// Reads the model and the material libraries it names. A library that
+    /// can't be read goes in `missing` rather than failing the load
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut obj = Self::parse(&fs::read_to_string(path)?)?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for library in &obj.libraries {
+            match fs::read_to_string(dir.join(library)) {
+                Ok(source) => obj.materials.extend(MtlMaterial::parse(&source)?),
+                Err(_) => obj.missing.push(library.clone()),
+            }
+        }
+
+        Ok(obj)
+    }
+
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let mut obj = Self::default();
+        let (mut name, mut material) = (DEFAULT_GROUP.to_string(), None);
+        // the entry faces go into, found again when a face follows a `g` or `usemtl`
+        let mut group = None;
+
+        for (number, line) in source.lines().enumerate() {
+            let invalid = |message: &str| invalid(number, message);
+            let mut fields = line.split_whitespace();
+
+            match fields.next() {
+                Some("v") => {
+                    let [x, y, z] = floats(fields).ok_or_else(|| invalid("vertex needs three coordinates"))?;
+                    obj.vertices.push(Tuple::point(x, y, z));
+                },
+                Some("vt") => {
+                    // v is optional and 0 if left out, the optional depth
+                    // coordinate is ignored
+                    let values: Vec<Float> = fields.take(2).map(str::parse).collect::<Result<_, _>>()
+                        .map_err(|_| invalid("texture coordinate values must be numbers"))?;
+                    match values[..] {
+                        [u] => obj.uvs.push([u, 0.0]),
+                        [u, v] => obj.uvs.push([u, v]),
+                        _ => return Err(invalid("texture coordinate needs a u value")),
+                    }
+                },
+                Some("vn") => {
+                    let [x, y, z] = floats(fields).ok_or_else(|| invalid("normal needs three values"))?;
+                    obj.normals.push(Tuple::vector(x, y, z));
+                },
+                Some("f") => {
+                    let corners: Vec<Corner> = fields.map(|field| obj.corner(field))
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| invalid("bad vertex index"))?;
+                    if corners.len() < 3 {
+                        return Err(invalid("face needs at least three vertices"));
+                    }
+
+                    let index = *group.get_or_insert_with(|| obj.group_index(&name, &material));
+                    let group = &mut obj.groups[index];
+                    for i in 1..corners.len() - 1 {
+                        let [a, b, c] = [corners[0], corners[i], corners[i + 1]];
+                        group.faces.push([a.vertex, b.vertex, c.vertex]);
+                        group.uvs.push(every([a.uv, b.uv, c.uv]));
+                        group.normals.push(every([a.normal, b.normal, c.normal]));
+                    }
+                },
+                Some("g") => {
+                    name = fields.collect::<Vec<_>>().join(" ");
+                    if name.is_empty() {
+                        name = DEFAULT_GROUP.to_string();
+                    }
+                    group = None;
+                },
+                Some("usemtl") => {
+                    material = Some(rest(line, "usemtl"));
+                    group = None;
+                },
+                Some("mtllib") => obj.libraries.push(rest(line, "mtllib")),
+                // blank lines don't count as ignored
+                None => {},
+                Some(_) => obj.ignored += 1,
+            }
+        }
+
+        Ok(obj)
+    }
+
+    /// Triangles of every part of the group called `name`
+    pub fn group(&self, name: &str) -> Option<Vec<[usize; 3]>> {
+        let mut parts = self.groups.iter().filter(|group| group.name == name).peekable();
+        parts.peek()?;
+        Some(parts.flat_map(|group| group.faces.iter().copied()).collect())
+    }
+
+    /// Material in `materials` called `name`
+    pub fn material(&self, name: &str) -> Option<&MtlMaterial> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+
+    /// The whole model as one mesh
+    pub fn mesh(&self, transform: Matrix, material: Material) -> Mesh {
+        let faces = self.groups.iter().flat_map(|group| group.faces.iter().copied()).collect();
+        let mut mesh = Mesh::new(self.vertices.clone(), faces, transform, material);
+        self.add_corners(&mut mesh, self.groups.iter());
+        mesh
+    }
+
+    /// A mesh for each group that has faces, named after it. Each only keeps
+    /// the vertices its own faces use, and takes its material from the
+    /// libraries when it names one that was found, `material` otherwise
+    pub fn meshes(&self, transform: Matrix, material: Material) -> Vec<(String, Mesh)> {
+        self.groups.iter()
+            .filter(|group| !group.faces.is_empty())
+            .map(|group| {
+                let mut remap = vec![usize::MAX; self.vertices.len()];
+                let mut vertices = Vec::new();
+                let faces = group.faces.iter().map(|face| face.map(|i| {
+                    if remap[i] == usize::MAX {
+                        remap[i] = vertices.len();
+                        vertices.push(self.vertices[i]);
+                    }
+                    remap[i]
+                })).collect();
+
+                let material = group.material.as_deref()
+                    .and_then(|name| self.material(name))
+                    .map_or(material, |found| found.build(material));
+                let mut mesh = Mesh::new(vertices, faces, transform, material);
+                self.add_corners(&mut mesh, std::iter::once(group));
+                (group.name.clone(), mesh)
+            })
+            .collect()
+    }
+
+    /// Gives `mesh`, made from the faces of `groups` in order, the normals
+    /// and texture coordinates of their corners if any face has them. Faces
+    /// without normals keep looking flat
+    fn add_corners<'a>(&self, mesh: &mut Mesh, groups: impl Iterator<Item = &'a ObjGroup> + Clone) {
+        let faces = || groups.clone().flat_map(|group| group.faces.iter().zip(group.uvs.iter().zip(&group.normals)));
+
+        if faces().any(|(_, (_, normals))| normals.is_some()) {
+            let normals = faces().map(|(&[a, b, c], (_, normals))| match normals {
+                Some(normals) => normals.map(|i| self.normals[i]),
+                None => [(self.vertices[c] - self.vertices[a]).cross(self.vertices[b] - self.vertices[a]); 3],
+            });
+            mesh.set_corner_normals(normals.collect());
+        }
+        if faces().any(|(_, (uvs, _))| uvs.is_some()) {
+            let uvs = faces().map(|(_, (uvs, _))| uvs.map_or([[0.0; 2]; 3], |uvs| uvs.map(|i| self.uvs[i])));
+            mesh.set_corner_uvs(uvs.collect());
+        }
+    }
+
+    fn group_index(&mut self, name: &str, material: &Option<String>) -> usize {
+        match self.groups.iter().position(|group| group.name == name && group.material == *material) {
+            Some(index) => index,
+            None => {
+                self.groups.push(ObjGroup {
+                    name: name.to_string(),
+                    material: material.clone(),
+                    faces: Vec::new(),
+                    uvs: Vec::new(),
+                    normals: Vec::new(),
+                });
+                self.groups.len() - 1
+            },
+        }
+    }
+
+    /// A corner of an `f` line written as `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+    /// Texture coordinates and normals that aren't there are left out
+    fn corner(&self, field: &str) -> Option<Corner> {
+        let mut indices = field.split('/');
+        let vertex = index(indices.next()?, self.vertices.len())?;
+        let mut next = |len| indices.next().and_then(|i| index(i, len));
+        Some(Corner {
+            vertex,
+            uv: next(self.uvs.len()),
+            normal: next(self.normals.len()),
+        })
+    }
+}
+
+/// Zero based index from an `f` line, counting back from the latest of the
+/// `len` so far when negative
+fn index(field: &str, len: usize) -> Option<usize> {
+    let index: isize = field.parse().ok()?;
+    let index = match index {
+        1.. => index as usize - 1,
+        ..=-1 => len.checked_sub(index.unsigned_abs())?,
+        0 => return None,
+    };
+    (index < len).then_some(index)
+}
+
+/// All three corners' indices, if they all have one
+fn every([a, b, c]: [Option<usize>; 3]) -> Option<[usize; 3]> {
+    Some([a?, b?, c?])
+}
+
+/// One `newmtl` entry of an `.mtl` library. Values the file leaves out are
+/// `None` and keep whatever the material they're applied to has
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// `Kd`
+    pub diffuse: Option<[Float; 3]>,
+    /// `Ks`
+    pub specular: Option<[Float; 3]>,
+    /// `Ns`
+    pub shininess: Option<Float>,
+    /// `d`, or one minus `Tr`. 1.0 is opaque
+    pub dissolve: Option<Float>,
+    /// `Ni`, kept for reference as materials don't refract
+    pub refractive_index: Option<Float>,
+    /// `map_Kd`, kept for reference as materials can't be textured
+    pub diffuse_map: Option<String>,
+}
+
+impl MtlMaterial {
+    /// Every material in an `.mtl` library
+    pub fn parse(source: &str) -> io::Result<Vec<Self>> {
+        let mut materials: Vec<Self> = Vec::new();
+
+        for (number, line) in source.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let Some(keyword) = fields.next() else {
+                continue;
+            };
+            if keyword == "newmtl" {
+                materials.push(Self {
+                    name: rest(line, "newmtl"),
+                    ..Default::default()
+                });
+                continue;
+            }
+
+            // anything before the first `newmtl` has nothing to apply to
+            let Some(material) = materials.last_mut() else {
+                continue;
+            };
+            let colour = |fields| floats(fields).ok_or_else(|| invalid(number, &format!("{keyword} needs three values")));
+            let float = |fields| floats(fields).map(|[value]| value)
+                .ok_or_else(|| invalid(number, &format!("{keyword} needs a value")));
+
+            match keyword {
+                "Kd" => material.diffuse = Some(colour(fields)?),
+                "Ks" => material.specular = Some(colour(fields)?),
+                "Ns" => material.shininess = Some(float(fields)?),
+                "d" => material.dissolve = Some(float(fields)?),
+                "Tr" => material.dissolve = Some(1.0 - float(fields)?),
+                "Ni" => material.refractive_index = Some(float(fields)?),
+                "map_Kd" => material.diffuse_map = Some(rest(line, "map_Kd")),
+                _ => {},
+            }
+        }
+
+        Ok(materials)
+    }
+
+    /// `base` with the values this material sets. `Ks` is a colour but
+    /// specular highlights take the light's colour, so only its average is used
+    pub fn build(&self, base: Material) -> Material {
+        let mut material = base;
+        if let Some([r, g, b]) = self.diffuse {
+            material.colour = Colour::new(r, g, b);
+        }
+        if let Some([r, g, b]) = self.specular {
+            material.specular = (r + g + b) / 3.0;
+        }
+        if let Some(shininess) = self.shininess {
+            material.shininess = shininess;
+        }
+        if let Some(dissolve) = self.dissolve {
+            material.transparency = (1.0 - dissolve).clamp(0.0, 1.0);
+        }
+        material
+    }
+}
+
+/// The first `N` fields as floats, `None` if there are too few or one isn't a number
+fn floats<'a, const N: usize>(fields: impl Iterator<Item = &'a str>) -> Option<[Float; N]> {
+    let values: Vec<Float> = fields.take(N).map(str::parse).collect::<Result<_, _>>().ok()?;
+    values.try_into().ok()
+}
+
+/// Everything after `keyword`, for names that may contain spaces
+fn rest(line: &str, keyword: &str) -> String {
+    line.trim_start()[keyword.len()..].trim().to_string()
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {message}", line + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{intersection::Intersection, shape::Shape};
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let obj = ObjParser::parse("
+There was a young lady named Bright
+v -1 1 0
+v -1.0000 0.5000 0.0000
+v 1 0 0
+v 1 1 0
+v 0 2 0
+
+f 1 2 3
+g FirstGroup
+f 1/4/2 3 4
+g SecondGroup
+f 1 2 3 4 5
+f -5 -4 -3
+").unwrap();
+
+        assert_eq!(obj.ignored, 1);
+        assert_eq!(obj.vertices[1], Tuple::point(-1.0, 0.5, 0.0));
+        assert_eq!(obj.group(DEFAULT_GROUP), Some(vec![[0, 1, 2]]));
+        assert_eq!(obj.group("FirstGroup"), Some(vec![[0, 2, 3]]));
+        assert_eq!(obj.group("SecondGroup"), Some(vec![[0, 1, 2], [0, 2, 3], [0, 3, 4], [0, 1, 2]]));
+        assert_eq!(obj.group("ThirdGroup"), None);
+
+        let meshes = obj.meshes(Matrix::identity(), Material::default());
+        assert_eq!(meshes.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), [DEFAULT_GROUP, "FirstGroup", "SecondGroup"]);
+        assert_eq!(meshes[1].1.vertices(), [obj.vertices[0], obj.vertices[2], obj.vertices[3]]);
+        assert_eq!(meshes[1].1.faces(), [[0, 1, 2]]);
+        assert_eq!(obj.mesh(Matrix::identity(), Material::default()).faces().len(), 6);
+    }
+
+    #[test]
+    fn smooth() {
+        let obj = ObjParser::parse("
+v 0 1 0
+v -1 0 0
+v 1 0 0
+v 0 -1 0
+vt 0.5 1
+vt 0 0.5 0
+vt 1 0.5
+vt 0.25
+vn -1 0 -1
+vn 1 0 -1
+vn 0 0 -1
+f 1/1/3 2/2/1 3/3/2
+f 2//1 4//3 3//2
+f 2/2 4 3/3
+").unwrap();
+
+        assert_eq!(obj.uvs[1], [0.0, 0.5]);
+        assert_eq!(obj.uvs[3], [0.25, 0.0]);
+        assert_eq!(obj.normals[0], Tuple::vector(-1.0, 0.0, -1.0));
+        let group = &obj.groups[0];
+        assert_eq!(group.uvs, [Some([0, 1, 2]), None, None]);
+        assert_eq!(group.normals, [Some([2, 0, 1]), Some([0, 2, 1]), None]);
+
+        let mesh = obj.mesh(Matrix::identity(), Material::default());
+        let i = Intersection::with_face(1.0, &mesh, 0);
+        assert_eq!(mesh.normal(Tuple::point(-1.0, 0.0, 0.0), &i), Tuple::vector(-1.0, 0.0, -1.0).norm());
+        assert_eq!(mesh.uv(Tuple::point(-1.0, 0.0, 0.0), &i), Some([0.0, 0.5]));
+        // the face without normals stays flat
+        let i = Intersection::with_face(1.0, &mesh, 2);
+        assert_eq!(mesh.normal(Tuple::point(-0.5, -0.25, 0.0), &i), Tuple::vector(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(ObjParser::parse("v 1 2").is_err());
+        assert!(ObjParser::parse("v 1 2 x").is_err());
+        assert!(ObjParser::parse("v 1 2 3\nv 1 2 4\nv 1 3 3\nf 1 2 4").is_err());
+        assert!(ObjParser::parse("v 1 2 3\nf 1 1").is_err());
+        assert!(ObjParser::parse("v 1 2 3\nf 0 1 1").is_err());
+        assert!(ObjParser::parse("vn 1 2").is_err());
+        assert!(ObjParser::parse("vt").is_err());
+        assert!(ObjParser::parse("vt 1 x").is_err());
+        assert!(MtlMaterial::parse("newmtl red\nKd 1 0").is_err());
+    }
+
+    #[test]
+    fn materials() {
+        let materials = MtlMaterial::parse("
+# a comment
+newmtl red paint
+Kd 1 0 0
+Ks 0.5 0.2 0.2
+Ns 50
+d 0.75
+Ni 1.5
+map_Kd textures/red paint.png
+illum 2
+
+newmtl glass
+Tr 0.9
+").unwrap();
+
+        assert_eq!(materials[0], MtlMaterial {
+            name: "red paint".to_string(),
+            diffuse: Some([1.0, 0.0, 0.0]),
+            specular: Some([0.5, 0.2, 0.2]),
+            shininess: Some(50.0),
+            dissolve: Some(0.75),
+            refractive_index: Some(1.5),
+            diffuse_map: Some("textures/red paint.png".to_string()),
+        });
+
+        let material = materials[0].build(Material::default());
+        assert_eq!(material.colour, Colour::new(1.0, 0.0, 0.0));
+        assert!(crate::eq(material.specular, 0.3));
+        assert_eq!(material.shininess, 50.0);
+        assert_eq!(material.transparency, 0.25);
+        assert_eq!(material.diffuse, Material::default().diffuse);
+        assert!(crate::eq(materials[1].build(Material::default()).transparency, 0.9));
+    }
+
+    #[test]
+    fn load() {
+        let dir = std::env::temp_dir().join(format!("rosemary-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("paint.mtl"), "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n").unwrap();
+        fs::write(dir.join("model.obj"), "mtllib paint.mtl
+mtllib gone.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+usemtl red
+f 1 2 3
+g top
+usemtl blue
+f 1 2 3
+usemtl missing
+f 3 2 1
+").unwrap();
+        let obj = ObjParser::load(dir.join("model.obj"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let obj = obj.unwrap();
+        assert_eq!(obj.libraries, ["paint.mtl", "gone.mtl"]);
+        assert_eq!(obj.missing, ["gone.mtl"]);
+        assert_eq!(obj.groups.iter().map(|group| group.material.as_deref()).collect::<Vec<_>>(),
+            [Some("red"), Some("blue"), Some("missing")]);
+        assert_eq!(obj.group("top"), Some(vec![[0, 1, 2], [2, 1, 0]]));
+
+        let colours: Vec<_> = obj.meshes(Matrix::identity(), Material::default()).iter()
+            .map(|(_, mesh)| mesh.material.colour)
+            .collect();
+        assert_eq!(colours, [Colour::new(1.0, 0.0, 0.0), Colour::new(0.0, 0.0, 1.0), Colour::new(1.0, 1.0, 1.0)]);
+    }
+}
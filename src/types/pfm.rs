@@ -0,0 +1,136 @@
+//! Portable float map (`.pfm`) images, full precision floats that can be
+//! negative, which makes them suitable for data passes such as positions
+
+use std::{fs::File, io::{self, BufRead, BufReader, BufWriter, Read, Write}, path::Path};
+
+use crate::Float;
+use super::{canvas::Canvas, colour::Colour};
+
+pub fn load(path: impl AsRef<Path>) -> io::Result<Canvas> {
+    decode(BufReader::new(File::open(path)?))
+}
+
+pub fn save(canvas: &Canvas, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode(canvas, &mut writer)?;
+    writer.flush()
+}
+
+/// Writes a little endian colour PFM, rows go from bottom to top as the format expects
+pub fn encode(canvas: &Canvas, mut writer: impl Write) -> io::Result<()> {
+    write!(writer, "PF\n{} {}\n-1.0\n", canvas.width, canvas.height)?;
+
+    for y in (0..canvas.height).rev() {
+        for x in 0..canvas.width {
+            let colour = canvas[(x, y)];
+            for channel in [colour.r, colour.g, colour.b] {
+                // PFM is always single precision, the cast only does something with the f64 feature
+                #[allow(clippy::unnecessary_cast)]
+                writer.write_all(&(channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn decode(mut reader: impl BufRead) -> io::Result<Canvas> {
+    let mut header = Vec::new();
+    while header.len() < 3 {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of header"));
+        }
+        header.extend(line.split_whitespace().map(str::to_string));
+    }
+
+    let channels = match header[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("missing PFM magic")),
+    };
+    let width: usize = header[1].parse().map_err(|_| invalid("bad width"))?;
+    let height: usize = header.get(2).and_then(|h| h.parse().ok()).ok_or_else(|| invalid("bad height"))?;
+
+    let scale = match header.get(3) {
+        Some(scale) => scale.clone(),
+        None => {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            line.trim().to_string()
+        }
+    };
+    let scale: f32 = scale.parse().map_err(|_| invalid("bad scale"))?;
+    let little_endian = scale < 0.0;
+
+    Canvas::checked_size(width, height)?;
+
+    let mut rows = Vec::new();
+    let mut buf = [0u8; 4];
+    let mut read_float = |reader: &mut dyn Read| -> io::Result<Float> {
+        reader.read_exact(&mut buf)?;
+        Ok(if little_endian { f32::from_le_bytes(buf) } else { f32::from_be_bytes(buf) } as Float)
+    };
+
+    // grown a row at a time, so a header claiming more than the file has
+    // fails before much is allocated
+    for _ in 0..height {
+        let mut row = Vec::with_capacity(width);
+        for _ in 0..width {
+            row.push(if channels == 3 {
+                Colour::new(read_float(&mut reader)?, read_float(&mut reader)?, read_float(&mut reader)?)
+            }
+            else {
+                let grey = read_float(&mut reader)?;
+                Colour::new(grey, grey, grey)
+            });
+        }
+        rows.push(row);
+    }
+
+    // rows go from bottom to top
+    Ok(Canvas::from_pixels(width, height, rows.into_iter().rev().flatten().collect()))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut canvas = Canvas::new(3, 2);
+        canvas[(0, 0)] = Colour::new(-12.5, 0.25, 1e6);
+        canvas[(2, 1)] = Colour::new(1.0, -2.0, 3.0);
+
+        let mut data = Vec::new();
+        encode(&canvas, &mut data).unwrap();
+        assert!(data.starts_with(b"PF\n3 2\n-1.0\n"));
+        assert_eq!(data.len(), 12 + 3 * 2 * 12);
+
+        // bottom row comes first
+        assert_eq!(&data[12 + 24..12 + 28], &1.0_f32.to_le_bytes()[..]);
+        assert_eq!(&data[12 + 28..12 + 32], &(-2.0_f32).to_le_bytes()[..]);
+
+        let decoded = decode(&data[..]).unwrap();
+        assert_eq!(decoded[(0, 0)], canvas[(0, 0)]);
+        assert_eq!(decoded[(2, 1)], canvas[(2, 1)]);
+        assert_eq!(decoded[(1, 1)], Colour::black());
+    }
+
+    #[test]
+    fn decode_big_endian_grey() {
+        let mut data = b"Pf\n1 1\n1.0\n".to_vec();
+        data.extend(0.5_f32.to_be_bytes());
+        assert_eq!(decode(&data[..]).unwrap()[(0, 0)], Colour::new(0.5, 0.5, 0.5));
+
+        assert!(decode(&b"P6\n1 1\n255\n"[..]).is_err());
+        for header in ["PF\n0 1\n-1.0\n", "PF\n4294967296 4294967297\n-1.0\n", "PF\n3000000 3000000\n-1.0\n",
+            "Pf\n60000 60000\n-1.0\n\0\0\0\0"] {
+            assert_eq!(decode(header.as_bytes()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
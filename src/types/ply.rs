@@ -0,0 +1,354 @@
+//! Stanford `.ply` models, ASCII or binary of either byte order, as scanners
+//! and research datasets produce them. Vertices, faces and any per vertex
+//! colours and normals are read, other elements are skipped
+
+use std::{fs, io, path::Path};
+
+use crate::{Float, Matrix, Tuple};
+use super::{colour::Colour, material::Material, mesh::Mesh};
+
+/// Everything used from a PLY file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ply {
+    pub vertices: Vec<Tuple>,
+    /// Polygons split into triangle fans
+    pub faces: Vec<[usize; 3]>,
+    /// From `red`, `green` and `blue`, one per vertex when the file has them
+    pub colours: Option<Vec<Colour>>,
+    /// From `nx`, `ny` and `nz`, one per vertex when the file has them
+    pub normals: Option<Vec<Tuple>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Colours stored as integers run up to the type's maximum instead of 1.0
+    fn colour_scale(self) -> f64 {
+        match self {
+            Self::U8 => 255.0,
+            Self::U16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Property {
+    Scalar(String, Scalar),
+    /// Count type then item type
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Self::Scalar(name, _) | Self::List(name, ..) => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Values in the body, one after another whatever the format
+struct Values<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Values<'_> {
+    fn next(&mut self, scalar: Scalar) -> io::Result<f64> {
+        if self.format == Format::Ascii {
+            let rest = &self.bytes[self.position..];
+            let start = rest.iter().position(|b| !b.is_ascii_whitespace()).ok_or_else(|| invalid("file ends early"))?;
+            let end = rest[start..].iter().position(u8::is_ascii_whitespace).map_or(rest.len(), |len| start + len);
+            self.position += end;
+            return std::str::from_utf8(&rest[start..end]).ok()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| invalid("bad number"));
+        }
+
+        let bytes = self.bytes.get(self.position..self.position + scalar.size()).ok_or_else(|| invalid("file ends early"))?;
+        self.position += scalar.size();
+        let mut buf = [0; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        if self.format == Format::BigEndian {
+            buf[..bytes.len()].reverse();
+        }
+
+        Ok(match scalar {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(buf[..4].try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf),
+        })
+    }
+}
+
+impl Ply {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        const END: &[u8] = b"end_header";
+        let header_end = bytes.windows(END.len()).position(|window| window == END).ok_or_else(|| invalid("no end_header"))?;
+        // the body starts on the line after `end_header`
+        let body = bytes[header_end..].iter().position(|b| *b == b'\n').map_or(bytes.len(), |i| header_end + i + 1);
+        let header = std::str::from_utf8(&bytes[..header_end]).map_err(|_| invalid("header isn't valid text"))?;
+        let (format, elements) = parse_header(header)?;
+
+        let mut values = Values {
+            format,
+            bytes,
+            position: body,
+        };
+        let mut ply = Self::default();
+
+        for element in &elements {
+            let column = |name: &str| element.properties.iter().position(|property| property.name() == name);
+            let position = [column("x"), column("y"), column("z")];
+            let colour = [column("red"), column("green"), column("blue")];
+            let normal = [column("nx"), column("ny"), column("nz")];
+            let indices = column("vertex_indices").or_else(|| column("vertex_index"));
+
+            for _ in 0..element.count {
+                let mut scalars = Vec::with_capacity(element.properties.len());
+                let mut list = Vec::new();
+                for (i, property) in element.properties.iter().enumerate() {
+                    match property {
+                        Property::Scalar(_, scalar) => scalars.push(values.next(*scalar)? / colour_scale(&colour, i, *scalar)),
+                        Property::List(_, count, item) => {
+                            scalars.push(0.0);
+                            let count = values.next(*count)? as usize;
+                            let items = (0..count).map(|_| values.next(*item)).collect::<io::Result<Vec<_>>>()?;
+                            if Some(i) == indices {
+                                list = items;
+                            }
+                        },
+                    }
+                }
+
+                match element.name.as_str() {
+                    "vertex" => {
+                        let [x, y, z] = position.map(|column| column.map_or(0.0, |c| scalars[c] as Float));
+                        ply.vertices.push(Tuple::point(x, y, z));
+                        if let [Some(r), Some(g), Some(b)] = colour {
+                            ply.colours.get_or_insert_with(Vec::new)
+                                .push(Colour::new(scalars[r] as Float, scalars[g] as Float, scalars[b] as Float));
+                        }
+                        if let [Some(x), Some(y), Some(z)] = normal {
+                            ply.normals.get_or_insert_with(Vec::new)
+                                .push(Tuple::vector(scalars[x] as Float, scalars[y] as Float, scalars[z] as Float));
+                        }
+                    },
+                    "face" => {
+                        let list: Vec<usize> = list.iter().map(|i| *i as usize).collect();
+                        if list.len() < 3 {
+                            return Err(invalid("face needs at least three vertices"));
+                        }
+                        ply.faces.extend((1..list.len() - 1).map(|i| [list[0], list[i], list[i + 1]]));
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        if ply.faces.iter().flatten().any(|i| *i >= ply.vertices.len()) {
+            return Err(invalid("face uses a vertex that doesn't exist"));
+        }
+        Ok(ply)
+    }
+
+    /// The model as a flat shaded mesh. Meshes have one material, so any
+    /// vertex colours and normals are left out
+    pub fn mesh(&self, transform: Matrix, material: Material) -> Mesh {
+        Mesh::new(self.vertices.clone(), self.faces.clone(), transform, material)
+    }
+
+    /// Average of the vertex colours, for a material close to the model's
+    pub fn average_colour(&self) -> Option<Colour> {
+        let colours = self.colours.as_ref().filter(|colours| !colours.is_empty())?;
+        let sum = colours.iter().fold(Colour::black(), |sum, colour| sum + *colour);
+        Some(sum * (1.0 / colours.len() as Float))
+    }
+}
+
+/// What to divide a value by, only integers in colour columns are scaled
+fn colour_scale(colour: &[Option<usize>; 3], column: usize, scalar: Scalar) -> f64 {
+    if colour.contains(&Some(column)) { scalar.colour_scale() } else { 1.0 }
+}
+
+fn parse_header(header: &str) -> io::Result<(Format, Vec<Element>)> {
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(invalid("missing PLY magic"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        match fields[..] {
+            ["format", name, _] => format = Some(match name {
+                "ascii" => Format::Ascii,
+                "binary_little_endian" => Format::LittleEndian,
+                "binary_big_endian" => Format::BigEndian,
+                _ => return Err(invalid("unknown format")),
+            }),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid("bad element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let scalar = |name| Scalar::parse(name).ok_or_else(|| invalid("unknown property type"));
+                let property = Property::List(name.to_string(), scalar(count)?, scalar(item)?);
+                elements.last_mut().ok_or_else(|| invalid("property before any element"))?.properties.push(property);
+            },
+            ["property", scalar, name] => {
+                let scalar = Scalar::parse(scalar).ok_or_else(|| invalid("unknown property type"))?;
+                let property = Property::Scalar(name.to_string(), scalar);
+                elements.last_mut().ok_or_else(|| invalid("property before any element"))?.properties.push(property);
+            },
+            _ => {},
+        }
+    }
+
+    Ok((format.ok_or_else(|| invalid("missing format"))?, elements))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply
+format {format} 1.0
+comment a square in two colours
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element edge 1
+property int vertex1
+property int vertex2
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    fn expected() -> Ply {
+        let (red, blue) = (Colour::new(1.0, 0.0, 0.0), Colour::new(0.0, 0.0, 1.0));
+        Ply {
+            vertices: vec![Tuple::point(0.0, 0.0, 0.0), Tuple::point(1.0, 0.0, 0.0), Tuple::point(1.0, 1.0, 0.0),
+                Tuple::point(0.0, 1.0, 0.0)],
+            faces: vec![[0, 1, 2], [0, 2, 3]],
+            colours: Some(vec![red, red, blue, blue]),
+            normals: Some(vec![Tuple::vector(0.0, 0.0, -1.0); 4]),
+        }
+    }
+
+    #[test]
+    fn ascii() {
+        let source = HEADER.replace("{format}", "ascii") + "0 0 0 0 0 -1 255 0 0
+1 0 0 0 0 -1 255 0 0
+1 1 0 0 0 -1 0 0 255
+0 1 0 0 0 -1 0 0 255
+0 1
+4 0 1 2 3
+";
+        let ply = Ply::decode(source.as_bytes()).unwrap();
+        assert_eq!(ply, expected());
+        assert_eq!(ply.average_colour(), Some(Colour::new(0.5, 0.0, 0.5)));
+        assert_eq!(ply.mesh(Matrix::identity(), Material::default()).faces(), [[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn binary() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut bytes = HEADER.replace("{format}", format).into_bytes();
+            let corners: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+            for (i, [x, y]) in corners.into_iter().enumerate() {
+                for value in [x, y, 0.0, 0.0, 0.0, -1.0] {
+                    bytes.extend(if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+                }
+                bytes.extend(if i < 2 { [255, 0, 0] } else { [0, 0, 255] });
+            }
+            let int = |value: i32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+            // the edge, then the face with its vertex count
+            bytes.extend([0, 1].map(int).as_flattened());
+            bytes.push(4);
+            bytes.extend([0, 1, 2, 3].map(int).as_flattened());
+
+            assert_eq!(Ply::decode(&bytes).unwrap(), expected(), "{format}");
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Ply::decode(b"not a ply").is_err());
+        assert!(Ply::decode(b"ply\nend_header\n").is_err());
+        assert!(Ply::decode(b"ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nend_header\n0\n").is_err());
+        assert!(Ply::decode(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n0\n3 0 1 2\n").is_err());
+    }
+}
@@ -0,0 +1,275 @@
+//! PNG (`.png`) images with an alpha channel. The zlib stream only uses
+//! stored blocks, so there's no compressor to depend on; files come out
+//! about as big as a BMP but every viewer and browser opens them
+
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+
+use super::{canvas::{channel_to_byte, Canvas}, metadata::Metadata};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Longest stored deflate block
+const MAX_STORED: usize = 65535;
+/// IDAT chunks are split at this size, well under the format's 2^31 - 1
+const MAX_IDAT: usize = 1 << 20;
+/// Colour type for red, green, blue, alpha
+const RGBA: u8 = 6;
+/// Longest tEXt keyword
+const MAX_KEYWORD: usize = 79;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn save(canvas: &Canvas, path: impl AsRef<Path>, sixteen_bit: bool) -> io::Result<()> {
+    save_with_metadata(canvas, path, sixteen_bit, &Metadata::new())
+}
+
+pub fn save_with_metadata(canvas: &Canvas, path: impl AsRef<Path>, sixteen_bit: bool, metadata: &Metadata) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode_with_metadata(canvas, &mut writer, sixteen_bit, metadata)?;
+    writer.flush()
+}
+
+/// Writes red, green, blue, alpha pixels from the top row down, 8 bits per
+/// channel or 16 if `sixteen_bit` is set
+pub fn encode(canvas: &Canvas, writer: impl Write, sixteen_bit: bool) -> io::Result<()> {
+    encode_with_metadata(canvas, writer, sixteen_bit, &Metadata::new())
+}
+
+/// `encode` with a tEXt chunk for each entry of `metadata`. Keys are cut
+/// off at the format's 79 bytes, and anything outside Latin-1 becomes `?`
+pub fn encode_with_metadata(canvas: &Canvas, mut writer: impl Write, sixteen_bit: bool, metadata: &Metadata) -> io::Result<()> {
+    let too_big = |_| io::Error::new(io::ErrorKind::InvalidInput, "canvas too big for a PNG");
+    let width = u32::try_from(canvas.width).map_err(too_big)?;
+    let height = u32::try_from(canvas.height).map_err(too_big)?;
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "a PNG can't be empty"));
+    }
+
+    writer.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // bit depth and colour type, then default compression, filtering and
+    // no interlacing
+    header.extend_from_slice(&[if sixteen_bit { 16 } else { 8 }, RGBA, 0, 0, 0]);
+    write_chunk(&mut writer, b"IHDR", &header)?;
+
+    for (key, value) in metadata.iter() {
+        let mut keyword = latin1(key.trim());
+        keyword.truncate(MAX_KEYWORD);
+        if keyword.is_empty() {
+            continue;
+        }
+        let text = [keyword, vec![0], latin1(value)].concat();
+        write_chunk(&mut writer, b"tEXt", &text)?;
+    }
+
+    let channels = if sixteen_bit { 8 } else { 4 };
+    let mut raw = Vec::with_capacity(canvas.height * (1 + canvas.width * channels));
+    for y in 0..canvas.height {
+        // no filter
+        raw.push(0);
+        for x in 0..canvas.width {
+            let colour = canvas[(x, y)];
+            for channel in [colour.r, colour.g, colour.b, canvas.alpha(x, y)] {
+                if sixteen_bit {
+                    // as with 8 bits, anything outside 0.0 to 1.0 saturates
+                    raw.extend_from_slice(&((channel * 65536.0) as u16).to_be_bytes());
+                }
+                else {
+                    raw.push(channel_to_byte(channel));
+                }
+            }
+        }
+    }
+
+    for data in zlib_stored(&raw).chunks(MAX_IDAT) {
+        write_chunk(&mut writer, b"IDAT", data)?;
+    }
+    write_chunk(&mut writer, b"IEND", &[])
+}
+
+/// Length, type, data and the CRC of the type and data
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc(kind.iter().chain(data)).to_be_bytes())
+}
+
+fn latin1(text: &str) -> Vec<u8> {
+    text.chars().map(|c| u8::try_from(c).unwrap_or(b'?')).collect()
+}
+
+fn crc<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    !bytes.into_iter().fold(!0, |c, &byte| CRC_TABLE[((c ^ byte as u32) & 0xFF) as usize] ^ (c >> 8))
+}
+
+/// `data` as a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(MAX_STORED);
+    let mut stream = Vec::with_capacity(2 + data.len() + blocks * 5 + 4);
+    // deflate with a 32K window, no dictionary, fastest
+    stream.extend_from_slice(&[0x78, 0x01]);
+
+    for (i, block) in data.chunks(MAX_STORED).enumerate() {
+        let len = block.len() as u16;
+        stream.push((i + 1 == blocks) as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1, 0);
+    // 5552 bytes is the most that can be summed before `b` could overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        (a, b) = (a % MOD, b % MOD);
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::colour::Colour;
+
+    /// The chunks of `data` as their types and contents, checking the CRCs
+    fn chunks(data: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(data[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &data[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+            let body = &rest[8..8 + len];
+            assert_eq!(rest[8 + len..12 + len], crc(kind.iter().chain(body)).to_be_bytes());
+            chunks.push((kind, body.to_vec()));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    /// Undo `zlib_stored`, checking the block headers and checksum
+    fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(stream[..2], [0x78, 0x01]);
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+        let mut data = Vec::new();
+        let mut rest = &stream[2..];
+        loop {
+            let last = rest[0] == 1;
+            let len = u16::from_le_bytes([rest[1], rest[2]]);
+            assert_eq!(u16::from_le_bytes([rest[3], rest[4]]), !len);
+            data.extend_from_slice(&rest[5..5 + len as usize]);
+            rest = &rest[5 + len as usize..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(rest, adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        // long enough that the sums would overflow without reducing them
+        let (a, b) = (0..100_000).fold((1u64, 0u64), |(a, b), _| (a + 255, b + a + 255));
+        assert_eq!(adler32(&[0xFF; 100_000]), ((b % 65521) << 16) as u32 | (a % 65521) as u32);
+    }
+
+    #[test]
+    fn encode() {
+        let mut canvas = Canvas::new(2, 2);
+        canvas[(0, 0)] = Colour::new(1.0, 0.5, 0.0);
+        canvas[(1, 1)] = Colour::new(0.0, 0.0, 2.0);
+
+        let mut data = Vec::new();
+        super::encode(&canvas, &mut data, false).unwrap();
+        let chunks = chunks(&data);
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 2, 8, RGBA, 0, 0, 0]);
+
+        let raw = inflate_stored(&chunks[1].1);
+        assert_eq!(raw, [
+            0, 255, 128, 0, 255, 0, 0, 0, 255,
+            0, 0, 0, 0, 255, 0, 0, 255, 255,
+        ]);
+    }
+
+    #[test]
+    fn encode_with_metadata() {
+        let mut metadata = Metadata::new();
+        metadata.set("scene", "café.yaml")
+            .set("camera", "40x30 ☃")
+            .set(&"k".repeat(100), "long");
+
+        let mut data = Vec::new();
+        super::encode_with_metadata(&Canvas::new(1, 1), &mut data, false, &metadata).unwrap();
+        let text: Vec<_> = chunks(&data).into_iter().filter(|(kind, _)| kind == b"tEXt").map(|(_, text)| text).collect();
+        assert_eq!(text[0], b"scene\0caf\xE9.yaml");
+        assert_eq!(text[1], b"camera\x0040x30 ?");
+        assert_eq!(text[2], [&[b'k'; 79][..], b"\0long"].concat());
+        // the text comes before the image data
+        assert_eq!(chunks(&data)[1].0, *b"tEXt");
+    }
+
+    #[test]
+    fn encode_16_bit() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas[(0, 0)] = Colour::new(1.5, 0.5, 0.001);
+
+        let mut data = Vec::new();
+        super::encode(&canvas, &mut data, true).unwrap();
+        let chunks = chunks(&data);
+        assert_eq!(chunks[0].1[8], 16);
+        assert_eq!(inflate_stored(&chunks[1].1), [0, 0xFF, 0xFF, 0x80, 0x00, 0x00, 0x41, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn encode_many_blocks() {
+        // rows of 4 * 20000 bytes each need more than one stored block
+        let canvas = Canvas::new(20_000, 2);
+        let mut data = Vec::new();
+        super::encode(&canvas, &mut data, false).unwrap();
+
+        let raw = inflate_stored(&chunks(&data)[1].1);
+        assert_eq!(raw.len(), 2 * (1 + 20_000 * 4));
+        assert!(raw.chunks(1 + 20_000 * 4).all(|row| row[0] == 0 && row[1..].chunks(4).all(|pixel| pixel == [0, 0, 0, 255])));
+    }
+
+    #[test]
+    fn encode_empty() {
+        let err = super::encode(&Canvas::new(0, 3), &mut Vec::new(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,100 @@
+//! Window showing a render as it happens, behind the `preview` feature, so
+//! bad setups can be spotted and abandoned without waiting for the end
+
+use std::time::{Duration, Instant};
+
+use minifb::{Key, Window, WindowOptions};
+
+use super::{camera::Camera, canvas::{channel_to_byte, Canvas}, tonemap::ToneMapping, world::World};
+
+/// Don't redraw the window more often than this, redrawing for every row
+/// of a quick render would take longer than the render itself
+const REDRAW_INTERVAL: Duration = Duration::from_millis(30);
+
+pub struct Preview {
+    window: Window,
+    /// 0RGB pixels as minifb wants them
+    buffer: Vec<u32>,
+    width: usize,
+    height: usize,
+    last_redraw: Instant,
+    pub tone_mapping: ToneMapping,
+}
+
+impl Preview {
+    pub fn new(title: &str, width: usize, height: usize) -> Result<Self, minifb::Error> {
+        let mut window = Window::new(title, width, height, WindowOptions::default())?;
+        // redraws are throttled here instead, minifb's limit would slow rendering down
+        window.set_target_fps(0);
+
+        Ok(Self {
+            window,
+            buffer: vec![0; width * height],
+            width,
+            height,
+            last_redraw: Instant::now(),
+            tone_mapping: ToneMapping::default(),
+        })
+    }
+
+    /// Until the window is closed or escape is pressed
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    /// Copy a finished row into the window, returning whether to carry on
+    pub fn show_row(&mut self, y: usize, row: &Canvas) -> bool {
+        self.copy_row(y, row, 0);
+
+        if y + 1 == self.height || self.last_redraw.elapsed() >= REDRAW_INTERVAL {
+            self.redraw();
+        }
+
+        self.is_open()
+    }
+
+    /// Copy a whole image into the window and redraw, returning whether to carry on
+    pub fn show(&mut self, image: &Canvas) -> bool {
+        for y in 0..image.height.min(self.height) {
+            self.copy_row(y, image, y);
+        }
+
+        self.redraw();
+        self.is_open()
+    }
+
+    /// Render with `camera` coarse to fine, see `Camera::render_progressive`,
+    /// so a blocky version of the whole image shows up almost straight away
+    pub fn render_progressive(&mut self, camera: &Camera, world: &World) -> Canvas {
+        camera.render_progressive(world, |_, image| self.show(image))
+    }
+
+    /// Render with `camera`, showing rows as they finish. Closing the window
+    /// stops the render early and returns what was done so far
+    pub fn render(&mut self, camera: &Camera, world: &World) -> Canvas {
+        camera.render_rows(world, |y, row| self.show_row(y, row))
+    }
+
+    /// Keep the window up until it's closed
+    pub fn wait(&mut self) {
+        self.window.set_target_fps(60);
+        while self.is_open() {
+            self.window.update();
+        }
+    }
+
+    /// Copy row `source_y` of `image` to row `y` of the window
+    fn copy_row(&mut self, y: usize, image: &Canvas, source_y: usize) {
+        let start = y * self.width;
+        for (x, pixel) in self.buffer[start..start + image.width.min(self.width)].iter_mut().enumerate() {
+            let colour = self.tone_mapping.apply(image[(x, source_y)]);
+            *pixel = u32::from_be_bytes([0, channel_to_byte(colour.r), channel_to_byte(colour.g), channel_to_byte(colour.b)]);
+        }
+    }
+
+    fn redraw(&mut self) {
+        // errors only happen for a buffer of the wrong size, which can't happen here
+        let _ = self.window.update_with_buffer(&self.buffer, self.width, self.height);
+        self.last_redraw = Instant::now();
+    }
+}
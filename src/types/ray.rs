@@ -1,4 +1,7 @@
-use crate::{Matrix, Tuple};
+use crate::{Float, Matrix, RosemaryError, Tuple};
+
+/// Rays intersected together by `Shape::intersect_packet`, one per SIMD lane
+pub const PACKET_SIZE: usize = 4;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
@@ -7,6 +10,7 @@ pub struct Ray {
 }
 
 impl Ray {
+    /// Panics if `origin` isn't a point or `direction` a vector, see `try_new`
     pub fn new(origin: Tuple, direction: Tuple) -> Self {
         assert!(origin.is_point(), "Origin must be a point");
         assert!(direction.is_vector(), "Direction must be a vector");
@@ -17,21 +21,31 @@ impl Ray {
         }
     }
 
-    pub fn position(&self, distance: f32) -> Tuple {
+    pub fn try_new(origin: Tuple, direction: Tuple) -> Result<Self, RosemaryError> {
+        if !origin.is_point() {
+            return Err(RosemaryError::NotAPoint("ray origin"));
+        }
+        if !direction.is_vector() {
+            return Err(RosemaryError::NotAVector("ray direction"));
+        }
+        Ok(Self::new(origin, direction))
+    }
+
+    pub fn position(&self, distance: Float) -> Tuple {
         self.origin + self.direction * distance
     }
 
-    pub fn transform(&self, transformation: Matrix) -> Self {
+    pub fn transform(&self, transformation: &Matrix) -> Self {
         Self {
-            origin: self.origin * transformation,
-            direction: self.direction * transformation,
+            origin: transformation * self.origin,
+            direction: transformation * self.direction,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Matrix, Tuple};
+    use crate::{Matrix, RosemaryError, Tuple};
     use super::Ray;
 
     #[test]
@@ -41,6 +55,10 @@ mod tests {
         let ray = Ray::new(origin, direction);
         assert_eq!(ray.origin, origin);
         assert_eq!(ray.direction, direction);
+
+        assert!(Ray::try_new(origin, direction).is_ok());
+        assert_eq!(Ray::try_new(direction, direction).unwrap_err(), RosemaryError::NotAPoint("ray origin"));
+        assert_eq!(Ray::try_new(origin, origin).unwrap_err(), RosemaryError::NotAVector("ray direction"));
     }
 
     #[test]
@@ -57,13 +75,13 @@ mod tests {
     fn transform() {
         let ray = Ray::new(Tuple::point(1.0, 2.0, 3.0),
             Tuple::vector(0.0, 1.0, 0.0));
-        let transformed = ray.transform(Matrix::translation(3.0, 4.0, 5.0));
+        let transformed = ray.transform(&Matrix::translation(3.0, 4.0, 5.0));
         assert_eq!(transformed.origin, Tuple::point(4.0, 6.0, 8.0));
         assert_eq!(transformed.direction, Tuple::vector(0.0, 1.0, 0.0));
 
         let ray = Ray::new(Tuple::point(1.0, 2.0, 3.0),
             Tuple::vector(0.0, 1.0, 0.0));
-        let transformed = ray.transform(Matrix::scaling(2.0, 3.0, 4.0));
+        let transformed = ray.transform(&Matrix::scaling(2.0, 3.0, 4.0));
         assert_eq!(transformed.origin, Tuple::point(2.0, 6.0, 12.0));
         assert_eq!(transformed.direction, Tuple::vector(0.0, 3.0, 0.0));
     }
@@ -0,0 +1,115 @@
+use crate::consts::PI;
+
+use crate::{Float, Tuple};
+use super::ray::Ray;
+
+/// Small, seedable pseudo random number generator (xorshift64*).
+///
+/// Not suitable for anything security related, but deterministic across
+/// platforms which keeps procedural content and test renders reproducible.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix the seed so nearby seeds don't give similar sequences
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        Self {
+            // xorshift gets stuck on zero
+            state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform float in `[0, 1)`
+    pub fn next_float(&mut self) -> Float {
+        // top 24 bits fit exactly in an f32 mantissa, and so in an f64's too
+        (self.next_u64() >> 40) as Float / (1u64 << 24) as Float
+    }
+
+    /// Uniform float in `[min, max)`
+    pub fn range(&mut self, min: Float, max: Float) -> Float {
+        min + (max - min) * self.next_float()
+    }
+}
+
+/// Anything that can hand out uniform floats, so callers can drive sampling
+/// with their own generator or a low discrepancy sequence
+pub trait RandomSource {
+    /// Uniform float in `[0, 1)`
+    fn next_float(&mut self) -> Float;
+}
+
+impl RandomSource for Rng {
+    fn next_float(&mut self) -> Float {
+        Rng::next_float(self)
+    }
+}
+
+/// FNV-1a style hash of some floats' bits, for seeding generators from
+/// positions so the same point always gets the same random numbers
+// the bits are already a u64 with the f64 feature
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn hash_floats(values: &[Float]) -> u64 {
+    values.iter()
+        .fold(0xCBF2_9CE4_8422_2325, |hash, f| (hash ^ f.to_bits() as u64).wrapping_mul(0x100_0000_01B3))
+}
+
+pub(crate) fn hash_ray(ray: Ray) -> u64 {
+    hash_floats(&[ray.origin.x, ray.origin.y, ray.origin.z, ray.direction.x, ray.direction.y, ray.direction.z])
+}
+
+/// Random direction in the hemisphere around `normal`, more likely near the normal
+pub(crate) fn cosine_direction(normal: Tuple, rng: &mut impl RandomSource) -> Tuple {
+    let phi = 2.0 * PI * rng.next_float();
+    let r2 = rng.next_float();
+    let r = r2.sqrt();
+
+    // orthonormal basis around the normal
+    let helper = if normal.x.abs() > 0.9 { Tuple::vector(0.0, 1.0, 0.0) } else { Tuple::vector(1.0, 0.0, 0.0) };
+    let tangent = helper.cross(normal).norm();
+    let bitangent = normal.cross(tangent);
+
+    (tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + normal * (1.0 - r2).sqrt()).norm()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        let mut c = Rng::new(43);
+        assert_ne!(Rng::new(42).next_u64(), c.next_u64());
+    }
+
+    #[test]
+    fn next_float() {
+        let mut rng = Rng::new(0);
+        for _ in 0..1000 {
+            let f = rng.next_float();
+            assert!((0.0..1.0).contains(&f));
+
+            let r = rng.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&r));
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{Float, types::{material::Material, mesh::{icosphere, Mesh}, noise::Perlin, rng::Rng}, Matrix, Tuple};
+
+/// Procedural asteroid/rock generator.
+///
+/// Starts from an icosphere, stretches it along random axes, displaces each
+/// vertex with fractal noise and pushes in a few craters. The same settings
+/// and seed always produce the same mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RockGenerator {
+    pub seed: u64,
+    /// Icosphere subdivisions, each level has 4x the faces of the last
+    pub subdivisions: usize,
+    /// How far the noise can push vertices in or out, relative to the radius
+    pub roughness: Float,
+    /// Scale of the largest noise features
+    pub frequency: Float,
+    pub octaves: usize,
+    /// Maximum amount each axis can be stretched or squashed by
+    pub elongation: Float,
+    pub craters: usize,
+}
+
+impl RockGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+
+    pub fn generate(&self) -> Mesh {
+        self.generate_with(Matrix::identity(), Material::default())
+    }
+
+    pub fn generate_with(&self, transform: Matrix, material: Material) -> Mesh {
+        let mut rng = Rng::new(self.seed);
+        let perlin = Perlin::new(rng.next_u64());
+        let (vertices, faces) = icosphere(self.subdivisions);
+
+        let stretch = (
+            1.0 + rng.range(-self.elongation, self.elongation),
+            1.0 + rng.range(-self.elongation, self.elongation),
+            1.0 + rng.range(-self.elongation, self.elongation),
+        );
+
+        // random noise offset so different seeds sample different areas
+        let offset = Tuple::vector(rng.range(-100.0, 100.0), rng.range(-100.0, 100.0), rng.range(-100.0, 100.0));
+
+        let craters: Vec<(Tuple, Float)> = (0..self.craters)
+            .map(|_| (random_unit_vector(&mut rng), rng.range(0.15, 0.45)))
+            .collect();
+
+        let vertices = vertices.into_iter().map(|vertex| {
+            let dir = vertex - Tuple::point(0.0, 0.0, 0.0);
+
+            let sample = Tuple::point(dir.x * self.frequency, dir.y * self.frequency, dir.z * self.frequency) + offset;
+            let mut radius = 1.0 + perlin.fbm(sample, self.octaves, 0.5) * self.roughness;
+
+            for (centre, size) in &craters {
+                // 0 at the crater rim, 1 at its centre
+                let dist = (dir - *centre).magnitude() / size;
+                if dist < 1.0 {
+                    radius -= (1.0 - dist * dist) * size * 0.25;
+                }
+            }
+
+            Tuple::point(dir.x * radius * stretch.0, dir.y * radius * stretch.1, dir.z * radius * stretch.2)
+        }).collect();
+
+        Mesh::new(vertices, faces, transform, material)
+    }
+}
+
+impl Default for RockGenerator {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            subdivisions: 3,
+            roughness: 0.3,
+            frequency: 1.5,
+            octaves: 4,
+            elongation: 0.3,
+            craters: 3,
+        }
+    }
+}
+
+fn random_unit_vector(rng: &mut Rng) -> Tuple {
+    loop {
+        let v = Tuple::vector(rng.range(-1.0, 1.0), rng.range(-1.0, 1.0), rng.range(-1.0, 1.0));
+        let mag = v.magnitude();
+        if mag > 0.001 && mag <= 1.0 {
+            return v / mag;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{ray::Ray, shape::Shape};
+
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let a = RockGenerator::new(1234).generate();
+        let b = RockGenerator::new(1234).generate();
+        assert_eq!(a.vertices(), b.vertices());
+        assert_eq!(a.faces(), b.faces());
+
+        let c = RockGenerator::new(4321).generate();
+        assert_ne!(a.vertices(), c.vertices());
+    }
+
+    #[test]
+    fn generate() {
+        let generator = RockGenerator {
+            subdivisions: 2,
+            ..RockGenerator::new(99)
+        };
+        let rock = generator.generate();
+        assert_eq!(rock.faces().len(), 320);
+
+        let max_radius = (1.0 + 2.0 * generator.roughness) * (1.0 + generator.elongation);
+        for v in rock.vertices() {
+            let radius = (*v - Tuple::point(0.0, 0.0, 0.0)).magnitude();
+            assert!(radius > 0.0 && radius < max_radius);
+        }
+
+        // still a closed surface
+        let r = Ray::new(Tuple::point(0.1, 0.2, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(rock.intersect(r).len(), 2);
+    }
+}
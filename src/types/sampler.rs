@@ -0,0 +1,347 @@
+//! Low discrepancy sequences for jittered samples. Their points spread out
+//! evenly however many are taken, so averages of them settle down much
+//! faster than averages of independent random points
+
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Float;
+use super::rng::{RandomSource, Rng};
+
+/// Sequence of points in the unit square
+pub trait Sampler: Send + Sync {
+    /// The `index`th point, each coordinate in `[0, 1)`
+    fn point(&self, index: u64) -> [Float; 2];
+}
+
+/// Radical inverses in bases 2 and 3
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Halton;
+
+impl Sampler for Halton {
+    fn point(&self, index: u64) -> [Float; 2] {
+        [radical_inverse(index, 2), radical_inverse(index, 3)]
+    }
+}
+
+/// First two dimensions of the Sobol sequence, every power of two run of
+/// points is stratified in both directions at once
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sobol;
+
+impl Sampler for Sobol {
+    fn point(&self, index: u64) -> [Float; 2] {
+        // the second dimension's direction numbers, each one shifted and xored into itself
+        let (mut y, mut direction) = (0u32, 1u32 << 31);
+        for bit in 0..32 {
+            if (index >> bit) & 1 == 1 {
+                y ^= direction;
+            }
+            direction ^= direction >> 1;
+        }
+
+        [bits_to_float((index as u32).reverse_bits()), bits_to_float(y)]
+    }
+}
+
+/// Fixed set of points with no two close together, from Mitchell's best
+/// candidate algorithm. Indices past the end wrap around
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlueNoise {
+    points: Vec<[Float; 2]>,
+}
+
+impl BlueNoise {
+    /// Points the shared set has, enough for any sensible sample count
+    const SHARED_POINTS: usize = 256;
+
+    /// `count` points, each the furthest from the earlier ones out of a few
+    /// random candidates. Distances wrap around the edges so tiles join up
+    pub fn new(count: usize, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut points: Vec<[Float; 2]> = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let candidates = points.len() + 1;
+            let best = (0..candidates)
+                .map(|_| [rng.next_float(), rng.next_float()])
+                .map(|candidate| (nearest_distance(&points, candidate), candidate))
+                .fold((-1.0, [0.0; 2]), |best, next| if next.0 > best.0 { next } else { best });
+            points.push(best.1);
+        }
+
+        Self {
+            points,
+        }
+    }
+
+    /// One set made the first time it's needed and shared by every render
+    pub fn shared() -> &'static Self {
+        static SHARED: OnceLock<BlueNoise> = OnceLock::new();
+        SHARED.get_or_init(|| Self::new(Self::SHARED_POINTS, 0))
+    }
+}
+
+impl Sampler for BlueNoise {
+    fn point(&self, index: u64) -> [Float; 2] {
+        self.points[(index % self.points.len() as u64) as usize]
+    }
+}
+
+/// Which sequence jittered samples follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SamplerKind {
+    /// Independent random points
+    #[default]
+    Random,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    /// Values for one pixel or shaded point, all decided by `seed`
+    pub fn samples(self, seed: u64) -> Samples {
+        let mut rng = Rng::new(seed);
+        let Some(sampler) = self.sequence() else {
+            return Samples::Random(rng);
+        };
+
+        Samples::Sequence {
+            sampler,
+            index: 0,
+            // the same sequence everywhere would line up into patterns, so
+            // each stream shifts it by its own random amount
+            offset: [rng.next_float(), rng.next_float()],
+            shifts: None,
+            pending: None,
+        }
+    }
+
+    /// Values for sample `index` of a pixel whose samples are all decided by
+    /// `seed`. Every pair of values is point `index` of the sequence, each
+    /// pair under its own shift, and the shifts are the same for all of the
+    /// pixel's samples. So the first pairs of a pixel's samples spread evenly,
+    /// and so do the second pairs and so on. Random samples are independent
+    pub fn sample(self, seed: u64, index: u64) -> Samples {
+        let mut rng = Rng::new(seed);
+        let Some(sampler) = self.sequence() else {
+            return Samples::Random(Rng::new(seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15)));
+        };
+
+        Samples::Sequence {
+            sampler,
+            index,
+            offset: [rng.next_float(), rng.next_float()],
+            shifts: Some(rng),
+            pending: None,
+        }
+    }
+
+    fn sequence(self) -> Option<&'static dyn Sampler> {
+        match self {
+            Self::Random => None,
+            Self::Halton => Some(&Halton),
+            Self::Sobol => Some(&Sobol),
+            Self::BlueNoise => Some(BlueNoise::shared()),
+        }
+    }
+}
+
+/// Which sample of which pixel a camera ray is, so sampling along its path
+/// can follow the pixel's sequence, see `SamplerKind::sample`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SampleId {
+    /// Seed shared by every sample of the pixel
+    pub pixel: u64,
+    pub index: u64,
+}
+
+/// Stream of uniform values from a `SamplerKind`. Sequence values come in
+/// pairs, the two coordinates of one point, so code taking two values per
+/// sample gets a whole point of the sequence each time
+pub enum Samples {
+    Random(Rng),
+    Sequence {
+        sampler: &'static dyn Sampler,
+        index: u64,
+        offset: [Float; 2],
+        /// Where the offsets for points after the first come from when the
+        /// stream keeps to one index, `None` to step through the sequence
+        shifts: Option<Rng>,
+        /// Second coordinate of the last point, still to be handed out
+        pending: Option<Float>,
+    },
+}
+
+impl Samples {
+    /// Next value in `[0, 1)`
+    pub fn next_float(&mut self) -> Float {
+        match self {
+            Self::Random(rng) => rng.next_float(),
+            Self::Sequence { sampler, index, offset, shifts, pending } => {
+                if let Some(value) = pending.take() {
+                    return value;
+                }
+
+                let [x, y] = sampler.point(*index);
+                let [dx, dy] = *offset;
+                match shifts {
+                    Some(rng) => *offset = [rng.next_float(), rng.next_float()],
+                    None => *index += 1,
+                }
+                *pending = Some(wrap(y + dy));
+                wrap(x + dx)
+            },
+        }
+    }
+}
+
+impl RandomSource for Samples {
+    fn next_float(&mut self) -> Float {
+        Samples::next_float(self)
+    }
+}
+
+impl std::fmt::Debug for Samples {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Random(rng) => f.debug_tuple("Random").field(rng).finish(),
+            Self::Sequence { index, offset, .. } => f.debug_struct("Sequence")
+                .field("index", index)
+                .field("offset", offset)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// `index` with its base `base` digits mirrored around the point
+fn radical_inverse(mut index: u64, base: u64) -> Float {
+    let (mut result, mut scale) = (0.0, 1.0 / base as f64);
+    while index > 0 {
+        result += (index % base) as f64 * scale;
+        index /= base;
+        scale /= base as f64;
+    }
+
+    // rounding to an f32 could land on 1.0
+    (result as Float).min(ONE_BELOW)
+}
+
+/// Fixed point fraction to a float, keeping only bits an f32 can hold
+fn bits_to_float(bits: u32) -> Float {
+    (bits >> 8) as Float / (1u32 << 24) as Float
+}
+
+/// Largest float below 1.0 at f32 precision
+const ONE_BELOW: Float = 1.0 - 1.0 / (1u32 << 24) as Float;
+
+/// `value` in `[0, 2)` back into `[0, 1)`
+fn wrap(value: Float) -> Float {
+    if value >= 1.0 { value - 1.0 } else { value }
+}
+
+/// Distance from `point` to the closest of `points` on the wrapped unit square
+fn nearest_distance(points: &[[Float; 2]], point: [Float; 2]) -> Float {
+    points.iter()
+        .map(|other| {
+            let dx = (other[0] - point[0]).abs();
+            let dy = (other[1] - point[1]).abs();
+            let (dx, dy) = (dx.min(1.0 - dx), dy.min(1.0 - dy));
+            dx * dx + dy * dy
+        })
+        .fold(Float::INFINITY, Float::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences() {
+        assert_eq!(Halton.point(0), [0.0, 0.0]);
+        let close = |[x, y]: [Float; 2], expected: [Float; 2]| crate::eq(x, expected[0]) && crate::eq(y, expected[1]);
+        assert!(close(Halton.point(1), [0.5, 1.0 / 3.0]));
+        assert!(close(Halton.point(5), [0.625, 7.0 / 9.0]));
+
+        let sobol: Vec<_> = (0..4).map(|i| Sobol.point(i)).collect();
+        assert_eq!(sobol, [[0.0, 0.0], [0.5, 0.5], [0.25, 0.75], [0.75, 0.25]]);
+
+        // every run of 16 has one point in each quarter by quarter cell
+        let mut cells: Vec<_> = (16..32).map(|i| Sobol.point(i)).map(|[x, y]| ((x * 4.0) as usize, (y * 4.0) as usize)).collect();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(cells.len(), 16);
+    }
+
+    #[test]
+    fn blue_noise() {
+        let closest = |points: &[[Float; 2]]| (1..points.len())
+            .map(|i| nearest_distance(&points[..i], points[i]))
+            .fold(Float::INFINITY, Float::min);
+
+        let blue = BlueNoise::new(64, 1);
+        let mut rng = Rng::new(1);
+        let random: Vec<_> = (0..64).map(|_| [rng.next_float(), rng.next_float()]).collect();
+        assert!(closest(&blue.points) > closest(&random) * 4.0);
+        assert_eq!(blue.point(64), blue.point(0));
+    }
+
+    #[test]
+    fn samples() {
+        for kind in [SamplerKind::Random, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise] {
+            let values: Vec<_> = (0..100).scan(kind.samples(3), |samples, _| Some(samples.next_float())).collect();
+            assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+            let again: Vec<_> = (0..100).scan(kind.samples(3), |samples, _| Some(samples.next_float())).collect();
+            assert_eq!(values, again);
+        }
+
+        // values come in pairs making up each shifted point
+        let mut samples = SamplerKind::Sobol.samples(3);
+        let (first, second) = (samples.next_float(), samples.next_float());
+        let [x, y] = Sobol.point(1);
+        assert_eq!(samples.next_float(), wrap(x + first));
+        assert_eq!(samples.next_float(), wrap(y + second));
+    }
+
+    #[test]
+    fn pixel_samples() {
+        let pairs = |kind: SamplerKind, index| {
+            let mut samples = kind.sample(3, index);
+            [[samples.next_float(), samples.next_float()], [samples.next_float(), samples.next_float()]]
+        };
+
+        // the first pairs are the points a stream for the pixel would give
+        let mut stream = SamplerKind::Sobol.samples(3);
+        for i in 0..4 {
+            assert_eq!(pairs(SamplerKind::Sobol, i)[0], [stream.next_float(), stream.next_float()]);
+        }
+
+        // later pairs keep to the sample's point, all shifted the same way.
+        // Point 0 is the origin, so sample 0 shows the shift
+        let shift = pairs(SamplerKind::Sobol, 0)[1];
+        for i in 0..16 {
+            let [x, y] = Sobol.point(i);
+            assert_eq!(pairs(SamplerKind::Sobol, i)[1], [wrap(x + shift[0]), wrap(y + shift[1])]);
+        }
+
+        assert_eq!(pairs(SamplerKind::Random, 5), pairs(SamplerKind::Random, 5));
+        assert_ne!(pairs(SamplerKind::Random, 5), pairs(SamplerKind::Random, 6));
+    }
+
+    #[test]
+    fn converges_faster() {
+        // average error integrating x * y over the unit square with 16 samples
+        let error = |kind: SamplerKind| (0..50).map(|seed| {
+            let mut samples = kind.samples(seed);
+            let estimate = (0..16).map(|_| samples.next_float() * samples.next_float()).sum::<Float>() / 16.0;
+            (estimate - 0.25).abs()
+        }).sum::<Float>() / 50.0;
+
+        let random = error(SamplerKind::Random);
+        for kind in [SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise] {
+            assert!(error(kind) < random * 0.75, "{kind:?}");
+        }
+    }
+}
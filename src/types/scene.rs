@@ -0,0 +1,1979 @@
+//! Scene files: a camera, lights and objects described in YAML, or the same
+//! structure in JSON for scenes written out by other tools, or in TOML or
+//! RON.
+//!
+//! ```yaml
+//! camera:
+//!   width: 320
+//!   height: 240
+//!   fov: 1.047
+//!   from: [0, 1.5, -5]
+//!   to: [0, 1, 0]
+//! lights:
+//!   - type: point
+//!     position: [-10, 10, -10]
+//! objects:
+//!   - type: sphere
+//!     name: ball
+//!     transform:
+//!       - scale: [0.5, 0.5, 0.5]
+//!       - translate: [0, 1, 0]
+//!     material:
+//!       colour: [1, 0.2, 0.8]
+//! ```
+//!
+//! Transforms are applied in the order they are listed.
+//!
+//! Files ending in `.json` are read as JSON, with the same keys and nesting.
+//! Each transform step is an object with one key, `{"translate": [0, 1, 0]}`.
+//!
+//! Files ending in `.toml` are read as TOML, lights and objects being arrays
+//! of tables:
+//!
+//! ```toml
+//! [camera]
+//! width = 320
+//! height = 240
+//! from = [0, 1.5, -5]
+//! to = [0, 1, 0]
+//!
+//! [[lights]]
+//! type = "point"
+//! position = [-10, 10, -10]
+//!
+//! [[objects]]
+//! type = "sphere"
+//! transform = [{ scale = [0.5, 0.5, 0.5] }, { translate = [0, 1, 0] }]
+//! material = { colour = [1, 0.2, 0.8] }
+//! ```
+//!
+//! Files ending in `.ron` are read as RON, in the shape the scene types
+//! serialise to. Lights and objects are maps with quoted keys, as their
+//! fields depend on their type, transform steps are maps keyed by the step,
+//! and everything else is a struct:
+//!
+//! ```ron
+//! (
+//!     camera: (width: 320, height: 240, from: (0, 1.5, -5), to: (0, 1, 0)),
+//!     lights: [{"type": "point", "position": (-10, 10, -10)}],
+//!     objects: [{
+//!         "type": "sphere",
+//!         "name": Some("ball"),
+//!         "transform": [{scale: (0.5, 0.5, 0.5)}, {translate: (0, 1, 0)}],
+//!         "material": (colour: (1, 0.2, 0.8)),
+//!     }],
+//!     sampler: halton,
+//! )
+//! ```
+//!
+//! RON files using `define` or `include` are read through plain values like
+//! the other formats, which lose bare names, so they write `"halton"` and
+//! `{"scale": (0.5, 0.5, 0.5)}` instead.
+//!
+//! Objects of type `obj`, `stl` or `ply` load a model of that format from
+//! `path`. An OBJ model becomes one object for each group and material,
+//! taking its materials from the `.mtl` libraries it names on top of the
+//! object's own. A library that can't be read is a warning, and its groups
+//! get the object's material.
+//!
+//! A `sky` section adds procedural daylight, lighting the scene with a sun
+//! and a baked sky environment instead of an image.
+//!
+//! Light linking uses names: a light can list the objects it lights with
+//! `only` or skip some with `except`, and an object can list the lights it
+//! wants with `lights` or skip some with `ignore_lights`.
+//!
+//! Animations give objects `keyframes`, transforms at times in seconds,
+//! and the camera a `path` of `from`, `to` and `up` at times. Steps are
+//! blended between keys, so every keyframe of an object lists the same kinds
+//! of steps in the same order:
+//!
+//! ```yaml
+//! keyframes:
+//!   - {time: 0, transform: [{rotate_y: 0}, {translate: [0, 1, 0]}]}
+//!   - {time: 10, transform: [{rotate_y: 6.283}, {translate: [0, 1, 0]}]}
+//! ```
+//!
+//! Values used more than once can go under `define` and be pulled in with
+//! `use`, keys alongside it overriding the definition's. `include` reads
+//! other files for their definitions, lights and objects. Model and image
+//! paths are found from the file they're written in:
+//!
+//! ```yaml
+//! include: [materials.yaml]
+//! define:
+//!   ball:
+//!     type: sphere
+//!     material: {use: shiny}
+//! objects:
+//!   - use: ball
+//!     transform: [{translate: [0, 1, 0]}]
+//! ```
+//!
+//! `params` gives names to values that can be changed from the command line
+//! with `--param name=value`, so one file can make a family of renders.
+//! `$name` is swapped for the value, `${name}` for its text inside longer
+//! strings and `$$` for a plain `$`. A list item with `repeat` is copied that
+//! many times, with `$i` counting the copies:
+//!
+//! ```yaml
+//! params: {seed: 7, detail: 2, rocks: 10}
+//! objects:
+//!   - {type: rock, seed: $seed, name: "rock_${seed}"}
+//!   - {type: icosphere, subdivisions: $detail}
+//!   - repeat: $rocks
+//!     type: rock
+//!     seed: $i
+//!     transform: [{translate: [$i, 0, 0]}]
+//! ```
+//!
+//! Scenes written for The Ray Tracer Challenge, lists of `add` and `define`
+//! items, are read too, see `challenge`.
+//!
+//! Keys a scene doesn't use are rejected rather than ignored, and problems
+//! in YAML files are reported with the line and column they're at.
+
+use std::{collections::HashSet, fmt, fs, io, path::Path};
+
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+use uuid::Uuid;
+
+use crate::{Float, Matrix, Tuple};
+use super::{camera::{Camera, Projection}, canvas::Dither, colour::Colour, environment::Environment, medium::Medium, obj::ObjParser, ply::Ply, sampler::SamplerKind, sky::Sky, stl::Stl, light::{AreaLight, DirectionalLight, Jitter,
+    Light, PointLight, SphereLight, SpotLight, TubeLight}, material::Material, mesh::Mesh, rock::RockGenerator, shape::Shape,
+    sphere::Sphere, tonemap::{ToneMap, ToneMapping}, world::{Ambient, Integrator, World}};
+
+mod challenge;
+mod expand;
+mod locate;
+use locate::Step;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(serde_yaml::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    Ron(ron::error::SpannedError),
+    /// Every problem `Scene::validate` found
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "couldn't read scene: {err}"),
+            Self::Parse(err) => write!(f, "invalid scene: {err}"),
+            Self::Json(err) => write!(f, "invalid scene: {err}"),
+            Self::Toml(err) => write!(f, "invalid scene: {err}"),
+            Self::Ron(err) => write!(f, "invalid scene: {err}"),
+            Self::Invalid(problems) => write!(f, "invalid scene: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_yaml::Error> for SceneError {
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::Parse(err)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Toml(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for SceneError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        Self::Ron(err)
+    }
+}
+
+/// Languages scenes can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Yaml,
+    Json,
+    Toml,
+    Ron,
+}
+
+impl Format {
+    /// By extension, YAML unless it says otherwise
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::Toml,
+            Some(ext) if ext.eq_ignore_ascii_case("ron") => Self::Ron,
+            _ => Self::Yaml,
+        }
+    }
+
+    /// `source` as a document of plain values
+    fn parse(self, source: &str) -> Result<serde_yaml::Value, SceneError> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::from_str(source)?,
+            Self::Json => serde_yaml::to_value(serde_json::from_str::<serde_json::Value>(source)?)
+                .expect("JSON always converts to YAML"),
+            Self::Toml => serde_yaml::to_value(toml::from_str::<toml::Table>(source)?).expect("TOML always converts to YAML"),
+            Self::Ron => serde_yaml::to_value(ron::from_str::<ron::Value>(source)?).expect("RON always converts to YAML"),
+        })
+    }
+
+    fn deserialize(self, source: &str) -> Result<Scene, SceneError> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::from_str(source)?,
+            Self::Json => serde_json::from_str(source)?,
+            Self::Toml => toml::from_str(source)?,
+            Self::Ron => ron::from_str(source)?,
+        })
+    }
+}
+
+/// Everything `Scene::build_all` makes
+pub struct Built {
+    pub world: World,
+    pub camera: Camera,
+    /// Index into `Scene::objects` of the description each of the world's
+    /// objects came from. Models can make more than one object
+    pub sources: Vec<usize>,
+    /// Problems that didn't stop the build, such as a missing material library
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub lights: Vec<LightDesc>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    pub ambient: Option<AmbientDesc>,
+    #[serde(default)]
+    pub medium: Option<MediumDesc>,
+    #[serde(default)]
+    pub environment: Option<EnvironmentDesc>,
+    #[serde(default)]
+    pub sky: Option<SkyDesc>,
+    #[serde(default)]
+    pub integrator: IntegratorDesc,
+    /// Limit on single samples to stop fireflies, see `World::max_sample_radiance`
+    #[serde(default)]
+    pub max_sample_radiance: Option<Float>,
+    /// Sweep all the spheres together, see `World::set_packed_spheres`
+    #[serde(default)]
+    pub packed_spheres: bool,
+    /// Sequence every jittered sample follows, see `SamplerKind`
+    #[serde(default)]
+    pub sampler: SamplerDesc,
+    /// How the render is made displayable when it's written out
+    #[serde(default)]
+    pub output: OutputDesc,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraDesc {
+    pub width: usize,
+    pub height: usize,
+    /// Horizontal field of view in radians
+    #[serde(default = "fov")]
+    pub fov: Float,
+    pub from: [Float; 3],
+    pub to: [Float; 3],
+    #[serde(default = "up")]
+    pub up: [Float; 3],
+    #[serde(default)]
+    pub projection: ProjectionDesc,
+    /// Pixels are split into a `samples` by `samples` grid for anti-aliasing
+    #[serde(default = "camera_samples")]
+    pub samples: usize,
+    /// Seed for jittered pixel samples, centred samples if missing
+    #[serde(default)]
+    pub jitter: Option<u64>,
+    /// Where the camera is at times in seconds, moving in straight lines
+    /// between them. Takes over from `from`, `to` and `up` in animations
+    #[serde(default)]
+    pub path: Vec<CameraKey>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraKey {
+    pub time: Float,
+    pub from: [Float; 3],
+    pub to: [Float; 3],
+    #[serde(default = "up")]
+    pub up: [Float; 3],
+}
+
+fn camera_samples() -> usize {
+    1
+}
+
+/// See `Projection`
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProjectionDesc {
+    #[default]
+    Perspective,
+    /// `width` is the width of the view in world units
+    Orthographic { width: Float },
+    Equirectangular,
+}
+
+impl ProjectionDesc {
+    pub fn build(&self) -> Projection {
+        match *self {
+            Self::Perspective => Projection::Perspective,
+            Self::Orthographic { width } => Projection::Orthographic { width },
+            Self::Equirectangular => Projection::Equirectangular,
+        }
+    }
+}
+
+fn fov() -> Float {
+    crate::consts::FRAC_PI_3
+}
+
+fn up() -> [Float; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn white() -> [Float; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightDesc {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub source: LightSource,
+    /// Names of the only objects this light illuminates
+    #[serde(default)]
+    pub only: Option<Vec<String>>,
+    /// Names of objects this light doesn't illuminate
+    #[serde(default)]
+    pub except: Vec<String>,
+    /// How strongly the light scatters in the scene's medium
+    #[serde(default = "one")]
+    pub scattering: Float,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightSource {
+    Point {
+        position: [Float; 3],
+        #[serde(default = "white")]
+        intensity: [Float; 3],
+        #[serde(default)]
+        range: Option<Float>,
+    },
+    Area {
+        corner: [Float; 3],
+        uvec: [Float; 3],
+        usteps: usize,
+        vvec: [Float; 3],
+        vsteps: usize,
+        #[serde(default = "white")]
+        intensity: [Float; 3],
+        /// Seed for jittered samples, centred samples if missing
+        #[serde(default)]
+        jitter: Option<u64>,
+    },
+    Sphere {
+        centre: [Float; 3],
+        radius: Float,
+        samples: usize,
+        #[serde(default = "white")]
+        intensity: [Float; 3],
+        #[serde(default)]
+        jitter: Option<u64>,
+    },
+    Tube {
+        start: [Float; 3],
+        end: [Float; 3],
+        samples: usize,
+        #[serde(default = "white")]
+        intensity: [Float; 3],
+        #[serde(default)]
+        jitter: Option<u64>,
+    },
+    Spot {
+        position: [Float; 3],
+        direction: [Float; 3],
+        inner_angle: Float,
+        outer_angle: Float,
+        #[serde(default = "white")]
+        intensity: [Float; 3],
+        #[serde(default)]
+        range: Option<Float>,
+    },
+    Directional {
+        direction: [Float; 3],
+        #[serde(default = "white")]
+        intensity: [Float; 3],
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectDesc {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub shape: ShapeDesc,
+    /// Written as single key maps like `translate: [1, 2, 3]`
+    #[serde(default, serialize_with = "single_key_steps", deserialize_with = "serde_yaml::with::singleton_map_recursive::deserialize")]
+    pub transform: Vec<TransformDesc>,
+    #[serde(default)]
+    pub material: MaterialDesc,
+    /// Names of the only lights that illuminate this object
+    #[serde(default)]
+    pub lights: Option<Vec<String>>,
+    /// Names of lights that don't illuminate this object
+    #[serde(default)]
+    pub ignore_lights: Vec<String>,
+    /// Transforms at times in seconds, each step blended into the next.
+    /// Takes over from `transform` in animations
+    #[serde(default)]
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// Every keyframe of an object has the same kinds of steps in the same order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time: Float,
+    #[serde(default, serialize_with = "single_key_steps", deserialize_with = "serde_yaml::with::singleton_map_recursive::deserialize")]
+    pub transform: Vec<TransformDesc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDesc {
+    Sphere,
+    /// From -1 to 1 on every axis
+    Cube,
+    /// A square in the xz plane facing up, standing in for an infinite plane
+    /// so big by default
+    Plane {
+        #[serde(default = "plane_size")]
+        size: Float,
+    },
+    Icosphere {
+        #[serde(default)]
+        subdivisions: usize,
+    },
+    Rock {
+        seed: u64,
+    },
+    /// Every face of a Wavefront `.obj` file as one mesh
+    Obj {
+        path: String,
+    },
+    /// Binary or ASCII STL model, see `Stl::mesh`
+    Stl {
+        path: String,
+        #[serde(default)]
+        recompute_normals: bool,
+    },
+    /// PLY model, see `Ply::mesh`
+    Ply {
+        path: String,
+    },
+}
+
+fn plane_size() -> Float {
+    1000.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransformDesc {
+    Translate([Float; 3]),
+    Scale([Float; 3]),
+    RotateX(Float),
+    RotateY(Float),
+    RotateZ(Float),
+    Shear([Float; 6]),
+}
+
+impl TransformDesc {
+    /// `t` of the way from `self` to `to`, if they're the same kind of step
+    pub fn lerp(self, to: Self, t: Float) -> Option<Self> {
+        Some(match (self, to) {
+            (Self::Translate(a), Self::Translate(b)) => Self::Translate(lerp(a, b, t)),
+            (Self::Scale(a), Self::Scale(b)) => Self::Scale(lerp(a, b, t)),
+            (Self::RotateX(a), Self::RotateX(b)) => Self::RotateX(lerp([a], [b], t)[0]),
+            (Self::RotateY(a), Self::RotateY(b)) => Self::RotateY(lerp([a], [b], t)[0]),
+            (Self::RotateZ(a), Self::RotateZ(b)) => Self::RotateZ(lerp([a], [b], t)[0]),
+            (Self::Shear(a), Self::Shear(b)) => Self::Shear(lerp(a, b, t)),
+            _ => return None,
+        })
+    }
+}
+
+fn lerp<const N: usize>(a: [Float; N], b: [Float; N], t: Float) -> [Float; N] {
+    std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// The keys either side of `time` and how far it is from the first to the
+/// second, the first or last key on its own before or after them all
+fn keys_around<K>(keys: &[K], time: Float, key_time: impl Fn(&K) -> Float) -> Option<(&K, &K, Float)> {
+    let after = keys.partition_point(|key| key_time(key) <= time);
+    match after {
+        _ if keys.is_empty() => None,
+        0 => Some((&keys[0], &keys[0], 0.0)),
+        n if n == keys.len() => Some((&keys[n - 1], &keys[n - 1], 0.0)),
+        n => {
+            let (before, after) = (&keys[n - 1], &keys[n]);
+            Some((before, after, (time - key_time(before)) / (key_time(after) - key_time(before))))
+        },
+    }
+}
+
+/// Whether `times` go up strictly
+fn increasing(times: impl Iterator<Item = Float>) -> bool {
+    times.collect::<Vec<_>>().windows(2).all(|pair| pair[0] < pair[1])
+}
+
+/// Transform steps as single key maps with the variant as the key, which
+/// RON reads back as an identifier rather than a string
+fn single_key_steps<S: Serializer>(steps: &[TransformDesc], serializer: S) -> Result<S::Ok, S::Error> {
+    struct Variant(u32, &'static str);
+    struct Step<'a>(&'a TransformDesc);
+
+    impl Serialize for Variant {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_unit_variant("TransformDesc", self.0, self.1)
+        }
+    }
+
+    impl Serialize for Step<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(1))?;
+            match self.0 {
+                TransformDesc::Translate(by) => map.serialize_entry(&Variant(0, "translate"), by)?,
+                TransformDesc::Scale(by) => map.serialize_entry(&Variant(1, "scale"), by)?,
+                TransformDesc::RotateX(angle) => map.serialize_entry(&Variant(2, "rotate_x"), angle)?,
+                TransformDesc::RotateY(angle) => map.serialize_entry(&Variant(3, "rotate_y"), angle)?,
+                TransformDesc::RotateZ(angle) => map.serialize_entry(&Variant(4, "rotate_z"), angle)?,
+                TransformDesc::Shear(by) => map.serialize_entry(&Variant(5, "shear"), by)?,
+            }
+            map.end()
+        }
+    }
+
+    serializer.collect_seq(steps.iter().map(Step))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDesc {
+    pub colour: [Float; 3],
+    pub ambient: Float,
+    pub diffuse: Float,
+    pub specular: Float,
+    pub shininess: Float,
+    pub transparency: Float,
+    pub casts_shadow: bool,
+    pub holdout: bool,
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        let m = Material::default();
+        Self {
+            colour: [m.colour.r, m.colour.g, m.colour.b],
+            ambient: m.ambient,
+            diffuse: m.diffuse,
+            specular: m.specular,
+            shininess: m.shininess,
+            transparency: m.transparency,
+            casts_shadow: m.casts_shadow,
+            holdout: m.holdout,
+        }
+    }
+}
+
+/// World wide ambient light, see `Ambient`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AmbientDesc {
+    pub mode: AmbientMode,
+    #[serde(default = "white")]
+    pub colour: [Float; 3],
+    #[serde(default = "one")]
+    pub intensity: Float,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmbientMode {
+    Scale,
+    Replace,
+}
+
+impl AmbientDesc {
+    pub fn build(&self) -> Ambient {
+        let colour = colour(self.colour) * self.intensity;
+        match self.mode {
+            AmbientMode::Scale => Ambient::Scale(colour),
+            AmbientMode::Replace => Ambient::Replace(colour),
+        }
+    }
+}
+
+/// Dust or haze filling the scene, see `Medium`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MediumDesc {
+    pub density: Float,
+    #[serde(default = "white")]
+    pub colour: [Float; 3],
+    #[serde(default = "medium_steps")]
+    pub steps: usize,
+    #[serde(default = "medium_distance")]
+    pub max_distance: Float,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn medium_steps() -> usize {
+    Medium::default().steps
+}
+
+fn medium_distance() -> Float {
+    Medium::default().max_distance
+}
+
+impl MediumDesc {
+    pub fn build(&self) -> Medium {
+        Medium {
+            density: self.density,
+            colour: colour(self.colour),
+            steps: self.steps,
+            max_distance: self.max_distance,
+            seed: self.seed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentDesc {
+    /// Equirectangular `.hdr` image
+    pub path: String,
+    #[serde(default = "one")]
+    pub intensity: Float,
+    #[serde(default)]
+    pub rotation: Float,
+    #[serde(default = "environment_samples")]
+    pub samples: usize,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+/// See `Integrator`
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IntegratorDesc {
+    #[default]
+    Whitted,
+    PathTracing {
+        #[serde(default = "max_bounces")]
+        max_bounces: usize,
+        #[serde(default)]
+        seed: u64,
+        /// See `Integrator::PathTracing`
+        #[serde(default)]
+        min_throughput: Float,
+    },
+}
+
+fn max_bounces() -> usize {
+    4
+}
+
+impl IntegratorDesc {
+    pub fn build(&self) -> Integrator {
+        match *self {
+            Self::Whitted => Integrator::Whitted,
+            Self::PathTracing { max_bounces, seed, min_throughput } => {
+                Integrator::PathTracing { max_bounces, seed, min_throughput, sampler: SamplerKind::default() }
+            },
+        }
+    }
+}
+
+/// See `SamplerKind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerDesc {
+    #[default]
+    Random,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerDesc {
+    pub fn build(self) -> SamplerKind {
+        match self {
+            Self::Random => SamplerKind::Random,
+            Self::Halton => SamplerKind::Halton,
+            Self::Sobol => SamplerKind::Sobol,
+            Self::BlueNoise => SamplerKind::BlueNoise,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct OutputDesc {
+    /// In stops
+    #[serde(default)]
+    pub exposure: Float,
+    #[serde(default)]
+    pub tone_map: ToneMapDesc,
+    #[serde(default)]
+    pub dither: DitherDesc,
+}
+
+/// See `ToneMap`
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapDesc {
+    #[default]
+    Clamp,
+    Reinhard,
+    Filmic,
+    Aces,
+}
+
+/// See `Dither`
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DitherDesc {
+    #[default]
+    None,
+    Ordered,
+    FloydSteinberg,
+}
+
+impl OutputDesc {
+    pub fn build(&self) -> ToneMapping {
+        let tone_map = match self.tone_map {
+            ToneMapDesc::Clamp => ToneMap::Clamp,
+            ToneMapDesc::Reinhard => ToneMap::Reinhard,
+            ToneMapDesc::Filmic => ToneMap::Filmic,
+            ToneMapDesc::Aces => ToneMap::Aces,
+        };
+        ToneMapping::new(self.exposure, tone_map)
+    }
+
+    pub fn dither(&self) -> Dither {
+        match self.dither {
+            DitherDesc::None => Dither::None,
+            DitherDesc::Ordered => Dither::Ordered,
+            DitherDesc::FloydSteinberg => Dither::FloydSteinberg,
+        }
+    }
+}
+
+/// Procedural daylight instead of an environment image, see `Sky`.
+/// Adds the sun as a directional light after the scene's own lights.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SkyDesc {
+    #[serde(default = "sky_turbidity")]
+    pub turbidity: Float,
+    /// Sun angle above the horizon in radians
+    pub elevation: Float,
+    #[serde(default)]
+    pub azimuth: Float,
+    #[serde(default = "sky_intensity")]
+    pub intensity: Float,
+    #[serde(default = "one")]
+    pub sun_intensity: Float,
+    /// Width of the baked environment image
+    #[serde(default = "sky_resolution")]
+    pub resolution: usize,
+}
+
+fn sky_turbidity() -> Float {
+    Sky::default().turbidity
+}
+
+fn sky_intensity() -> Float {
+    Sky::default().intensity
+}
+
+fn sky_resolution() -> usize {
+    128
+}
+
+impl SkyDesc {
+    pub fn build(&self) -> Sky {
+        Sky {
+            turbidity: self.turbidity,
+            sun_elevation: self.elevation,
+            sun_azimuth: self.azimuth,
+            intensity: self.intensity,
+            sun_intensity: self.sun_intensity,
+        }
+    }
+}
+
+fn one() -> Float {
+    1.0
+}
+
+fn environment_samples() -> usize {
+    32
+}
+
+fn point([x, y, z]: [Float; 3]) -> Tuple {
+    Tuple::point(x, y, z)
+}
+
+fn vector([x, y, z]: [Float; 3]) -> Tuple {
+    Tuple::vector(x, y, z)
+}
+
+fn colour([r, g, b]: [Float; 3]) -> Colour {
+    Colour::new(r, g, b)
+}
+
+impl Scene {
+    /// Read and validate a scene, refusing one with any problems. The
+    /// extension picks the language, any case: `.json` is JSON, `.toml` is
+    /// TOML, `.ron` is RON and `.yaml`, `.yml` or anything else is YAML.
+    /// Problems in YAML scenes are given with their line and column, and
+    /// includes, models and images are found relative to the file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        Self::load_with_params(path, &[])
+    }
+
+    /// Whether `path` ends in the extension of a language scenes can be
+    /// written in, for picking scenes out of a directory
+    pub fn is_scene_file(path: impl AsRef<Path>) -> bool {
+        path.as_ref().extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
+            ["yaml", "yml", "json", "toml", "ron"].iter().any(|known| ext.eq_ignore_ascii_case(known))
+        })
+    }
+
+    /// Whether the file at `path` is only something for other scenes to
+    /// include, as it hasn't a camera of its own. Files that can't be read
+    /// or parsed aren't, so loading them says what's wrong
+    pub fn is_fragment(path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        fs::read_to_string(path).ok()
+            .and_then(|source| Format::of(path).parse(&source).ok())
+            .and_then(|document| document.as_mapping().map(|document| !document.contains_key("camera")))
+            .unwrap_or(false)
+    }
+
+    /// Like `load` with some of the scene's parameters given other values,
+    /// each written as YAML like `7` or `[0, 1, 0]`
+    pub fn load_with_params(path: impl AsRef<Path>, params: &[(String, String)]) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        Self::checked(&fs::read_to_string(path)?, Format::of(path), path.parent().unwrap_or(Path::new("")), params)
+    }
+
+    /// Like `load_with_params` for YAML or JSON that isn't in a file, such
+    /// as a scene piped in. Includes are found relative to the working
+    /// directory
+    pub fn from_yaml_with_params(source: &str, params: &[(String, String)]) -> Result<Self, SceneError> {
+        Self::checked(source, Format::Yaml, Path::new(""), params)
+    }
+
+    /// `read` with `params` parsed and the scene validated
+    fn checked(source: &str, format: Format, dir: &Path, params: &[(String, String)]) -> Result<Self, SceneError> {
+        let params = params.iter()
+            .map(|(name, value)| Ok((name.as_str().into(), serde_yaml::from_str(value)?)))
+            .collect::<Result<_, SceneError>>()?;
+        let scene = Self::read(source, format, dir, &params)?;
+
+        let problems = match format {
+            Format::Yaml => scene.validate_source(source),
+            Format::Json | Format::Toml | Format::Ron => scene.validate(),
+        };
+        if !problems.is_empty() {
+            return Err(SceneError::Invalid(problems));
+        }
+        Ok(scene)
+    }
+
+    /// Fails on keys the scene doesn't use, as they're most likely typos.
+    /// Includes are found relative to the working directory
+    pub fn from_yaml(source: &str) -> Result<Self, SceneError> {
+        Self::read(source, Format::Yaml, Path::new(""), &serde_yaml::Mapping::new())
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("scenes always serialise")
+    }
+
+    /// Like `from_yaml`
+    pub fn from_json(source: &str) -> Result<Self, SceneError> {
+        Self::read(source, Format::Json, Path::new(""), &serde_yaml::Mapping::new())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("scenes always serialise")
+    }
+
+    /// Like `from_yaml`
+    pub fn from_toml(source: &str) -> Result<Self, SceneError> {
+        Self::read(source, Format::Toml, Path::new(""), &serde_yaml::Mapping::new())
+    }
+
+    /// Missing options are left out, as TOML has no null
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("scenes always serialise")
+    }
+
+    /// Like `from_yaml`
+    pub fn from_ron(source: &str) -> Result<Self, SceneError> {
+        Self::read(source, Format::Ron, Path::new(""), &serde_yaml::Mapping::new())
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).expect("scenes always serialise")
+    }
+
+    /// Parse `source` with its includes and definitions worked out, refusing
+    /// keys the scene doesn't use. Scenes without either are read straight
+    /// from `source` so syntax errors keep their position
+    fn read(source: &str, format: Format, dir: &Path, params: &serde_yaml::Mapping) -> Result<Self, SceneError> {
+        let document = format.parse(source)?;
+        if challenge::is_challenge(&document) {
+            return challenge::convert(&document, dir);
+        }
+        let expanded = expand::expand(document.clone(), dir, params)?;
+        let mut scene: Self = if expanded == document { format.deserialize(source)? } else { serde_yaml::from_value(expanded.clone())? };
+
+        let unknown = scene.unknown_keys(&expanded);
+        if !unknown.is_empty() {
+            let unknown = unknown.into_iter().map(|path| {
+                let problem = format!("{}: unknown key", scene.describe(&path));
+                match format {
+                    Format::Yaml => locate::with_position(source, &path, problem),
+                    Format::Json | Format::Toml | Format::Ron => problem,
+                }
+            });
+            return Err(SceneError::Invalid(unknown.collect()));
+        }
+        scene.rebase(dir);
+        Ok(scene)
+    }
+
+    /// Puts `dir`, the scene file's directory, in front of relative model
+    /// and environment paths, as they're otherwise found from the working
+    /// directory. Included files have already made theirs absolute
+    fn rebase(&mut self, dir: &Path) {
+        let models = self.objects.iter_mut().filter_map(|object| match &mut object.shape {
+            ShapeDesc::Obj { path } | ShapeDesc::Stl { path, .. } | ShapeDesc::Ply { path } => Some(path),
+            _ => None,
+        });
+        for path in models.chain(self.environment.as_mut().map(|environment| &mut environment.path)) {
+            *path = dir.join(&*path).to_string_lossy().into_owned();
+        }
+    }
+
+    /// Paths to keys in `source` that didn't make it into the scene, found by
+    /// comparing it with the scene written back out
+    fn unknown_keys(&self, source: &serde_yaml::Value) -> Vec<Vec<Step>> {
+        fn walk(source: &serde_yaml::Value, known: &serde_yaml::Value, path: &mut Vec<Step>, unknown: &mut Vec<Vec<Step>>) {
+            use serde_yaml::Value;
+
+            match (source, known) {
+                (Value::Mapping(source), Value::Mapping(known)) => {
+                    for (key, value) in source {
+                        let Some(name) = key.as_str() else { continue };
+                        path.push(Step::key(name));
+                        match known.get(key) {
+                            Some(known) => walk(value, known, path, unknown),
+                            None => unknown.push(path.clone()),
+                        }
+                        path.pop();
+                    }
+                },
+                (Value::Sequence(source), Value::Sequence(known)) => {
+                    for (i, (value, known)) in source.iter().zip(known).enumerate() {
+                        path.push(Step::Index(i));
+                        walk(value, known, path, unknown);
+                        path.pop();
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        let known = serde_yaml::to_value(self).expect("scenes always serialise");
+        let mut unknown = Vec::new();
+        walk(source, &known, &mut Vec::new(), &mut unknown);
+        unknown
+    }
+
+    /// `path` named after the light or object it's in, like `ball: material.colr`
+    fn describe(&self, path: &[Step]) -> String {
+        let label = match path {
+            [Step::Key(list), Step::Index(i), ..] if list == "lights" => self.lights.get(*i).map(|light| light.label(*i)),
+            [Step::Key(list), Step::Index(i), ..] if list == "objects" => self.objects.get(*i).map(|object| object.label(*i)),
+            _ => None,
+        };
+        match (label, path.get(2..)) {
+            (Some(label), Some([])) => label,
+            (Some(label), Some(rest)) => format!("{label}: {}", locate::display(rest)),
+            _ => locate::display(path),
+        }
+    }
+
+    /// How long the animation runs, the time of the last keyframe
+    pub fn duration(&self) -> Float {
+        let camera = self.camera.path.iter().map(|key| key.time);
+        let objects = self.objects.iter().flat_map(|object| object.keyframes.iter().map(|key| key.time));
+        camera.chain(objects).fold(0.0, Float::max)
+    }
+
+    /// The scene as it is `time` seconds into its animation
+    pub fn at(&self, time: Float) -> Self {
+        let mut scene = self.clone();
+        if let Some((before, after, t)) = keys_around(&self.camera.path, time, |key| key.time) {
+            let camera = &mut scene.camera;
+            (camera.from, camera.to, camera.up) = (lerp(before.from, after.from, t), lerp(before.to, after.to, t),
+                lerp(before.up, after.up, t));
+        }
+        for object in &mut scene.objects {
+            if let Some((before, after, t)) = keys_around(&object.keyframes, time, |key| key.time) {
+                object.transform = before.transform.iter().zip(&after.transform)
+                    .filter_map(|(a, b)| a.lerp(*b, t))
+                    .collect();
+            }
+        }
+        scene
+    }
+
+    /// Everything that would stop the scene from building, empty if it's fine
+    pub fn validate(&self) -> Vec<String> {
+        self.problems().into_iter().map(|(_, problem)| problem).collect()
+    }
+
+    /// `validate` with each problem's line and column in `source`, the YAML
+    /// the scene was read from
+    pub fn validate_source(&self, source: &str) -> Vec<String> {
+        self.problems().into_iter().map(|(path, problem)| locate::with_position(source, &path, problem)).collect()
+    }
+
+    /// Every problem along with the path to the part of the scene it's in
+    fn problems(&self) -> Vec<(Vec<Step>, String)> {
+        let mut located = Vec::new();
+        let mut problems = Vec::new();
+        let mut found_in = |problems: &mut Vec<String>, path: Vec<Step>| {
+            located.extend(problems.drain(..).map(|problem| (path.clone(), problem)));
+        };
+
+        self.camera.validate(&mut problems);
+        found_in(&mut problems, vec![Step::key("camera")]);
+
+        let object_names: Vec<_> = self.objects.iter().filter_map(|o| o.name.as_deref()).collect();
+        let light_names: Vec<_> = self.lights.iter().filter_map(|l| l.name.as_deref()).collect();
+        let check_names = |problems: &mut Vec<String>, label: &str, names: &[String], known: &[&str], kind: &str| {
+            for name in names.iter().filter(|name| !known.contains(&name.as_str())) {
+                problems.push(format!("{label}: no {kind} called {name}"));
+            }
+        };
+
+        for (i, light) in self.lights.iter().enumerate() {
+            let label = light.label(i);
+            check_names(&mut problems, &label, light.only.as_deref().unwrap_or_default(), &object_names, "object");
+            check_names(&mut problems, &label, &light.except, &object_names, "object");
+            found_in(&mut problems, vec![Step::key("lights"), Step::Index(i)]);
+        }
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let label = object.label(i);
+            check_names(&mut problems, &label, object.lights.as_deref().unwrap_or_default(), &light_names, "light");
+            check_names(&mut problems, &label, &object.ignore_lights, &light_names, "light");
+            found_in(&mut problems, vec![Step::key("objects"), Step::Index(i)]);
+        }
+
+        for (i, light) in self.lights.iter().enumerate() {
+            light.source.validate(&light.label(i), &mut problems);
+            if light.scattering < 0.0 {
+                problems.push(format!("{}: scattering can't be negative", light.label(i)));
+            }
+            found_in(&mut problems, vec![Step::key("lights"), Step::Index(i)]);
+        }
+
+        if let Some(medium) = &self.medium {
+            if medium.density < 0.0 || medium.max_distance < 0.0 {
+                problems.push("medium: density and max_distance can't be negative".to_string());
+            }
+            found_in(&mut problems, vec![Step::key("medium")]);
+        }
+
+        for (i, object) in self.objects.iter().enumerate() {
+            object.validate(&object.label(i), &mut problems);
+            found_in(&mut problems, vec![Step::key("objects"), Step::Index(i)]);
+        }
+
+        if let Some(environment) = &self.environment {
+            if !Path::new(&environment.path).exists() {
+                problems.push(format!("environment: {} does not exist", environment.path));
+            }
+            found_in(&mut problems, vec![Step::key("environment"), Step::key("path")]);
+        }
+
+        if self.max_sample_radiance.is_some_and(|max| max < 0.0) {
+            problems.push("max_sample_radiance can't be negative".to_string());
+            found_in(&mut problems, vec![Step::key("max_sample_radiance")]);
+        }
+
+        if let IntegratorDesc::PathTracing { min_throughput, .. } = self.integrator {
+            if min_throughput < 0.0 {
+                problems.push("integrator: min_throughput can't be negative".to_string());
+            }
+            found_in(&mut problems, vec![Step::key("integrator"), Step::key("min_throughput")]);
+        }
+
+        if let Some(sky) = &self.sky {
+            if sky.turbidity < 1.0 {
+                problems.push("sky: turbidity must be at least 1".to_string());
+            }
+            if sky.resolution < 2 {
+                problems.push("sky: resolution must be at least 2".to_string());
+            }
+            if self.environment.is_some() {
+                problems.push("sky: can't be used with an environment".to_string());
+            }
+            found_in(&mut problems, vec![Step::key("sky")]);
+        }
+
+        located
+    }
+
+    /// Validate the scene and create the world and camera it describes
+    pub fn build(&self) -> Result<(World, Camera), SceneError> {
+        self.build_all().map(|built| (built.world, built.camera))
+    }
+
+    /// `build`, also giving where each object came from and any warnings
+    pub fn build_all(&self) -> Result<Built, SceneError> {
+        let problems = self.validate();
+        if !problems.is_empty() {
+            return Err(SceneError::Invalid(problems));
+        }
+
+        let sampler = self.sampler.build();
+        let (mut objects, mut sources, mut warnings) = (Vec::new(), Vec::new(), Vec::new());
+        for (i, desc) in self.objects.iter().enumerate() {
+            let built = desc.build(&desc.label(i), &mut warnings)?;
+            sources.extend(std::iter::repeat_n(i, built.len()));
+            objects.extend(built);
+        }
+        let mut lights: Vec<_> = self.lights.iter().map(|light| light.source.build(sampler)).collect();
+        let sky = self.sky.map(|desc| desc.build());
+        if let Some(sky) = &sky {
+            lights.push(Box::new(sky.sun()));
+        }
+        let mut world = World::new(objects, lights);
+        self.link_lights(&mut world, &sources);
+
+        if let Some(ambient) = &self.ambient {
+            world.set_ambient(ambient.build());
+        }
+
+        if let Some(medium) = &self.medium {
+            world.set_medium(Some(medium.build()));
+        }
+        world.set_integrator(match self.integrator.build() {
+            Integrator::PathTracing { max_bounces, seed, min_throughput, .. } => {
+                Integrator::PathTracing { max_bounces, seed, min_throughput, sampler }
+            },
+            integrator => integrator,
+        });
+        world.set_max_sample_radiance(self.max_sample_radiance);
+        world.set_packed_spheres(self.packed_spheres);
+        for (i, light) in self.lights.iter().enumerate() {
+            world.set_light_scattering(i, light.scattering);
+        }
+
+        if let Some(desc) = &self.environment {
+            let mut environment = Environment::from_hdr(&desc.path)?;
+            environment.intensity = desc.intensity;
+            environment.rotation = desc.rotation;
+            environment.samples = desc.samples;
+            environment.seed = desc.seed;
+            environment.sampler = sampler;
+            world.set_environment(Some(environment));
+        }
+
+        if let (Some(sky), Some(desc)) = (sky, &self.sky) {
+            let mut environment = sky.environment(desc.resolution);
+            environment.sampler = sampler;
+            world.set_environment(Some(environment));
+        }
+
+        let mut camera = self.camera.build();
+        camera.jitter.sampler = sampler;
+        Ok(Built {
+            world,
+            camera,
+            sources,
+            warnings,
+        })
+    }
+
+    /// `sources` gives the description each of the world's objects came from
+    fn link_lights(&self, world: &mut World, sources: &[usize]) {
+        let ids: Vec<_> = world.objects().iter().map(|o| o.id()).collect();
+        let owned: Vec<_> = sources.iter().map(|&i| &self.objects[i]).zip(&ids).collect();
+        let object_ids = |names: &[String]| -> HashSet<Uuid> {
+            owned.iter()
+                .filter(|(o, _)| o.name.as_ref().is_some_and(|name| names.contains(name)))
+                .map(|(_, id)| **id)
+                .collect()
+        };
+        let light_indices = |names: &[String]| -> Vec<usize> {
+            self.lights.iter().enumerate()
+                .filter(|(_, l)| l.name.as_ref().is_some_and(|name| names.contains(name)))
+                .map(|(i, _)| i)
+                .collect()
+        };
+
+        for (i, light) in self.lights.iter().enumerate() {
+            let link = world.light_link_mut(i);
+            link.only = light.only.as_deref().map(object_ids);
+            link.except = object_ids(&light.except);
+        }
+
+        for (object, id) in owned.iter().copied() {
+            if let Some(lights) = &object.lights {
+                world.only_lights(*id, &light_indices(lights));
+            }
+            for light in light_indices(&object.ignore_lights) {
+                world.exclude_light(*id, light);
+            }
+        }
+    }
+}
+
+impl CameraDesc {
+    fn validate(&self, problems: &mut Vec<String>) {
+        if self.width == 0 || self.height == 0 {
+            problems.push(format!("camera: size {}x{} is empty", self.width, self.height));
+        }
+        match self.projection {
+            ProjectionDesc::Perspective if !(self.fov > 0.0 && self.fov < crate::consts::PI) => {
+                problems.push(format!("camera: fov {} must be between 0 and pi radians", self.fov));
+            },
+            ProjectionDesc::Orthographic { width } if width <= 0.0 => {
+                problems.push(format!("camera: orthographic width {width} must be positive"));
+            },
+            _ => {},
+        }
+        if self.samples == 0 {
+            problems.push("camera: samples must be at least 1".to_string());
+        }
+        if !increasing(self.path.iter().map(|key| key.time)) {
+            problems.push("camera: path times must go up".to_string());
+        }
+
+        let forward = point(self.to) - point(self.from);
+        if forward.magnitude() == 0.0 {
+            problems.push("camera: from and to are the same point".to_string());
+        }
+        else if forward.cross(vector(self.up)).magnitude() == 0.0 {
+            problems.push("camera: up is parallel to the view direction".to_string());
+        }
+    }
+
+    pub fn build(&self) -> Camera {
+        Camera::builder(self.width, self.height)
+            .fov(self.fov)
+            .projection(self.projection.build())
+            .transform(Matrix::view_transform(point(self.from), point(self.to), vector(self.up)))
+            .samples(self.samples)
+            .jitter(self.jitter.map(Jitter::seeded).unwrap_or_default())
+            .build()
+    }
+}
+
+impl LightDesc {
+    pub fn new(source: LightSource) -> Self {
+        Self {
+            name: None,
+            source,
+            only: None,
+            except: Vec::new(),
+            scattering: 1.0,
+        }
+    }
+
+    /// Name to show in messages, falling back to its position in the scene
+    pub fn label(&self, index: usize) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("lights[{index}]"),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.source.kind()
+    }
+}
+
+impl LightSource {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Point { .. } => "point",
+            Self::Area { .. } => "area",
+            Self::Sphere { .. } => "sphere",
+            Self::Tube { .. } => "tube",
+            Self::Spot { .. } => "spot",
+            Self::Directional { .. } => "directional",
+        }
+    }
+
+    fn validate(&self, label: &str, problems: &mut Vec<String>) {
+        let mut check = |ok: bool, message: &str| {
+            if !ok {
+                problems.push(format!("{label}: {message}"));
+            }
+        };
+
+        match self {
+            Self::Point { range, .. } | Self::Spot { range, .. } if range.is_some_and(|r| r <= 0.0) => {
+                check(false, "range must be positive");
+            },
+            _ => {},
+        }
+
+        match self {
+            Self::Area { uvec, usteps, vvec, vsteps, .. } => {
+                check(*usteps > 0 && *vsteps > 0, "usteps and vsteps must be at least 1");
+                check(vector(*uvec).cross(vector(*vvec)).magnitude() > 0.0, "uvec and vvec don't span an area");
+            },
+            Self::Sphere { radius, samples, .. } => {
+                check(*radius >= 0.0, "radius can't be negative");
+                check(*samples > 0, "samples must be at least 1");
+            },
+            Self::Tube { samples, .. } => {
+                check(*samples > 0, "samples must be at least 1");
+            },
+            Self::Spot { direction, inner_angle, outer_angle, .. } => {
+                check(vector(*direction).magnitude() > 0.0, "direction can't be zero");
+                check(inner_angle <= outer_angle, "inner_angle is wider than outer_angle");
+            },
+            Self::Directional { direction, .. } => {
+                check(vector(*direction).magnitude() > 0.0, "direction can't be zero");
+            },
+            Self::Point { .. } => {},
+        }
+    }
+
+    /// The light, with any jitter following `sampler`
+    pub fn build(&self, sampler: SamplerKind) -> Box<dyn Light> {
+        let jittered = |seed: Option<u64>| seed.map(|seed| Jitter::seeded(seed).with_sampler(sampler)).unwrap_or_default();
+        match *self {
+            Self::Point { position, intensity, range } => {
+                let light = PointLight::new(colour(intensity), point(position));
+                Box::new(match range {
+                    Some(range) => light.with_range(range),
+                    None => light,
+                })
+            },
+            Self::Area { corner, uvec, usteps, vvec, vsteps, intensity, jitter } => {
+                let mut light = AreaLight::new(colour(intensity), point(corner), vector(uvec), usteps,
+                    vector(vvec), vsteps);
+                light.jitter = jittered(jitter);
+                Box::new(light)
+            },
+            Self::Sphere { centre, radius, samples, intensity, jitter } => {
+                let mut light = SphereLight::new(colour(intensity), point(centre), radius, samples);
+                light.jitter = jittered(jitter);
+                Box::new(light)
+            },
+            Self::Tube { start, end, samples, intensity, jitter } => {
+                let mut light = TubeLight::new(colour(intensity), point(start), point(end), samples);
+                light.jitter = jittered(jitter);
+                Box::new(light)
+            },
+            Self::Spot { position, direction, inner_angle, outer_angle, intensity, range } => {
+                let light = SpotLight::new(colour(intensity), point(position), vector(direction),
+                    inner_angle, outer_angle);
+                Box::new(match range {
+                    Some(range) => light.with_range(range),
+                    None => light,
+                })
+            },
+            Self::Directional { direction, intensity } => {
+                Box::new(DirectionalLight::new(colour(intensity), vector(direction)))
+            },
+        }
+    }
+}
+
+impl ObjectDesc {
+    /// Name to show in messages, falling back to its position in the scene
+    pub fn label(&self, index: usize) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("objects[{index}]"),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self.shape {
+            ShapeDesc::Sphere => "sphere",
+            ShapeDesc::Cube => "cube",
+            ShapeDesc::Plane { .. } => "plane",
+            ShapeDesc::Icosphere { .. } => "icosphere",
+            ShapeDesc::Rock { .. } => "rock",
+            ShapeDesc::Obj { .. } => "obj",
+            ShapeDesc::Stl { .. } => "stl",
+            ShapeDesc::Ply { .. } => "ply",
+        }
+    }
+
+    pub fn transform(&self) -> Matrix {
+        self.transform.iter().fold(Matrix::identity(), |matrix, op| match *op {
+            TransformDesc::Translate([x, y, z]) => matrix.translate(x, y, z),
+            TransformDesc::Scale([x, y, z]) => matrix.scale(x, y, z),
+            TransformDesc::RotateX(ang) => matrix.rotate_x(ang),
+            TransformDesc::RotateY(ang) => matrix.rotate_y(ang),
+            TransformDesc::RotateZ(ang) => matrix.rotate_z(ang),
+            TransformDesc::Shear([xy, xz, yx, yz, zx, zy]) => matrix.shear(xy, xz, yx, yz, zx, zy),
+        })
+    }
+
+    fn validate(&self, label: &str, problems: &mut Vec<String>) {
+        if !self.transform().invertible() {
+            problems.push(format!("{label}: transform can't be inverted"));
+        }
+        if !increasing(self.keyframes.iter().map(|key| key.time)) {
+            problems.push(format!("{label}: keyframe times must go up"));
+        }
+        let steps = |key: &Keyframe| key.transform.iter().map(std::mem::discriminant).collect::<Vec<_>>();
+        if self.keyframes.iter().any(|key| steps(key) != steps(&self.keyframes[0])) {
+            problems.push(format!("{label}: keyframes must all have the same transform steps"));
+        }
+        if let ShapeDesc::Obj { path } | ShapeDesc::Stl { path, .. } | ShapeDesc::Ply { path } = &self.shape {
+            if !Path::new(path).exists() {
+                problems.push(format!("{label}: {path} does not exist"));
+            }
+        }
+
+        let material = &self.material;
+        if !(0.0..=1.0).contains(&material.transparency) {
+            problems.push(format!("{label}: transparency must be between 0 and 1"));
+        }
+        if material.ambient < 0.0 || material.diffuse < 0.0 || material.specular < 0.0 || material.shininess < 0.0 {
+            problems.push(format!("{label}: material values can't be negative"));
+        }
+    }
+
+    /// The objects this describes, one unless it's an OBJ model with several
+    /// groups or materials. Only fails reading a model file
+    pub fn build(&self, label: &str, warnings: &mut Vec<String>) -> Result<Vec<Box<dyn Shape>>, SceneError> {
+        let transform = self.transform();
+        let material = self.material.build();
+        let placed = |mut mesh: Mesh| -> Box<dyn Shape> {
+            mesh.set_transform(transform);
+            mesh.material = material;
+            Box::new(mesh)
+        };
+
+        let object: Box<dyn Shape> = match &self.shape {
+            ShapeDesc::Sphere => Box::new(Sphere::new(transform, material)),
+            ShapeDesc::Cube => placed(Mesh::cube()),
+            ShapeDesc::Plane { size } => placed(Mesh::plane(*size)),
+            ShapeDesc::Icosphere { subdivisions } => placed(Mesh::icosphere(*subdivisions)),
+            ShapeDesc::Rock { seed } => Box::new(RockGenerator::new(*seed).generate_with(transform, material)),
+            ShapeDesc::Obj { path } => {
+                let obj = ObjParser::load(path)?;
+                for library in &obj.missing {
+                    warnings.push(format!("{label}: couldn't read material library {library}, using the object's material"));
+                }
+                return Ok(obj.meshes(transform, material).into_iter()
+                    .map(|(_, mesh)| Box::new(mesh) as Box<dyn Shape>)
+                    .collect());
+            },
+            ShapeDesc::Stl { path, recompute_normals } => {
+                Box::new(Stl::load(path)?.mesh(transform, material, *recompute_normals))
+            },
+            ShapeDesc::Ply { path } => Box::new(Ply::load(path)?.mesh(transform, material)),
+        };
+        Ok(vec![object])
+    }
+}
+
+impl MaterialDesc {
+    pub fn build(&self) -> Material {
+        Material {
+            colour: colour(self.colour),
+            ambient: self.ambient,
+            diffuse: self.diffuse,
+            specular: self.specular,
+            shininess: self.shininess,
+            transparency: self.transparency,
+            casts_shadow: self.casts_shadow,
+            holdout: self.holdout,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::FRAC_PI_2;
+
+    use crate::types::ray::Ray;
+
+    use super::*;
+
+    const DEFAULT_WORLD: &str = "
+camera:
+  width: 11
+  height: 11
+  fov: 1.5707964
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+lights:
+  - type: point
+    position: [-10, 10, -10]
+objects:
+  - type: sphere
+    name: outer
+    material:
+      colour: [0.8, 1.0, 0.6]
+      diffuse: 0.7
+      specular: 0.2
+  - type: sphere
+    transform:
+      - scale: [0.5, 0.5, 0.5]
+";
+
+    #[test]
+    fn from_yaml() {
+        let scene = Scene::from_yaml(DEFAULT_WORLD).unwrap();
+        assert_eq!(scene.camera.up, [0.0, 1.0, 0.0]);
+        assert_eq!(scene.camera.projection, ProjectionDesc::Perspective);
+        assert_eq!(scene.lights[0], LightDesc::new(LightSource::Point {
+            position: [-10.0, 10.0, -10.0],
+            intensity: [1.0, 1.0, 1.0],
+            range: None,
+        }));
+        assert_eq!(scene.objects[0].name.as_deref(), Some("outer"));
+        assert_eq!(scene.objects[0].material.ambient, 0.1);
+        assert_eq!(scene.objects[1].transform, vec![TransformDesc::Scale([0.5, 0.5, 0.5])]);
+
+        assert_eq!(scene.ambient, None);
+        assert_eq!(scene.output.build(), ToneMapping::default());
+        assert_eq!(Scene::from_yaml(&scene.to_yaml()).unwrap(), scene);
+
+        let ambient = Scene::from_yaml(&format!("{DEFAULT_WORLD}ambient: {{mode: replace, intensity: 0.5}}")).unwrap()
+            .ambient.unwrap();
+        assert_eq!(ambient.build(), Ambient::Replace(Colour::new(0.5, 0.5, 0.5)));
+
+        let (world, _) = Scene::from_yaml(&format!("{DEFAULT_WORLD}medium: {{density: 0.2}}")).unwrap().build().unwrap();
+        assert_eq!(world.medium(), Some(&Medium::new(0.2)));
+        assert_eq!(world.light_scattering(0), 1.0);
+        assert_eq!(world.max_sample_radiance(), None);
+        assert_eq!(world.integrator(), Integrator::Whitted);
+        assert!(!world.packed_spheres());
+
+        let (world, _) = Scene::from_yaml(&format!("{DEFAULT_WORLD}packed_spheres: true")).unwrap().build().unwrap();
+        assert!(world.packed_spheres());
+
+        let source = format!("{DEFAULT_WORLD}integrator: {{type: path_tracing, max_bounces: 2}}");
+        let (world, _) = Scene::from_yaml(&source).unwrap().build().unwrap();
+        assert_eq!(world.integrator(), Integrator::PathTracing { max_bounces: 2, seed: 0, min_throughput: 0.0,
+            sampler: SamplerKind::Random });
+
+        let (world, camera) = Scene::from_yaml(&format!("{source}\nsampler: sobol")).unwrap().build().unwrap();
+        assert_eq!(world.integrator(), Integrator::PathTracing { max_bounces: 2, seed: 0, min_throughput: 0.0,
+            sampler: SamplerKind::Sobol });
+        assert_eq!(camera.jitter.sampler, SamplerKind::Sobol);
+
+        let output = Scene::from_yaml(&format!("{DEFAULT_WORLD}output: {{exposure: -1, tone_map: aces}}")).unwrap().output;
+        assert_eq!(output.build(), ToneMapping::new(-1.0, ToneMap::Aces));
+        assert_eq!(output.dither(), Dither::None);
+        let output = Scene::from_yaml(&format!("{DEFAULT_WORLD}output: {{dither: floyd_steinberg}}")).unwrap().output;
+        assert_eq!(output.dither(), Dither::FloydSteinberg);
+        assert!(matches!(Scene::from_yaml("camera: 1"), Err(SceneError::Parse(_))));
+    }
+
+    #[test]
+    fn from_json() {
+        let source = r#"{
+            "camera": {"width": 11, "height": 11, "fov": 1.5707964, "from": [0, 0, -5], "to": [0, 0, 0]},
+            "lights": [{"type": "point", "position": [-10, 10, -10]}],
+            "objects": [
+                {"type": "sphere", "name": "outer",
+                    "material": {"colour": [0.8, 1.0, 0.6], "diffuse": 0.7, "specular": 0.2}},
+                {"type": "sphere", "transform": [{"scale": [0.5, 0.5, 0.5]}]}
+            ]
+        }"#;
+        let scene = Scene::from_json(source).unwrap();
+        assert_eq!(scene, Scene::from_yaml(DEFAULT_WORLD).unwrap());
+        assert_eq!(Scene::from_json(&scene.to_json()).unwrap(), scene);
+        assert!(matches!(Scene::from_json("{\"camera\": 1}"), Err(SceneError::Json(_))));
+
+        let path = std::env::temp_dir().join(format!("rosemary-{}.json", Uuid::new_v4()));
+        fs::write(&path, source).unwrap();
+        let loaded = Scene::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), scene);
+    }
+
+    #[test]
+    fn from_toml() {
+        let source = r#"
+[camera]
+width = 11
+height = 11
+fov = 1.5707964
+from = [0, 0, -5]
+to = [0, 0, 0]
+
+[[lights]]
+type = "point"
+position = [-10, 10, -10]
+
+[[objects]]
+type = "sphere"
+name = "outer"
+material = { colour = [0.8, 1.0, 0.6], diffuse = 0.7, specular = 0.2 }
+
+[[objects]]
+type = "sphere"
+transform = [{ scale = [0.5, 0.5, 0.5] }]
+"#;
+        let mut scene = Scene::from_toml(source).unwrap();
+        assert_eq!(scene, Scene::from_yaml(DEFAULT_WORLD).unwrap());
+
+        scene.integrator = IntegratorDesc::PathTracing { max_bounces: 2, seed: 7, min_throughput: 0.1 };
+        scene.lights[0].only = Some(vec!["outer".to_string()]);
+        scene.camera.projection = ProjectionDesc::Orthographic { width: 4.0 };
+        assert_eq!(Scene::from_toml(&scene.to_toml()).unwrap(), scene);
+
+        assert!(matches!(Scene::from_toml("[camera]\nwidth = 1"), Err(SceneError::Toml(_))));
+        assert!(matches!(Scene::from_toml(&source.replace("diffuse", "difuse")), Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn from_ron() {
+        let source = r#"(
+    camera: (width: 11, height: 11, fov: 1.5707964, from: (0, 0, -5), to: (0, 0, 0)),
+    lights: [{"type": "point", "position": (-10, 10, -10)}],
+    objects: [
+        {"type": "sphere", "name": Some("outer"), "material": (colour: (0.8, 1.0, 0.6), diffuse: 0.7, specular: 0.2)},
+        {"type": "sphere", "transform": [{scale: (0.5, 0.5, 0.5)}]},
+    ],
+    sampler: random,
+)"#;
+        let mut scene = Scene::from_ron(source).unwrap();
+        assert_eq!(scene, Scene::from_yaml(DEFAULT_WORLD).unwrap());
+
+        scene.integrator = IntegratorDesc::PathTracing { max_bounces: 2, seed: 7, min_throughput: 0.1 };
+        scene.lights[0].only = Some(vec!["outer".to_string()]);
+        scene.camera.projection = ProjectionDesc::Orthographic { width: 4.0 };
+        assert_eq!(Scene::from_ron(&scene.to_ron()).unwrap(), scene);
+
+        assert!(matches!(Scene::from_ron("(camera: (width: 1"), Err(SceneError::Ron(_))));
+        let defined = source.replace("lights:", r#"define: {"light": {"type": "point"}}, lights:"#)
+            .replace(r#"{"type": "point","#, r#"{"use": "light","#).replace("random", r#""random""#).replace("scale", r#""scale""#);
+        assert_eq!(Scene::from_ron(&defined).unwrap(), Scene::from_yaml(DEFAULT_WORLD).unwrap());
+        assert!(matches!(Scene::from_ron(&source.replace("diffuse", "difuse")), Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn from_yaml_with_params() {
+        let source = DEFAULT_WORLD.replace("width: 11", "width: $size") + "params: {size: 11}\n";
+        assert_eq!(Scene::from_yaml_with_params(&source, &[]).unwrap(), Scene::from_yaml(DEFAULT_WORLD).unwrap());
+        let wider = Scene::from_yaml_with_params(&source, &[("size".to_string(), "20".to_string())]).unwrap();
+        assert_eq!(wider.camera.width, 20);
+
+        // validated like a loaded file, unlike from_yaml
+        let broken = source.replace("to: [0, 0, 0]", "to: [0, 0, -5]");
+        assert!(Scene::from_yaml(&broken).is_ok());
+        assert!(matches!(Scene::from_yaml_with_params(&broken, &[]), Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn includes() {
+        let dir = std::env::temp_dir().join(format!("rosemary-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("materials.yaml"), "define:\n  pale: {colour: [0.8, 1.0, 0.6], diffuse: 0.7}").unwrap();
+        let source = DEFAULT_WORLD.replace("colour: [0.8, 1.0, 0.6]\n      diffuse: 0.7", "use: pale")
+            + "include: materials.yaml\n";
+        assert!(source.contains("use: pale"));
+        fs::write(dir.join("scene.yaml"), &source).unwrap();
+
+        let loaded = Scene::load(dir.join("scene.yaml"));
+        let unresolved = Scene::from_yaml(&source);
+        let fragments = ["materials.yaml", "scene.yaml", "missing.yaml"].map(|name| Scene::is_fragment(dir.join(name)));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap(), Scene::from_yaml(DEFAULT_WORLD).unwrap());
+        assert!(unresolved.is_err());
+        assert_eq!(fragments, [true, false, false]);
+    }
+
+    #[test]
+    fn relative_paths() {
+        let dir = std::env::temp_dir().join(format!("rosemary-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("models")).unwrap();
+        fs::write(dir.join("models/quad.obj"), "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        fs::write(dir.join("absolute.stl"), "").unwrap();
+        fs::write(dir.join("sky.hdr"), "").unwrap();
+        let source = format!("{DEFAULT_WORLD}  - type: obj\n    path: models/quad.obj\n  - type: stl\n    path: {}\n\
+            environment: {{path: sky.hdr}}\n", dir.join("absolute.stl").display());
+        fs::write(dir.join("scene.yaml"), &source).unwrap();
+
+        let loaded = Scene::load(dir.join("scene.yaml"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let scene = loaded.unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        assert_eq!(scene.objects[2].shape, ShapeDesc::Obj { path: path("models/quad.obj") });
+        assert_eq!(scene.objects[3].shape, ShapeDesc::Stl { path: path("absolute.stl"), recompute_normals: false });
+        assert_eq!(scene.environment.unwrap().path, path("sky.hdr"));
+        // nothing to find them from otherwise
+        assert_eq!(Scene::from_yaml(&source).unwrap().objects[2].shape, ShapeDesc::Obj { path: "models/quad.obj".to_string() });
+    }
+
+    #[test]
+    fn models() {
+        let stl = "solid\nfacet normal 0 0 0\nvertex 0 0 0\nvertex 1 0 0\nvertex 1 1 0\nendfacet\n\
+            facet normal 0 0 0\nvertex 0 0 0\nvertex 1 1 0\nvertex 0 1 0\nendfacet\nendsolid\n";
+        let ply = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n";
+        for (kind, contents) in [("obj", "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n"), ("stl", stl), ("ply", ply)] {
+            let path = std::env::temp_dir().join(format!("rosemary-{}.{kind}", Uuid::new_v4()));
+            fs::write(&path, contents).unwrap();
+            let source = format!("{DEFAULT_WORLD}  - type: {kind}\n    path: {}\n", path.display());
+            let built = Scene::from_yaml(&source).unwrap().build();
+            fs::remove_file(&path).unwrap();
+
+            let (world, _) = built.unwrap();
+            let quad = &world.objects()[2];
+            for (x, y) in [(0.75, 0.25), (0.25, 0.75)] {
+                let hits = quad.intersect(Ray::new(Tuple::point(x, y, -1.0), Tuple::vector(0.0, 0.0, 1.0)));
+                assert_eq!(hits.iter().map(|hit| hit.t).collect::<Vec<_>>(), [1.0]);
+            }
+            assert!(matches!(Scene::from_yaml(&source).unwrap().build(), Err(SceneError::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn obj_materials() {
+        let dir = std::env::temp_dir().join(format!("rosemary-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("paint.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+        fs::write(dir.join("model.obj"), "mtllib paint.mtl\nmtllib gone.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+            usemtl red\nf 1 2 3\nusemtl blue\nf 3 2 1\n").unwrap();
+        let source = format!("{DEFAULT_WORLD}  - type: obj\n    name: model\n    path: {}\n", dir.join("model.obj").display());
+        let mut scene = Scene::from_yaml(&source).unwrap();
+        scene.lights[0].except = vec!["model".to_string()];
+        let built = scene.build_all();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let built = built.unwrap();
+        assert_eq!(built.sources, [0, 1, 2, 2]);
+        assert_eq!(built.warnings, ["model: couldn't read material library gone.mtl, using the object's material"]);
+        let objects = built.world.objects();
+        assert_eq!(objects[2].material().colour, Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(objects[3].material().colour, scene.objects[2].material.build().colour);
+        // both parts are still the named object for light linking
+        let except = &built.world.light_link(0).except;
+        assert!(except.contains(&objects[2].id()) && except.contains(&objects[3].id()));
+    }
+
+    #[test]
+    fn sky() {
+        let mut scene = Scene::from_yaml(&format!("{DEFAULT_WORLD}sky: {{elevation: 0.5, resolution: 16}}")).unwrap();
+        let sky = scene.sky.unwrap();
+        assert_eq!(sky.build(), Sky {
+            sun_elevation: 0.5,
+            ..Default::default()
+        });
+
+        let (world, _) = scene.build().unwrap();
+        assert_eq!(world.lights().len(), 2);
+        assert_eq!(world.lights()[1].samples(Tuple::point(0.0, 0.0, 0.0)), vec![sky.build().sun_direction()]);
+        assert!(world.environment().is_some());
+
+        scene.sky = Some(SkyDesc {
+            turbidity: 0.5,
+            ..sky
+        });
+        assert_eq!(scene.validate(), vec!["sky: turbidity must be at least 1".to_string()]);
+    }
+
+    #[test]
+    fn build() {
+        let (world, camera) = Scene::from_yaml(DEFAULT_WORLD).unwrap().build().unwrap();
+        assert_eq!(camera.hsize(), 11);
+        assert!(crate::eq(camera.fov(), FRAC_PI_2));
+        assert_eq!(camera.samples, 1);
+
+        let mut scene = Scene::from_yaml(DEFAULT_WORLD).unwrap();
+        scene.camera.projection = ProjectionDesc::Orthographic { width: 3.0 };
+        let (_, camera) = scene.build().unwrap();
+        assert_eq!(camera.projection(), Projection::Orthographic { width: 3.0 });
+
+        let source = DEFAULT_WORLD.replace("  to: [0, 0, 0]\n", "  to: [0, 0, 0]\n  projection: {type: equirectangular}\n");
+        let (_, camera) = Scene::from_yaml(&source).unwrap().build().unwrap();
+        assert_eq!(camera.projection(), Projection::Equirectangular);
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(world.colour_at(r), World::default().colour_at(r));
+        assert_eq!(camera.render(&world)[(5, 5)], Colour::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn transform_order() {
+        let object = ObjectDesc {
+            name: None,
+            shape: ShapeDesc::Sphere,
+            transform: vec![TransformDesc::RotateZ(FRAC_PI_2), TransformDesc::Translate([1.0, 0.0, 0.0])],
+            material: MaterialDesc::default(),
+            lights: None,
+            ignore_lights: Vec::new(),
+            keyframes: Vec::new(),
+        };
+        assert_eq!(object.transform() * Tuple::point(1.0, 0.0, 0.0), Tuple::point(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn animation() {
+        let source = format!("{DEFAULT_WORLD}
+    keyframes:
+      - time: 0
+        transform: [{{translate: [0, 0, 0]}}, {{rotate_y: 0}}]
+      - time: 2
+        transform: [{{translate: [4, 0, 0]}}, {{rotate_y: 1}}]
+").replace("  to: [0, 0, 0]\n", "  to: [0, 0, 0]
+  path:
+    - {time: 0, from: [0, 0, -5], to: [0, 0, 0]}
+    - {time: 4, from: [0, 0, -9], to: [0, 0, 0]}
+");
+        let scene = Scene::from_yaml(&source).unwrap();
+        assert!(scene.validate().is_empty());
+        assert_eq!(scene.duration(), 4.0);
+
+        let halfway = scene.at(1.0);
+        assert_eq!(halfway.objects[1].transform, [TransformDesc::Translate([2.0, 0.0, 0.0]), TransformDesc::RotateY(0.5)]);
+        assert_eq!(halfway.camera.from, [0.0, 0.0, -6.0]);
+        // before the first key and after the last the ends are held
+        assert_eq!(scene.at(-1.0).objects[1].transform, scene.objects[1].keyframes[0].transform);
+        assert_eq!(scene.at(3.0).objects[1].transform, scene.objects[1].keyframes[1].transform);
+        assert_eq!(scene.at(5.0).camera.from, [0.0, 0.0, -9.0]);
+        assert_eq!(Scene::from_yaml(&scene.to_yaml()).unwrap(), scene);
+
+        let mismatched = Scene::from_yaml(&source.replace("{rotate_y: 1}", "{rotate_x: 1}")).unwrap();
+        assert_eq!(mismatched.validate(), ["objects[1]: keyframes must all have the same transform steps"]);
+        let backwards = Scene::from_yaml(&source.replace("time: 4", "time: 0")).unwrap();
+        assert_eq!(backwards.validate(), ["camera: path times must go up"]);
+    }
+
+    #[test]
+    fn light_linking() {
+        let source = format!("{DEFAULT_WORLD}
+  - type: sphere
+    name: lamp_shade
+    ignore_lights: [fill]
+    transform:
+      - translate: [0, 5, 0]
+");
+        let mut scene = Scene::from_yaml(&source).unwrap();
+        scene.lights[0].name = Some("key".to_string());
+        scene.lights[0].except = vec!["outer".to_string()];
+        let mut fill = LightDesc::new(LightSource::Point {
+            position: [10.0, 10.0, -10.0],
+            intensity: [0.5, 0.5, 0.5],
+            range: None,
+        });
+        fill.name = Some("fill".to_string());
+        fill.only = Some(vec!["lamp_shade".to_string()]);
+        scene.lights.push(fill);
+        scene.objects[0].lights = Some(vec!["fill".to_string()]);
+
+        let (world, _) = scene.build().unwrap();
+        let [outer, inner, shade] = [0, 1, 2].map(|i| world.objects()[i].id());
+        assert!(!world.light_link(0).affects(outer));
+        assert!(world.light_link(0).affects(inner));
+        assert!(world.light_link(0).affects(shade));
+        // listed by the fill light but opted out itself
+        assert!(!world.light_link(1).affects(shade));
+        assert!(!world.light_link(1).affects(inner));
+
+        scene.lights[1].only = Some(vec!["lampshade".to_string()]);
+        scene.objects[2].ignore_lights = vec!["fil".to_string()];
+        assert_eq!(scene.validate(), vec![
+            "fill: no object called lampshade".to_string(),
+            "lamp_shade: no light called fil".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn validate() {
+        let mut scene = Scene::from_yaml(DEFAULT_WORLD).unwrap();
+        assert!(scene.validate().is_empty());
+
+        scene.camera.to = scene.camera.from;
+        scene.objects[0].transform.push(TransformDesc::Scale([1.0, 0.0, 1.0]));
+        scene.lights.push(LightDesc::new(LightSource::Sphere {
+            centre: [0.0, 5.0, 0.0],
+            radius: 1.0,
+            samples: 0,
+            intensity: [1.0, 1.0, 1.0],
+            jitter: None,
+        }));
+
+        let problems = scene.validate();
+        assert_eq!(problems, vec![
+            "camera: from and to are the same point".to_string(),
+            "lights[1]: samples must be at least 1".to_string(),
+            "outer: transform can't be inverted".to_string(),
+        ]);
+        assert!(matches!(scene.build(), Err(SceneError::Invalid(p)) if p == problems));
+    }
+
+    #[test]
+    fn locations() {
+        let typos = DEFAULT_WORLD.replace("diffuse: 0.7", "difuse: 0.7").replace("    transform:", "    radius: 2\n    transform:");
+        let Err(SceneError::Invalid(problems)) = Scene::from_yaml(&typos) else { panic!("typos weren't caught") };
+        assert_eq!(problems, vec![
+            "line 16, column 7: outer: material.difuse: unknown key".to_string(),
+            "line 19, column 5: objects[1]: radius: unknown key".to_string(),
+        ]);
+
+        let Err(SceneError::Invalid(problems)) = Scene::from_json(r#"{"camera": {"width": 1, "height": 1,
+            "from": [0, 0, 0], "to": [0, 0, 1], "zoom": 2}}"#) else { panic!("typo wasn't caught") };
+        assert_eq!(problems, vec!["camera.zoom: unknown key".to_string()]);
+
+        let broken = DEFAULT_WORLD.replace("[0.5, 0.5, 0.5]", "[0.5, 0, 0.5]");
+        let scene = Scene::from_yaml(&broken).unwrap();
+        assert_eq!(scene.validate_source(&broken), vec!["line 18, column 3: objects[1]: transform can't be inverted".to_string()]);
+
+        let path = std::env::temp_dir().join(format!("rosemary-{}.yaml", Uuid::new_v4()));
+        fs::write(&path, &broken).unwrap();
+        let loaded = Scene::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(SceneError::Invalid(p)) if p == scene.validate_source(&broken)));
+    }
+}
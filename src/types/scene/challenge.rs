@@ -0,0 +1,341 @@
+//! Scenes in the YAML dialect of The Ray Tracer Challenge, a list of `add`
+//! and `define` items, turned into a `Scene`.
+//!
+//! Cameras, point and area lights, spheres, cubes, planes, OBJ models and
+//! groups are understood. Planes become large squares, and groups are
+//! flattened into their children with the group's transform after their
+//! own. Materials keep what `MaterialDesc` has. Reflection, refraction and
+//! patterns are left out, as this renderer has none of them.
+
+use std::path::{Path, PathBuf};
+
+use serde_yaml::{Mapping, Value};
+
+use crate::Float;
+use super::{plane_size, CameraDesc, LightDesc, LightSource, MaterialDesc, ObjectDesc, ProjectionDesc, Scene, SceneError, ShapeDesc,
+    TransformDesc};
+
+/// Whether `document` is written in the book's dialect rather than ours
+pub fn is_challenge(document: &Value) -> bool {
+    document.as_sequence().is_some_and(|items| items.iter().any(|item| item.get("add").is_some() || item.get("define").is_some()))
+}
+
+/// `document` as a scene, with OBJ files found from `dir`
+pub fn convert(document: &Value, dir: &Path) -> Result<Scene, SceneError> {
+    let mut reader = Reader {
+        dir: dir.to_path_buf(),
+        ..Default::default()
+    };
+    for (i, item) in document.as_sequence().into_iter().flatten().enumerate() {
+        reader.item(item).map_err(|problem| SceneError::Invalid(vec![format!("[{i}]: {problem}")]))?;
+    }
+
+    let camera = reader.camera.ok_or_else(|| SceneError::Invalid(vec!["no camera added".to_string()]))?;
+    Ok(Scene {
+        camera,
+        lights: reader.lights,
+        objects: reader.objects,
+        ambient: None,
+        medium: None,
+        environment: None,
+        sky: None,
+        integrator: Default::default(),
+        max_sample_radiance: None,
+        packed_spheres: false,
+        sampler: Default::default(),
+        output: Default::default(),
+    })
+}
+
+#[derive(Default)]
+struct Reader {
+    /// Directory of the scene file
+    dir: PathBuf,
+    definitions: Mapping,
+    camera: Option<CameraDesc>,
+    lights: Vec<LightDesc>,
+    objects: Vec<ObjectDesc>,
+}
+
+impl Reader {
+    fn item(&mut self, item: &Value) -> Result<(), String> {
+        if let Some(name) = item.get("define") {
+            let name = name.as_str().ok_or("define: names must be strings")?;
+            let mut value = item.get("value").cloned().ok_or_else(|| format!("define {name}: needs a value"))?;
+            if let Some(base) = item.get("extend") {
+                let mut extended = self.definition(base)?.clone();
+                match (&mut extended, value) {
+                    (Value::Mapping(extended), Value::Mapping(own)) => extended.extend(own),
+                    _ => return Err(format!("define {name}: can only extend a map with a map")),
+                }
+                value = extended;
+            }
+            self.definitions.insert(name.into(), value);
+            return Ok(());
+        }
+
+        match item.get("add").and_then(Value::as_str) {
+            Some("camera") => self.camera = Some(camera(item)?),
+            Some("light") => self.lights.push(light(item)?),
+            Some(_) => {
+                let objects = self.object(item)?;
+                self.objects.extend(objects);
+            },
+            None => return Err("needs add or define".to_string()),
+        }
+        Ok(())
+    }
+
+    fn definition(&self, name: &Value) -> Result<&Value, String> {
+        let name = name.as_str().ok_or("definitions are named with strings")?;
+        self.definitions.get(name).ok_or_else(|| format!("no definition called {name}"))
+    }
+
+    /// The shape in `item`, or every shape in it for groups
+    fn object(&self, item: &Value) -> Result<Vec<ObjectDesc>, String> {
+        let kind = item.get("add").and_then(Value::as_str).unwrap_or_default();
+        let transform = match item.get("transform") {
+            Some(transform) => self.transform(transform)?,
+            None => Vec::new(),
+        };
+
+        let shape = match kind {
+            "sphere" => ShapeDesc::Sphere,
+            "cube" => ShapeDesc::Cube,
+            "plane" => ShapeDesc::Plane { size: plane_size() },
+            "obj" => {
+                let file = item.get("file").and_then(Value::as_str).ok_or("obj: needs a file")?;
+                ShapeDesc::Obj { path: self.dir.join(file).to_string_lossy().into_owned() }
+            },
+            "group" => {
+                let mut children = Vec::new();
+                for child in item.get("children").and_then(Value::as_sequence).ok_or("group: needs children")? {
+                    children.extend(self.object(child)?);
+                }
+                for child in &mut children {
+                    child.transform.extend(&transform);
+                }
+                return Ok(children);
+            },
+            kind => return Err(format!("{kind} shapes aren't supported")),
+        };
+
+        let mut material = match item.get("material") {
+            Some(material) => self.material(material)?,
+            None => MaterialDesc::default(),
+        };
+        if let Some(shadow) = item.get("shadow").and_then(Value::as_bool) {
+            material.casts_shadow = shadow;
+        }
+
+        Ok(vec![ObjectDesc {
+            name: None,
+            shape,
+            transform,
+            material,
+            lights: None,
+            ignore_lights: Vec::new(),
+            keyframes: Vec::new(),
+        }])
+    }
+
+    fn material(&self, material: &Value) -> Result<MaterialDesc, String> {
+        let material = if material.is_string() { self.definition(material)? } else { material };
+        let material = material.as_mapping().ok_or("materials are maps or names of them")?;
+
+        let mut desc = MaterialDesc::default();
+        for (key, value) in material {
+            let float = || number(value).ok_or_else(|| format!("material: {} must be a number", key.as_str().unwrap_or_default()));
+            match key.as_str().unwrap_or_default() {
+                "color" => desc.colour = triple(value).ok_or("material: color must be three numbers")?,
+                "ambient" => desc.ambient = float()?,
+                "diffuse" => desc.diffuse = float()?,
+                "specular" => desc.specular = float()?,
+                "shininess" => desc.shininess = float()?,
+                "transparency" => desc.transparency = float()?,
+                _ => {},
+            }
+        }
+        Ok(desc)
+    }
+
+    /// Steps are lists like `[translate, 1, 2, 3]`, or names of lists of them
+    fn transform(&self, transform: &Value) -> Result<Vec<TransformDesc>, String> {
+        let mut steps = Vec::new();
+        for step in transform.as_sequence().ok_or("transforms are lists of steps")? {
+            if step.is_string() {
+                steps.extend(self.transform(self.definition(step)?)?);
+                continue;
+            }
+
+            let step = step.as_sequence().ok_or("transform steps are lists like [translate, 1, 2, 3]")?;
+            let op = step.first().and_then(Value::as_str).unwrap_or_default();
+            let args = step.iter().skip(1).map(number).collect::<Option<Vec<_>>>()
+                .ok_or_else(|| format!("{op}: arguments must be numbers"))?;
+            let wrong = || format!("{op}: wrong number of arguments");
+            let angle = || match args[..] {
+                [angle] => Ok(angle),
+                _ => Err(wrong()),
+            };
+
+            steps.push(match op {
+                "translate" => TransformDesc::Translate(args.try_into().map_err(|_| wrong())?),
+                "scale" => TransformDesc::Scale(args.try_into().map_err(|_| wrong())?),
+                "rotate-x" => TransformDesc::RotateX(angle()?),
+                "rotate-y" => TransformDesc::RotateY(angle()?),
+                "rotate-z" => TransformDesc::RotateZ(angle()?),
+                "shear" => TransformDesc::Shear(args.try_into().map_err(|_| wrong())?),
+                op => return Err(format!("unknown transform {op}")),
+            });
+        }
+        Ok(steps)
+    }
+}
+
+#[allow(clippy::unnecessary_cast)] // already a Float with the f64 feature
+fn number(value: &Value) -> Option<Float> {
+    value.as_f64().map(|n| n as Float)
+}
+
+fn triple(value: &Value) -> Option<[Float; 3]> {
+    let values: Vec<_> = value.as_sequence()?.iter().map(number).collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+fn field<'a>(item: &'a Value, kind: &str, key: &str) -> Result<&'a Value, String> {
+    item.get(key).ok_or_else(|| format!("{kind}: needs {key}"))
+}
+
+fn field_triple(item: &Value, kind: &str, key: &str) -> Result<[Float; 3], String> {
+    triple(field(item, kind, key)?).ok_or_else(|| format!("{kind}: {key} must be three numbers"))
+}
+
+fn field_count(item: &Value, kind: &str, key: &str) -> Result<usize, String> {
+    field(item, kind, key)?.as_u64().map(|n| n as usize).ok_or_else(|| format!("{kind}: {key} must be a whole number"))
+}
+
+fn camera(item: &Value) -> Result<CameraDesc, String> {
+    Ok(CameraDesc {
+        width: field_count(item, "camera", "width")?,
+        height: field_count(item, "camera", "height")?,
+        fov: number(field(item, "camera", "field-of-view")?).ok_or("camera: field-of-view must be a number")?,
+        from: field_triple(item, "camera", "from")?,
+        to: field_triple(item, "camera", "to")?,
+        up: field_triple(item, "camera", "up")?,
+        projection: ProjectionDesc::Perspective,
+        samples: 1,
+        jitter: None,
+        path: Vec::new(),
+    })
+}
+
+/// Point lights, or area lights if they have a `corner`
+fn light(item: &Value) -> Result<LightDesc, String> {
+    let intensity = field_triple(item, "light", "intensity")?;
+    let source = if item.get("corner").is_some() {
+        LightSource::Area {
+            corner: field_triple(item, "light", "corner")?,
+            uvec: field_triple(item, "light", "uvec")?,
+            usteps: field_count(item, "light", "usteps")?,
+            vvec: field_triple(item, "light", "vvec")?,
+            vsteps: field_count(item, "light", "vsteps")?,
+            intensity,
+            jitter: item.get("jitter").and_then(Value::as_bool).unwrap_or_default().then_some(0),
+        }
+    }
+    else {
+        LightSource::Point { position: field_triple(item, "light", "at")?, intensity, range: None }
+    };
+    Ok(LightDesc::new(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COVER: &str = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [-6, 6, -10]
+  to: [6, 0, 6]
+  up: [-0.45, 1, 0]
+
+- add: light
+  at: [50, 100, -50]
+  intensity: [1, 1, 1]
+
+- define: white-material
+  value:
+    color: [1, 1, 1]
+    diffuse: 0.7
+    reflective: 0.1
+
+- define: blue-material
+  extend: white-material
+  value:
+    color: [0.537, 0.831, 0.914]
+
+- define: standard-transform
+  value:
+    - [translate, 1, -1, 1]
+    - [scale, 0.5, 0.5, 0.5]
+
+- define: large-object
+  value:
+    - standard-transform
+    - [scale, 3.5, 3.5, 3.5]
+
+- add: plane
+  material: { color: [1, 1, 1], ambient: 1, diffuse: 0, specular: 0 }
+  transform:
+    - [rotate-x, 1.5707963267948966]
+    - [translate, 0, 0, 500]
+
+- add: cube
+  material: blue-material
+  shadow: false
+  transform:
+    - large-object
+    - [translate, 3, 1, 0]
+
+- add: group
+  transform: [[translate, 0, 2, 0]]
+  children:
+    - add: sphere
+      transform: [[scale, 2, 2, 2]]
+";
+
+    #[test]
+    fn cover() {
+        let document: Value = serde_yaml::from_str(COVER).unwrap();
+        assert!(is_challenge(&document));
+        let scene = convert(&document, Path::new("")).unwrap();
+
+        assert_eq!(scene.camera.fov, 0.785);
+        assert_eq!(scene.camera.up, [-0.45, 1.0, 0.0]);
+        assert_eq!(scene.lights[0].source, LightSource::Point { position: [50.0, 100.0, -50.0], intensity: [1.0; 3], range: None });
+
+        let [plane, cube, sphere] = &scene.objects[..] else { panic!("expected three objects") };
+        assert_eq!(plane.shape, ShapeDesc::Plane { size: 1000.0 });
+        assert_eq!(plane.material.ambient, 1.0);
+        assert_eq!(cube.material.colour, [0.537, 0.831, 0.914]);
+        assert_eq!(cube.material.diffuse, 0.7);
+        assert!(!cube.material.casts_shadow);
+        assert_eq!(cube.transform, [TransformDesc::Translate([1.0, -1.0, 1.0]), TransformDesc::Scale([0.5; 3]),
+            TransformDesc::Scale([3.5; 3]), TransformDesc::Translate([3.0, 1.0, 0.0])]);
+        assert_eq!(sphere.transform, [TransformDesc::Scale([2.0; 3]), TransformDesc::Translate([0.0, 2.0, 0.0])]);
+        assert!(scene.validate().is_empty());
+
+        let model: Value = serde_yaml::from_str(&format!("{COVER}\n- add: obj\n  file: teapot.obj\n")).unwrap();
+        let scene = convert(&model, Path::new("scenes")).unwrap();
+        let path = Path::new("scenes").join("teapot.obj").to_string_lossy().into_owned();
+        assert_eq!(scene.objects[3].shape, ShapeDesc::Obj { path });
+
+        let cylinder = convert(&serde_yaml::from_str("[{add: cylinder}]").unwrap(), Path::new(""));
+        assert!(matches!(cylinder, Err(SceneError::Invalid(p)) if p == ["[0]: cylinder shapes aren't supported"]));
+        let unknown = convert(&serde_yaml::from_str("[{add: sphere, material: shiny}]").unwrap(), Path::new(""));
+        assert!(matches!(unknown, Err(SceneError::Invalid(p)) if p == ["[0]: no definition called shiny"]));
+    }
+}
@@ -0,0 +1,448 @@
+//! `include`, `define` and `params` in scene files, worked out on the parsed
+//! document before it becomes a `Scene`.
+//!
+//! Definitions are named values. A map with a `use` key takes the named
+//! definition, or several in turn, and lays its own keys over the top, maps
+//! merging key by key and anything else being replaced. Included files add
+//! their definitions and their lights and objects, which come after the
+//! including file's own. Its own definitions win over included ones. Paths
+//! to models and images in an included file are relative to that file, and a
+//! file included more than once, such as through two others, is only read
+//! the first time.
+//!
+//! Parameters are named values with defaults that can be changed when the
+//! scene is loaded. A string `$name` anywhere in the scene or its definitions
+//! becomes the parameter's value, and `${name}` inside a longer string is
+//! replaced by it as text. `$$` is a `$` that isn't a parameter.
+//!
+//! A list item with a `repeat` key is copied that many times, its `as` key
+//! naming a parameter, `i` by default, that counts the copies from 0. The
+//! count can itself be a parameter. The counter is only set in the item
+//! itself, not in definitions it uses.
+
+use std::{fs, path::{Path, PathBuf}};
+
+use serde_yaml::{Mapping, Value};
+
+use super::{Format, SceneError};
+
+/// The only sections an included file can have
+const INCLUDABLE: [&str; 4] = ["include", "define", "lights", "objects"];
+
+/// `document` with its includes read, its parameters filled in and every
+/// `use` replaced by what it names. `params` change the defaults the scene
+/// gives its parameters
+pub fn expand(document: Value, dir: &Path, params: &Mapping) -> Result<Value, SceneError> {
+    let mut definitions = Mapping::new();
+    let mut document = gather(document, dir, &mut definitions, &mut Vec::new(), &mut Vec::new())?;
+
+    let mut defaults = match document.as_mapping_mut().and_then(|document| document.remove("params")) {
+        None => Mapping::new(),
+        Some(Value::Mapping(defaults)) => defaults,
+        Some(_) => return Err(invalid("params: must map names to values".to_string())),
+    };
+    for (name, value) in params {
+        let param = defaults.get_mut(name)
+            .ok_or_else(|| invalid(format!("params: no parameter called {}", name.as_str().unwrap_or_default())))?;
+        *param = value.clone();
+    }
+    substitute(&mut document, &defaults)?;
+    for (_, definition) in definitions.iter_mut() {
+        substitute(definition, &defaults)?;
+    }
+
+    resolve(&mut document, &definitions, &mut Vec::new())?;
+    Ok(document)
+}
+
+fn invalid(problem: String) -> SceneError {
+    SceneError::Invalid(vec![problem])
+}
+
+/// Takes `include` and `define` out of `document`, collecting the
+/// definitions and adding in the lights and objects of included files.
+/// `including` is the chain of files being read, to catch loops, and `read`
+/// is every file included so far
+fn gather(document: Value, dir: &Path, definitions: &mut Mapping, including: &mut Vec<PathBuf>, read: &mut Vec<PathBuf>)
+    -> Result<Value, SceneError> {
+    let Value::Mapping(mut document) = document else { return Ok(document) };
+
+    let includes = match document.remove("include") {
+        None => Vec::new(),
+        Some(Value::String(path)) => vec![path],
+        Some(Value::Sequence(paths)) => paths.into_iter()
+            .map(|path| path.as_str().map(str::to_string).ok_or_else(|| invalid("include: paths must be strings".to_string())))
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(invalid("include: must be a path or a list of paths".to_string())),
+    };
+
+    for include in includes {
+        let path = dir.join(&include);
+        let canonical = fs::canonicalize(&path).map_err(|err| invalid(format!("include: {include}: {err}")))?;
+        if including.contains(&canonical) {
+            return Err(invalid(format!("include: {include} includes itself")));
+        }
+        if read.contains(&canonical) {
+            continue;
+        }
+        read.push(canonical.clone());
+
+        let source = fs::read_to_string(&path)?;
+        let mut included = Format::of(&path).parse(&source)?;
+        if let Some(key) = included.as_mapping().into_iter().flat_map(Mapping::keys)
+            .find(|key| !key.as_str().is_some_and(|key| INCLUDABLE.contains(&key))) {
+            return Err(invalid(format!("include: {include} can't have {}, only {}", key.as_str().unwrap_or("non-string keys"),
+                INCLUDABLE.join(", "))));
+        }
+
+        let included_dir = path.parent().unwrap_or(Path::new(""));
+        rebase(&mut included, canonical.parent().unwrap_or(Path::new("")));
+        including.push(canonical);
+        let included = gather(included, included_dir, definitions, including, read)?;
+        including.pop();
+
+        for section in ["lights", "objects"] {
+            if let Some(Value::Sequence(items)) = included.get(section) {
+                let own = document.entry(section.into()).or_insert_with(|| Value::Sequence(Vec::new()));
+                let Value::Sequence(own) = own else { return Err(invalid(format!("{section}: must be a list"))) };
+                own.extend(items.iter().cloned());
+            }
+        }
+    }
+
+    match document.remove("define") {
+        None => {},
+        Some(Value::Mapping(defined)) => definitions.extend(defined),
+        Some(_) => return Err(invalid("define: must map names to definitions".to_string())),
+    }
+
+    Ok(Value::Mapping(document))
+}
+
+/// Puts `dir`, the included file's absolute directory, in front of the
+/// relative `path` of each object and definition in it, so the scene doesn't
+/// then find them from its own file. Paths given by a parameter are left
+/// for the scene, as that's where parameters are set
+fn rebase(document: &mut Value, dir: &Path) {
+    for section in ["objects", "define"] {
+        let items: Vec<&mut Value> = match document.get_mut(section) {
+            Some(Value::Sequence(items)) => items.iter_mut().collect(),
+            Some(Value::Mapping(items)) => items.values_mut().collect(),
+            _ => continue,
+        };
+        for item in items {
+            if let Some(Value::String(path)) = item.get_mut("path") {
+                if !path.starts_with('$') {
+                    *path = dir.join(&*path).to_string_lossy().into_owned();
+                }
+            }
+        }
+    }
+}
+
+/// Replaces every map with a `use` key in `value` by what it names.
+/// `using` is the chain of definitions being expanded, to catch loops
+fn resolve(value: &mut Value, definitions: &Mapping, using: &mut Vec<String>) -> Result<(), SceneError> {
+    match value {
+        Value::Sequence(items) => items.iter_mut().try_for_each(|item| resolve(item, definitions, using)),
+        Value::Mapping(map) => {
+            let names = match map.remove("use") {
+                None => Vec::new(),
+                Some(Value::String(name)) => vec![name],
+                Some(Value::Sequence(names)) => names.into_iter()
+                    .map(|name| name.as_str().map(str::to_string).ok_or_else(|| invalid("use: names must be strings".to_string())))
+                    .collect::<Result<_, _>>()?,
+                Some(_) => return Err(invalid("use: must be a name or a list of names".to_string())),
+            };
+            for (_, child) in map.iter_mut() {
+                resolve(child, definitions, using)?;
+            }
+            if names.is_empty() {
+                return Ok(());
+            }
+
+            let mut base = Value::Mapping(Mapping::new());
+            for name in names {
+                let mut definition = definitions.get(name.as_str()).cloned()
+                    .ok_or_else(|| invalid(format!("use: no definition called {name}")))?;
+                if using.contains(&name) {
+                    return Err(invalid(format!("define: {name} uses itself")));
+                }
+                using.push(name);
+                resolve(&mut definition, definitions, using)?;
+                using.pop();
+                merge(&mut base, definition);
+            }
+
+            let own = std::mem::take(map);
+            if !own.is_empty() {
+                if !base.is_mapping() {
+                    return Err(invalid("use: only maps can have keys added to them".to_string()));
+                }
+                merge(&mut base, Value::Mapping(own));
+            }
+            *value = base;
+            Ok(())
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Replaces `$name` strings in `value` with the parameter's value,
+/// `${name}` in other strings with its text and `$$` with `$`. List items
+/// with a `repeat` key are copied that many times
+fn substitute(value: &mut Value, params: &Mapping) -> Result<(), SceneError> {
+    let param = |name: &str| params.get(name).ok_or_else(|| invalid(format!("params: no parameter called {name}")));
+
+    match value {
+        Value::Sequence(items) => {
+            let mut expanded = Vec::with_capacity(items.len());
+            for mut item in std::mem::take(items) {
+                match item.as_mapping_mut().and_then(|item| item.remove("repeat")) {
+                    None => {
+                        substitute(&mut item, params)?;
+                        expanded.push(item);
+                    },
+                    Some(count) => expanded.extend(repeat(item, count, params)?),
+                }
+            }
+            *items = expanded;
+            Ok(())
+        },
+        Value::Mapping(map) => map.iter_mut().try_for_each(|(_, child)| substitute(child, params)),
+        Value::String(text) => {
+            if let Some(name) = text.strip_prefix('$').filter(|name| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')) {
+                *value = param(name)?.clone();
+                return Ok(());
+            }
+
+            let mut substituted = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find('$') {
+                substituted.push_str(&rest[..start]);
+                rest = &rest[start + 1..];
+                if let Some(escaped) = rest.strip_prefix('$') {
+                    substituted.push('$');
+                    rest = escaped;
+                    continue;
+                }
+                let Some(braced) = rest.strip_prefix('{') else {
+                    substituted.push('$');
+                    continue;
+                };
+
+                let end = braced.find('}').ok_or_else(|| invalid(format!("params: {text} has an unclosed ${{")))?;
+                match param(&braced[..end])? {
+                    Value::String(param) => substituted.push_str(param),
+                    Value::Number(param) => substituted.push_str(&param.to_string()),
+                    Value::Bool(param) => substituted.push_str(&param.to_string()),
+                    _ => return Err(invalid(format!("params: {} can't be put in text", &braced[..end]))),
+                }
+                rest = &braced[end + 1..];
+            }
+            substituted.push_str(rest);
+            *text = substituted;
+            Ok(())
+        },
+        _ => Ok(()),
+    }
+}
+
+/// `count` copies of `item`, each with the parameter named by its `as` key,
+/// `i` if it hasn't one, set to the copy's number from 0
+fn repeat(mut item: Value, mut count: Value, params: &Mapping) -> Result<Vec<Value>, SceneError> {
+    substitute(&mut count, params)?;
+    let count = count.as_u64().ok_or_else(|| invalid("repeat: must be a whole number of copies".to_string()))?;
+    let index = match item.as_mapping_mut().and_then(|item| item.remove("as")) {
+        None => Value::from("i"),
+        Some(name @ Value::String(_)) => name,
+        Some(_) => return Err(invalid("as: must be the name of a parameter".to_string())),
+    };
+
+    let mut params = params.clone();
+    (0..count).map(|i| {
+        params.insert(index.clone(), Value::from(i));
+        let mut copy = item.clone();
+        substitute(&mut copy, &params)?;
+        Ok(copy)
+    }).collect()
+}
+
+/// Lays `over` on top of `base`, merging maps and replacing anything else
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Mapping(base), Value::Mapping(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+        (base, over) => *base = over,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn yaml(source: &str) -> Value {
+        serde_yaml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn define() {
+        let document = yaml("
+define:
+  shiny: {diffuse: 0.7, specular: 0.3}
+  red: {colour: [1, 0, 0]}
+  red_ball: {type: sphere, material: {use: [shiny, red]}}
+  small: [{scale: [0.5, 0.5, 0.5]}]
+objects:
+  - use: red_ball
+    material: {specular: 0.9}
+    transform: {use: small}
+");
+        assert_eq!(expand(document, Path::new(""), &Mapping::new()).unwrap(), yaml("
+objects:
+  - type: sphere
+    material: {diffuse: 0.7, specular: 0.9, colour: [1, 0, 0]}
+    transform: [{scale: [0.5, 0.5, 0.5]}]
+"));
+
+        let missing = expand(yaml("objects: [{use: nothing}]"), Path::new(""), &Mapping::new());
+        assert!(matches!(missing, Err(SceneError::Invalid(p)) if p == ["use: no definition called nothing"]));
+        let looped = expand(yaml("define: {a: {use: b}, b: {use: a}}\nobjects: [{use: a}]"), Path::new(""), &Mapping::new());
+        assert!(matches!(looped, Err(SceneError::Invalid(p)) if p == ["define: a uses itself"]));
+    }
+
+    #[test]
+    fn params() {
+        let document = yaml("
+params: {count: 3, seed: 7, name: ball}
+define:
+  rock: {type: rock, seed: $seed}
+objects:
+  - {use: rock, name: '${name}_${seed}'}
+  - {type: icosphere, subdivisions: $count, name: '$name'}
+");
+        assert_eq!(expand(document.clone(), Path::new(""), &Mapping::new()).unwrap(), yaml("
+objects:
+  - {type: rock, seed: 7, name: ball_7}
+  - {type: icosphere, subdivisions: 3, name: ball}
+"));
+
+        let changed = expand(document.clone(), Path::new(""), &yaml("{seed: 12}").as_mapping().unwrap().clone()).unwrap();
+        assert_eq!(changed["objects"][0], yaml("{type: rock, seed: 12, name: ball_12}"));
+
+        let unknown = expand(document, Path::new(""), &yaml("{seeds: 12}").as_mapping().unwrap().clone());
+        assert!(matches!(unknown, Err(SceneError::Invalid(p)) if p == ["params: no parameter called seeds"]));
+        let undeclared = expand(yaml("objects: [{type: rock, seed: $seed}]"), Path::new(""), &Mapping::new());
+        assert!(matches!(undeclared, Err(SceneError::Invalid(p)) if p == ["params: no parameter called seed"]));
+    }
+
+    #[test]
+    fn escapes() {
+        let document = yaml("
+params: {price: 5}
+objects: [{name: '$$price', label: 'costs $$${price}, not $$$$1 or $ 2'}]
+");
+        assert_eq!(expand(document, Path::new(""), &Mapping::new()).unwrap(), yaml("
+objects: [{name: $price, label: 'costs $5, not $$1 or $ 2'}]
+"));
+    }
+
+    #[test]
+    fn repeat() {
+        let document = yaml("
+params: {count: 2}
+objects:
+  - {type: plane}
+  - repeat: $count
+    type: sphere
+    name: 'ball_${i}'
+    transform: [{translate: [$i, 0, 0]}]
+  - {repeat: 2, as: row, type: cube, seed: $row}
+");
+        assert_eq!(expand(document.clone(), Path::new(""), &Mapping::new()).unwrap(), yaml("
+objects:
+  - {type: plane}
+  - {type: sphere, name: ball_0, transform: [{translate: [0, 0, 0]}]}
+  - {type: sphere, name: ball_1, transform: [{translate: [1, 0, 0]}]}
+  - {type: cube, seed: 0}
+  - {type: cube, seed: 1}
+"));
+
+        let more = expand(document, Path::new(""), &yaml("{count: 5}").as_mapping().unwrap().clone()).unwrap();
+        assert_eq!(more["objects"].as_sequence().unwrap().len(), 8);
+        let negative = expand(yaml("objects: [{repeat: -1, type: sphere}]"), Path::new(""), &Mapping::new());
+        assert!(matches!(negative, Err(SceneError::Invalid(p)) if p == ["repeat: must be a whole number of copies"]));
+    }
+
+    #[test]
+    fn include() {
+        let dir = std::env::temp_dir().join(format!("rosemary-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("shared.yaml"), "
+define:
+  shiny: {specular: 0.9}
+  dull: {specular: 0.1}
+objects: [{type: sphere, name: shared}]
+").unwrap();
+        fs::write(dir.join("loop.yaml"), "include: loop.yaml").unwrap();
+        fs::write(dir.join("camera.yaml"), "camera: {}").unwrap();
+
+        let expanded = expand(yaml("
+include: shared.yaml
+define:
+  dull: {specular: 0.2}
+objects: [{type: sphere, material: {use: dull}}]
+"), &dir, &Mapping::new());
+        let looped = expand(yaml("include: loop.yaml"), &dir, &Mapping::new());
+        let camera = expand(yaml("include: [camera.yaml]"), &dir, &Mapping::new());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(expanded.unwrap(), yaml("
+objects:
+  - {type: sphere, material: {specular: 0.2}}
+  - {type: sphere, name: shared}
+"));
+        assert!(matches!(looped, Err(SceneError::Invalid(p)) if p == ["include: loop.yaml includes itself"]));
+        assert!(matches!(camera, Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn nested_includes() {
+        let dir = std::env::temp_dir().join(format!("rosemary-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("parts")).unwrap();
+        fs::write(dir.join("parts/model.yaml"), "
+define:
+  sky: {path: sky.hdr}
+objects:
+  - {type: obj, path: model.obj}
+  - {type: stl, path: $model}
+").unwrap();
+        fs::write(dir.join("parts/left.yaml"), "include: model.yaml").unwrap();
+        fs::write(dir.join("right.yaml"), "include: parts/model.yaml").unwrap();
+
+        let expanded = expand(yaml("
+params: {model: other.stl}
+include: [parts/left.yaml, right.yaml]
+environment: {use: sky}
+"), &dir, &Mapping::new());
+        let parts = fs::canonicalize(dir.join("parts")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let path = |name: &str| Value::String(parts.join(name).to_string_lossy().into_owned());
+        let expanded = expanded.unwrap();
+        assert_eq!(expanded["environment"]["path"], path("sky.hdr"));
+        assert_eq!(expanded["objects"].as_sequence().unwrap().len(), 2);
+        assert_eq!(expanded["objects"][0]["path"], path("model.obj"));
+        assert_eq!(expanded["objects"][1]["path"], Value::String("other.stl".to_string()));
+    }
+}
@@ -0,0 +1,141 @@
+//! Finding where things are in a block style YAML scene, since parsed values
+//! don't remember their position. Flow style collections like `[1, 2, 3]`
+//! are only found as far as the key holding them.
+
+use std::fmt::Write;
+
+/// One step from a value into one of its children
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl Step {
+    pub fn key(key: &str) -> Self {
+        Self::Key(key.to_string())
+    }
+}
+
+/// `path` written the way it's shown in messages, like `objects[2].material`
+pub fn display(path: &[Step]) -> String {
+    let mut shown = String::new();
+    for step in path {
+        match step {
+            Step::Key(key) if shown.is_empty() => shown.push_str(key),
+            Step::Key(key) => write!(shown, ".{key}").unwrap(),
+            Step::Index(index) => write!(shown, "[{index}]").unwrap(),
+        }
+    }
+    shown
+}
+
+/// A list marker or the content after it, with its 0 based line and column
+struct Token<'a> {
+    line: usize,
+    column: usize,
+    dash: bool,
+    text: &'a str,
+}
+
+fn tokens(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (line, text) in source.lines().enumerate() {
+        let mut rest = text.trim_start();
+        if rest.is_empty() || rest.starts_with('#') || rest.starts_with("---") {
+            continue;
+        }
+
+        let mut column = text.len() - rest.len();
+        while let Some(after) = rest.strip_prefix('-').filter(|after| after.is_empty() || after.starts_with(' ')) {
+            tokens.push(Token { line, column, dash: true, text: "" });
+            let content = after.trim_start();
+            column += rest.len() - content.len();
+            rest = content;
+        }
+        if !rest.is_empty() {
+            tokens.push(Token { line, column, dash: false, text: rest });
+        }
+    }
+    tokens
+}
+
+/// The key a line starts with, if it's a mapping entry
+fn key_of(text: &str) -> Option<&str> {
+    let (key, _) = text.split_once(':')?;
+    let key = key.trim();
+    Some(key.strip_prefix('"').and_then(|key| key.strip_suffix('"')).unwrap_or(key))
+}
+
+/// 1 based line and column of the value at `path` in `source`. If only the
+/// start of the path is there, which happens for values left to defaults,
+/// this is as far as it gets
+pub fn position(source: &str, path: &[Step]) -> Option<(usize, usize)> {
+    let tokens = tokens(source);
+    let mut region = &tokens[..];
+    let mut found = None;
+
+    for step in path {
+        let Some(column) = region.first().map(|token| token.column) else { break };
+        let mut children = region.iter().enumerate().filter(|(_, token)| token.column == column);
+        let child = match step {
+            Step::Key(key) => children.find(|(_, token)| !token.dash && key_of(token.text) == Some(key)),
+            Step::Index(index) => children.filter(|(_, token)| token.dash).nth(*index),
+        };
+        let Some((i, token)) = child else { break };
+        found = Some((token.line + 1, token.column + 1));
+
+        // a key's list can sit at the same indent as the key itself
+        let end = region[i + 1..].iter()
+            .position(|next| next.column < token.column || (next.column == token.column && (token.dash || !next.dash)))
+            .map_or(region.len(), |n| i + 1 + n);
+        region = &region[i + 1..end];
+    }
+
+    found
+}
+
+/// `message` with the line and column of `path` in `source` in front
+pub fn with_position(source: &str, path: &[Step], message: String) -> String {
+    match position(source, path) {
+        Some((line, column)) => format!("line {line}, column {column}: {message}"),
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "camera:
+  width: 10
+objects:
+- type: sphere
+  material:
+    colour: [1, 0, 0]
+  # a comment
+- type: rock
+  transform:
+    - scale: [1, 2, 1]
+    - translate: [0, 1, 0]
+";
+
+    #[test]
+    fn position() {
+        let at = |path: &[Step]| super::position(SOURCE, path);
+        assert_eq!(at(&[Step::key("camera"), Step::key("width")]), Some((2, 3)));
+        assert_eq!(at(&[Step::key("objects"), Step::Index(0), Step::key("material"), Step::key("colour")]), Some((6, 5)));
+        assert_eq!(at(&[Step::key("objects"), Step::Index(1)]), Some((8, 1)));
+        assert_eq!(at(&[Step::key("objects"), Step::Index(1), Step::key("transform"), Step::Index(1),
+            Step::key("translate")]), Some((11, 7)));
+
+        // as far as the path goes
+        assert_eq!(at(&[Step::key("objects"), Step::Index(1), Step::key("material")]), Some((8, 1)));
+        assert_eq!(at(&[Step::key("lights")]), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(super::display(&[Step::key("objects"), Step::Index(2), Step::key("material")]), "objects[2].material");
+    }
+}
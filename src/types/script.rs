@@ -0,0 +1,287 @@
+//! Scenes built by [Rhai](https://rhai.rs) scripts, for scenes that need loops,
+//! randomness or maths that a static scene file can't express.
+//!
+//! Scripts get constructors mirroring the Rust API (`point`, `vector`, `colour`,
+//! `translation`, `material`, `sphere`, `icosphere`, `rock`, `point_light`, ...)
+//! and build the world by passing shapes and lights to `add`:
+//!
+//! ```text
+//! let rng = rng(42);
+//! for i in 0..10 {
+//!     let m = material();
+//!     m.colour = colour(rng.next(), rng.next(), rng.next());
+//!     add(sphere(translation(i * 2.5, 0.0, 0.0), m));
+//! }
+//! add(point_light(colour(1.0, 1.0, 1.0), point(-10.0, 10.0, -10.0)));
+//! ```
+
+use std::{path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use rhai::{Engine, EvalAltResult, FLOAT, INT};
+
+use crate::{Float, Matrix, RosemaryError, Tuple};
+use super::{colour::Colour, environment::Environment, light::{AreaLight, DirectionalLight, Light, PointLight, SphereLight, SpotLight, TubeLight},
+    material::Material, mesh::Mesh, rng::Rng, rock::RockGenerator, shape::Shape, sphere::Sphere, world::World};
+
+/// Everything a script has added so far
+#[derive(Default)]
+struct Scene {
+    objects: Vec<Box<dyn Shape>>,
+    lights: Vec<Box<dyn Light>>,
+    environment: Option<Environment>,
+}
+
+/// Run the script at `path` and return the world it built
+pub fn load(path: impl AsRef<Path>) -> Result<World, Box<EvalAltResult>> {
+    build(|engine| engine.run_file(PathBuf::from(path.as_ref())))
+}
+
+/// Run a script and return the world it built
+pub fn run(source: &str) -> Result<World, Box<EvalAltResult>> {
+    build(|engine| engine.run(source))
+}
+
+fn build(run: impl FnOnce(&Engine) -> Result<(), Box<EvalAltResult>>) -> Result<World, Box<EvalAltResult>> {
+    let scene = Arc::new(Mutex::new(Scene::default()));
+    run(&engine(&scene))?;
+
+    let scene = std::mem::take(&mut *scene.lock().unwrap());
+    let mut world = World::new(scene.objects, scene.lights);
+    world.set_environment(scene.environment);
+    Ok(world)
+}
+
+fn engine(scene: &Arc<Mutex<Scene>>) -> Engine {
+    let mut engine = Engine::new();
+    register_maths(&mut engine);
+    register_materials(&mut engine);
+    register_shapes(&mut engine, scene);
+    register_lights(&mut engine, scene);
+
+    let env_scene = scene.clone();
+    engine.register_fn("environment", move |path: &str| -> Result<(), Box<EvalAltResult>> {
+        let environment = Environment::from_hdr(path).map_err(|e| format!("{path}: {e}"))?;
+        env_scene.lock().unwrap().environment = Some(environment);
+        Ok(())
+    });
+
+    engine.register_type_with_name::<Rng>("Rng")
+        .register_fn("rng", |seed: INT| Rng::new(seed as u64))
+        .register_fn("next", |rng: &mut Rng| rng.next_float() as FLOAT)
+        .register_fn("range", |rng: &mut Rng, min: FLOAT, max: FLOAT| rng.range(min as Float, max as Float) as FLOAT);
+
+    engine
+}
+
+// Rhai's FLOAT is f32 because Cargo.toml turns on its `f32_float` feature,
+// but `Float` can be built as f64, hence the casts whenever numbers cross
+// between scripts and the renderer
+fn register_maths(engine: &mut Engine) {
+    engine.register_type_with_name::<Tuple>("Tuple")
+        .register_fn("point", |x: FLOAT, y: FLOAT, z: FLOAT| Tuple::point(x as Float, y as Float, z as Float))
+        .register_fn("vector", |x: FLOAT, y: FLOAT, z: FLOAT| Tuple::vector(x as Float, y as Float, z as Float))
+        .register_get_set("x", |t: &mut Tuple| t.x as FLOAT, |t: &mut Tuple, v: FLOAT| t.x = v as Float)
+        .register_get_set("y", |t: &mut Tuple| t.y as FLOAT, |t: &mut Tuple, v: FLOAT| t.y = v as Float)
+        .register_get_set("z", |t: &mut Tuple| t.z as FLOAT, |t: &mut Tuple, v: FLOAT| t.z = v as Float)
+        .register_fn("+", |a: Tuple, b: Tuple| a + b)
+        .register_fn("-", |a: Tuple, b: Tuple| a - b)
+        .register_fn("-", |a: Tuple| -a)
+        .register_fn("*", |a: Tuple, b: FLOAT| a * b as Float)
+        .register_fn("dot", |a: &mut Tuple, b: Tuple| a.dot(b) as FLOAT)
+        .register_fn("cross", |a: &mut Tuple, b: Tuple| a.cross(b))
+        .register_fn("norm", |a: &mut Tuple| a.norm())
+        .register_fn("magnitude", |a: &mut Tuple| a.magnitude() as FLOAT);
+
+    engine.register_type_with_name::<Colour>("Colour")
+        .register_fn("colour", |r: FLOAT, g: FLOAT, b: FLOAT| Colour::new(r as Float, g as Float, b as Float))
+        .register_get_set("r", |c: &mut Colour| c.r as FLOAT, |c: &mut Colour, v: FLOAT| c.r = v as Float)
+        .register_get_set("g", |c: &mut Colour| c.g as FLOAT, |c: &mut Colour, v: FLOAT| c.g = v as Float)
+        .register_get_set("b", |c: &mut Colour| c.b as FLOAT, |c: &mut Colour, v: FLOAT| c.b = v as Float)
+        .register_fn("+", |a: Colour, b: Colour| a + b)
+        .register_fn("*", |a: Colour, b: Colour| a * b)
+        .register_fn("*", |a: Colour, b: FLOAT| a * b as Float);
+
+    engine.register_type_with_name::<Matrix>("Matrix")
+        .register_fn("identity", Matrix::identity)
+        .register_fn("translation", |x: FLOAT, y: FLOAT, z: FLOAT| Matrix::translation(x as Float, y as Float, z as Float))
+        .register_fn("scaling", |x: FLOAT, y: FLOAT, z: FLOAT| Matrix::scaling(x as Float, y as Float, z as Float))
+        .register_fn("rotation_x", |ang: FLOAT| Matrix::rotation_x(ang as Float))
+        .register_fn("rotation_y", |ang: FLOAT| Matrix::rotation_y(ang as Float))
+        .register_fn("rotation_z", |ang: FLOAT| Matrix::rotation_z(ang as Float))
+        .register_fn("shearing", |xy: FLOAT, xz: FLOAT, yx: FLOAT, yz: FLOAT, zx: FLOAT, zy: FLOAT| {
+            Matrix::shearing(xy as Float, xz as Float, yx as Float, yz as Float, zx as Float, zy as Float)
+        })
+        .register_fn("view_transform", Matrix::view_transform)
+        .register_fn("translate", |m: &mut Matrix, x: FLOAT, y: FLOAT, z: FLOAT| m.translate(x as Float, y as Float, z as Float))
+        .register_fn("scale", |m: &mut Matrix, x: FLOAT, y: FLOAT, z: FLOAT| m.scale(x as Float, y as Float, z as Float))
+        .register_fn("rotate_x", |m: &mut Matrix, ang: FLOAT| m.rotate_x(ang as Float))
+        .register_fn("rotate_y", |m: &mut Matrix, ang: FLOAT| m.rotate_y(ang as Float))
+        .register_fn("rotate_z", |m: &mut Matrix, ang: FLOAT| m.rotate_z(ang as Float))
+        .register_fn("*", |a: Matrix, b: Matrix| a * b)
+        .register_fn("*", |a: Matrix, b: Tuple| a * b);
+}
+
+fn register_materials(engine: &mut Engine) {
+    engine.register_type_with_name::<Material>("Material")
+        .register_fn("material", Material::default)
+        .register_get_set("colour", |m: &mut Material| m.colour, |m: &mut Material, v: Colour| m.colour = v)
+        .register_get_set("ambient", |m: &mut Material| m.ambient as FLOAT, |m: &mut Material, v: FLOAT| m.ambient = v as Float)
+        .register_get_set("diffuse", |m: &mut Material| m.diffuse as FLOAT, |m: &mut Material, v: FLOAT| m.diffuse = v as Float)
+        .register_get_set("specular", |m: &mut Material| m.specular as FLOAT, |m: &mut Material, v: FLOAT| m.specular = v as Float)
+        .register_get_set("shininess", |m: &mut Material| m.shininess as FLOAT, |m: &mut Material, v: FLOAT| m.shininess = v as Float)
+        .register_get_set("transparency", |m: &mut Material| m.transparency as FLOAT,
+            |m: &mut Material, v: FLOAT| m.transparency = v as Float)
+        .register_get_set("casts_shadow", |m: &mut Material| m.casts_shadow,
+            |m: &mut Material, v: bool| m.casts_shadow = v)
+        .register_get_set("holdout", |m: &mut Material| m.holdout, |m: &mut Material, v: bool| m.holdout = v);
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Bad values from a script become errors in the script rather than
+/// panics in the renderer
+fn checked<T>(result: Result<T, RosemaryError>) -> ScriptResult<T> {
+    result.map_err(|err| err.to_string().into())
+}
+
+/// A script's count, with negative ones as zero so they're refused too
+fn count(count: INT) -> usize {
+    usize::try_from(count).unwrap_or(0)
+}
+
+fn range(range: FLOAT) -> ScriptResult<Float> {
+    let range = range as Float;
+    checked(if range > 0.0 { Ok(range) } else { Err(RosemaryError::Negative("light range")) })
+}
+
+fn register_shapes(engine: &mut Engine, scene: &Arc<Mutex<Scene>>) {
+    engine.register_type_with_name::<Sphere>("Sphere")
+        .register_fn("sphere", Sphere::default)
+        .register_fn("sphere", |transform: Matrix| checked(Sphere::try_new(transform, Material::default())))
+        .register_fn("sphere", |transform: Matrix, material: Material| checked(Sphere::try_new(transform, material)));
+
+    engine.register_type_with_name::<Mesh>("Mesh")
+        .register_fn("icosphere", |subdivisions: INT, transform: Matrix, material: Material| -> ScriptResult<Mesh> {
+            let mut mesh = Mesh::icosphere(subdivisions.max(0) as usize);
+            checked(mesh.try_set_transform(transform))?;
+            mesh.material = material;
+            Ok(mesh)
+        })
+        .register_fn("rock", |seed: INT, transform: Matrix, material: Material| -> ScriptResult<Mesh> {
+            if transform.inverse().is_none() {
+                return checked(Err(RosemaryError::NotInvertible));
+            }
+            Ok(RockGenerator::new(seed as u64).generate_with(transform, material))
+        });
+
+    let add_scene = scene.clone();
+    engine.register_fn("add", move |sphere: Sphere| add_scene.lock().unwrap().objects.push(Box::new(sphere)));
+    let add_scene = scene.clone();
+    engine.register_fn("add", move |mesh: Mesh| add_scene.lock().unwrap().objects.push(Box::new(mesh)));
+}
+
+fn register_lights(engine: &mut Engine, scene: &Arc<Mutex<Scene>>) {
+    engine.register_type_with_name::<PointLight>("PointLight")
+        .register_fn("point_light", |intensity: Colour, pos: Tuple| checked(PointLight::try_new(intensity, pos)))
+        .register_fn("with_range", |light: &mut PointLight, range: FLOAT| self::range(range).map(|range| light.with_range(range)));
+    engine.register_type_with_name::<AreaLight>("AreaLight")
+        .register_fn("area_light", |intensity: Colour, corner: Tuple, uvec: Tuple, usteps: INT, vvec: Tuple, vsteps: INT| {
+            checked(AreaLight::try_new(intensity, corner, uvec, count(usteps), vvec, count(vsteps)))
+        });
+    engine.register_type_with_name::<SphereLight>("SphereLight")
+        .register_fn("sphere_light", |intensity: Colour, centre: Tuple, radius: FLOAT, samples: INT| {
+            checked(SphereLight::try_new(intensity, centre, radius as Float, count(samples)))
+        });
+    engine.register_type_with_name::<TubeLight>("TubeLight")
+        .register_fn("tube_light", |intensity: Colour, start: Tuple, end: Tuple, samples: INT| {
+            checked(TubeLight::try_new(intensity, start, end, count(samples)))
+        });
+    engine.register_type_with_name::<SpotLight>("SpotLight")
+        .register_fn("spot_light", |intensity: Colour, pos: Tuple, direction: Tuple, inner: FLOAT, outer: FLOAT| {
+            checked(SpotLight::try_new(intensity, pos, direction, inner as Float, outer as Float))
+        })
+        .register_fn("with_range", |light: &mut SpotLight, range: FLOAT| self::range(range).map(|range| light.with_range(range)));
+    engine.register_type_with_name::<DirectionalLight>("DirectionalLight")
+        .register_fn("directional_light", |intensity: Colour, direction: Tuple| {
+            checked(DirectionalLight::try_new(intensity, direction))
+        });
+
+    let add_scene = scene.clone();
+    engine.register_fn("add", move |light: PointLight| add_scene.lock().unwrap().lights.push(Box::new(light)));
+    let add_scene = scene.clone();
+    engine.register_fn("add", move |light: AreaLight| add_scene.lock().unwrap().lights.push(Box::new(light)));
+    let add_scene = scene.clone();
+    engine.register_fn("add", move |light: SphereLight| add_scene.lock().unwrap().lights.push(Box::new(light)));
+    let add_scene = scene.clone();
+    engine.register_fn("add", move |light: TubeLight| add_scene.lock().unwrap().lights.push(Box::new(light)));
+    let add_scene = scene.clone();
+    engine.register_fn("add", move |light: SpotLight| add_scene.lock().unwrap().lights.push(Box::new(light)));
+    let add_scene = scene.clone();
+    engine.register_fn("add", move |light: DirectionalLight| add_scene.lock().unwrap().lights.push(Box::new(light)));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::ray::Ray;
+
+    use super::*;
+
+    #[test]
+    fn run_default_world() {
+        // the same scene as World::default
+        let world = run(r#"
+            add(point_light(colour(1.0, 1.0, 1.0), point(-10.0, 10.0, -10.0)));
+
+            let m = material();
+            m.colour = colour(0.8, 1.0, 0.6);
+            m.diffuse = 0.7;
+            m.specular = 0.2;
+            add(sphere(identity(), m));
+            add(sphere(scaling(0.5, 0.5, 0.5)));
+        "#).unwrap();
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(world.colour_at(r), World::default().colour_at(r));
+    }
+
+    #[test]
+    fn run_loops_and_randomness() {
+        let source = r#"
+            let rng = rng(7);
+            for i in 0..5 {
+                add(sphere(translation(i * 3.0, rng.range(-1.0, 1.0), 0.0)));
+            }
+            add(icosphere(1, scaling(2.0, 2.0, 2.0).translate(0.0, 10.0, 0.0), material()));
+        "#;
+        let world = run(source).unwrap();
+
+        // spheres are jittered by less than their radius so a ray through the middle one hits
+        let r = Ray::new(Tuple::point(6.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(world.intersect(r).len(), 2);
+        assert_eq!(world.colour_at(r), run(source).unwrap().colour_at(r));
+
+        let r = Ray::new(Tuple::point(0.1, 10.2, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(world.intersect(r).len(), 2);
+    }
+
+    #[test]
+    fn run_errors() {
+        assert!(run("add(1);").is_err());
+        assert!(run("environment(\"missing.hdr\");").is_err());
+        assert!(load("missing.rhai").is_err());
+
+        // bad values are script errors, not panics
+        for source in [
+            "sphere(scaling(1.0, 0.0, 1.0));",
+            "icosphere(1, scaling(0.0, 0.0, 0.0), material());",
+            "point_light(colour(1.0, 1.0, 1.0), vector(0.0, 1.0, 0.0));",
+            "point_light(colour(1.0, 1.0, 1.0), point(0.0, 1.0, 0.0)).with_range(0.0);",
+            "area_light(colour(1.0, 1.0, 1.0), point(0.0, 0.0, 0.0), vector(1.0, 0.0, 0.0), 0, vector(0.0, 0.0, 1.0), 2);",
+            "sphere_light(colour(1.0, 1.0, 1.0), point(0.0, 0.0, 0.0), -1.0, 4);",
+            "spot_light(colour(1.0, 1.0, 1.0), point(0.0, 0.0, 0.0), vector(0.0, -1.0, 0.0), 0.5, 0.25);",
+        ] {
+            let err = run(source).unwrap_err();
+            assert!(matches!(*err, EvalAltResult::ErrorRuntime(..)), "{source}: {err}");
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{types::{bounds::BoundingSphere, intersection::{Intersection, Intersections}, material::Material, matrix::Transform,
+    ray::{Ray, PACKET_SIZE}}, Tuple};
+use super::{mesh::Mesh, sphere::Sphere};
+
+/// Common interface for everything that can be placed in a world.
+///
+/// Implementors only deal with object space through `local_intersect` and
+/// `local_normal`, the provided methods handle moving between world and
+/// object space.
+pub trait Shape: Debug + Send + Sync {
+    fn id(&self) -> Uuid;
+
+    /// Object to world transform, along with its inverse and normal matrix
+    fn transform(&self) -> &Transform;
+
+    fn material(&self) -> &Material;
+
+    /// Pushes where `ray` hits the shape onto `inters`, rather than returning
+    /// a new `Vec`, so one buffer can be reused for every shape a ray meets
+    fn local_intersect<'a>(&'a self, ray: Ray, inters: &mut Vec<Intersection<'a>>);
+
+    fn local_normal(&self, point: Tuple, hit: &Intersection) -> Tuple;
+
+    /// Sphere containing the whole shape in object space
+    fn local_bounds(&self) -> BoundingSphere;
+
+    /// Sphere containing the whole shape in world space
+    fn bounds(&self) -> BoundingSphere {
+        self.local_bounds().transform(self.transform().matrix())
+    }
+
+    fn intersect(&self, ray: Ray) -> Vec<Intersection<'_>> {
+        let mut inters = Vec::new();
+        self.intersect_into(ray, &mut inters);
+        inters
+    }
+
+    /// Same as `intersect` but appends to an existing buffer
+    fn intersect_into<'a>(&'a self, ray: Ray, inters: &mut Vec<Intersection<'a>>) {
+        self.local_intersect(ray.transform(self.transform().inverse()), inters);
+    }
+
+    /// `intersect_into` for several rays at once, each ray's hits going to
+    /// the buffer at the same index. Worth overriding when the maths can run
+    /// across SIMD lanes, since coherent rays mostly take the same branches
+    fn intersect_packet<'a>(&'a self, rays: &[Ray; PACKET_SIZE], inters: &mut [Intersections<'a>; PACKET_SIZE]) {
+        for (ray, inters) in rays.iter().zip(inters) {
+            self.intersect_into(*ray, inters);
+        }
+    }
+
+    /// The sphere behind this shape if it is one, so worlds can pack spheres
+    /// together. Only `Sphere` overrides this
+    fn as_sphere(&self) -> Option<&Sphere> {
+        None
+    }
+
+    /// The shape as something that can be written out with the world, `None`
+    /// for shapes defined outside this crate
+    fn saved(&self) -> Option<SavedShape> {
+        None
+    }
+
+    fn normal(&self, point: Tuple, hit: &Intersection) -> Tuple {
+        assert!(point.is_point());
+        let local_point = self.transform().inverse() * point;
+        let local_normal = self.local_normal(local_point, hit);
+        let mut normal = self.transform().normal() * local_normal;
+        normal.w = 0.0;
+        normal.norm()
+    }
+}
+
+/// Every shape type the crate has, so worlds can save and load their objects
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)] // only made while saving and loading
+pub enum SavedShape {
+    Sphere(Sphere),
+    Mesh(Mesh),
+}
+
+impl From<SavedShape> for Box<dyn Shape> {
+    fn from(shape: SavedShape) -> Self {
+        match shape {
+            SavedShape::Sphere(sphere) => Box::new(sphere),
+            SavedShape::Mesh(mesh) => Box::new(mesh),
+        }
+    }
+}
+
+impl PartialEq for dyn Shape + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
@@ -0,0 +1,230 @@
+use crate::consts::{FRAC_PI_2, PI};
+
+use crate::{Float, Tuple};
+use super::{canvas::Canvas, colour::Colour, environment::Environment, light::DirectionalLight};
+
+/// Daylight sky from the Preetham et al. analytic model, with a matching sun.
+///
+/// `environment` bakes the sky into an `Environment` for image based
+/// lighting and `sun` gives the directional light, so an outdoor scene only
+/// needs the sun's position and how hazy the air is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sky {
+    /// Haziness of the air, 2.0 is a very clear day and 10.0 is hazy
+    pub turbidity: Float,
+    /// Angle of the sun above the horizon in radians
+    pub sun_elevation: Float,
+    /// Angle of the sun around the y axis in radians, measured from -z towards +x
+    pub sun_azimuth: Float,
+    /// Brightness multiplier for the sky
+    pub intensity: Float,
+    /// Brightness multiplier for the sun
+    pub sun_intensity: Float,
+}
+
+/// Perez distribution coefficients A to E
+type Perez = [Float; 5];
+
+impl Sky {
+    pub fn new(turbidity: Float, sun_elevation: Float, sun_azimuth: Float) -> Self {
+        assert!(turbidity >= 1.0);
+
+        Self {
+            turbidity,
+            sun_elevation,
+            sun_azimuth,
+            ..Default::default()
+        }
+    }
+
+    /// Unit vector pointing towards the sun
+    pub fn sun_direction(&self) -> Tuple {
+        let (sin_e, cos_e) = self.sun_elevation.sin_cos();
+        let (sin_a, cos_a) = self.sun_azimuth.sin_cos();
+        Tuple::vector(cos_e * sin_a, sin_e, -cos_e * cos_a)
+    }
+
+    /// Sky colour seen looking along `direction`
+    pub fn colour(&self, direction: Tuple) -> Colour {
+        let d = direction.norm();
+
+        // the model only covers the upper hemisphere, so below the horizon
+        // see a darker reflection of the horizon instead
+        let (d, ground) = if d.y < 0.0 {
+            (Tuple::vector(d.x, 0.0, d.z).norm(), 0.3)
+        }
+        else {
+            (d, 1.0)
+        };
+
+        let theta = d.y.clamp(0.001, 1.0).acos().min(FRAC_PI_2 - 0.001);
+        let gamma = d.dot(self.sun_direction()).clamp(-1.0, 1.0).acos();
+
+        let theta_s = self.sun_zenith();
+        let t = self.turbidity;
+        let (zenith_x, zenith_y, zenith_lum) = self.zenith();
+
+        let x = zenith_x * perez_ratio(perez_x(t), theta, gamma, theta_s);
+        let y = zenith_y * perez_ratio(perez_y(t), theta, gamma, theta_s);
+        let lum = zenith_lum * perez_ratio(perez_lum(t), theta, gamma, theta_s);
+
+        xyy_to_rgb(x, y, lum) * (self.intensity * ground)
+    }
+
+    /// Directional light for the sun, reddened as it gets lower in the sky
+    pub fn sun(&self) -> DirectionalLight {
+        // relative optical air mass (Kasten and Young)
+        let zenith_deg = self.sun_zenith().to_degrees();
+        let air_mass = 1.0 / (self.sun_zenith().cos().max(0.0) + 0.50572 * (96.07995 - zenith_deg).powf(-1.6364));
+
+        // shorter wavelengths scatter more, more so in hazy air
+        let haze = 0.3 + 0.1 * self.turbidity;
+        let transmit = |beta: Float| (-air_mass * beta * haze).exp();
+        let colour = Colour::new(transmit(0.035), transmit(0.07), transmit(0.15));
+
+        let visible = if self.sun_elevation > 0.0 { 1.0 } else { 0.0 };
+        DirectionalLight::new(colour * (self.sun_intensity * visible), -self.sun_direction())
+    }
+
+    /// Bake the sky into an equirectangular environment `width` pixels wide
+    pub fn environment(&self, width: usize) -> Environment {
+        let height = (width / 2).max(1);
+        let mut image = Canvas::new(width, height);
+
+        for y in 0..height {
+            let theta = (y as Float + 0.5) / height as Float * PI;
+            for x in 0..width {
+                // inverse of the mapping in `Environment::sample`
+                let phi = ((x as Float + 0.5) / width as Float - 0.5) * 2.0 * PI;
+                let direction = Tuple::vector(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+                image[(x, y)] = self.colour(direction);
+            }
+        }
+
+        Environment::new(image)
+    }
+
+    fn sun_zenith(&self) -> Float {
+        FRAC_PI_2 - self.sun_elevation.clamp(0.0, FRAC_PI_2)
+    }
+
+    /// Chromaticity and luminance (kcd/m²) straight up
+    fn zenith(&self) -> (Float, Float, Float) {
+        let t = self.turbidity;
+        let theta_s = self.sun_zenith();
+        let (t2, s, s2, s3) = (t * t, theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let lum = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let x = t2 * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let y = t2 * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        (x, y, lum.max(0.0))
+    }
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            turbidity: 3.0,
+            sun_elevation: PI / 4.0,
+            sun_azimuth: 0.0,
+            intensity: 0.1,
+            sun_intensity: 1.0,
+        }
+    }
+}
+
+fn perez_lum(t: Float) -> Perez {
+    [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703]
+}
+
+fn perez_x(t: Float) -> Perez {
+    [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452]
+}
+
+fn perez_y(t: Float) -> Perez {
+    [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529]
+}
+
+fn perez([a, b, c, d, e]: Perez, theta: Float, gamma: Float) -> Float {
+    (1.0 + a * (b / theta.cos()).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// Distribution at `theta`, `gamma` relative to the zenith
+fn perez_ratio(coefficients: Perez, theta: Float, gamma: Float, theta_s: Float) -> Float {
+    perez(coefficients, theta, gamma) / perez(coefficients, 0.0, theta_s)
+}
+
+/// CIE xyY to linear sRGB
+fn xyy_to_rgb(x: Float, y: Float, lum: Float) -> Colour {
+    let big_x = x * lum / y;
+    let big_z = (1.0 - x - y) * lum / y;
+
+    Colour::new(
+        (3.2406 * big_x - 1.5372 * lum - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * lum + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * lum + 1.0570 * big_z).max(0.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::light::Light;
+
+    use super::*;
+
+    #[test]
+    fn sun() {
+        let sky = Sky::new(3.0, FRAC_PI_2 / 3.0, FRAC_PI_2);
+        let sun = sky.sun_direction();
+        assert_eq!(sun, Tuple::vector(0.86603, 0.5, 0.0));
+        assert_eq!(sky.sun().samples(Tuple::point(0.0, 0.0, 0.0)), vec![sun]);
+
+        // lower suns are dimmer and redder
+        let high = Sky::new(3.0, 1.2, 0.0).sun().intensity;
+        let low = Sky::new(3.0, 0.05, 0.0).sun().intensity;
+        assert!(low.b < high.b && low.r < high.r);
+        assert!(low.r / low.b > high.r / high.b);
+
+        assert_eq!(Sky::new(3.0, -0.1, 0.0).sun().intensity, Colour::black());
+    }
+
+    #[test]
+    fn colour() {
+        let sky = Sky::default();
+        let zenith = sky.colour(Tuple::vector(0.0, 1.0, 0.0));
+        assert!(zenith.b > zenith.r);
+
+        // brighter near the sun than away from it
+        let near_sun = sky.colour(sky.sun_direction() + Tuple::vector(0.0, 0.1, 0.0));
+        let away = sky.colour(Tuple::vector(0.0, 0.7, 0.7));
+        assert!(near_sun.r + near_sun.g + near_sun.b > away.r + away.g + away.b);
+
+        // hazier skies are less saturated
+        let hazy = Sky::new(10.0, PI / 4.0, 0.0).colour(Tuple::vector(0.0, 1.0, 0.0));
+        assert!(hazy.r / hazy.b > zenith.r / zenith.b);
+
+        let ground = sky.colour(Tuple::vector(0.0, -1.0, 1.0));
+        assert_eq!(ground, sky.colour(Tuple::vector(0.0, 0.0, 1.0)) * 0.3);
+    }
+
+    #[test]
+    fn environment() {
+        let sky = Sky::default();
+        let environment = sky.environment(64);
+        let up = Tuple::vector(0.0, 1.0, 0.0);
+        let side = Tuple::vector(0.3, 0.5, 0.8);
+
+        // within 10%, the pole is the least accurate being half a pixel from the top row
+        let close = |a: Colour, b: Colour| [(a.r, b.r), (a.g, b.g), (a.b, b.b)].iter()
+            .all(|(a, b)| (a - b).abs() <= 0.1 * b);
+        assert!(close(environment.sample(up), sky.colour(up)));
+        assert!(close(environment.sample(side), sky.colour(side)));
+    }
+}
@@ -1,33 +1,61 @@
+use std::array;
+
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{types::ray::Ray, Tuple, types::intersection::Intersection, Matrix, types::material::Material};
+use crate::{Float, types::ray::{Ray, PACKET_SIZE}, Tuple, types::intersection::{Intersection, Intersections}, Matrix,
+    types::material::Material, RosemaryError};
+use super::{bounds::BoundingSphere, matrix::Transform, shape::{SavedShape, Shape}};
+#[cfg(target_arch = "x86_64")]
+use super::cpu::{simd_level, SimdLevel};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Sphere {
     id: Uuid,
-    transform: Matrix,
+    transform: Transform,
     pub material: Material,
-    transform_inverse: Matrix,
 }
 
 impl Sphere {
+    /// Panics if `transform` can't be inverted, see `try_new`
     pub fn new(transform: Matrix, material: Material) -> Self {
-        Self {
+        Self::try_new(transform, material).unwrap()
+    }
+
+    pub fn try_new(transform: Matrix, material: Material) -> Result<Self, RosemaryError> {
+        Ok(Self {
             id: Uuid::new_v4(),
-            transform,
+            transform: Transform::try_from(transform)?,
             material,
-            transform_inverse: transform.inverse().unwrap(),
-        }
+        })
     }
 
+    /// Panics if `transform` can't be inverted, see `try_set_transform`
     pub fn set_transform(&mut self, transform: Matrix) {
-        self.transform = transform;
-        self.transform_inverse = transform.inverse().unwrap();
+        self.try_set_transform(transform).unwrap();
     }
 
-    pub fn intersect(&self, ray: Ray) -> Vec<Intersection> {
-        let ray = ray.transform(self.transform_inverse);
+    /// Leaves the sphere as it was if `transform` can't be inverted
+    pub fn try_set_transform(&mut self, transform: Matrix) -> Result<(), RosemaryError> {
+        self.transform = Transform::try_from(transform)?;
+        Ok(())
+    }
+}
+
+impl Shape for Sphere {
+    fn id(&self) -> Uuid {
+        self.id
+    }
 
+    fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn local_intersect<'a>(&'a self, ray: Ray, inters: &mut Vec<Intersection<'a>>) {
         let sphere_ray_vec = ray.origin - Tuple::point(0.0, 0.0, 0.0);
         let a = ray.direction.dot(ray.direction);
         let b = 2.0 * ray.direction.dot(sphere_ray_vec);
@@ -35,45 +63,199 @@ impl Sphere {
         let discriminant = b.powi(2) - 4.0 * a * c;
 
         if discriminant < 0.0 {
-            return vec![];
+            return;
         }
 
         let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
         let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
 
-        vec![Intersection::new(t1, *self), Intersection::new(t2, *self)]
+        inters.push(Intersection::new(t1, self));
+        inters.push(Intersection::new(t2, self));
+    }
+
+    fn intersect_packet<'a>(&'a self, rays: &[Ray; PACKET_SIZE], inters: &mut [Intersections<'a>; PACKET_SIZE]) {
+        // same sums as `local_intersect` but one lane per ray, so each line
+        // can compile to a single vector instruction
+        let local = rays.map(|ray| ray.transform(self.transform.inverse()));
+        let (ox, oy, oz) = (local.map(|r| r.origin.x), local.map(|r| r.origin.y), local.map(|r| r.origin.z));
+        let (dx, dy, dz) = (local.map(|r| r.direction.x), local.map(|r| r.direction.y), local.map(|r| r.direction.z));
+
+        let a: [Float; PACKET_SIZE] = array::from_fn(|i| dx[i] * dx[i] + dy[i] * dy[i] + dz[i] * dz[i]);
+        let b: [Float; PACKET_SIZE] = array::from_fn(|i| 2.0 * (dx[i] * ox[i] + dy[i] * oy[i] + dz[i] * oz[i]));
+        let c: [Float; PACKET_SIZE] = array::from_fn(|i| ox[i] * ox[i] + oy[i] * oy[i] + oz[i] * oz[i] - 1.0);
+        let discriminant: [Float; PACKET_SIZE] = array::from_fn(|i| b[i] * b[i] - 4.0 * a[i] * c[i]);
+
+        for (i, inters) in inters.iter_mut().enumerate() {
+            if discriminant[i] < 0.0 {
+                continue;
+            }
+
+            let root = discriminant[i].sqrt();
+            inters.push(Intersection::new((-b[i] - root) / (2.0 * a[i]), self));
+            inters.push(Intersection::new((-b[i] + root) / (2.0 * a[i]), self));
+        }
+    }
+
+    fn local_normal(&self, point: Tuple, _hit: &Intersection) -> Tuple {
+        point - Tuple::point(0.0, 0.0, 0.0)
+    }
+
+    fn local_bounds(&self) -> BoundingSphere {
+        BoundingSphere::new(Tuple::point(0.0, 0.0, 0.0), 1.0)
     }
 
-    pub fn normal(&self, point: Tuple) -> Tuple {
-        assert!(point.is_point());
-        let point = self.transform_inverse * point;
-        let mut normal = point - Tuple::point(0.0, 0.0, 0.0);
-        normal *= self.transform_inverse.transpose();
-        normal.w = 0.0;
-        normal.norm()
+    fn as_sphere(&self) -> Option<&Sphere> {
+        Some(self)
+    }
+
+    fn saved(&self) -> Option<SavedShape> {
+        Some(SavedShape::Sphere(*self))
     }
 }
 
 impl Default for Sphere {
     fn default() -> Self {
-        Self::new(Matrix::identity(4), Material::default())
+        Self::new(Matrix::identity(), Material::default())
+    }
+}
+
+/// Spheres swept together by `SpherePack::sweep`, the tail is padded to this
+const LANES: usize = 8;
+
+/// Every sphere in a world laid out in flat arrays, one entry per sphere, so
+/// a ray can be tested against them all with loops the compiler vectorises
+/// rather than by chasing each `Sphere` through its box
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpherePack {
+    /// Where each sphere is in the list it was packed from
+    indices: Vec<usize>,
+    /// World space bounds, to skip the transform for most spheres
+    centres: [Vec<Float>; 3],
+    radii_squared: Vec<Float>,
+    /// Top three rows of each inverse transform, the bottom row never changes
+    inverses: [Vec<Float>; 12],
+}
+
+impl SpherePack {
+    /// Pack the spheres out of `objects`, leaving every other shape out
+    pub fn new(objects: &[Box<dyn Shape>]) -> Self {
+        let mut pack = Self::default();
+        for (i, sphere) in objects.iter().enumerate().filter_map(|(i, obj)| Some((i, obj.as_sphere()?))) {
+            let bounds = sphere.bounds();
+            pack.push(i, [bounds.centre.x, bounds.centre.y, bounds.centre.z], bounds.radius * bounds.radius,
+                sphere.transform.inverse());
+        }
+
+        // padding bounds that no ray can pass through, so every sweep is whole lanes
+        while pack.indices.len() % LANES != 0 {
+            pack.push(usize::MAX, [0.0; 3], Float::NEG_INFINITY, &Matrix::identity());
+        }
+        pack
+    }
+
+    fn push(&mut self, index: usize, centre: [Float; 3], radius_squared: Float, inverse: &Matrix) {
+        self.indices.push(index);
+        for (axis, value) in self.centres.iter_mut().zip(centre) {
+            axis.push(value);
+        }
+        self.radii_squared.push(radius_squared);
+        for (i, entry) in self.inverses.iter_mut().enumerate() {
+            entry.push(inverse[(i / 4, i % 4)]);
+        }
+    }
+
+    /// Whether the object at `index` of the packed list is in here
+    pub fn contains(&self, index: usize) -> bool {
+        self.indices.binary_search(&index).is_ok()
+    }
+
+    /// Call `hit` with the index and both `t`s of every sphere `ray` passes
+    /// through, in the same order `Sphere::local_intersect` gives them
+    pub fn sweep(&self, ray: Ray, hit: impl FnMut(usize, Float, Float)) {
+        #[cfg(target_arch = "x86_64")]
+        if simd_level() == SimdLevel::Avx2 {
+            // SAFETY: the CPU has just been checked for AVX2
+            return unsafe { self.sweep_avx2(ray, hit) };
+        }
+
+        self.sweep_lanes(ray, hit);
+    }
+
+    /// `sweep_lanes` compiled for AVX2, which fits a whole group of lanes in
+    /// one vector. The sums are the same so the results are too
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    fn sweep_avx2(&self, ray: Ray, hit: impl FnMut(usize, Float, Float)) {
+        self.sweep_lanes(ray, hit);
+    }
+
+    #[inline(always)]
+    fn sweep_lanes(&self, ray: Ray, mut hit: impl FnMut(usize, Float, Float)) {
+        let (o, d) = (ray.origin, ray.direction);
+        let a = d.dot(d);
+
+        for start in (0..self.indices.len()).step_by(LANES) {
+            let lanes = start..start + LANES;
+            let [cx, cy, cz] = self.centres.each_ref().map(|axis| &axis[lanes.clone()]);
+            let radii_squared = &self.radii_squared[lanes.clone()];
+
+            let near: [bool; LANES] = array::from_fn(|i| {
+                let (x, y, z) = (o.x - cx[i], o.y - cy[i], o.z - cz[i]);
+                let b = d.x * x + d.y * y + d.z * z;
+                b * b - a * (x * x + y * y + z * z - radii_squared[i]) >= 0.0
+            });
+            if !near.contains(&true) {
+                continue;
+            }
+
+            let m = self.inverses.each_ref().map(|entry| &entry[lanes.clone()]);
+            for i in (0..LANES).filter(|&i| near[i]) {
+                let row = |r: usize, v: [Float; 4]| m[r * 4][i] * v[0] + m[r * 4 + 1][i] * v[1] + m[r * 4 + 2][i] * v[2] + m[r * 4 + 3][i] * v[3];
+                let local_o = [0, 1, 2].map(|r| row(r, [o.x, o.y, o.z, 1.0]));
+                let local_d = [0, 1, 2].map(|r| row(r, [d.x, d.y, d.z, 0.0]));
+
+                let dot = |u: [Float; 3], v: [Float; 3]| u[0] * v[0] + u[1] * v[1] + u[2] * v[2];
+                let a = dot(local_d, local_d);
+                let b = 2.0 * dot(local_d, local_o);
+                let c = dot(local_o, local_o) - 1.0;
+                let discriminant = b.powi(2) - 4.0 * a * c;
+                if discriminant < 0.0 {
+                    continue;
+                }
+
+                let root = discriminant.sqrt();
+                hit(self.indices[start + i], (-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::f32::consts::{PI, SQRT_2};
+    use crate::consts::{FRAC_1_SQRT_2, PI, SQRT_2};
+    use crate::Float;
 
-    use super::Sphere;
-    use crate::types::material::Material;
-    use crate::{Matrix, Tuple};
-    use crate::types::ray::Ray;
+    use super::*;
+
+    fn normal_at(s: &Sphere, point: Tuple) -> Tuple {
+        s.normal(point, &Intersection::new(0.0, s))
+    }
 
     #[test]
     fn new() {
         let s = Sphere::default();
-        assert_eq!(s.transform, Matrix::identity(4));
+        assert_eq!(*s.transform.matrix(), Matrix::identity());
         assert_eq!(s.material, Material::default());
+
+        let mut s = Sphere::default();
+        let transform = Matrix::scaling(1.0, 0.5, 2.0).rotate_z(PI / 5.0);
+        s.set_transform(transform);
+        assert_eq!(*s.transform().normal(), transform.inverse().unwrap().transpose());
+
+        let flat = Matrix::scaling(1.0, 0.0, 1.0);
+        assert_eq!(Sphere::try_new(flat, Material::default()), Err(RosemaryError::NotInvertible));
+        assert_eq!(s.try_set_transform(flat), Err(RosemaryError::NotInvertible));
+        assert_eq!(*s.transform().matrix(), transform);
     }
 
     #[test]
@@ -83,8 +265,8 @@ mod tests {
         let inters = s.intersect(r);
         assert_eq!(inters[0].t, 4.0);
         assert_eq!(inters[1].t, 6.0);
-        assert_eq!(inters[0].obj, s);
-        assert_eq!(inters[1].obj, s);
+        assert_eq!(inters[0].obj.id(), s.id());
+        assert_eq!(inters[1].obj.id(), s.id());
 
         let r = Ray::new(Tuple::point(0.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
         let s = Sphere::default();
@@ -113,7 +295,7 @@ mod tests {
         assert_eq!(inters[1].t, -4.0);
 
         let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
-        let mut s = Sphere::new(Matrix::scaling(2.0, 2.0, 2.0), Material::default());
+        let s = Sphere::new(Matrix::scaling(2.0, 2.0, 2.0), Material::default());
         let inters = s.intersect(r);
         dbg!(&inters);
         assert_eq!(inters.len(), 2);
@@ -121,29 +303,81 @@ mod tests {
         assert_eq!(inters[1].t, 7.0);
 
         let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
-        let mut s = Sphere::new(Matrix::translation(5.0, 0.0, 0.0), Material::default());
+        let s = Sphere::new(Matrix::translation(5.0, 0.0, 0.0), Material::default());
         let inters = s.intersect(r);
         assert_eq!(inters.len(), 0);
     }
 
+    #[test]
+    fn intersect_packet() {
+        let s = Sphere::new(Matrix::scaling(2.0, 1.0, 1.5).translate(0.5, 0.0, 0.0), Material::default());
+        let rays = [
+            Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.0, 2.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.3, 0.9, -5.0), Tuple::vector(0.1, -0.2, 1.0).norm()),
+            Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(1.0, 0.0, 0.0)),
+        ];
+
+        let mut inters: [Intersections; PACKET_SIZE] = Default::default();
+        s.intersect_packet(&rays, &mut inters);
+        for (ray, inters) in rays.iter().zip(&inters) {
+            let expected: Vec<_> = s.intersect(*ray).iter().map(|i| i.t).collect();
+            assert_eq!(inters.iter().map(|i| i.t).collect::<Vec<_>>(), expected);
+        }
+        assert!(inters[1].is_empty());
+    }
+
+    #[test]
+    fn sphere_pack() {
+        let objects: Vec<Box<dyn Shape>> = vec![
+            Box::new(Sphere::new(Matrix::scaling(2.0, 1.0, 1.5).translate(0.5, 0.0, 0.0), Material::default())),
+            Box::new(crate::types::mesh::Mesh::new(vec![Tuple::point(0.0, 0.0, 0.0), Tuple::point(1.0, 0.0, 0.0),
+                Tuple::point(0.0, 1.0, 0.0)], vec![[0, 1, 2]], Matrix::identity(), Material::default())),
+            Box::new(Sphere::new(Matrix::translation(0.0, 0.0, 3.0), Material::default())),
+            Box::new(Sphere::new(Matrix::translation(5.0, 0.0, 0.0), Material::default())),
+        ];
+        let pack = SpherePack::new(&objects);
+        assert!(pack.contains(0) && pack.contains(2) && pack.contains(3));
+        assert!(!pack.contains(1));
+
+        let rays = [
+            Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.3, 0.9, -5.0), Tuple::vector(0.1, -0.2, 1.0).norm()),
+            Ray::new(Tuple::point(0.0, 5.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+        ];
+        for ray in rays {
+            let mut swept = Vec::new();
+            pack.sweep(ray, |i, t1, t2| swept.extend([(i, t1), (i, t2)]));
+
+            let expected: Vec<_> = [0, 2, 3].into_iter()
+                .flat_map(|i| objects[i].intersect(ray).into_iter().map(move |hit| (i, hit.t)))
+                .collect();
+            assert_eq!(swept.len(), expected.len());
+            for ((i, t), (expected_i, expected_t)) in swept.into_iter().zip(expected) {
+                assert_eq!(i, expected_i);
+                assert!(crate::eq(t, expected_t));
+            }
+        }
+    }
+
     #[test]
     fn normal() {
         let s = Sphere::default();
-        assert_eq!(s.normal(Tuple::point(1.0, 0.0, 0.0)), Tuple::vector(1.0, 0.0, 0.0));
-        assert_eq!(s.normal(Tuple::point(0.0, 1.0, 0.0)), Tuple::vector(0.0, 1.0, 0.0));
-        assert_eq!(s.normal(Tuple::point(0.0, 0.0, 1.0)), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(normal_at(&s, Tuple::point(1.0, 0.0, 0.0)), Tuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(normal_at(&s, Tuple::point(0.0, 1.0, 0.0)), Tuple::vector(0.0, 1.0, 0.0));
+        assert_eq!(normal_at(&s, Tuple::point(0.0, 0.0, 1.0)), Tuple::vector(0.0, 0.0, 1.0));
 
-        let root_3_over_3 = 3.0_f32.sqrt() / 3.0;
-        assert_eq!(s.normal(Tuple::point(root_3_over_3, root_3_over_3, root_3_over_3)),
+        let root_3_over_3 = (3.0 as Float).sqrt() / 3.0;
+        assert_eq!(normal_at(&s, Tuple::point(root_3_over_3, root_3_over_3, root_3_over_3)),
             Tuple::vector(root_3_over_3, root_3_over_3, root_3_over_3));
 
-        let n = s.normal(Tuple::point(root_3_over_3, root_3_over_3, root_3_over_3));
+        let n = normal_at(&s, Tuple::point(root_3_over_3, root_3_over_3, root_3_over_3));
         assert_eq!(n, n.norm());
 
         let s = Sphere::new(Matrix::translation(0.0, 1.0, 0.0), Material::default());
-        assert_eq!(s.normal(Tuple::point(0.0, 1.70711, -0.70711)), Tuple::vector(0.0, 0.70711, -0.70711));
+        assert_eq!(normal_at(&s, Tuple::point(0.0, 1.0 + FRAC_1_SQRT_2, -FRAC_1_SQRT_2)), Tuple::vector(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
 
         let s = Sphere::new(Matrix::rotation_z(PI / 5.0).scale(1.0, 0.5, 1.0), Material::default());
-        assert_eq!(s.normal(Tuple::point(0.0, SQRT_2 / 2.0, -SQRT_2 / 2.0)), Tuple::vector(0.0, 0.97014, -0.24254));
+        assert_eq!(normal_at(&s, Tuple::point(0.0, SQRT_2 / 2.0, -SQRT_2 / 2.0)), Tuple::vector(0.0, 0.97014, -0.24254));
     }
 }
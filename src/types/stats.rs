@@ -0,0 +1,49 @@
+use std::{fmt, time::Duration};
+
+/// Counts and timings from a render, for profiling scenes and comparing
+/// optimisations
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenderStats {
+    /// Rays from the camera, one per pixel sample
+    pub primary_rays: usize,
+    pub shadow_rays: usize,
+    /// Camera rays by how many times their path bounced, index 0 for rays
+    /// that stopped at the first surface
+    pub depths: Vec<usize>,
+    pub time: Duration,
+}
+
+impl RenderStats {
+    /// Average rays traced per second of wall time
+    pub fn rays_per_second(&self) -> f64 {
+        (self.primary_rays + self.shadow_rays) as f64 / self.time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} primary rays, {} shadow rays in {:.2?}", self.primary_rays, self.shadow_rays, self.time)?;
+        for (depth, count) in self.depths.iter().enumerate().filter(|(_, count)| **count > 0) {
+            write!(f, ", {count} at depth {depth}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let stats = RenderStats {
+            primary_rays: 100,
+            shadow_rays: 40,
+            depths: vec![100],
+            time: Duration::from_millis(2),
+        };
+        assert_eq!(stats.to_string(), "100 primary rays, 40 shadow rays in 2.00ms, 100 at depth 0");
+        assert_eq!(stats.rays_per_second(), 70000.0);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::Float;
+use super::{camera::Camera, canvas::Canvas, colour::Colour, world::World};
+
+/// Pair of eyes either side of a camera, for rendering 3D images.
+///
+/// The eyes turn inwards to look at the point `convergence` units in front
+/// of the camera, which ends up at the depth of the screen. Things nearer
+/// than that stand out of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stereo {
+    /// Distance between the eyes
+    pub interocular: Float,
+    /// Distance to where the eyes' views cross, infinite for parallel eyes
+    pub convergence: Float,
+}
+
+impl Stereo {
+    pub fn new(interocular: Float, convergence: Float) -> Self {
+        assert!(interocular >= 0.0);
+        assert!(convergence > 0.0);
+
+        Self {
+            interocular,
+            convergence,
+        }
+    }
+
+    /// Cameras for the left and right eyes of `camera`
+    pub fn eyes(&self, camera: &Camera) -> (Camera, Camera) {
+        let half = self.interocular / 2.0;
+        let toe_in = half.atan2(self.convergence);
+
+        // +x is to the left in camera space
+        let eye = |offset: Float, angle: Float| {
+            let mut eye = *camera;
+            eye.set_transform(camera.transform().translate(offset, 0.0, 0.0).rotate_y(angle));
+            eye
+        };
+
+        (eye(-half, -toe_in), eye(half, toe_in))
+    }
+
+    /// Render the left and right eye images
+    pub fn render(&self, camera: &Camera, world: &World) -> (Canvas, Canvas) {
+        let (left, right) = self.eyes(camera);
+        (left.render(world), right.render(world))
+    }
+}
+
+/// Left and right images next to each other, for parallel viewing
+pub fn side_by_side(left: &Canvas, right: &Canvas) -> Canvas {
+    assert!(left.width == right.width && left.height == right.height);
+
+    let mut image = Canvas::new(left.width * 2, left.height);
+    image.paste(0, 0, left);
+    image.paste(left.width, 0, right);
+
+    image
+}
+
+/// Red/cyan anaglyph for viewing with coloured glasses, red over the left eye
+pub fn anaglyph(left: &Canvas, right: &Canvas) -> Canvas {
+    assert!(left.width == right.width && left.height == right.height);
+
+    let mut image = Canvas::new(left.width, left.height);
+    for y in 0..left.height {
+        for x in 0..left.width {
+            let (l, r) = (left[(x, y)], right[(x, y)]);
+            image[(x, y)] = Colour::new(l.r, r.g, r.b);
+            image.set_alpha(x, y, left.alpha(x, y).max(right.alpha(x, y)));
+        }
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::FRAC_PI_2;
+
+    use crate::{Matrix, Tuple};
+
+    use super::*;
+
+    #[test]
+    fn eyes() {
+        let mut camera = Camera::new(11, 11, FRAC_PI_2);
+        camera.set_transform(Matrix::view_transform(Tuple::point(0.0, 0.0, -5.0),
+            Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 1.0, 0.0)));
+
+        let (left, right) = Stereo::new(0.2, 5.0).eyes(&camera);
+        let (l, r) = (left.ray_for_pixel(5.0, 5.0), right.ray_for_pixel(5.0, 5.0));
+        assert_eq!(l.origin, Tuple::point(-0.1, 0.0, -5.0));
+        assert_eq!(r.origin, Tuple::point(0.1, 0.0, -5.0));
+        // both looking at the convergence point
+        assert_eq!(l.position((25.01 as Float).sqrt()), Tuple::point(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, Tuple::vector(-l.direction.x, l.direction.y, l.direction.z));
+
+        let (left, right) = Stereo::new(0.2, Float::INFINITY).eyes(&camera);
+        assert_eq!(left.ray_for_pixel(5.0, 5.0).direction, Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(right.ray_for_pixel(5.0, 5.0).direction, Tuple::vector(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn combine() {
+        let mut left = Canvas::new(2, 1);
+        let mut right = Canvas::new(2, 1);
+        left[(0, 0)] = Colour::new(1.0, 0.5, 0.25);
+        right.set_alpha(0, 0, 0.0);
+        right[(0, 0)] = Colour::new(0.1, 0.2, 0.3);
+
+        let image = side_by_side(&left, &right);
+        assert_eq!((image.width, image.height), (4, 1));
+        assert_eq!(image[(0, 0)], left[(0, 0)]);
+        assert_eq!(image[(2, 0)], right[(0, 0)]);
+        assert_eq!(image.alpha(0, 0), 1.0);
+        assert_eq!(image.alpha(2, 0), 0.0);
+
+        let image = anaglyph(&left, &right);
+        assert_eq!(image[(0, 0)], Colour::new(1.0, 0.2, 0.3));
+        assert_eq!(image.alpha(0, 0), 1.0);
+    }
+}
@@ -0,0 +1,199 @@
+//! STL models, binary or ASCII, as they come out of CAD and slicing tools.
+//! Every triangle is stored on its own, so shared corners are merged back
+//! together when loading
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::{Float, Matrix, Tuple};
+use super::{material::Material, mesh::Mesh};
+
+/// Triangles read from an STL file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stl {
+    pub vertices: Vec<Tuple>,
+    /// Counter-clockwise seen from outside, as STL wants
+    pub faces: Vec<[usize; 3]>,
+    /// The normal stored with each face, often zero or unreliable
+    pub normals: Vec<Tuple>,
+}
+
+impl Stl {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    /// Binary files say how many triangles they hold, so anything of exactly
+    /// that size is binary even if its header happens to start with `solid`
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let binary_size = bytes.get(80..84)
+            .map(|count| 84 + 50 * u32::from_le_bytes(count.try_into().unwrap()) as usize);
+
+        if binary_size == Some(bytes.len()) {
+            Ok(Self::decode_binary(bytes))
+        }
+        else if bytes.trim_ascii_start().starts_with(b"solid") {
+            let source = std::str::from_utf8(bytes).map_err(|_| invalid("ASCII STL isn't valid text"))?;
+            Self::decode_ascii(source)
+        }
+        else {
+            Err(invalid("neither a binary nor an ASCII STL"))
+        }
+    }
+
+    fn decode_binary(bytes: &[u8]) -> Self {
+        let mut stl = Self::default();
+        let mut merged = HashMap::new();
+
+        for triangle in bytes[84..].chunks_exact(50) {
+            let floats: Vec<Float> = triangle[..48].chunks_exact(4)
+                .map(|float| f32::from_le_bytes(float.try_into().unwrap()) as Float)
+                .collect();
+            let corners = [3, 6, 9].map(|i| Tuple::point(floats[i], floats[i + 1], floats[i + 2]));
+            stl.push(&mut merged, Tuple::vector(floats[0], floats[1], floats[2]), corners);
+        }
+
+        stl
+    }
+
+    fn decode_ascii(source: &str) -> io::Result<Self> {
+        let mut stl = Self::default();
+        let mut merged = HashMap::new();
+        let (mut normal, mut corners) = (Tuple::vector(0.0, 0.0, 0.0), Vec::new());
+
+        for (number, line) in source.lines().enumerate() {
+            let invalid = |message: &str| invalid(&format!("line {}: {message}", number + 1));
+            let mut fields = line.split_whitespace();
+
+            match fields.next() {
+                Some("facet") => {
+                    let [x, y, z] = floats(fields.skip(1)).ok_or_else(|| invalid("bad facet normal"))?;
+                    normal = Tuple::vector(x, y, z);
+                    corners.clear();
+                },
+                Some("vertex") => {
+                    let [x, y, z] = floats(fields).ok_or_else(|| invalid("bad vertex"))?;
+                    corners.push(Tuple::point(x, y, z));
+                },
+                Some("endfacet") => {
+                    let corners = std::mem::take(&mut corners).try_into()
+                        .map_err(|_| invalid("facet needs three vertices"))?;
+                    stl.push(&mut merged, normal, corners);
+                },
+                _ => {},
+            }
+        }
+
+        Ok(stl)
+    }
+
+    #[allow(clippy::unnecessary_cast)] // bits are already a u64 with the f64 feature
+    fn push(&mut self, merged: &mut HashMap<[u64; 3], usize>, normal: Tuple, corners: [Tuple; 3]) {
+        let face = corners.map(|corner| {
+            let key = [corner.x, corner.y, corner.z].map(|f| f.to_bits() as u64);
+            *merged.entry(key).or_insert_with(|| {
+                self.vertices.push(corner);
+                self.vertices.len() - 1
+            })
+        });
+        self.faces.push(face);
+        self.normals.push(normal);
+    }
+
+    /// The model as a mesh. Unless `recompute_normals` is set, faces whose
+    /// winding disagrees with their stored normal are turned around to match
+    /// it. Faces with no stored normal always go by their winding
+    pub fn mesh(&self, transform: Matrix, material: Material, recompute_normals: bool) -> Mesh {
+        let faces = self.faces.iter().zip(&self.normals).map(|(&[a, b, c], normal)| {
+            let winding = (self.vertices[b] - self.vertices[a]).cross(self.vertices[c] - self.vertices[a]);
+            // meshes take their normals from the opposite winding
+            if recompute_normals || winding.dot(*normal) >= 0.0 { [a, c, b] } else { [a, b, c] }
+        }).collect();
+
+        Mesh::new(self.vertices.clone(), faces, transform, material)
+    }
+}
+
+/// The first three fields as floats
+fn floats<'a>(fields: impl Iterator<Item = &'a str>) -> Option<[Float; 3]> {
+    let values: Vec<Float> = fields.take(3).map(str::parse).collect::<Result<_, _>>().ok()?;
+    values.try_into().ok()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{ray::Ray, shape::Shape};
+
+    use super::*;
+
+    const SQUARE: &str = "solid square
+facet normal 0 0 1
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 1 1 0
+  endloop
+endfacet
+facet normal 0 0 -1
+  outer loop
+    vertex 0 0 0
+    vertex 1 1 0
+    vertex 0 1 0
+  endloop
+endfacet
+endsolid square
+";
+
+    fn binary(triangles: &[([f32; 3], [[f32; 3]; 3])]) -> Vec<u8> {
+        let mut bytes = b"solid but really binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend((triangles.len() as u32).to_le_bytes());
+        for (normal, corners) in triangles {
+            for value in normal.iter().chain(corners.as_flattened()) {
+                bytes.extend(value.to_le_bytes());
+            }
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn decode() {
+        let ascii = Stl::decode(SQUARE.as_bytes()).unwrap();
+        assert_eq!(ascii.vertices.len(), 4);
+        assert_eq!(ascii.faces, [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(ascii.normals[1], Tuple::vector(0.0, 0.0, -1.0));
+
+        let bytes = binary(&[
+            ([0.0, 0.0, 1.0], [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]]),
+            ([0.0, 0.0, -1.0], [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]),
+        ]);
+        assert_eq!(Stl::decode(&bytes).unwrap(), ascii);
+
+        assert!(Stl::decode(b"not an stl").is_err());
+        assert!(Stl::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Stl::decode(b"solid\nfacet normal 0 0 1\nvertex 0 0 0\nendfacet\n").is_err());
+    }
+
+    #[test]
+    fn normals() {
+        let stl = Stl::decode(SQUARE.as_bytes()).unwrap();
+        let normal_at = |mesh: &Mesh, x, y| {
+            let ray = Ray::new(Tuple::point(x, y, -1.0), Tuple::vector(0.0, 0.0, 1.0));
+            let hit = mesh.intersect(ray)[0];
+            mesh.normal(ray.position(hit.t), &hit)
+        };
+
+        // the second face is wound the same way as the first but says it faces the other way
+        let kept = stl.mesh(Matrix::identity(), Material::default(), false);
+        assert_eq!(normal_at(&kept, 0.75, 0.25), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(normal_at(&kept, 0.25, 0.75), Tuple::vector(0.0, 0.0, -1.0));
+
+        let recomputed = stl.mesh(Matrix::identity(), Material::default(), true);
+        assert_eq!(normal_at(&recomputed, 0.75, 0.25), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(normal_at(&recomputed, 0.25, 0.75), Tuple::vector(0.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,141 @@
+//! Truevision TGA (`.tga`) images with an alpha channel, still read directly
+//! by plenty of texture pipelines and game tools
+
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+
+use super::{canvas::{channel_to_byte, Canvas}, metadata::Metadata};
+
+/// Longest run or raw packet the format allows
+const MAX_PACKET: usize = 128;
+/// Longest image ID, where metadata goes
+const MAX_ID: usize = 255;
+
+pub fn save(canvas: &Canvas, path: impl AsRef<Path>, rle: bool) -> io::Result<()> {
+    save_with_metadata(canvas, path, rle, &Metadata::new())
+}
+
+pub fn save_with_metadata(canvas: &Canvas, path: impl AsRef<Path>, rle: bool, metadata: &Metadata) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode_with_metadata(canvas, &mut writer, rle, metadata)?;
+    writer.flush()
+}
+
+/// Writes 32 bit blue, green, red, alpha pixels from the top row down,
+/// run length encoding each row if `rle` is set
+pub fn encode(canvas: &Canvas, writer: impl Write, rle: bool) -> io::Result<()> {
+    encode_with_metadata(canvas, writer, rle, &Metadata::new())
+}
+
+/// `encode` with `metadata` as lines of the image ID, cut off at the
+/// format's 255 bytes
+pub fn encode_with_metadata(canvas: &Canvas, mut writer: impl Write, rle: bool, metadata: &Metadata) -> io::Result<()> {
+    let too_big = |_| io::Error::new(io::ErrorKind::InvalidInput, "canvas too big for a TGA");
+    let width = u16::try_from(canvas.width).map_err(too_big)?;
+    let height = u16::try_from(canvas.height).map_err(too_big)?;
+    let id = metadata.lines().collect::<Vec<_>>().join("\n");
+    let id = &id.as_bytes()[..id.len().min(MAX_ID)];
+
+    // no colour map, true colour image
+    writer.write_all(&[id.len() as u8, 0, if rle { 10 } else { 2 }])?;
+    writer.write_all(&[0; 5])?;
+    // x and y origin
+    writer.write_all(&[0; 4])?;
+    writer.write_all(&width.to_le_bytes())?;
+    writer.write_all(&height.to_le_bytes())?;
+    // 32 bits per pixel, 8 of them alpha, top left origin
+    writer.write_all(&[32, 0x28])?;
+    writer.write_all(id)?;
+
+    for y in 0..canvas.height {
+        let row: Vec<[u8; 4]> = (0..canvas.width)
+            .map(|x| {
+                let colour = canvas[(x, y)];
+                [
+                    channel_to_byte(colour.b),
+                    channel_to_byte(colour.g),
+                    channel_to_byte(colour.r),
+                    channel_to_byte(canvas.alpha(x, y)),
+                ]
+            })
+            .collect();
+
+        if rle {
+            write_rle_row(&row, &mut writer)?;
+        }
+        else {
+            writer.write_all(row.as_flattened())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Packets never cross the end of a row, which some readers rely on
+fn write_rle_row(row: &[[u8; 4]], writer: &mut impl Write) -> io::Result<()> {
+    let mut i = 0;
+    while i < row.len() {
+        let run = row[i..].iter().take(MAX_PACKET).take_while(|&&pixel| pixel == row[i]).count();
+        if run > 1 {
+            writer.write_all(&[0x80 | (run - 1) as u8])?;
+            writer.write_all(&row[i])?;
+            i += run;
+            continue;
+        }
+
+        // raw packet up to the start of the next run
+        let mut end = i + 1;
+        while end < row.len() && end - i < MAX_PACKET && (end + 1 == row.len() || row[end] != row[end + 1]) {
+            end += 1;
+        }
+        writer.write_all(&[(end - i - 1) as u8])?;
+        writer.write_all(row[i..end].as_flattened())?;
+        i = end;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::colour::Colour;
+
+    #[test]
+    fn uncompressed() {
+        let mut canvas = Canvas::new(2, 2);
+        canvas[(1, 0)] = Colour::new(1.0, 0.5, 0.0);
+        canvas.set_alpha(0, 1, 0.0);
+
+        let mut data = Vec::new();
+        encode(&canvas, &mut data, false).unwrap();
+
+        assert_eq!(data.len(), 18 + 4 * 4);
+        assert_eq!(data[2], 2);
+        assert_eq!(&data[12..18], &[2, 0, 2, 0, 32, 0x28]);
+        // top row first
+        assert_eq!(&data[18..26], &[0, 0, 0, 255, 0, 128, 255, 255]);
+        assert_eq!(&data[26..30], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn run_length_encoded() {
+        let mut canvas = Canvas::new(200, 1);
+        canvas[(1, 0)] = Colour::white();
+
+        let mut data = Vec::new();
+        encode(&canvas, &mut data, true).unwrap();
+        assert_eq!(data[2], 10);
+
+        let black = [0, 0, 0, 255];
+        let white = [255, 255, 255, 255];
+        let mut expected = vec![1];
+        expected.extend(black);
+        expected.extend(white);
+        // a run can only be 128 long, so the remaining 198 are split
+        expected.push(0x80 | 127);
+        expected.extend(black);
+        expected.push(0x80 | 69);
+        expected.extend(black);
+        assert_eq!(&data[18..], &expected[..]);
+    }
+}
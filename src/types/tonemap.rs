@@ -0,0 +1,89 @@
+use crate::Float;
+use super::colour::Colour;
+
+/// Curve squeezing unbounded scene colours into the 0.0 to 1.0 a display can show
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ToneMap {
+    /// Cut off anything over 1.0, bright areas lose all detail
+    #[default]
+    Clamp,
+    /// `c / (1 + c)`, never quite reaches white
+    Reinhard,
+    /// Hable's Uncharted 2 curve, lifted blacks and a long soft roll off
+    /// towards a white point of 11.2
+    Filmic,
+    /// Narkowicz's fit of the ACES filmic curve, with a gentle toe and shoulder
+    Aces,
+}
+
+/// Exposure and tone mapping, the step turning a render's linear light
+/// into something displayable before any LUT is applied
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ToneMapping {
+    /// In stops, each one doubles the brightness
+    pub exposure: Float,
+    pub tone_map: ToneMap,
+}
+
+impl ToneMapping {
+    pub fn new(exposure: Float, tone_map: ToneMap) -> Self {
+        Self {
+            exposure,
+            tone_map,
+        }
+    }
+
+    pub fn apply(&self, colour: Colour) -> Colour {
+        let scale = self.exposure.exp2();
+        let map = |c: Float| {
+            let c = (c * scale).max(0.0);
+            match self.tone_map {
+                ToneMap::Clamp => c.min(1.0),
+                ToneMap::Reinhard => c / (1.0 + c),
+                ToneMap::Filmic => (hable(2.0 * c) / hable(FILMIC_WHITE)).min(1.0),
+                ToneMap::Aces => (c * (2.51 * c + 0.03) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0),
+            }
+        };
+
+        Colour::new(map(colour.r), map(colour.g), map(colour.b))
+    }
+}
+
+/// Scene brightness that the filmic curve maps to pure white
+const FILMIC_WHITE: Float = 11.2;
+
+fn hable(x: Float) -> Float {
+    const A: Float = 0.15; // shoulder strength
+    const B: Float = 0.50; // linear strength
+    const C: Float = 0.10; // linear angle
+    const D: Float = 0.20; // toe strength
+    const E: Float = 0.02; // toe numerator
+    const F: Float = 0.30; // toe denominator
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply() {
+        let bright = Colour::new(1.9, 0.5, -0.1);
+
+        assert_eq!(ToneMapping::default().apply(bright), Colour::new(1.0, 0.5, 0.0));
+        assert_eq!(ToneMapping::new(-1.0, ToneMap::Clamp).apply(bright), Colour::new(0.95, 0.25, 0.0));
+        assert_eq!(ToneMapping::new(0.0, ToneMap::Reinhard).apply(bright), Colour::new(0.65517, 0.33333, 0.0));
+
+        let aces = ToneMapping::new(0.0, ToneMap::Aces);
+        assert_eq!(aces.apply(Colour::black()), Colour::black());
+        assert_eq!(aces.apply(Colour::new(100.0, 100.0, 100.0)), Colour::white());
+        // keeps getting brighter where clamping would have stopped
+        assert!(aces.apply(bright).r > aces.apply(Colour::new(1.0, 0.0, 0.0)).r);
+
+        let filmic = ToneMapping::new(0.0, ToneMap::Filmic);
+        assert_eq!(filmic.apply(Colour::black()), Colour::black());
+        assert_eq!(filmic.apply(Colour::new(5.6, 5.6, 5.6)), Colour::white());
+        assert_eq!(filmic.apply(Colour::new(0.5, 0.5, 0.5)), Colour::new(0.3043, 0.3043, 0.3043));
+        assert!(filmic.apply(bright).r > filmic.apply(Colour::new(1.0, 0.0, 0.0)).r);
+    }
+}
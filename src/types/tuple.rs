@@ -1,19 +1,20 @@
-use crate::Matrix;
-use std::ops::{Mul, Div, MulAssign, DivAssign};
+use crate::{Float, Matrix};
+use std::ops::{Mul, Div, MulAssign};
 
 use derive_more::{Add, Sub, Neg, AddAssign, SubAssign, MulAssign};
+use serde::{Deserialize, Serialize};
 use super::eq;
 
-#[derive(Debug, Clone, Copy, Add, Sub, Neg, AddAssign, SubAssign, MulAssign)]
+#[derive(Debug, Clone, Copy, Add, Sub, Neg, AddAssign, SubAssign, MulAssign, Serialize, Deserialize)]
 pub struct Tuple {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-    pub w: f32,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
+    pub w: Float,
 }
 
 impl Tuple {
-    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+    pub fn new(x: Float, y: Float, z: Float, w: Float) -> Self {
         Self {
             x,
             y,
@@ -22,7 +23,7 @@ impl Tuple {
         }
     }
 
-    pub fn vector(x: f32, y: f32, z: f32) -> Self {
+    pub fn vector(x: Float, y: Float, z: Float) -> Self {
         Self {
             x,
             y,
@@ -31,7 +32,7 @@ impl Tuple {
         }
     }
 
-    pub fn point(x: f32, y: f32, z: f32) -> Self {
+    pub fn point(x: Float, y: Float, z: Float) -> Self {
         Self {
             x,
             y,
@@ -41,14 +42,14 @@ impl Tuple {
     }
 
     pub fn is_vector(&self) -> bool {
-        return self.w == 0.0;
+        self.w == 0.0
     }
 
     pub fn is_point(&self) -> bool {
-        return self.w == 1.0;
+        self.w == 1.0
     }
 
-    pub fn magnitude(&self) -> f32 {
+    pub fn magnitude(&self) -> Float {
         (self.x.powi(2) + self.y.powi(2) + self.z.powi(2) + self.w.powi(2)).sqrt()
     }
 
@@ -56,11 +57,11 @@ impl Tuple {
         *self / self.magnitude()
     }
 
-    pub fn mul_scalar(&self, factor: f32) -> Self {
+    pub fn mul_scalar(&self, factor: Float) -> Self {
         *self * factor
     }
 
-    pub fn dot(&self, other: Self) -> f32 {
+    pub fn dot(&self, other: Self) -> Float {
         *self * other
     }
 
@@ -89,10 +90,10 @@ impl PartialEq for Tuple {
     }
 }
 
-impl Mul<f32> for Tuple {
+impl Mul<Float> for Tuple {
     type Output = Tuple;
 
-    fn mul(self, factor: f32) -> Self::Output {
+    fn mul(self, factor: Float) -> Self::Output {
         Self {
             x: self.x * factor,
             y: self.y * factor,
@@ -103,7 +104,7 @@ impl Mul<f32> for Tuple {
 }
 
 impl Mul<Tuple> for Tuple {
-    type Output = f32;
+    type Output = Float;
 
     fn mul(self, other: Tuple) -> Self::Output {
         self.x * other.x +
@@ -127,10 +128,10 @@ impl MulAssign<Matrix> for Tuple {
     }
 }
 
-impl Div<f32> for Tuple {
+impl Div<Float> for Tuple {
     type Output = Tuple;
 
-    fn div(self, factor: f32) -> Self::Output {
+    fn div(self, factor: Float) -> Self::Output {
         Self {
             x: self.x / factor,
             y: self.y / factor,
@@ -142,7 +143,7 @@ impl Div<f32> for Tuple {
 
 #[cfg(test)]
 mod tests {
-    use std::f32::consts::SQRT_2;
+    use crate::consts::SQRT_2;
 
     use super::*;
 
@@ -259,10 +260,10 @@ mod tests {
         assert_eq!(vector.magnitude(), 1.0);
 
         let vector = Tuple::vector(1.0, 2.0, 3.0);
-        assert_eq!(vector.magnitude(), 14.0_f32.sqrt());
+        assert_eq!(vector.magnitude(), (14.0 as Float).sqrt());
 
         let vector = Tuple::vector(-1.0, -2.0, -3.0);
-        assert_eq!(vector.magnitude(), 14.0_f32.sqrt());
+        assert_eq!(vector.magnitude(), (14.0 as Float).sqrt());
     }
 
     #[test]
@@ -1,55 +1,921 @@
-use std::vec;
+use std::{array, cell::{Cell, RefCell}, collections::{HashMap, HashSet}, sync::atomic::{AtomicUsize, Ordering}};
 
-use crate::{types::{light::PointLight, sphere::Sphere, ray::Ray, colour::Colour,
-    intersection::Intersection, material::Material}, Matrix, Tuple};
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use uuid::Uuid;
 
+use crate::{Float, types::{arena::Scratch, bounds::BoundingSphere, bvh::Bvh, environment::Environment, medium::Medium, light::{Light, PointLight, SavedLight}, shape::{SavedShape, Shape}, sphere::{Sphere, SpherePack}, ray::{Ray, PACKET_SIZE}, colour::Colour,
+    intersection::{Computations, Intersection, Intersections, SortedIntersections}, material::Material, rng::{cosine_direction, hash_ray}, sampler::{SampleId, SamplerKind, Samples}}, Matrix, RosemaryError, Tuple};
+
+/// Result of tracing a single camera ray
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trace {
+    pub colour: Colour,
+    pub alpha: Float,
+    /// World space position of the hit, if anything was hit
+    pub position: Option<Tuple>,
+    /// World space surface normal at the hit, facing back towards the ray
+    pub normal: Option<Tuple>,
+    /// Index into `World::objects` of what was hit
+    pub object: Option<usize>,
+    /// How many times the path bounced, 0 for misses and paths that stopped
+    /// at the first surface
+    pub depth: usize,
+}
+
+/// Which objects a light illuminates, all of them by default.
+///
+/// Unlinked objects get nothing from the light, not even its ambient
+/// contribution, though they can still block its shadow rays.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LightLink {
+    /// Only light these objects
+    pub only: Option<HashSet<Uuid>>,
+    /// Never light these objects
+    pub except: HashSet<Uuid>,
+}
+
+impl LightLink {
+    pub fn affects(&self, object: Uuid) -> bool {
+        self.only.as_ref().is_none_or(|only| only.contains(&object)) && !self.except.contains(&object)
+    }
+}
+
+/// How ambient light is worked out for the whole world
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Ambient {
+    /// Each light adds the material's ambient tinted by its intensity
+    #[default]
+    PerLight,
+    /// Like `PerLight` but multiplied by a colour, to tune the whole scene at once
+    Scale(Colour),
+    /// Lights add no ambient, instead every object gets its material's colour
+    /// times this, ignoring the material's ambient value
+    Replace(Colour),
+}
+
+/// How the colour of a hit is worked out
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Integrator {
+    /// Direct light from each light plus a flat ambient term, see `shade_hit`
+    #[default]
+    Whitted,
+    /// Monte Carlo path tracing: direct light plus light bounced diffusely
+    /// off other surfaces, up to `max_bounces` times, replacing the ambient
+    /// term. Noisy, so use more camera samples per pixel to smooth it out.
+    /// A path stops bouncing early once no channel of the light it could
+    /// carry back is above `min_throughput`, 0.0 always goes to `max_bounces`
+    /// Bounce directions follow `sampler`, one point of it per bounce
+    PathTracing { max_bounces: usize, seed: u64, min_throughput: Float, sampler: SamplerKind },
+}
+
+#[derive(Debug)]
 pub struct World {
-    objects: Vec<Sphere>,
-    light: Option<PointLight>,
+    objects: Vec<Box<dyn Shape>>,
+    /// Index of each object by its id, so hits can say which object they're on
+    indices: HashMap<Uuid, usize>,
+    /// World space bounds of each object, so rays can skip objects cheaply
+    bounds: Vec<BoundingSphere>,
+    /// Hierarchy over `bounds` so rays only look at nearby objects
+    bvh: Bvh,
+    /// Spheres in flat arrays for `intersect` to sweep, instead of going
+    /// through `bvh` for them, if turned on
+    packed: Option<SpherePack>,
+    lights: Vec<Box<dyn Light>>,
+    /// Light linking for each light, lights without one light everything
+    links: Vec<LightLink>,
+    ambient: Ambient,
+    integrator: Integrator,
+    /// How strongly each light scatters in the medium, 1.0 if missing
+    scattering: Vec<Float>,
+    medium: Option<Medium>,
+    environment: Option<Environment>,
+    max_sample_radiance: Option<Float>,
+    /// Count for `shadow_rays`, atomic so rendering can share the world
+    shadow_rays: AtomicUsize,
+}
+
+thread_local! {
+    /// Index + 1 of the object that last blocked a shadow ray towards each
+    /// light on this thread, 0 for none. Only ever a hint, forgotten at the
+    /// start of each tile so the pixels of a tile share it
+    static OCCLUDERS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    /// Index of the light `is_shadowed` is tracing shadow rays towards, if
+    /// it's one of the world's
+    static SHADOWING: Cell<Option<usize>> = const { Cell::new(None) };
 }
 
 impl World {
-    pub fn new(objects: Vec<Sphere>, light: Option<PointLight>) -> Self {
-        Self { 
+    pub fn new(objects: Vec<Box<dyn Shape>>, lights: Vec<Box<dyn Light>>) -> Self {
+        let bounds: Vec<_> = objects.iter().map(|obj| obj.bounds()).collect();
+
+        Self {
+            bvh: Bvh::new(&bounds),
+            bounds,
+            indices: index_ids(&objects),
             objects,
-            light,
+            packed: None,
+            links: vec![LightLink::default(); lights.len()],
+            scattering: vec![1.0; lights.len()],
+            lights,
+            ambient: Ambient::default(),
+            integrator: Integrator::default(),
+            medium: None,
+            environment: None,
+            max_sample_radiance: None,
+            shadow_rays: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn builder() -> WorldBuilder {
+        WorldBuilder::new()
+    }
+
+    /// Get the world ready to render and look for likely mistakes. Bounds, the
+    /// bvh and packed spheres are rebuilt and this thread's cached occluders
+    /// forgotten, so the world is in the same state as a fresh one. The returned warnings
+    /// don't stop a render but usually mean it won't look as intended
+    pub fn prepare(&mut self) -> Vec<String> {
+        self.rebuild_bounds();
+        Self::forget_occluders();
+
+        let mut warnings = Vec::new();
+        if self.lights.is_empty() && self.environment.is_none() && !matches!(self.ambient, Ambient::Replace(_)) {
+            warnings.push("no lights or environment, so everything will be black".to_string());
+        }
+
+        for (i, obj) in self.objects.iter().enumerate() {
+            // shapes can't be made with a singular transform, but one that's
+            // nearly so still gives unreliable normals. The box the unit axes
+            // map to is measured at its thinnest against its longest edge, so
+            // small or large objects aren't mistaken for flat ones
+            let matrix = obj.transform().matrix();
+            let axes = [Tuple::vector(1.0, 0.0, 0.0), Tuple::vector(0.0, 1.0, 0.0), Tuple::vector(0.0, 0.0, 1.0)].map(|axis| matrix * axis);
+            let longest = axes.iter().map(Tuple::magnitude).fold(0.0, Float::max);
+            let widest = [(0, 1), (1, 2), (2, 0)].map(|(a, b)| axes[a].cross(axes[b]).magnitude()).into_iter().fold(0.0, Float::max);
+            let thinnest = matrix.determinant().abs() / widest;
+            if thinnest < crate::EPSILON * longest {
+                warnings.push(format!("object {i}: transform squashes it nearly flat"));
+            }
+        }
+
+        let ids: HashSet<Uuid> = self.objects.iter().map(|obj| obj.id()).collect();
+        for (i, link) in self.links.iter().enumerate() {
+            if link.only.iter().flatten().chain(&link.except).any(|id| !ids.contains(id)) {
+                warnings.push(format!("light {i}: linked to an object that isn't in the world"));
+            }
+            if link.only.as_ref().is_some_and(|only| only.is_empty()) {
+                warnings.push(format!("light {i}: only lights an empty set of objects"));
+            }
+        }
+
+        warnings
+    }
+
+    pub fn objects(&self) -> &[Box<dyn Shape>] {
+        &self.objects
+    }
+
+    /// Changes that move or resize objects leave the bvh out of date, so
+    /// call `prepare` before rendering again
+    pub fn objects_mut(&mut self) -> &mut [Box<dyn Shape>] {
+        &mut self.objects
+    }
+
+    /// Add `object` to the end of `objects`, returning its index
+    pub fn add_object(&mut self, object: Box<dyn Shape>) -> usize {
+        self.objects.push(object);
+        self.rebuild_bounds();
+        self.objects.len() - 1
+    }
+
+    /// Take out the object at `index`, moving the ones after it down. Light
+    /// links to it are kept, in case it's added back
+    pub fn remove_object(&mut self, index: usize) -> Option<Box<dyn Shape>> {
+        if index >= self.objects.len() {
+            return None;
+        }
+        let object = self.objects.remove(index);
+        self.rebuild_bounds();
+        Some(object)
+    }
+
+    /// Index in `objects` of the object with `id`
+    pub fn object_index(&self, id: Uuid) -> Option<usize> {
+        self.indices.get(&id).copied().filter(|&i| self.objects.get(i).is_some_and(|obj| obj.id() == id))
+            // objects swapped through `objects_mut` aren't indexed until `prepare`
+            .or_else(|| self.objects.iter().position(|obj| obj.id() == id))
+    }
+
+    /// Bounds, the bvh, packed spheres and indices worked out again from `objects`
+    fn rebuild_bounds(&mut self) {
+        self.indices = index_ids(&self.objects);
+        self.bounds = self.objects.iter().map(|obj| obj.bounds()).collect();
+        self.bvh = Bvh::new(&self.bounds);
+        if self.packed.is_some() {
+            self.packed = Some(SpherePack::new(&self.objects));
+        }
+    }
+
+    pub fn lights(&self) -> &[Box<dyn Light>] {
+        &self.lights
+    }
+
+    /// Which objects the light at `index` illuminates. Panics if there's no
+    /// such light, see `try_light_link`
+    pub fn light_link(&self, index: usize) -> &LightLink {
+        self.try_light_link(index).unwrap()
+    }
+
+    pub fn try_light_link(&self, index: usize) -> Result<&LightLink, RosemaryError> {
+        self.links.get(index).ok_or(RosemaryError::NoLight(index))
+    }
+
+    /// Panics if there's no light at `index`, see `try_light_link_mut`
+    pub fn light_link_mut(&mut self, index: usize) -> &mut LightLink {
+        self.try_light_link_mut(index).unwrap()
+    }
+
+    pub fn try_light_link_mut(&mut self, index: usize) -> Result<&mut LightLink, RosemaryError> {
+        self.links.get_mut(index).ok_or(RosemaryError::NoLight(index))
+    }
+
+    /// Stop the light at `index` from illuminating `object`. Panics if
+    /// there's no such light, see `try_exclude_light`
+    pub fn exclude_light(&mut self, object: Uuid, index: usize) {
+        self.try_exclude_light(object, index).unwrap();
+    }
+
+    pub fn try_exclude_light(&mut self, object: Uuid, index: usize) -> Result<(), RosemaryError> {
+        self.try_light_link_mut(index)?.except.insert(object);
+        Ok(())
+    }
+
+    /// Only let `object` be lit by the lights at `indices`
+    pub fn only_lights(&mut self, object: Uuid, indices: &[usize]) {
+        for (i, link) in self.links.iter_mut().enumerate() {
+            if !indices.contains(&i) {
+                link.except.insert(object);
+            }
+        }
+    }
+
+    /// Lights linked to `object`
+    fn lights_for(&self, object: Uuid) -> impl Iterator<Item = (usize, &dyn Light)> {
+        self.lights.iter().enumerate()
+            .filter(move |(i, _)| self.links.get(*i).is_none_or(|link| link.affects(object)))
+            .map(|(i, light)| (i, light.as_ref()))
+    }
+
+    pub fn packed_spheres(&self) -> bool {
+        self.packed.is_some()
+    }
+
+    /// Sweep every sphere at once when intersecting rather than one at a
+    /// time through the bvh, quicker for scenes made mostly of spheres
+    pub fn set_packed_spheres(&mut self, packed: bool) {
+        self.packed = packed.then(|| SpherePack::new(&self.objects));
+    }
+
+    pub fn ambient(&self) -> Ambient {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: Ambient) {
+        self.ambient = ambient;
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        self.integrator
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+
+    pub fn medium(&self) -> Option<&Medium> {
+        self.medium.as_ref()
+    }
+
+    /// Participating medium filling the world, for visible light beams
+    pub fn set_medium(&mut self, medium: Option<Medium>) {
+        self.medium = medium;
+    }
+
+    /// How strongly the light at `index` scatters in the medium
+    pub fn light_scattering(&self, index: usize) -> Float {
+        self.scattering.get(index).copied().unwrap_or(1.0)
+    }
+
+    /// 0.0 stops the light at `index` making beams, above 1.0 exaggerates
+    /// them. Panics if there's no such light or `scattering` is negative, see
+    /// `try_set_light_scattering`
+    pub fn set_light_scattering(&mut self, index: usize, scattering: Float) {
+        self.try_set_light_scattering(index, scattering).unwrap();
+    }
+
+    /// Leaves the light as it was if `scattering` is negative
+    pub fn try_set_light_scattering(&mut self, index: usize, scattering: Float) -> Result<(), RosemaryError> {
+        if scattering.is_nan() || scattering < 0.0 {
+            return Err(RosemaryError::Negative("light scattering"));
+        }
+        *self.scattering.get_mut(index).ok_or(RosemaryError::NoLight(index))? = scattering;
+        Ok(())
+    }
+
+    pub fn environment(&self) -> Option<&Environment> {
+        self.environment.as_ref()
+    }
+
+    /// Image based lighting, also seen by rays that miss everything
+    pub fn set_environment(&mut self, environment: Option<Environment>) {
+        self.environment = environment;
+    }
+
+    /// Brightest any single sample can be when several are averaged, which
+    /// stops rare very bright samples showing up as speckles (fireflies)
+    /// at the cost of a little energy. `None` for no limit
+    pub fn max_sample_radiance(&self) -> Option<Float> {
+        self.max_sample_radiance
+    }
+
+    pub fn set_max_sample_radiance(&mut self, max: Option<Float>) {
+        assert!(max.is_none_or(|max| max >= 0.0));
+        self.max_sample_radiance = max;
+    }
+
+    /// `sample` limited by `max_sample_radiance`
+    pub fn clamp_sample(&self, sample: Colour) -> Colour {
+        match self.max_sample_radiance {
+            Some(max) => sample.limit(max),
+            None => sample,
+        }
+    }
+
+    /// Shadow rays traced since the world was created, for profiling
+    pub fn shadow_rays(&self) -> usize {
+        self.shadow_rays.load(Ordering::Relaxed)
+    }
+
+    pub fn intersect(&self, ray: Ray) -> SortedIntersections<'_> {
+        let mut result = SortedIntersections::new();
+        self.intersect_into(ray, &mut result);
+        result
+    }
+
+    /// Clears `inters` and fills it with every hit along `ray` in order of `t`,
+    /// reusing its allocation. Each object's hits are merged in as they come
+    pub fn intersect_into<'a>(&'a self, ray: Ray, inters: &mut SortedIntersections<'a>) {
+        inters.clear();
+
+        if let Some(packed) = &self.packed {
+            packed.sweep(ray, |i, t1, t2| {
+                let obj = self.objects[i].as_ref();
+                inters.merge(&mut [Intersection::new(t1, obj), Intersection::new(t2, obj)]);
+            });
         }
+
+        let mut batch = Scratch::new();
+        self.bvh.candidates(ray, |i| {
+            if !self.is_packed(i) && self.bounds[i].intersects(ray) {
+                batch.clear();
+                self.objects[i].intersect_into(ray, &mut batch);
+                inters.merge(&mut batch);
+            }
+        });
+    }
+
+    /// What `ray` first hits without shading it, for data passes that only
+    /// need the position, normal and object
+    pub(crate) fn probe(&self, ray: Ray) -> Trace {
+        let Some(hit) = self.nearest_hit(ray) else {
+            return Trace {
+                colour: Colour::black(),
+                alpha: 0.0,
+                position: None,
+                normal: None,
+                object: None,
+                depth: 0,
+            };
+        };
+
+        let comps = hit.prepare_computations(ray);
+        Trace {
+            colour: Colour::black(),
+            alpha: 1.0,
+            position: Some(comps.point),
+            normal: Some(comps.normal),
+            object: self.object_index(comps.obj.id()),
+            depth: 0,
+        }
+    }
+
+    /// Nearest hit in front of `ray`, without sorting or allocating
+    pub(crate) fn nearest_hit(&self, ray: Ray) -> Option<Intersection<'_>> {
+        let mut inters = Scratch::new();
+        self.unsorted_intersect_into(ray, &mut inters);
+        Intersection::hit(inters.iter().copied())
+    }
+
+    fn unsorted_intersect_into<'a>(&'a self, ray: Ray, inters: &mut Intersections<'a>) {
+        if let Some(packed) = &self.packed {
+            packed.sweep(ray, |i, t1, t2| {
+                let obj = self.objects[i].as_ref();
+                inters.extend([Intersection::new(t1, obj), Intersection::new(t2, obj)]);
+            });
+        }
+
+        self.bvh.candidates(ray, |i| {
+            if !self.is_packed(i) && self.bounds[i].intersects(ray) {
+                self.objects[i].intersect_into(ray, inters);
+            }
+        });
+    }
+
+    /// Whether the object at `index` is intersected through `packed` instead of `bvh`
+    fn is_packed(&self, index: usize) -> bool {
+        self.packed.as_ref().is_some_and(|packed| packed.contains(index))
     }
 
-    pub fn intersect(&self, ray: Ray) -> Vec<Intersection> {
-        let mut result = Vec::new();
+    pub fn shade_hit(&self, comps: Computations) -> Colour {
+        let mut result = Colour::black();
+
+        let material = comps.obj.material();
+
+        for (i, light) in self.lights_for(comps.obj.id()) {
+            let intensity = self.light_reaching(i, light, comps.over_point);
+            result += material.direct_lighting(comps.over_point, light, comps.eye, comps.normal, intensity);
+
+            match self.ambient {
+                Ambient::PerLight => result += material.ambient_lighting(light),
+                Ambient::Scale(scale) => result += material.ambient_lighting(light) * scale,
+                Ambient::Replace(_) => {},
+            }
+        }
+
+        if let Ambient::Replace(ambient) = self.ambient {
+            result += material.colour * ambient;
+        }
 
-        for obj in &self.objects {
-            result.append(&mut obj.intersect(ray));
+        if let Some(environment) = &self.environment {
+            result += environment.lighting(&comps, self);
         }
 
-        result.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
         result
     }
+
+    pub fn colour_at(&self, ray: Ray) -> Colour {
+        match self.nearest_hit(ray) {
+            Some(hit) => self.through_medium(ray, Some(hit.t), self.shade(ray, hit.prepare_computations(ray), None).0),
+            None => self.through_medium(ray, None, self.background(ray)),
+        }
+    }
+
+    /// Colour of the hit in `comps` seen along `ray` with the world's
+    /// integrator, and how many times the path bounced. Bounces follow the
+    /// pixel's sequence when the camera says which sample `ray` is, or a
+    /// sequence of their own otherwise
+    fn shade(&self, ray: Ray, comps: Computations, sample: Option<SampleId>) -> (Colour, usize) {
+        match self.integrator {
+            Integrator::Whitted => (self.shade_hit(comps), 0),
+            Integrator::PathTracing { max_bounces, seed, min_throughput, sampler } => {
+                let mut rng = match sample {
+                    Some(sample) => sampler.sample(seed ^ sample.pixel, sample.index),
+                    None => sampler.samples(seed ^ hash_ray(ray)),
+                };
+                self.shade_path(comps, max_bounces, Colour::white(), min_throughput, &mut rng)
+            },
+        }
+    }
+
+    /// Direct lighting plus one randomly bounced ray's worth of indirect light,
+    /// and the bounces taken from here. Cosine weighted bounces make the
+    /// diffuse colour the whole weight. `throughput` is how much of this
+    /// point's light makes it back to the camera
+    fn shade_path(&self, comps: Computations, bounces: usize, throughput: Colour, min_throughput: Float,
+        rng: &mut Samples) -> (Colour, usize) {
+        let material = comps.obj.material();
+        let mut result = Colour::black();
+        let mut depth = 0;
+
+        for (i, light) in self.lights_for(comps.obj.id()) {
+            let intensity = self.light_reaching(i, light, comps.over_point);
+            result += material.direct_lighting(comps.over_point, light, comps.eye, comps.normal, intensity);
+        }
+
+        // a bounce that can only bring back a sliver of light isn't worth the rays
+        let throughput = throughput * material.colour * material.diffuse;
+        if bounces > 0 && material.diffuse > 0.0 && throughput.max_channel() >= min_throughput {
+            let bounce = Ray::new(comps.over_point, cosine_direction(comps.normal, rng));
+            let (incoming, further) = match self.nearest_hit(bounce) {
+                Some(hit) => self.shade_path(hit.prepare_computations(bounce), bounces - 1, throughput, min_throughput, rng),
+                None => (self.background(bounce), 0),
+            };
+            result += material.colour * self.clamp_sample(incoming) * material.diffuse;
+            depth = further + 1;
+        }
+
+        (result, depth)
+    }
+
+    /// `colour` seen from `t` along `ray` (or `Medium::max_distance` for a miss)
+    /// after travelling through the medium, single scattering from each light
+    fn through_medium(&self, ray: Ray, t: Option<Float>, colour: Colour) -> Colour {
+        let medium = match &self.medium {
+            Some(medium) if medium.density > 0.0 && medium.steps > 0 => medium,
+            _ => return colour,
+        };
+
+        let t = t.unwrap_or(medium.max_distance / ray.direction.magnitude());
+        let mut scattered = Colour::black();
+
+        for (step_t, length) in medium.steps(ray, t) {
+            let point = ray.position(step_t);
+            let mut lit = Colour::black();
+
+            for (i, light) in self.lights.iter().enumerate() {
+                let scattering = self.light_scattering(i);
+                if scattering > 0.0 {
+                    lit += light.intensity() * (scattering * self.light_reaching(i, light.as_ref(), point));
+                }
+            }
+
+            let distance = step_t * ray.direction.magnitude();
+            scattered += lit * (medium.density * length * medium.transmittance(distance));
+        }
+
+        let distance = t * ray.direction.magnitude();
+        colour * medium.transmittance(distance) + medium.colour * scattered
+    }
+
+    fn background(&self, ray: Ray) -> Colour {
+        match &self.environment {
+            Some(environment) => environment.sample(ray.direction),
+            None => Colour::black(),
+        }
+    }
+
+    /// Colour and coverage seen along `ray`, see `trace`
+    pub fn colour_alpha_at(&self, ray: Ray) -> (Colour, Float) {
+        let trace = self.trace(ray);
+        (trace.colour, trace.alpha)
+    }
+
+    /// Everything a camera ray records about what it sees.
+    ///
+    /// Misses are fully transparent unless there is an environment. Holdout
+    /// objects are black with an alpha of how much shadow falls on them, so a
+    /// composite darkens the plate underneath only where shadows land.
+    pub fn trace(&self, ray: Ray) -> Trace {
+        self.trace_with(ray, &mut Scratch::new())
+    }
+
+    /// `trace` using `scratch` for the camera ray's intersections, so a render
+    /// loop can keep one buffer instead of allocating for every sample
+    pub fn trace_with<'a>(&'a self, ray: Ray, scratch: &mut Intersections<'a>) -> Trace {
+        self.trace_ray(ray, None, scratch)
+    }
+
+    /// `trace_with` for `sample` of a pixel, see `SampleId`
+    pub fn trace_sample<'a>(&'a self, ray: Ray, sample: SampleId, scratch: &mut Intersections<'a>) -> Trace {
+        self.trace_ray(ray, Some(sample), scratch)
+    }
+
+    fn trace_ray<'a>(&'a self, ray: Ray, sample: Option<SampleId>, scratch: &mut Intersections<'a>) -> Trace {
+        // only the hit matters here, so there's no need to keep them in order
+        scratch.clear();
+        self.unsorted_intersect_into(ray, scratch);
+        self.trace_hits(ray, scratch, sample)
+    }
+
+    /// `trace_sample` for a packet of neighbouring camera rays, which are
+    /// intersected together so shapes can work across all of them at once
+    pub fn trace_packet<'a>(&'a self, rays: &[Ray; PACKET_SIZE], samples: &[SampleId; PACKET_SIZE],
+        scratch: &mut [Intersections<'a>; PACKET_SIZE]) -> [Trace; PACKET_SIZE] {
+        // coherent rays mostly want the same objects, so test the union
+        let mut candidates = Vec::new();
+        for ray in rays {
+            self.bvh.candidates(*ray, |i| {
+                if self.bounds[i].intersects(*ray) {
+                    candidates.push(i);
+                }
+            });
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        for inters in scratch.iter_mut() {
+            inters.clear();
+        }
+        for i in candidates {
+            self.objects[i].intersect_packet(rays, scratch);
+        }
+
+        array::from_fn(|i| self.trace_hits(rays[i], &scratch[i], Some(samples[i])))
+    }
+
+    /// Shade the nearest hit in front of `ray` out of `inters`
+    pub(crate) fn trace_hits(&self, ray: Ray, inters: &[Intersection], sample: Option<SampleId>) -> Trace {
+        let hit = match Intersection::hit(inters.iter().copied()) {
+            Some(hit) => hit,
+            None => return Trace {
+                colour: self.through_medium(ray, None, self.background(ray)),
+                alpha: if self.environment.is_some() || self.medium.is_some() { 1.0 } else { 0.0 },
+                position: None,
+                normal: None,
+                object: None,
+                depth: 0,
+            },
+        };
+
+        let comps = hit.prepare_computations(ray);
+        let position = Some(comps.point);
+        let normal = Some(comps.normal);
+        let object = self.object_index(comps.obj.id());
+
+        if !comps.obj.material().holdout {
+            let (colour, depth) = self.shade(ray, comps, sample);
+            return Trace {
+                colour: self.through_medium(ray, Some(hit.t), colour),
+                alpha: 1.0,
+                position,
+                normal,
+                object,
+                depth,
+            };
+        }
+
+        let lit: Vec<Float> = self.lights_for(comps.obj.id())
+            .map(|(i, light)| self.light_reaching(i, light, comps.over_point))
+            .collect();
+        let alpha = if lit.is_empty() {
+            0.0
+        }
+        else {
+            1.0 - lit.iter().sum::<Float>() / lit.len() as Float
+        };
+
+        Trace {
+            colour: Colour::black(),
+            alpha,
+            position,
+            normal,
+            object,
+            depth: 0,
+        }
+    }
+
+    /// Fraction of `light`, the one at `index`, reaching `point` after
+    /// attenuation and shadows, without tracing shadow rays for lights that
+    /// are out of range
+    fn light_reaching(&self, index: usize, light: &dyn Light, point: Tuple) -> Float {
+        let attenuation = light.attenuation(point);
+        if attenuation == 0.0 {
+            return 0.0;
+        }
+
+        let outer = SHADOWING.replace(Some(index));
+        let intensity = light.intensity_at(point, self);
+        SHADOWING.set(outer);
+        attenuation * intensity
+    }
+
+    /// Forget which objects blocked shadow rays on this thread, done at the
+    /// start of each tile as a tile's pixels are the ones likely to share them
+    pub(crate) fn forget_occluders() {
+        OCCLUDERS.with_borrow_mut(Vec::clear);
+    }
+
+    /// Whether anything lies between `point` and the light sample `light_pos`.
+    /// Vector samples are treated as directions to a light at infinity.
+    pub fn is_shadowed(&self, point: Tuple, light_pos: Tuple) -> bool {
+        let (direction, distance) = if light_pos.is_vector() {
+            (light_pos.norm(), Float::INFINITY)
+        }
+        else {
+            let vec = light_pos - point;
+            (vec.norm(), vec.magnitude())
+        };
+
+        let ray = Ray::new(point, direction);
+        self.shadow_rays.fetch_add(1, Ordering::Relaxed);
+
+        let mut inters = Scratch::new();
+        let mut blocks = |i: usize| {
+            let object = &self.objects[i];
+            if !object.material().occludes() {
+                return false;
+            }
+
+            // any hit before the light will do, so no need to sort
+            inters.clear();
+            object.intersect_into(ray, &mut inters);
+            inters.iter().any(|hit| hit.t > 0.0 && hit.t < distance)
+        };
+
+        // neighbouring points nearly always share a blocker, so try the last
+        // one towards this light first
+        let light = SHADOWING.get();
+        let last = light.and_then(|light| OCCLUDERS.with_borrow(|cached| cached.get(light).copied()));
+        if let Some(last) = last.and_then(|last| last.checked_sub(1)).filter(|&i| i < self.objects.len()) {
+            if blocks(last) {
+                return true;
+            }
+        }
+
+        let mut occluder = None;
+        self.bvh.candidates(ray, |i| {
+            if occluder.is_none() && self.bounds[i].intersects(ray) && blocks(i) {
+                occluder = Some(i);
+            }
+        });
+
+        match occluder {
+            Some(i) => {
+                if let Some(light) = light {
+                    OCCLUDERS.with_borrow_mut(|cached| {
+                        if cached.len() <= light {
+                            cached.resize(light + 1, 0);
+                        }
+                        cached[light] = i + 1;
+                    });
+                }
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// `World::indices` for `objects`
+fn index_ids(objects: &[Box<dyn Shape>]) -> HashMap<Uuid, usize> {
+    objects.iter().enumerate().map(|(i, obj)| (obj.id(), i)).collect()
 }
 
 impl Default for World {
     fn default() -> Self {
         let l = PointLight::new(Colour::new(1.0, 1.0, 1.0),
             Tuple::point(-10.0, 10.0, -10.0));
-        
+
         let mut s1 = Sphere::default();
-        let mut mat = Material::default();
-        mat.colour = Colour::new(0.8, 1.0, 0.6);
-        mat.diffuse = 0.7;
-        mat.specular = 0.2;
-        s1.material = mat;
+        s1.material = Material {
+            colour: Colour::new(0.8, 1.0, 0.6),
+            diffuse: 0.7,
+            specular: 0.2,
+            ..Material::default()
+        };
 
         let mut s2 = Sphere::default();
         s2.set_transform(Matrix::scaling(0.5, 0.5, 0.5));
 
-        Self::new(vec![s1, s2], Some(l))
+        Self::new(vec![Box::new(s1), Box::new(s2)], vec![Box::new(l)])
+    }
+}
+
+/// Settings for the whole world, each as described on its `World` setter
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WorldSettings {
+    pub ambient: Ambient,
+    pub integrator: Integrator,
+    pub medium: Option<Medium>,
+    pub max_sample_radiance: Option<Float>,
+    pub packed_spheres: bool,
+}
+
+/// World put together step by step: no objects, no lights, no background
+/// and default settings until they're given
+#[derive(Debug, Default)]
+pub struct WorldBuilder {
+    objects: Vec<Box<dyn Shape>>,
+    lights: Vec<Box<dyn Light>>,
+    background: Option<Environment>,
+    settings: WorldSettings,
+}
+
+impl WorldBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_object(mut self, object: impl Shape + 'static) -> Self {
+        self.objects.push(Box::new(object));
+        self
+    }
+
+    pub fn with_light(mut self, light: impl Light + 'static) -> Self {
+        self.lights.push(Box::new(light));
+        self
+    }
+
+    /// Seen by rays that miss everything and lighting the scene, see
+    /// `World::set_environment`
+    pub fn with_background(mut self, environment: Environment) -> Self {
+        self.background = Some(environment);
+        self
+    }
+
+    pub fn with_settings(mut self, settings: WorldSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Refuses a negative `max_sample_radiance`, and an object given twice
+    /// (like a copied sphere) since lights and traces tell objects apart
+    /// by their id
+    pub fn build(self) -> Result<World, RosemaryError> {
+        let settings = self.settings;
+        if settings.max_sample_radiance.is_some_and(|max| max < 0.0) {
+            return Err(RosemaryError::Negative("max sample radiance"));
+        }
+        let mut ids = HashSet::new();
+        if let Some(object) = self.objects.iter().find(|obj| !ids.insert(obj.id())) {
+            return Err(RosemaryError::DuplicateObject(object.id()));
+        }
+
+        let mut world = World::new(self.objects, self.lights);
+        world.environment = self.background;
+        world.ambient = settings.ambient;
+        world.integrator = settings.integrator;
+        world.medium = settings.medium;
+        world.max_sample_radiance = settings.max_sample_radiance;
+        world.set_packed_spheres(settings.packed_spheres);
+        Ok(world)
+    }
+}
+
+/// Everything about a world that gets saved. Bounds, the bvh and packed
+/// spheres are rebuilt on load, and the shadow ray counters start again
+#[derive(Serialize, Deserialize)]
+struct SavedWorld {
+    objects: Vec<SavedShape>,
+    lights: Vec<SavedLight>,
+    links: Vec<LightLink>,
+    scattering: Vec<Float>,
+    ambient: Ambient,
+    integrator: Integrator,
+    medium: Option<Medium>,
+    environment: Option<Environment>,
+    max_sample_radiance: Option<Float>,
+    packed_spheres: bool,
+}
+
+/// Fails for shapes and lights from outside the crate, which have no saved form
+impl Serialize for World {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let objects = self.objects.iter().enumerate()
+            .map(|(i, obj)| obj.saved().ok_or_else(|| ser::Error::custom(format!("object {i} can't be saved"))))
+            .collect::<Result<_, _>>()?;
+        let lights = self.lights.iter().enumerate()
+            .map(|(i, light)| light.saved().ok_or_else(|| ser::Error::custom(format!("light {i} can't be saved"))))
+            .collect::<Result<_, _>>()?;
+
+        SavedWorld {
+            objects,
+            lights,
+            links: self.links.clone(),
+            scattering: self.scattering.clone(),
+            ambient: self.ambient,
+            integrator: self.integrator,
+            medium: self.medium,
+            environment: self.environment.clone(),
+            max_sample_radiance: self.max_sample_radiance,
+            packed_spheres: self.packed_spheres(),
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for World {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = SavedWorld::deserialize(deserializer)?;
+        let lights = saved.lights.len();
+        if saved.links.len() != lights || saved.scattering.len() != lights {
+            return Err(de::Error::custom("need a link and scattering for every light"));
+        }
+        if saved.scattering.iter().any(|scattering| *scattering < 0.0) {
+            return Err(de::Error::custom("light scattering can't be negative"));
+        }
+        if saved.max_sample_radiance.is_some_and(|max| max < 0.0) {
+            return Err(de::Error::custom("max sample radiance can't be negative"));
+        }
+
+        let mut world = Self::new(
+            saved.objects.into_iter().map(Into::into).collect(),
+            saved.lights.into_iter().map(Into::into).collect(),
+        );
+        world.links = saved.links;
+        world.scattering = saved.scattering;
+        world.ambient = saved.ambient;
+        world.integrator = saved.integrator;
+        world.medium = saved.medium;
+        world.environment = saved.environment;
+        world.max_sample_radiance = saved.max_sample_radiance;
+        world.set_packed_spheres(saved.packed_spheres);
+        Ok(world)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::types::tuple;
+    use crate::types::{canvas::Canvas, light::SpotLight};
 
     use super::*;
 
@@ -58,11 +924,502 @@ mod tests {
         let w = World::default();
         let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
         let inters = w.intersect(r);
-        
+
         assert_eq!(inters.len(), 4);
         assert_eq!(inters[0].t, 4.0);
         assert_eq!(inters[1].t, 4.5);
         assert_eq!(inters[2].t, 5.5);
         assert_eq!(inters[3].t, 6.0);
+
+        assert_eq!(inters.hit().unwrap().t, 4.0);
+
+        // the buffer is cleared before being filled again
+        let mut buffer = inters;
+        w.intersect_into(r, &mut buffer);
+        assert_eq!(buffer.len(), 4);
+        w.intersect_into(Ray::new(Tuple::point(0.0, 5.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)), &mut buffer);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn shade_hit() {
+        let w = World::default();
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let i = Intersection::new(4.0, w.objects[0].as_ref());
+        assert_eq!(w.shade_hit(i.prepare_computations(r)), Colour::new(0.38066, 0.47583, 0.2855));
+
+        // shading from the inside
+        let l = PointLight::new(Colour::white(), Tuple::point(0.0, 0.25, 0.0));
+        let w = World {
+            lights: vec![Box::new(l)],
+            ..World::default()
+        };
+        let r = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0));
+        let i = Intersection::new(0.5, w.objects[1].as_ref());
+        assert_eq!(w.shade_hit(i.prepare_computations(r)), Colour::new(0.90498, 0.90498, 0.90498));
+
+        // intersection in shadow
+        let l = PointLight::new(Colour::white(), Tuple::point(0.0, 0.0, -10.0));
+        let s2 = Sphere::new(Matrix::translation(0.0, 0.0, 10.0), Material::default());
+        let w = World::new(vec![Box::new(Sphere::default()), Box::new(s2)], vec![Box::new(l)]);
+        let r = Ray::new(Tuple::point(0.0, 0.0, 5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let i = Intersection::new(4.0, w.objects[1].as_ref());
+        assert_eq!(w.shade_hit(i.prepare_computations(r)), Colour::new(0.1, 0.1, 0.1));
+    }
+
+    #[test]
+    fn colour_at() {
+        let w = World::default();
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 1.0, 0.0));
+        assert_eq!(w.colour_at(r), Colour::black());
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(w.colour_at(r), Colour::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn colour_alpha_at() {
+        let w = World::default();
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 1.0, 0.0));
+        assert_eq!(w.colour_alpha_at(r), (Colour::black(), 0.0));
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(w.colour_alpha_at(r), (Colour::new(0.38066, 0.47583, 0.2855), 1.0));
+    }
+
+    #[test]
+    fn trace() {
+        let w = World::default();
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 1.0, 0.0));
+        assert_eq!(w.trace(r).position, None);
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let trace = w.trace(r);
+        assert_eq!(trace.colour, Colour::new(0.38066, 0.47583, 0.2855));
+        assert_eq!(trace.position, Some(Tuple::point(0.0, 0.0, -1.0)));
+
+        // a dirty scratch buffer doesn't change the result
+        let mut scratch: Intersections = w.intersect(r).into();
+        assert_eq!(w.trace_with(r, &mut scratch), trace);
+        assert_eq!(scratch.len(), 4);
+    }
+
+    #[test]
+    fn holdout() {
+        let light = PointLight::new(Colour::white(), Tuple::point(0.0, 10.0, 0.0));
+        let mut floor = Sphere::new(Matrix::scaling(10.0, 1.0, 10.0).translate(0.0, -1.0, 0.0), Material::default());
+        floor.material.holdout = true;
+        let blocker = Sphere::new(Matrix::translation(0.0, 3.0, 0.0), Material::default());
+
+        // unshadowed holdout is fully transparent
+        let w = World::new(vec![Box::new(floor)], vec![Box::new(light)]);
+        let r = Ray::new(Tuple::point(0.0, 5.0, -5.0), (Tuple::point(0.0, 0.0, 0.0) - Tuple::point(0.0, 5.0, -5.0)).norm());
+        assert_eq!(w.colour_alpha_at(r), (Colour::black(), 0.0));
+
+        // shadowed holdout keeps the shadow in its alpha
+        let w = World::new(vec![Box::new(floor), Box::new(blocker)], vec![Box::new(light)]);
+        assert_eq!(w.colour_alpha_at(r), (Colour::black(), 1.0));
+
+        // and still occludes other objects
+        assert!(w.is_shadowed(Tuple::point(0.0, -5.0, 0.0), light.pos));
+    }
+
+    #[test]
+    fn is_shadowed() {
+        let w = World::default();
+        let light_pos = Tuple::point(-10.0, 10.0, -10.0);
+        assert!(!w.is_shadowed(Tuple::point(0.0, 10.0, 0.0), light_pos));
+        assert!(w.is_shadowed(Tuple::point(10.0, -10.0, 10.0), light_pos));
+        assert!(!w.is_shadowed(Tuple::point(-20.0, 20.0, -20.0), light_pos));
+        assert!(!w.is_shadowed(Tuple::point(-2.0, 2.0, -2.0), light_pos));
+
+        // lights at infinity
+        assert!(w.is_shadowed(Tuple::point(0.0, -5.0, 0.0), Tuple::vector(0.0, 1.0, 0.0)));
+        assert!(!w.is_shadowed(Tuple::point(0.0, 5.0, 0.0), Tuple::vector(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn is_shadowed_non_occluders() {
+        let light_pos = Tuple::point(-10.0, 10.0, -10.0);
+        let point = Tuple::point(10.0, -10.0, 10.0);
+
+        let mut s1 = Sphere::default();
+        s1.material.casts_shadow = false;
+        let mut s2 = Sphere::new(Matrix::scaling(0.5, 0.5, 0.5), Material::default());
+        s2.material.transparency = 1.0;
+        let w = World::new(vec![Box::new(s1), Box::new(s2)], vec![]);
+        assert!(!w.is_shadowed(point, light_pos));
+
+        // partially transparent objects still block
+        s2.material.transparency = 0.5;
+        let w = World::new(vec![Box::new(s1), Box::new(s2)], vec![]);
+        assert!(w.is_shadowed(point, light_pos));
+
+        // an occluder behind a non-occluder still counts
+        let s3 = Sphere::new(Matrix::translation(5.0, -5.0, 5.0), Material::default());
+        let w = World::new(vec![Box::new(s1), Box::new(s3)], vec![]);
+        assert!(w.is_shadowed(point, light_pos));
+    }
+
+    #[test]
+    fn is_shadowed_cache() {
+        // two blockers on opposite sides under a light straight above, and a
+        // second light off to the side that nothing blocks
+        let left = Sphere::new(Matrix::translation(-3.0, 0.0, 0.0), Material::default());
+        let right = Sphere::new(Matrix::translation(3.0, 0.0, 0.0), Material::default());
+        let above = PointLight::new(Colour::white(), Tuple::point(0.0, 10.0, 0.0));
+        let side = PointLight::new(Colour::white(), Tuple::point(-20.0, -5.0, 0.0));
+        let w = World::new(vec![Box::new(left), Box::new(right)], vec![Box::new(above), Box::new(side)]);
+        let cached = || OCCLUDERS.with_borrow(|cached| cached.clone());
+        let reaching = |light: usize, x: Float| w.light_reaching(light, w.lights[light].as_ref(), Tuple::point(x, -5.0, 0.0));
+
+        World::forget_occluders();
+        assert_eq!(reaching(0, -3.0), 0.0);
+        assert_eq!(cached(), [1]);
+        assert_eq!(reaching(1, -3.0), 1.0);
+        assert_eq!(cached(), [1]);
+
+        // a stale hint still finds the real blocker, and then remembers it
+        assert_eq!(reaching(0, 3.0), 0.0);
+        assert_eq!(cached(), [2]);
+        assert_eq!(reaching(0, 0.0), 1.0);
+
+        // shadow rays not towards one of the world's lights leave it alone
+        assert!(w.is_shadowed(Tuple::point(-3.0, -5.0, 0.0), Tuple::point(0.0, 10.0, 0.0)));
+        assert_eq!(cached(), [2]);
+    }
+
+    #[test]
+    fn occluders_shared_by_neighbours() {
+        // both spheres shadow the floor under them, the bvh finding the
+        // lower one first
+        let low = Sphere::new(Matrix::translation(0.0, 2.0, 0.0), Material::default());
+        let high = Sphere::new(Matrix::translation(0.0, 5.0, 0.0), Material::default());
+        let light = PointLight::new(Colour::white(), Tuple::point(0.0, 10.0, 0.0));
+        let w = World::new(vec![Box::new(low), Box::new(high)], vec![Box::new(light)]);
+        let cached = || OCCLUDERS.with_borrow(|cached| cached.clone());
+
+        World::forget_occluders();
+        assert_eq!(w.light_reaching(0, w.lights[0].as_ref(), Tuple::point(0.0, -5.0, 0.0)), 0.0);
+        let first = cached()[0];
+
+        // the neighbouring point tries the cached blocker first, so the
+        // other one isn't even looked at
+        let other = 3 - first;
+        OCCLUDERS.with_borrow_mut(|cached| cached[0] = other);
+        assert_eq!(w.light_reaching(0, w.lights[0].as_ref(), Tuple::point(0.05, -5.0, 0.0)), 0.0);
+        assert_eq!(cached(), [other]);
+    }
+
+    #[test]
+    fn multiple_lights() {
+        let mut w = World::default();
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let single = w.colour_at(r);
+
+        w.lights.push(Box::new(PointLight::new(Colour::white(), Tuple::point(-10.0, 10.0, -10.0))));
+        assert_eq!(w.colour_at(r), single + single);
+    }
+
+    #[test]
+    fn light_linking() {
+        let mut w = World::default();
+        w.lights.push(Box::new(PointLight::new(Colour::new(0.5, 0.5, 0.5), Tuple::point(10.0, 10.0, -10.0))));
+        w.links.push(LightLink::default());
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let outer = w.objects[0].id();
+        let both = w.colour_at(r);
+
+        // the outer sphere opts out of the second light
+        w.exclude_light(outer, 1);
+        let first_only = w.colour_at(r);
+        assert_eq!(first_only, World::default().colour_at(r));
+
+        // the second light lists only the inner sphere
+        w.links[1] = LightLink {
+            only: Some(HashSet::from([w.objects[1].id()])),
+            ..LightLink::default()
+        };
+        assert_eq!(w.colour_at(r), first_only);
+        assert!(!w.light_link(1).affects(outer));
+
+        // opting in to just the second light
+        w.links[1] = LightLink::default();
+        w.only_lights(outer, &[1]);
+        assert!(!w.light_link(0).affects(outer));
+        assert_ne!(w.colour_at(r), both);
+        assert_ne!(w.colour_at(r), first_only);
+
+        assert_eq!(w.try_exclude_light(outer, 2), Err(RosemaryError::NoLight(2)));
+        assert!(w.try_light_link(2).is_err());
+        w.only_lights(outer, &[]);
+        assert_eq!(w.colour_at(r), Colour::black());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ambient() {
+        let mut w = World::default();
+        let shape = w.objects[0].as_ref();
+        let material = *shape.material();
+        let comps = Intersection::new(4.0, shape)
+            .prepare_computations(Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)));
+        let per_light = w.shade_hit(comps);
+        let ambient = material.colour * material.ambient;
+
+        w.ambient = Ambient::Scale(Colour::new(2.0, 0.0, 1.0));
+        assert_eq!(w.shade_hit(comps), per_light + ambient * Colour::new(1.0, -1.0, 0.0));
+
+        w.ambient = Ambient::Replace(Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(w.shade_hit(comps), per_light - ambient + material.colour * 0.5);
+
+        // applied once, not per light
+        w.lights.clear();
+        assert_eq!(w.shade_hit(comps), material.colour * 0.5);
+    }
+
+    #[test]
+    fn path_tracing() {
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let mut w = World::default();
+
+        // without bounces it's just the direct light, no ambient
+        w.set_integrator(Integrator::PathTracing { max_bounces: 0, seed: 0, min_throughput: 0.0, sampler: SamplerKind::Random });
+        assert_eq!(w.colour_at(r), Colour::new(0.30066, 0.37583, 0.2255));
+        assert_eq!(w.trace(r).depth, 0);
+
+        // a bright environment bounces extra light in
+        let mut image = Canvas::new(4, 2);
+        for y in 0..2 {
+            for x in 0..4 {
+                image[(x, y)] = Colour::white();
+            }
+        }
+        w.set_environment(Some(Environment::new(image)));
+        w.set_integrator(Integrator::PathTracing { max_bounces: 2, seed: 0, min_throughput: 0.0, sampler: SamplerKind::Random });
+        let bounced = w.colour_at(r);
+        assert!(bounced.r > 0.30066 && bounced.g > 0.37583);
+        assert_eq!(w.colour_at(r), bounced);
+        assert_eq!(w.trace(r).colour, bounced);
+        // the bounce off the outer sphere heads away from it into the environment
+        assert_eq!(w.trace(r).depth, 1);
+
+        // the sphere passes on at most 0.7 of the light, so a higher threshold
+        // stops the bounces altogether
+        w.set_integrator(Integrator::PathTracing { max_bounces: 2, seed: 0, min_throughput: 0.5, sampler: SamplerKind::Random });
+        assert_eq!(w.colour_at(r), bounced);
+        w.set_integrator(Integrator::PathTracing { max_bounces: 2, seed: 0, min_throughput: 0.9, sampler: SamplerKind::Random });
+        assert_eq!(w.colour_at(r), Colour::new(0.30066, 0.37583, 0.2255));
+        assert_eq!(w.trace(r).depth, 0);
+    }
+
+    #[test]
+    fn medium() {
+        let spot = SpotLight::new(Colour::white(), Tuple::point(0.0, 5.0, 0.0), Tuple::vector(0.0, -1.0, 0.0),
+            0.2, 0.3);
+        let mut w = World::new(vec![], vec![Box::new(spot)]);
+        let through_beam = Ray::new(Tuple::point(-5.0, 0.0, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        let past_beam = Ray::new(Tuple::point(-5.0, 0.0, 3.0), Tuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(w.colour_at(through_beam), Colour::black());
+
+        w.set_medium(Some(Medium {
+            max_distance: 10.0,
+            steps: 64,
+            ..Medium::new(0.1)
+        }));
+        let beam = w.colour_at(through_beam);
+        assert!(beam.r > 0.0);
+        assert_eq!(w.colour_at(past_beam), Colour::black());
+        assert_eq!(w.colour_alpha_at(through_beam), (beam, 1.0));
+
+        w.set_light_scattering(0, 0.0);
+        assert_eq!(w.colour_at(through_beam), Colour::black());
+
+        // surfaces are dimmed by the medium in front of them
+        let mut w = World::default();
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let clear = w.colour_at(r);
+        w.set_medium(Some(Medium::new(0.1)));
+        w.set_light_scattering(0, 0.0);
+        assert_eq!(w.colour_at(r), clear * (-0.4 as Float).exp());
+
+        assert_eq!(w.try_set_light_scattering(0, -1.0), Err(RosemaryError::Negative("light scattering")));
+        assert_eq!(w.try_set_light_scattering(1, 1.0), Err(RosemaryError::NoLight(1)));
+        assert_eq!(w.light_scattering(0), 0.0);
+    }
+
+    #[test]
+    fn bounds_culling() {
+        let w = World::default();
+
+        // passes by both spheres' bounds
+        let r = Ray::new(Tuple::point(0.0, 1.5, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(w.intersect(r).is_empty());
+
+        // inside the outer sphere's bounds but missing the inner one
+        let r = Ray::new(Tuple::point(0.0, 0.75, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(w.intersect(r).len(), 2);
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(w.intersect(r).len(), 4);
+    }
+
+    #[test]
+    fn packed_spheres() {
+        let mut w = World::default();
+        let rays = [
+            Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.0, 0.75, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.0, 1.5, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+        ];
+        let expected: Vec<Vec<Float>> = rays.iter().map(|r| w.intersect(*r).iter().map(|i| i.t).collect()).collect();
+        let colours: Vec<_> = rays.iter().map(|r| w.colour_at(*r)).collect();
+
+        w.set_packed_spheres(true);
+        assert!(w.packed_spheres());
+        for (i, r) in rays.iter().enumerate() {
+            assert_eq!(w.intersect(*r).iter().map(|i| i.t).collect::<Vec<_>>(), expected[i]);
+            assert_eq!(w.colour_at(*r), colours[i]);
+        }
+    }
+
+    #[test]
+    fn prepare() {
+        let mut w = World::default();
+        assert!(w.prepare().is_empty());
+        w.set_packed_spheres(true);
+        assert!(w.prepare().is_empty());
+        assert!(w.packed_spheres());
+
+        let flat = Sphere::new(Matrix::scaling(1.0, 0.00001, 1.0), Material::default());
+        let mut w = World::new(vec![Box::new(Sphere::default()), Box::new(flat)], vec![]);
+        assert_eq!(w.prepare(), ["no lights or environment, so everything will be black",
+            "object 1: transform squashes it nearly flat"]);
+
+        // flatness doesn't depend on size
+        let small = Sphere::new(Matrix::scaling(0.01, 0.01, 0.01), Material::default());
+        let large = Sphere::new(Matrix::scaling(1000.0, 0.01, 1000.0), Material::default());
+        let light = PointLight::new(Colour::white(), Tuple::point(0.0, 10.0, 0.0));
+        let mut sized = World::new(vec![Box::new(small), Box::new(large)], vec![Box::new(light)]);
+        assert_eq!(sized.prepare(), ["object 1: transform squashes it nearly flat"]);
+
+        // ambient on its own is enough to see something
+        w.set_ambient(Ambient::Replace(Colour::white()));
+        assert_eq!(w.prepare().len(), 1);
+
+        let mut w = World::default();
+        w.exclude_light(Uuid::new_v4(), 0);
+        assert_eq!(w.prepare(), ["light 0: linked to an object that isn't in the world"]);
+        w.light_link_mut(0).except.clear();
+        w.light_link_mut(0).only = Some(HashSet::new());
+        assert_eq!(w.prepare(), ["light 0: only lights an empty set of objects"]);
+    }
+
+    #[test]
+    fn light_out_of_range() {
+        let mut w = World::default();
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+
+        // the hit is about 15.6 units from the light
+        w.lights = vec![Box::new(PointLight::new(Colour::white(), Tuple::point(-10.0, 10.0, -10.0)).with_range(15.0))];
+        let material = w.objects[0].material();
+        assert_eq!(w.colour_at(r), material.colour * material.ambient);
+
+        w.lights = vec![Box::new(PointLight::new(Colour::white(), Tuple::point(-10.0, 10.0, -10.0)).with_range(1000.0))];
+        assert_eq!(w.colour_at(r), World::default().colour_at(r));
+    }
+
+    #[test]
+    fn builder() {
+        let sphere = Sphere::new(Matrix::translation(0.0, 0.0, 5.0), Material::default());
+        let settings = WorldSettings {
+            ambient: Ambient::Scale(Colour::new(0.5, 0.5, 0.5)),
+            max_sample_radiance: Some(4.0),
+            packed_spheres: true,
+            ..WorldSettings::default()
+        };
+        let w = World::builder()
+            .with_object(sphere)
+            .with_light(PointLight::new(Colour::white(), Tuple::point(0.0, 10.0, 0.0)))
+            .with_background(Environment::new(Canvas::new(1, 1)))
+            .with_settings(settings)
+            .build()
+            .unwrap();
+        assert_eq!(w.objects().len(), 1);
+        assert_eq!(w.lights().len(), 1);
+        assert!(w.environment().is_some());
+        assert_eq!((w.ambient(), w.max_sample_radiance(), w.packed_spheres()), (settings.ambient, Some(4.0), true));
+
+        let twice = World::builder().with_object(sphere).with_object(sphere).build();
+        assert_eq!(twice.unwrap_err(), RosemaryError::DuplicateObject(sphere.id()));
+        let negative = World::builder().with_settings(WorldSettings { max_sample_radiance: Some(-1.0), ..settings }).build();
+        assert_eq!(negative.unwrap_err(), RosemaryError::Negative("max sample radiance"));
+    }
+
+    #[test]
+    fn add_and_remove_objects() {
+        let mut w = World::default();
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let far = Sphere::new(Matrix::translation(0.0, 0.0, 10.0), Material::default());
+        assert_eq!(w.add_object(Box::new(far)), 2);
+        assert_eq!(w.intersect(r).len(), 6);
+
+        let first = w.objects()[0].id();
+        assert_eq!(w.remove_object(0).unwrap().id(), first);
+        assert_eq!(w.intersect(r).len(), 4);
+        assert_eq!(w.objects()[1].id(), far.id());
+        assert_eq!(w.object_index(far.id()), Some(1));
+        assert_eq!(w.object_index(first), None);
+        assert!(w.remove_object(2).is_none());
+
+        // indices are right even before prepare, and the bvh catches up after
+        w.objects_mut().swap(0, 1);
+        assert_eq!(w.object_index(far.id()), Some(0));
+        w.prepare();
+        assert_eq!(w.object_index(far.id()), Some(0));
+        assert_eq!(w.trace(r).object, Some(1));
+    }
+
+    #[test]
+    fn serde() {
+        let mut w = World::default();
+        let mut mesh = crate::types::mesh::Mesh::icosphere(1);
+        mesh.set_transform(Matrix::translation(2.0, 0.0, 0.0));
+        w.objects.push(Box::new(mesh));
+        w.lights.push(Box::new(SpotLight::new(Colour::white(), Tuple::point(0.0, 5.0, -5.0),
+            Tuple::vector(0.0, -1.0, 1.0), 0.2, 0.4)));
+        w.links.push(LightLink::default());
+        w.scattering.push(1.0);
+        w.exclude_light(w.objects[1].id(), 1);
+        w.set_ambient(Ambient::Scale(Colour::new(0.5, 0.5, 0.5)));
+        w.set_medium(Some(Medium::new(0.1)));
+        w.set_max_sample_radiance(Some(4.0));
+        w.set_packed_spheres(true);
+
+        let json = serde_json::to_string(&w).unwrap();
+        let loaded: World = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+        assert!(loaded.objects.iter().zip(&w.objects).all(|(a, b)| a.id() == b.id()));
+        assert!(loaded.packed_spheres());
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(loaded.colour_at(r), w.colour_at(r));
+
+        // a light without its link can't be loaded
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["links"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<World>(value).is_err());
+
+        #[derive(Debug)]
+        struct Custom;
+        impl Light for Custom {
+            fn intensity(&self) -> Colour {
+                Colour::white()
+            }
+
+            fn samples(&self, point: Tuple) -> Vec<Tuple> {
+                vec![point]
+            }
+        }
+        w.lights.push(Box::new(Custom));
+        assert!(serde_json::to_string(&w).is_err());
+    }
+}